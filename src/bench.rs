@@ -0,0 +1,90 @@
+/*!
+Utilities for reproducible benchmarks.
+
+This module is only available with the `bench-util` feature.
+!*/
+
+use crate::{util::SplitMix64, EGraph, FromOp, Id};
+
+/**
+Deterministically builds an [`EGraph`] with `n_classes` e-classes from `seed`.
+
+Each step picks an operator from `ops`, given as `(op, arity)` pairs,
+and applies it to children chosen among the e-classes built so far.
+Some of the new e-nodes are unioned into an existing e-class,
+so the result has e-classes with several e-nodes and may contain cycles,
+just like an e-graph after a few iterations of equality saturation.
+The same `seed`, `n_classes` and `ops` always give the same e-graph.
+
+The returned e-graph is rebuilt.
+
+# Panics
+
+Panics if `ops` has no leaf (arity 0) operator,
+if `n_classes` e-classes cannot be reached
+(e.g. because every operator is a leaf),
+or if an operator fails to parse with [`FromOp`].
+
+# Example
+
+```
+use egg::{*, bench::random_egraph};
+
+let ops = [("x", 0), ("y", 0), ("+", 2), ("neg", 1)];
+let egraph: EGraph<SymbolLang, ()> = random_egraph(42, 100, &ops);
+assert_eq!(egraph.number_of_classes(), 100);
+
+let again: EGraph<SymbolLang, ()> = random_egraph(42, 100, &ops);
+assert_eq!(egraph.total_number_of_nodes(), again.total_number_of_nodes());
+```
+**/
+#[cfg_attr(docsrs, doc(cfg(feature = "bench-util")))]
+pub fn random_egraph<L: FromOp>(
+    seed: u64,
+    n_classes: usize,
+    ops: &[(&str, usize)],
+) -> EGraph<L, ()> {
+    assert!(
+        ops.iter().any(|&(_, arity)| arity == 0),
+        "random_egraph needs at least one leaf operator"
+    );
+
+    let mut rng = SplitMix64(seed);
+    let mut egraph = EGraph::default();
+    let mut classes: Vec<Id> = vec![];
+    let max_steps = 100 * (n_classes + ops.len());
+
+    for _ in 0..max_steps {
+        if egraph.number_of_classes() >= n_classes {
+            break;
+        }
+        let (op, arity) = ops[rng.below(ops.len())];
+        if arity > 0 && classes.is_empty() {
+            continue;
+        }
+        let children: Vec<Id> = (0..arity)
+            .map(|_| classes[rng.below(classes.len())])
+            .collect();
+        let node = L::from_op(op, children)
+            .unwrap_or_else(|_| panic!("random_egraph failed to parse operator {}", op));
+
+        let before = egraph.number_of_classes();
+        let id = egraph.add(node);
+        if egraph.number_of_classes() > before {
+            if !classes.is_empty() && rng.below(4) == 0 {
+                let other = classes[rng.below(classes.len())];
+                egraph.union(id, other);
+                egraph.rebuild();
+            }
+            classes.push(id);
+        }
+    }
+
+    egraph.rebuild();
+    assert!(
+        egraph.number_of_classes() >= n_classes,
+        "random_egraph could not build {} e-classes from the given operators",
+        n_classes
+    );
+    egraph
+}
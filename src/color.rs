@@ -0,0 +1,391 @@
+use std::borrow::Cow;
+use std::cmp::Ordering;
+
+use crate::extract::cmp_cost;
+use crate::util::HashMap;
+#[cfg(not(feature = "deterministic"))]
+use crate::util::SwapRemove;
+use crate::*;
+
+/**
+An [`EGraph`] as seen from one of its colors, created by
+[`EGraph::colored`].
+
+A `ColoredEGraph` is an overlay: it borrows the base e-graph and only
+stores what the color changes, namely which base e-classes the color's
+unions merge (directly or by congruence) and the e-nodes whose children
+they merge.
+So making one costs about as much as rebuilding after those unions,
+not as much as cloning the [`EGraph`].
+
+It keeps the `Id`s of the base e-graph: each colored e-class is made of
+one or more base e-classes, and [`find`](ColoredEGraph::find) returns one
+of them as its representative.
+The analysis data is not merged, so sorts and
+[leaf predicates](Pattern::with_leaf_predicate) are satisfied by a colored
+e-class when one of its base e-classes satisfies them, and
+[`Analysis::allow_ematching_cycles`] is not taken into account.
+
+See [`EGraph::new_color`] for an example.
+**/
+pub struct ColoredEGraph<'a, L: Language, N: Analysis<L>> {
+    egraph: &'a EGraph<L, N>,
+    // the colored parent of each base e-class that was merged into another
+    parents: HashMap<Id, Id>,
+    // the base e-classes of each colored e-class made of more than one
+    members: HashMap<Id, Vec<Id>>,
+    // the e-nodes with a child in `members`, with colored children
+    memo: HashMap<L, Id>,
+}
+
+impl<'a, L: Language, N: Analysis<L>> ColoredEGraph<'a, L, N> {
+    pub(crate) fn new(egraph: &'a EGraph<L, N>, unions: &[(Id, Id)]) -> Self {
+        assert!(egraph.clean, "Tried to color a dirty e-graph!");
+        let mut colored = ColoredEGraph {
+            egraph,
+            parents: HashMap::default(),
+            members: HashMap::default(),
+            memo: HashMap::default(),
+        };
+        let mut pending = unions.to_vec();
+        while !pending.is_empty() {
+            for (a, b) in pending {
+                colored.merge(a, b);
+            }
+            pending = colored.congruences();
+        }
+        colored
+    }
+
+    /// Returns the base e-graph.
+    pub fn egraph(&self) -> &'a EGraph<L, N> {
+        self.egraph
+    }
+
+    /// Canonicalizes an e-class id in this color.
+    ///
+    /// Two ids are equal in this color exactly when this returns the same
+    /// id for both.
+    pub fn find(&self, id: Id) -> Id {
+        let mut id = self.egraph.find(id);
+        while let Some(&parent) = self.parents.get(&id) {
+            id = parent;
+        }
+        id
+    }
+
+    /// Iterates over the canonical ids of the e-classes in this color.
+    pub fn classes(&self) -> impl Iterator<Item = Id> + '_ {
+        self.egraph
+            .classes()
+            .map(|class| class.id)
+            .filter(move |id| !self.parents.contains_key(id))
+    }
+
+    /// Returns the canonical ids of the base e-classes that make up the
+    /// colored e-class of `id`.
+    pub fn base_classes(&self, id: Id) -> Vec<Id> {
+        let id = self.find(id);
+        self.members.get(&id).cloned().unwrap_or_else(|| vec![id])
+    }
+
+    /// Looks up an e-node in this color, like [`EGraph::lookup`].
+    pub fn lookup(&self, enode: L) -> Option<Id> {
+        let enode = enode.map_children(|child| self.find(child));
+        if let Some(&id) = self.memo.get(&enode) {
+            return Some(id);
+        }
+        if enode.any(|child| self.members.contains_key(&child)) {
+            return None;
+        }
+        self.egraph.lookup(enode).map(|id| self.find(id))
+    }
+
+    /// Looks up a whole expression in this color, like
+    /// [`EGraph::lookup_expr`].
+    pub fn lookup_expr(&self, expr: &RecExpr<L>) -> Option<Id> {
+        let mut ids = Vec::with_capacity(expr.len());
+        for node in expr {
+            let node = node.clone().map_children(|i| ids[usize::from(i)]);
+            ids.push(self.lookup(node)?);
+        }
+        ids.last().copied()
+    }
+
+    /// Searches this color for `pattern`, like [`Searcher::search`].
+    pub fn search<'p>(&self, pattern: &'p Pattern<L>) -> Vec<SearchMatches<'p, L>> {
+        let canonical_root = pattern.compiled().canonical_root;
+        let root = pattern.ast.root();
+        // e-nodes merged by the color can witness the same operator, so use
+        // the same witness for each operator to not find a match twice
+        let mut witnesses: HashMap<L, Id> = HashMap::default();
+        let mut matches = vec![];
+        for eclass in self.classes() {
+            let mut substs = self.ematch(pattern, root, eclass, Subst::default(), canonical_root);
+            for ops in substs.iter_mut().filter_map(|subst| subst.ops.as_mut()) {
+                for (_, witness) in ops.iter_mut() {
+                    let op = self.egraph.id_to_node(*witness).clone();
+                    let op = op.map_children(|_| Id::from(0));
+                    *witness = *witnesses.entry(op).or_insert(*witness);
+                }
+            }
+            substs.sort();
+            substs.dedup();
+            if !substs.is_empty() {
+                matches.push(SearchMatches {
+                    eclass,
+                    substs,
+                    ast: Some(Cow::Borrowed(&pattern.ast)),
+                });
+            }
+        }
+        matches
+    }
+
+    /// Finds the cheapest term of the colored e-class of `id`, like
+    /// [`Extractor::find_best`].
+    ///
+    /// As for the [`Extractor`], `cost_function` should be monotonic.
+    /// Panics if the e-class has no finite term.
+    pub fn find_best<CF: CostFunction<L>>(
+        &self,
+        id: Id,
+        mut cost_function: CF,
+    ) -> (CF::Cost, RecExpr<L>) {
+        let mut costs: HashMap<Id, (CF::Cost, L)> = HashMap::default();
+        let mut did_something = true;
+        while did_something {
+            did_something = false;
+            for class in self.egraph.classes() {
+                let eclass = self.find(class.id);
+                for node in class.iter() {
+                    if !node.all(|child| costs.contains_key(&self.find(child))) {
+                        continue;
+                    }
+                    let cost = cost_function.cost(node, |child| costs[&self.find(child)].0.clone());
+                    let better = match costs.get(&eclass) {
+                        Some((old, _)) => cmp_cost(&cost, old) == Ordering::Less,
+                        None => true,
+                    };
+                    if better {
+                        costs.insert(eclass, (cost, node.clone()));
+                        did_something = true;
+                    }
+                }
+            }
+        }
+        let (cost, node) = costs
+            .get(&self.find(id))
+            .filter(|(cost, _)| !cost_function.is_infinite(cost))
+            .expect("the colored e-class has no finite term")
+            .clone();
+        let expr = node.build_recexpr(|child| costs[&self.find(child)].1.clone());
+        (cost, expr)
+    }
+
+    fn merge(&mut self, a: Id, b: Id) {
+        let (a, b) = (self.find(a), self.find(b));
+        if a == b {
+            return;
+        }
+        let size = |id| self.members.get(&id).map_or(1, Vec::len);
+        let (root, other) = if size(a) >= size(b) { (a, b) } else { (b, a) };
+        self.parents.insert(other, root);
+        let moved = self
+            .members
+            .swap_remove(&other)
+            .unwrap_or_else(|| vec![other]);
+        self.members
+            .entry(root)
+            .or_insert_with(|| vec![root])
+            .extend(moved);
+    }
+
+    /// Recomputes `memo`, returning the unions that congruence implies.
+    fn congruences(&mut self) -> Vec<(Id, Id)> {
+        let egraph = self.egraph;
+        let mut memo: HashMap<L, Id> = HashMap::default();
+        let mut unions = vec![];
+        for members in self.members.values() {
+            for &member in members {
+                for parent in egraph[member].parents() {
+                    let node = egraph.id_to_node(parent).clone();
+                    let node = node.map_children(|child| self.find(child));
+                    let eclass = self.find(parent);
+                    match memo.get(&node) {
+                        Some(&other) if other != eclass => unions.push((other, eclass)),
+                        Some(_) => {}
+                        None => {
+                            memo.insert(node, eclass);
+                        }
+                    }
+                }
+            }
+        }
+        self.memo = memo;
+        unions
+    }
+
+    /// Returns the extensions of `subst` under which `pattern.ast[pat]`
+    /// matches the colored e-class `eclass`, only looking at the first
+    /// matching e-node if `first_only`.
+    fn ematch(
+        &self,
+        pattern: &Pattern<L>,
+        pat: Id,
+        eclass: Id,
+        subst: Subst,
+        first_only: bool,
+    ) -> Vec<Subst> {
+        let egraph = self.egraph;
+        let single = [eclass];
+        let members = self.members.get(&eclass).map_or(&single[..], |m| m);
+        let mut matches = vec![];
+        match &pattern.ast[pat] {
+            ENodeOrVar::Wildcard => matches.push(subst),
+            ENodeOrVar::Var(v) => {
+                if let Some(&bound) = subst.get(*v) {
+                    if self.find(bound) == eclass {
+                        matches.push(subst);
+                    }
+                    return matches;
+                }
+                if let Some(sort) = v.sort() {
+                    let has_sort = |m: &Id| {
+                        sort.with_str(|sort| egraph.analysis.has_sort(&egraph[*m].data, sort))
+                    };
+                    if !members.iter().any(has_sort) {
+                        return matches;
+                    }
+                }
+                let predicates = &pattern.compiled().leaf_predicates;
+                for (_, pred) in predicates.iter().filter(|(var, _)| var == v) {
+                    if !members.iter().any(|&m| pred.matches::<N>(&egraph[m])) {
+                        return matches;
+                    }
+                }
+                let mut subst = subst;
+                subst.insert(*v, eclass);
+                matches.push(subst);
+            }
+            ENodeOrVar::ENode(enode) => {
+                let nodes = members.iter().flat_map(|&m| egraph[m].iter());
+                for node in nodes.filter(|node| enode.matches(node)) {
+                    let substs = subst.clone();
+                    matches.extend(self.ematch_children(pattern, enode.children(), node, substs));
+                    if first_only {
+                        break;
+                    }
+                }
+            }
+            ENodeOrVar::OpVar(v, children) => {
+                let nodes = members.iter().flat_map(|&m| egraph[m].iter());
+                for node in nodes.filter(|node| node.len() == children.len()) {
+                    let witness = match subst.get_op(*v) {
+                        Some(witness) if egraph.id_to_node(witness).matches(node) => witness,
+                        Some(_) => continue,
+                        // any e-node with the same operator will do,
+                        // and one of them is a parent of the first child
+                        None => match egraph[node.children()[0]]
+                            .parents()
+                            .find(|&p| egraph.id_to_node(p).matches(node))
+                        {
+                            Some(witness) => witness,
+                            None => continue,
+                        },
+                    };
+                    let mut subst = subst.clone();
+                    subst.insert_op(*v, witness);
+                    matches.extend(self.ematch_children(pattern, children, node, subst));
+                    if first_only {
+                        break;
+                    }
+                }
+            }
+        }
+        matches
+    }
+
+    /// Matches the children `pats` of a pattern against those of `node`.
+    fn ematch_children(
+        &self,
+        pattern: &Pattern<L>,
+        pats: &[Id],
+        node: &L,
+        subst: Subst,
+    ) -> Vec<Subst> {
+        let mut substs = vec![subst];
+        for (&pat, &child) in pats.iter().zip(node.children()) {
+            let child = self.find(child);
+            substs = substs
+                .into_iter()
+                .flat_map(|subst| self.ematch(pattern, pat, child, subst, false))
+                .collect();
+        }
+        substs
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::*;
+
+    #[test]
+    fn colored_matches_clone() {
+        let rules: Vec<Rewrite<SymbolLang, ()>> = vec![
+            rewrite!("comm"; "(+ ?x ?y)" => "(+ ?y ?x)"),
+            rewrite!("zero"; "(* ?x 0)" => "0"),
+        ];
+        let runner = Runner::default()
+            .with_expr(&"(+ (* a b) (f (* a b) (g a)))".parse().unwrap())
+            .with_expr(&"(+ (* c b) (f (* a b) (g c)))".parse().unwrap())
+            .with_expr(&"(* 0 (h b))".parse().unwrap())
+            .run(&rules);
+        let mut egraph = runner.egraph;
+        let leaf =
+            |egraph: &EGraph<SymbolLang, ()>, op| egraph.lookup(SymbolLang::leaf(op)).unwrap();
+        let (a, b, c) = (leaf(&egraph, "a"), leaf(&egraph, "b"), leaf(&egraph, "c"));
+        let color = egraph.new_color();
+        egraph.union_in_color(color, a, c);
+        egraph.union_in_color(color, b, leaf(&egraph, "0"));
+
+        let colored = egraph.colored(color);
+        let mut cloned = egraph.clone();
+        for &(x, y) in egraph.color_unions(color) {
+            cloned.union(x, y);
+        }
+        cloned.rebuild();
+
+        let ids: Vec<Id> = egraph.classes().map(|class| class.id).collect();
+        for &x in &ids {
+            for &y in &ids {
+                let same = cloned.find(x) == cloned.find(y);
+                assert_eq!(colored.find(x) == colored.find(y), same);
+            }
+        }
+        assert_eq!(colored.classes().count(), cloned.number_of_classes());
+
+        for pattern in [
+            "(+ ?x ?y)",
+            "(f ?x (g c))",
+            "(* ?x b)",
+            "(?op a ?y)",
+            "(f ?x ?y)",
+        ] {
+            let pattern: Pattern<SymbolLang> = pattern.parse().unwrap();
+            let n_matches = |ms: Vec<SearchMatches<SymbolLang>>| {
+                ms.iter().map(|m| m.substs.len()).sum::<usize>()
+            };
+            let expected = n_matches(pattern.search(&cloned));
+            assert_ne!(expected, 0, "{}", pattern);
+            assert_eq!(n_matches(colored.search(&pattern)), expected, "{}", pattern);
+        }
+
+        for &x in &ids {
+            let (cost, _) = Extractor::new(&cloned, AstSize).find_best(x);
+            let (colored_cost, best) = colored.find_best(x, AstSize);
+            assert_eq!(colored_cost, cost);
+            assert_eq!(colored.lookup_expr(&best), Some(colored.find(x)));
+        }
+    }
+}
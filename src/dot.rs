@@ -6,12 +6,13 @@ Use the [`Dot`] struct to visualize an [`EGraph`]
 [GraphViz]: https://graphviz.gitlab.io/
 !*/
 
+use std::collections::BTreeMap;
 use std::ffi::OsStr;
 use std::fmt::{self, Debug, Display, Formatter};
 use std::io::{Error, ErrorKind, Result, Write};
 use std::path::Path;
 
-use crate::{egraph::EGraph, Analysis, Language};
+use crate::{egraph::EGraph, Analysis, EClass, Language};
 
 /**
 A wrapper for an [`EGraph`] that can output [GraphViz] for
@@ -57,8 +58,11 @@ pub struct Dot<'a, L: Language, N: Analysis<L>> {
     /// Whether or not to anchor the edges in the output.
     /// True by default.
     pub use_anchors: bool,
+    pub(crate) cluster_by: Option<ClusterFn<'a, L, N>>,
 }
 
+type ClusterFn<'a, L, N> = Box<dyn Fn(&<N as Analysis<L>>::Data) -> Option<String> + 'a>;
+
 impl<'a, L, N> Dot<'a, L, N>
 where
     L: Language + Display,
@@ -84,6 +88,46 @@ where
         self
     }
 
+    /// Groups e-classes into named clusters based on their analysis data.
+    ///
+    /// Each e-class for which `f` returns `Some(name)` is drawn inside a
+    /// `subgraph cluster_...` labeled `name`, together with every other
+    /// e-class that got the same name.
+    /// E-classes for which `f` returns `None` are drawn ungrouped.
+    ///
+    /// ```
+    /// use egg::*;
+    ///
+    /// #[derive(Default)]
+    /// struct IsNum;
+    /// impl Analysis<SymbolLang> for IsNum {
+    ///     type Data = bool;
+    ///     fn make(_: &mut EGraph<SymbolLang, Self>, enode: &SymbolLang) -> bool {
+    ///         enode.op.as_str().parse::<i32>().is_ok()
+    ///     }
+    ///     fn merge(&mut self, a: &mut bool, b: bool) -> DidMerge {
+    ///         merge_max(a, b)
+    ///     }
+    /// }
+    ///
+    /// let mut egraph = EGraph::<SymbolLang, IsNum>::default();
+    /// egraph.add_expr(&"(+ 1 (* x 2))".parse().unwrap());
+    /// egraph.rebuild();
+    ///
+    /// let dot = egraph
+    ///     .dot()
+    ///     .with_cluster_by(|&is_num| if is_num { Some("numbers".into()) } else { None })
+    ///     .to_string();
+    /// assert_eq!(dot.matches("label = \"numbers\"").count(), 1);
+    /// ```
+    pub fn with_cluster_by<F>(mut self, f: F) -> Self
+    where
+        F: Fn(&N::Data) -> Option<String> + 'a,
+    {
+        self.cluster_by = Some(Box::new(f));
+        self
+    }
+
     /// Renders the `Dot` to a .png file with the given filename.
     /// Requires a `dot` binary to be on your `$PATH`.
     pub fn to_png(&self, filename: impl AsRef<Path>) -> Result<()> {
@@ -170,6 +214,26 @@ where
     }
 }
 
+impl<'a, L, N> Dot<'a, L, N>
+where
+    L: Language + Display,
+    N: Analysis<L>,
+{
+    fn write_class(
+        &self,
+        f: &mut Formatter,
+        class: &EClass<L, N::Data>,
+        indent: &str,
+    ) -> fmt::Result {
+        writeln!(f, "{}subgraph cluster_{} {{", indent, class.id)?;
+        writeln!(f, "{}  style=dotted", indent)?;
+        for (i, node) in class.iter().enumerate() {
+            writeln!(f, "{}  {}.{}[label = \"{}\"]", indent, class.id, i, node)?;
+        }
+        writeln!(f, "{}}}", indent)
+    }
+}
+
 impl<'a, L: Language, N: Analysis<L>> Debug for Dot<'a, L, N> {
     fn fmt(&self, f: &mut Formatter) -> fmt::Result {
         f.debug_tuple("Dot").field(self.egraph).finish()
@@ -192,12 +256,25 @@ where
             writeln!(f, "  {}", line)?;
         }
 
-        // define all the nodes, clustered by eclass
+        // group the eclasses by the user-provided cluster names, if any
+        let mut ungrouped = vec![];
+        let mut groups: BTreeMap<String, Vec<&EClass<L, N::Data>>> = BTreeMap::new();
         for class in self.egraph.classes() {
-            writeln!(f, "  subgraph cluster_{} {{", class.id)?;
-            writeln!(f, "    style=dotted")?;
-            for (i, node) in class.iter().enumerate() {
-                writeln!(f, "    {}.{}[label = \"{}\"]", class.id, i, node)?;
+            match self.cluster_by.as_ref().and_then(|by| by(&class.data)) {
+                Some(name) => groups.entry(name).or_default().push(class),
+                None => ungrouped.push(class),
+            }
+        }
+
+        // define all the nodes, clustered by eclass
+        for class in ungrouped {
+            self.write_class(f, class, "  ")?;
+        }
+        for (i, (name, classes)) in groups.iter().enumerate() {
+            writeln!(f, "  subgraph cluster_group_{} {{", i)?;
+            writeln!(f, "    label = {:?}", name)?;
+            for class in classes {
+                self.write_class(f, class, "    ")?;
             }
             writeln!(f, "  }}")?;
         }
@@ -49,6 +49,46 @@ impl<L: Language, D> EClass<L, D> {
         self.nodes.iter().filter(|&n| n.is_leaf())
     }
 
+    /// Returns the e-nodes of this e-class in sorted order.
+    ///
+    /// [`EGraph::rebuild`] canonicalizes, sorts and dedups the e-nodes of
+    /// every e-class, so this only holds on a clean e-graph: adding e-nodes
+    /// or making unions breaks the order until the next rebuild.
+    /// This is checked in debug builds.
+    pub fn nodes_sorted(&self) -> &[L] {
+        debug_assert!(
+            self.nodes.windows(2).all(|w| w[0] < w[1]),
+            "e-class {} is not sorted, did you forget to rebuild?",
+            self.id
+        );
+        &self.nodes
+    }
+
+    /// Checks whether this e-class contains `node`, using a binary search.
+    ///
+    /// `node` must have canonical children, and, like
+    /// [`nodes_sorted`](EClass::nodes_sorted()), this relies on the
+    /// e-nodes being sorted, which only holds after a rebuild.
+    ///
+    /// # Example
+    /// ```
+    /// # use egg::*;
+    /// let mut egraph = EGraph::<SymbolLang, ()>::default();
+    /// let x = egraph.add(SymbolLang::leaf("x"));
+    /// let fx = egraph.add(SymbolLang::new("f", vec![x]));
+    /// let y = egraph.add(SymbolLang::leaf("y"));
+    /// egraph.union(fx, y);
+    /// egraph.rebuild();
+    ///
+    /// let class = &egraph[y];
+    /// assert!(class.contains(&SymbolLang::leaf("y")));
+    /// assert!(class.contains(&SymbolLang::new("f", vec![egraph.find(x)])));
+    /// assert!(!class.contains(&SymbolLang::leaf("x")));
+    /// ```
+    pub fn contains(&self, node: &L) -> bool {
+        self.nodes_sorted().binary_search(node).is_ok()
+    }
+
     /// Asserts that the childless enodes in this eclass are unique.
     pub fn assert_unique_leaves(&self)
     where
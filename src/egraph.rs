@@ -1,14 +1,19 @@
 use crate::*;
 use std::{
+    any::Any,
     borrow::BorrowMut,
+    cmp::Ordering,
+    collections::{BTreeMap, VecDeque},
     fmt::{self, Debug, Display},
     marker::PhantomData,
+    sync::Arc,
 };
 
 #[cfg(feature = "serde-1")]
 use serde::{Deserialize, Serialize};
 
 use log::*;
+use thiserror::Error;
 
 /** A data structure to keep track of equalities between expressions.
 
@@ -67,6 +72,10 @@ pub struct EGraph<L: Language, N: Analysis<L>> {
     /// not the canonical id of the eclass.
     pending: Vec<Id>,
     analysis_pending: UniqueQueue<Id>,
+    /// E-classes touched since the last rebuild, used by
+    /// [`EGraph::rebuild_partial`]. These are uncanonical `Id`s.
+    #[cfg_attr(feature = "serde-1", serde(skip))]
+    dirty: Vec<Id>,
     #[cfg_attr(
         feature = "serde-1",
         serde(bound(
@@ -78,6 +87,38 @@ pub struct EGraph<L: Language, N: Analysis<L>> {
     #[cfg_attr(feature = "serde-1", serde(skip))]
     #[cfg_attr(feature = "serde-1", serde(default = "default_classes_by_op"))]
     classes_by_op: HashMap<L::Discriminant, HashSet<Id>>,
+    /// See [`EGraph::with_max_nodes_per_class`].
+    #[cfg_attr(feature = "serde-1", serde(skip))]
+    max_nodes_per_class: Option<usize>,
+    /// See [`EGraph::with_canonical_min_id`].
+    #[cfg_attr(feature = "serde-1", serde(skip))]
+    canonical_min_id: bool,
+    #[cfg_attr(feature = "serde-1", serde(skip))]
+    refused_unions: usize,
+    /// The canonical ids of the e-classes frozen by [`EGraph::freeze`].
+    #[cfg_attr(feature = "serde-1", serde(skip))]
+    frozen: HashSet<Id>,
+    /// When set, every union is recorded here as the pair of merged
+    /// e-classes, the new root first. Used by [`Runner::on_union`].
+    #[cfg_attr(feature = "serde-1", serde(skip))]
+    pub(crate) union_log: Option<Vec<(Id, Id)>>,
+    /// Metadata attached to e-nodes by [`EGraph::add_with_meta`], keyed by
+    /// the uncanonical id each e-node was created with.
+    #[cfg_attr(feature = "serde-1", serde(skip))]
+    meta: HashMap<Id, Arc<dyn Any + Send + Sync>>,
+    /// The extra unions assumed in each color, see [`EGraph::new_color`].
+    #[cfg_attr(feature = "serde-1", serde(skip))]
+    colors: Vec<Vec<(Id, Id)>>,
+    /// The number of rebuilds that changed something, see
+    /// [`EGraph::epoch`].
+    #[cfg_attr(feature = "serde-1", serde(skip))]
+    epoch: usize,
+    /// The e-classes touched by each of the last `change_history` epochs,
+    /// see [`EGraph::with_change_tracking`].
+    #[cfg_attr(feature = "serde-1", serde(skip))]
+    change_log: VecDeque<Vec<Id>>,
+    #[cfg_attr(feature = "serde-1", serde(skip))]
+    change_history: usize,
     /// Whether or not reading operation are allowed on this e-graph.
     /// Mutating operations will set this to `false`, and
     /// [`EGraph::rebuild`] will set it to true.
@@ -87,6 +128,103 @@ pub struct EGraph<L: Language, N: Analysis<L>> {
     pub clean: bool,
 }
 
+/// A handle to a color of an [`EGraph`], created by [`EGraph::new_color`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct ColorId(usize);
+
+/// A broken invariant of an [`EGraph`], found by
+/// [`EGraph::check_invariants`].
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+#[non_exhaustive]
+pub enum InvariantViolation<L> {
+    /// The union-find doesn't have one id per e-node, or points outside of
+    /// itself.
+    #[error("the union-find has {ids} ids for {nodes} e-nodes")]
+    UnionFind {
+        /// The number of ids in the union-find.
+        ids: usize,
+        /// The number of e-nodes ever added.
+        nodes: usize,
+    },
+    /// An e-class is stored under an id that doesn't exist.
+    #[error("e-class {0} does not exist")]
+    UnknownClass(Id),
+    /// An e-node of an e-class has a child that doesn't exist.
+    #[error("e-node {node:?} in e-class {class} has an unknown child")]
+    UnknownChild {
+        /// The e-class of the e-node.
+        class: Id,
+        /// The offending e-node.
+        node: L,
+    },
+    /// A hashcons entry mentions an id that doesn't exist.
+    #[error("hashcons entry {node:?} -> {class} has an unknown id")]
+    UnknownHashconsId {
+        /// The key of the entry.
+        node: L,
+        /// The e-class it maps to.
+        class: Id,
+    },
+    /// An e-class is stored under an id that is not its canonical id.
+    #[error("e-class {class} is stored under the non-canonical id {stored_as}")]
+    NonCanonicalClass {
+        /// The id the e-class thinks it has.
+        class: Id,
+        /// The id it is stored under.
+        stored_as: Id,
+    },
+    /// A union-find root has no e-class, so no analysis data either.
+    #[error("the canonical id {0} has no e-class")]
+    MissingClass(Id),
+    /// An e-node of an e-class has a non-canonical child.
+    #[error("e-node {node:?} in e-class {class} is not canonical")]
+    NonCanonicalNode {
+        /// The e-class of the e-node.
+        class: Id,
+        /// The offending e-node.
+        node: L,
+    },
+    /// The same e-node is in two e-classes, which should have been merged
+    /// by congruence.
+    #[error("e-node {node:?} is in both e-classes {first} and {second}")]
+    DuplicateNode {
+        /// The duplicated e-node.
+        node: L,
+        /// One of the e-classes holding it.
+        first: Id,
+        /// The other one.
+        second: Id,
+    },
+    /// The hashcons doesn't map an e-node to its e-class.
+    #[error("the hashcons maps e-node {node:?} of e-class {class} to {hashcons:?}")]
+    HashconsMismatch {
+        /// The e-node.
+        node: L,
+        /// The e-class holding it.
+        class: Id,
+        /// The canonical e-class it is mapped to instead, if any.
+        hashcons: Option<Id>,
+    },
+    /// A canonical hashcons entry's e-node is in no e-class, or not in the
+    /// one it maps to.
+    #[error("the hashcons has an entry {node:?} -> {class} that is in no e-class")]
+    StaleHashcons {
+        /// The key of the entry.
+        node: L,
+        /// The e-class it maps to.
+        class: Id,
+    },
+}
+
+/// Statistics about a single [`EGraph::rebuild`].
+#[derive(Debug, Clone, Copy, Default)]
+pub(crate) struct RebuildStats {
+    /// The number of unions found by congruence.
+    pub(crate) congruence_merges: usize,
+    /// The number of times the worklist was processed.
+    pub(crate) worklist_iters: usize,
+}
+
 #[cfg(feature = "serde-1")]
 fn default_classes_by_op<K>() -> HashMap<K, HashSet<Id>> {
     HashMap::default()
@@ -121,7 +259,18 @@ impl<L: Language, N: Analysis<L>> EGraph<L, N> {
             pending: Default::default(),
             memo: Default::default(),
             analysis_pending: Default::default(),
+            dirty: Default::default(),
             classes_by_op: Default::default(),
+            max_nodes_per_class: None,
+            canonical_min_id: false,
+            refused_unions: 0,
+            frozen: Default::default(),
+            union_log: None,
+            meta: Default::default(),
+            colors: Default::default(),
+            epoch: 0,
+            change_log: Default::default(),
+            change_history: 0,
         }
     }
 
@@ -151,6 +300,43 @@ impl<L: Language, N: Analysis<L>> EGraph<L, N> {
         &self.nodes
     }
 
+    /// Releases the spare capacity of the collections backing this
+    /// `EGraph`, including the node and parent lists of every e-class.
+    ///
+    /// This is only about memory, e.g. to keep a finished e-graph around
+    /// for extraction; the e-graph means the same afterwards, though the
+    /// iteration order of its hash maps, which is unspecified anyway, may
+    /// change.
+    ///
+    /// ```
+    /// # use egg::*;
+    /// let mut egraph = EGraph::<SymbolLang, ()>::default();
+    /// egraph.add_expr(&"(+ (* a b) (* a c))".parse().unwrap());
+    /// egraph.rebuild();
+    /// let classes = egraph.number_of_classes();
+    /// egraph.shrink_to_fit();
+    /// assert_eq!(egraph.number_of_classes(), classes);
+    /// assert!(egraph.lookup_expr(&"(* a c)".parse().unwrap()).is_some());
+    /// ```
+    pub fn shrink_to_fit(&mut self) {
+        self.unionfind.shrink_to_fit();
+        self.nodes.shrink_to_fit();
+        self.memo.shrink_to_fit();
+        self.pending.shrink_to_fit();
+        self.analysis_pending.shrink_to_fit();
+        self.dirty.shrink_to_fit();
+        self.classes.shrink_to_fit();
+        for class in self.classes.values_mut() {
+            class.nodes.shrink_to_fit();
+            class.parents.shrink_to_fit();
+        }
+        self.classes_by_op.shrink_to_fit();
+        for ids in self.classes_by_op.values_mut() {
+            ids.shrink_to_fit();
+        }
+        self.meta.shrink_to_fit();
+    }
+
     /// Returns `true` if the egraph is empty
     /// # Example
     /// ```
@@ -189,14 +375,269 @@ impl<L: Language, N: Analysis<L>> EGraph<L, N> {
         self.classes().map(|c| c.len()).sum()
     }
 
+    /// Returns the number of e-nodes stored in the e-classes of the e-graph.
+    ///
+    /// This is the same as
+    /// [`total_number_of_nodes`](EGraph::total_number_of_nodes); after a
+    /// [`rebuild`](EGraph::rebuild) it also equals
+    /// [`total_size`](EGraph::total_size).
+    pub fn number_of_nodes(&self) -> usize {
+        self.total_number_of_nodes()
+    }
+
     /// Returns the number of eclasses in the egraph.
     pub fn number_of_classes(&self) -> usize {
         self.classes.len()
     }
 
+    /// Maps each e-class size (in e-nodes) to the number of e-classes that
+    /// have that size.
+    ///
+    /// A few very large e-classes are a typical sign of a blow-up, e.g. from
+    /// associativity and commutativity rules.
+    ///
+    /// # Example
+    /// ```
+    /// use egg::{*, SymbolLang as S};
+    /// let mut egraph = EGraph::<S, ()>::default();
+    /// let x = egraph.add(S::leaf("x"));
+    /// let y = egraph.add(S::leaf("y"));
+    /// let z = egraph.add(S::leaf("z"));
+    /// egraph.add(S::leaf("w"));
+    /// egraph.union(x, y);
+    /// egraph.union(x, z);
+    /// egraph.rebuild();
+    ///
+    /// let histogram = egraph.class_size_histogram();
+    /// assert_eq!(histogram.into_iter().collect::<Vec<_>>(), [(1, 1), (3, 1)]);
+    /// assert_eq!(egraph.number_of_nodes(), 4);
+    /// ```
+    pub fn class_size_histogram(&self) -> BTreeMap<usize, usize> {
+        let mut histogram = BTreeMap::new();
+        for class in self.classes() {
+            *histogram.entry(class.len()).or_default() += 1;
+        }
+        histogram
+    }
+
+    /// Caps the number of e-nodes an e-class may hold.
+    ///
+    /// Once set, a [`union`](EGraph::union) (or any other union that
+    /// doesn't come from congruence, like the ones performed by
+    /// [`Rewrite`]s) is refused if the merged e-class would hold more than
+    /// `max` e-nodes. The union then returns `false` and
+    /// [`union_instantiations`](EGraph::union_instantiations) returns the
+    /// existing canonical id, so the new e-node stays in its own e-class.
+    /// Refused unions are counted by
+    /// [`refused_unions`](EGraph::refused_unions).
+    ///
+    /// This is a pragmatic knob against blow-ups (e.g. from associativity
+    /// and commutativity), but it makes the e-graph incomplete: equalities
+    /// are silently lost, so saturation and extraction results can change.
+    /// Unions found by congruence during [`rebuild`](EGraph::rebuild) are
+    /// never refused, so an e-class can still grow past the cap that way.
+    ///
+    /// # Example
+    /// ```
+    /// use egg::{*, SymbolLang as S};
+    /// let mut egraph = EGraph::<S, ()>::default().with_max_nodes_per_class(2);
+    /// let x = egraph.add(S::leaf("x"));
+    /// let y = egraph.add(S::leaf("y"));
+    /// let z = egraph.add(S::leaf("z"));
+    /// assert!(egraph.union(x, y));
+    /// assert!(!egraph.union(x, z));
+    /// egraph.rebuild();
+    ///
+    /// assert_ne!(egraph.find(x), egraph.find(z));
+    /// assert_eq!(egraph.refused_unions(), 1);
+    /// ```
+    pub fn with_max_nodes_per_class(mut self, max: usize) -> Self {
+        self.max_nodes_per_class = Some(max);
+        self
+    }
+
+    /// Makes this e-graph remember which e-classes changed in each of its
+    /// last `history` [epochs](EGraph::epoch), so that
+    /// [`classes_changed_since`](EGraph::classes_changed_since) can tell.
+    ///
+    /// This is off by default, since it costs some time and memory on
+    /// every [`rebuild`](EGraph::rebuild).
+    /// Older epochs are forgotten, so a cache like [`CachingSearcher`](crate::CachingSearcher)
+    /// falls back to recomputing everything if it was last updated more
+    /// than `history` epochs ago.
+    pub fn with_change_tracking(mut self, history: usize) -> Self {
+        self.change_history = history;
+        self.change_log.truncate(history);
+        self
+    }
+
+    /// Makes [`union`](EGraph::union) keep the smaller of the two roots as
+    /// the root of the merged e-class, so that every canonical id is the
+    /// smallest id in its e-class, and doesn't depend on the order in which
+    /// the unions happened.
+    /// This makes the output of [`find`](EGraph::find) stable across runs
+    /// that add the same terms in the same order.
+    ///
+    /// [`Analysis::prefer`] still takes precedence, this only replaces the
+    /// default tie-breaking, which keeps the e-class with more parents as
+    /// the root to update fewer of them.
+    /// Since path compression keeps the union-find shallow either way, the
+    /// cost of this is negligible.
+    ///
+    /// ```
+    /// use egg::{*, SymbolLang as S};
+    /// let mut egraph = EGraph::<S, ()>::default().with_canonical_min_id(true);
+    /// let x = egraph.add(S::leaf("x"));
+    /// let y = egraph.add(S::leaf("y"));
+    /// // y has more parents, but x has the smaller id
+    /// egraph.add(S::new("f", vec![y]));
+    /// egraph.add(S::new("g", vec![y]));
+    /// egraph.union(y, x);
+    /// egraph.rebuild();
+    /// assert_eq!(egraph.find(y), x);
+    /// ```
+    pub fn with_canonical_min_id(mut self, enabled: bool) -> Self {
+        self.canonical_min_id = enabled;
+        self
+    }
+
+    /// Returns the number of unions refused because of
+    /// [`with_max_nodes_per_class`](EGraph::with_max_nodes_per_class)
+    /// or [`freeze`](EGraph::freeze).
+    pub fn refused_unions(&self) -> usize {
+        self.refused_unions
+    }
+
+    /// Freezes the e-class of `id`, so nothing new can be merged into it.
+    ///
+    /// Any [`union`](EGraph::union) involving a frozen e-class (including
+    /// the ones performed by [`Rewrite`]s and by [`Analysis::modify`]) is
+    /// refused and counted by [`refused_unions`](EGraph::refused_unions),
+    /// just like with
+    /// [`with_max_nodes_per_class`](EGraph::with_max_nodes_per_class).
+    /// Since [`add`](EGraph::add) only ever puts new e-nodes into fresh
+    /// e-classes, this also means that no e-node can be added to a frozen
+    /// e-class: a rewrite that tries will leave its right-hand side in its
+    /// own e-class.
+    ///
+    /// The policy for merges that cannot be refused is to keep the result
+    /// frozen: unions found by congruence during
+    /// [`rebuild`](EGraph::rebuild) are still performed, since the e-graph
+    /// would be broken otherwise, and if one side was frozen the merged
+    /// e-class is frozen too.
+    ///
+    /// Freezing makes saturation incomplete in the same way as a node cap:
+    /// equalities involving frozen e-classes are silently dropped, so
+    /// terms that would have become equivalent may stay apart.
+    ///
+    /// # Example
+    /// ```
+    /// use egg::{*, SymbolLang as S};
+    /// let mut egraph = EGraph::<S, ()>::default();
+    /// let x = egraph.add(S::leaf("x"));
+    /// let y = egraph.add(S::leaf("y"));
+    /// egraph.freeze(x);
+    /// assert!(egraph.is_frozen(x));
+    ///
+    /// assert!(!egraph.union(y, x));
+    /// egraph.rebuild();
+    /// assert_ne!(egraph.find(x), egraph.find(y));
+    /// assert_eq!(egraph.refused_unions(), 1);
+    /// ```
+    pub fn freeze(&mut self, id: Id) {
+        let id = self.find(id);
+        self.frozen.insert(id);
+    }
+
+    /// Returns whether the e-class of `id` has been [`freeze`](EGraph::freeze)d.
+    pub fn is_frozen(&self, id: Id) -> bool {
+        !self.frozen.is_empty() && self.frozen.contains(&self.find(id))
+    }
+
+    /// Creates a new, empty color: a layer of extra unions that are only
+    /// assumed to hold in that color, e.g. for case splits like
+    /// "assume `x > 0`".
+    ///
+    /// Colors never change the `EGraph` itself.
+    /// Add assumptions with [`union_in_color`](EGraph::union_in_color), then
+    /// get the e-graph as seen from the color with
+    /// [`colored`](EGraph::colored), which is the base e-graph plus the
+    /// color's unions and everything they imply by congruence.
+    /// Searching and extracting are done on that [`ColoredEGraph`], and since
+    /// it keeps the `Id`s of the base e-graph, the results can be related
+    /// back to it.
+    ///
+    /// This is a first version: the [`ColoredEGraph`] only stores what the
+    /// color changes, but it is computed again on each call to
+    /// [`colored`](EGraph::colored), and colors cannot be nested.
+    ///
+    /// # Example
+    /// ```
+    /// use egg::*;
+    /// let mut egraph = EGraph::<SymbolLang, ()>::default();
+    /// let abs = egraph.add_expr(&"(abs x)".parse().unwrap());
+    /// let x = egraph.lookup(SymbolLang::leaf("x")).unwrap();
+    /// egraph.rebuild();
+    ///
+    /// // in the case where x is positive, (abs x) is x
+    /// let positive = egraph.new_color();
+    /// egraph.union_in_color(positive, abs, x);
+    /// egraph.add_expr(&"(f (abs x))".parse().unwrap());
+    /// egraph.rebuild();
+    ///
+    /// let view = egraph.colored(positive);
+    /// assert_eq!(view.find(abs), view.find(x));
+    /// let fx: RecExpr<SymbolLang> = "(f x)".parse().unwrap();
+    /// assert!(view.lookup_expr(&fx).is_some());
+    ///
+    /// let fx_pattern: Pattern<SymbolLang> = "(f x)".parse().unwrap();
+    /// assert_eq!(view.search(&fx_pattern).len(), 1);
+    /// assert!(fx_pattern.search(&egraph).is_empty());
+    /// assert_eq!(view.find_best(abs, AstSize).1, "x".parse().unwrap());
+    ///
+    /// // the base e-graph is untouched
+    /// assert_ne!(egraph.find(abs), egraph.find(x));
+    /// ```
+    pub fn new_color(&mut self) -> ColorId {
+        self.colors.push(vec![]);
+        ColorId(self.colors.len() - 1)
+    }
+
+    /// Assumes that `a` and `b` are equal in `color`, without touching the
+    /// base e-graph. See [`new_color`](EGraph::new_color).
+    ///
+    /// Panics if `color` does not belong to this `EGraph`.
+    pub fn union_in_color(&mut self, color: ColorId, a: Id, b: Id) {
+        self.colors[color.0].push((a, b));
+    }
+
+    /// Returns the unions assumed in `color` so far, as they were passed to
+    /// [`union_in_color`](EGraph::union_in_color).
+    ///
+    /// Panics if `color` does not belong to this `EGraph`.
+    pub fn color_unions(&self, color: ColorId) -> &[(Id, Id)] {
+        &self.colors[color.0]
+    }
+
+    /// Returns this `EGraph` as seen from `color`, i.e. with the unions
+    /// assumed in `color` and everything they imply by congruence.
+    /// See [`new_color`](EGraph::new_color) and [`ColoredEGraph`].
+    ///
+    /// Panics if `color` does not belong to this `EGraph`, or if it needs
+    /// to be [rebuilt](EGraph::rebuild).
+    pub fn colored(&self, color: ColorId) -> ColoredEGraph<'_, L, N> {
+        let unions = self
+            .colors
+            .get(color.0)
+            .expect("color does not belong to this e-graph");
+        ColoredEGraph::new(self, unions)
+    }
+
     /// Enable explanations for this `EGraph`.
     /// This allows the egraph to explain why two expressions are
     /// equivalent with the [`explain_equivalence`](EGraph::explain_equivalence) function.
+    /// Use [`enable_explanations`](EGraph::enable_explanations) to start
+    /// recording on an `EGraph` that is not empty.
     pub fn with_explanations_enabled(mut self) -> Self {
         if self.explain.is_some() {
             return self;
@@ -383,6 +824,39 @@ impl<L: Language, N: Analysis<L>> EGraph<L, N> {
         res_id
     }
 
+    /// Extracts the cheapest term for the e-class that `var` is bound to in
+    /// `subst`, according to `cost_fn`.
+    ///
+    /// This is handy in an [`Applier`] that needs a concrete term for a
+    /// variable, for example to log it or pass it to an external tool.
+    /// It builds a fresh [`Extractor`] on every call, so if you need terms
+    /// for several variables, build one [`Extractor`] and use
+    /// [`find_best_var`](Extractor::find_best_var) instead.
+    ///
+    /// Panics if `var` is not bound in `subst`.
+    ///
+    /// ```
+    /// use egg::*;
+    ///
+    /// let mut egraph = EGraph::<SymbolLang, ()>::default();
+    /// let root = egraph.add_expr(&"(+ (* x 1) y)".parse().unwrap());
+    /// let x1 = egraph.add_expr(&"(* x 1)".parse().unwrap());
+    /// let x = egraph.add_expr(&"x".parse().unwrap());
+    /// egraph.union(x1, x);
+    /// egraph.rebuild();
+    ///
+    /// let pat: Pattern<SymbolLang> = "(+ ?a ?b)".parse().unwrap();
+    /// let matches = pat.search_eclass(&egraph, root).unwrap();
+    /// let a = egraph.extract_var(&matches.substs[0], "?a".parse().unwrap(), AstSize);
+    /// assert_eq!(a.to_string(), "x");
+    /// ```
+    pub fn extract_var<CF>(&self, subst: &Subst, var: Var, cost_fn: CF) -> RecExpr<L>
+    where
+        CF: CostFunction<L>,
+    {
+        Extractor::new(self, cost_fn).find_best_var(subst, var).1
+    }
+
     /// Like [`id_to_expr`](EGraph::id_to_expr) but only goes one layer deep
     pub fn id_to_node(&self, id: Id) -> &L {
         &self.nodes[usize::from(id)]
@@ -434,12 +908,120 @@ impl<L: Language, N: Analysis<L>> EGraph<L, N> {
         }
     }
 
+    /// Lists the unions attributed to the rule named `rule`, in terms of
+    /// e-node ids, so their effect can be analyzed or left out.
+    ///
+    /// This is [`get_union_equalities`](EGraph::get_union_equalities)
+    /// restricted to one rule, so it includes unions the rule re-derived
+    /// for e-nodes that were already equal.
+    /// Unions cannot be deleted from an `EGraph`, but a copy without the
+    /// rule's merges can be built from
+    /// [`copy_without_unions`](EGraph::copy_without_unions) and the unions
+    /// of all the other rules, as in the example below.
+    /// Unions found by congruence are not attributed to any rule, so they
+    /// are recovered by rebuilding the copy.
+    ///
+    /// Panics if explanations are not enabled.
+    ///
+    /// # Example
+    /// ```
+    /// # use egg::*;
+    /// let mut egraph = EGraph::<SymbolLang, ()>::default().with_explanations_enabled();
+    /// let a = egraph.add(SymbolLang::leaf("a"));
+    /// let b = egraph.add(SymbolLang::leaf("b"));
+    /// let c = egraph.add(SymbolLang::leaf("c"));
+    /// egraph.union_trusted(a, b, "ab");
+    /// egraph.union_trusted(b, c, "bc");
+    /// egraph.rebuild();
+    ///
+    /// let bc = Symbol::from("bc");
+    /// assert_eq!(egraph.explain_contributions(bc), vec![(b, c)]);
+    ///
+    /// let mut without = egraph.copy_without_unions(());
+    /// for (x, y, rule) in egraph.get_union_equalities() {
+    ///     if rule != bc {
+    ///         without.union_trusted(x, y, rule);
+    ///     }
+    /// }
+    /// without.rebuild();
+    /// assert_eq!(without.find(a), without.find(b));
+    /// assert_ne!(without.find(b), without.find(c));
+    /// ```
+    pub fn explain_contributions(&self, rule: Symbol) -> Vec<(Id, Id)> {
+        self.get_union_equalities()
+            .into_iter()
+            .filter(|&(_, _, r)| r == rule)
+            .map(|(a, b, _)| (a, b))
+            .collect()
+    }
+
     /// Disable explanations for this `EGraph`.
     pub fn with_explanations_disabled(mut self) -> Self {
         self.explain = None;
         self
     }
 
+    /// Start recording explanations from this point on, even if this
+    /// `EGraph` already has e-nodes in it.
+    ///
+    /// Proofs only cover unions performed while recording is on.
+    /// Any equality that already held when recording started shows up in
+    /// an [`Explanation`] as a single step justified by the rule
+    /// `"<unrecorded>"`, so such explanations cannot be checked.
+    /// Does nothing if explanations are already enabled.
+    ///
+    /// ```
+    /// use egg::*;
+    /// let mut egraph = EGraph::<SymbolLang, ()>::default();
+    /// let a = egraph.add(SymbolLang::leaf("a"));
+    /// let b = egraph.add(SymbolLang::leaf("b"));
+    /// egraph.union(a, b);
+    /// egraph.rebuild();
+    ///
+    /// egraph.enable_explanations();
+    /// let c = egraph.add(SymbolLang::leaf("c"));
+    /// egraph.union_trusted(b, c, "b=c");
+    /// egraph.rebuild();
+    ///
+    /// let (b, c) = ("b".parse().unwrap(), "c".parse().unwrap());
+    /// let explanation = egraph.explain_equivalence(&b, &c).get_flat_string();
+    /// assert!(explanation.contains("b=c"));
+    /// assert!(!explanation.contains("<unrecorded>"));
+    ///
+    /// let a = "a".parse().unwrap();
+    /// let explanation = egraph.explain_equivalence(&a, &c).get_flat_string();
+    /// assert!(explanation.contains("<unrecorded>"));
+    ///
+    /// egraph.disable_explanations();
+    /// assert!(!egraph.are_explanations_enabled());
+    /// ```
+    pub fn enable_explanations(&mut self) {
+        if self.explain.is_some() {
+            return;
+        }
+        let unrecorded = Justification::Rule(Symbol::from("<unrecorded>"));
+        let mut explain = Explain::new();
+        for (i, node) in self.nodes.iter().enumerate() {
+            explain.add(node.clone(), Id::from(i));
+        }
+        for i in 0..self.nodes.len() {
+            let id = Id::from(i);
+            let root = self.unionfind.find(id);
+            if root != id {
+                explain.union(id, root, unrecorded.clone());
+            }
+        }
+        self.explain = Some(explain);
+    }
+
+    /// Stop recording explanations and free everything recorded so far.
+    ///
+    /// This is the in-place version of
+    /// [`with_explanations_disabled`](EGraph::with_explanations_disabled).
+    pub fn disable_explanations(&mut self) {
+        self.explain = None;
+    }
+
     /// Check if explanations are enabled.
     pub fn are_explanations_enabled(&self) -> bool {
         self.explain.is_some()
@@ -558,6 +1140,60 @@ impl<L: Language, N: Analysis<L>> EGraph<L, N> {
         self.unionfind.find(id)
     }
 
+    /// Returns statistics about the union-find behind [`find`](EGraph::find),
+    /// e.g. to see whether finds are a bottleneck.
+    ///
+    /// Counting finds requires the `union-find-stats` feature, see
+    /// [`UnionFindStats`].
+    ///
+    /// ```
+    /// use egg::{*, SymbolLang as S};
+    /// let mut egraph = EGraph::<S, ()>::default();
+    /// let x = egraph.add(S::leaf("x"));
+    /// let y = egraph.add(S::leaf("y"));
+    /// egraph.add(S::leaf("z"));
+    /// egraph.union(x, y);
+    /// egraph.rebuild();
+    ///
+    /// let stats = egraph.union_find_stats();
+    /// assert_eq!((stats.ids, stats.sets, stats.max_height), (3, 2, 1));
+    /// ```
+    pub fn union_find_stats(&self) -> UnionFindStats {
+        self.unionfind.stats()
+    }
+
+    /// Resets the find counters of
+    /// [`union_find_stats`](EGraph::union_find_stats).
+    pub fn reset_union_find_stats(&mut self) {
+        self.unionfind.reset_stats()
+    }
+
+    /// Returns a [`Root`] handle to the e-class of `id`, which can be held
+    /// across unions and rebuilds and turned back into a canonical [`Id`]
+    /// with [`resolve`](EGraph::resolve()).
+    ///
+    /// # Example
+    /// ```
+    /// use egg::{*, SymbolLang as S};
+    /// let mut egraph = EGraph::<S, ()>::default();
+    /// let x = egraph.add(S::leaf("x"));
+    /// let y = egraph.add(S::leaf("y"));
+    /// let root = egraph.root(x);
+    ///
+    /// egraph.union(y, x);
+    /// egraph.rebuild();
+    /// assert_eq!(egraph.resolve(root), egraph.find(y));
+    /// assert_eq!(egraph.resolve(root), egraph.resolve(egraph.root(y)));
+    /// ```
+    pub fn root(&self, id: Id) -> Root {
+        Root(self.find(id))
+    }
+
+    /// Returns the current canonical [`Id`] of the e-class `root` refers to.
+    pub fn resolve(&self, root: Root) -> Id {
+        self.find(root.0)
+    }
+
     /// This is private, but internals should use this whenever
     /// possible because it does path compression.
     fn find_mut(&mut self, id: Id) -> Id {
@@ -570,6 +1206,7 @@ impl<L: Language, N: Analysis<L>> EGraph<L, N> {
             egraph: self,
             config: vec![],
             use_anchors: true,
+            cluster_by: None,
         }
     }
 }
@@ -634,6 +1271,7 @@ where
                 .map(|x| self.map_node(x))
                 .collect(),
             analysis_pending: src_egraph.analysis_pending,
+            dirty: src_egraph.dirty,
             classes: src_egraph
                 .classes
                 .into_iter()
@@ -644,6 +1282,16 @@ where
                 .into_iter()
                 .map(|(k, v)| (self.map_discriminant(k), v))
                 .collect(),
+            max_nodes_per_class: src_egraph.max_nodes_per_class,
+            canonical_min_id: src_egraph.canonical_min_id,
+            refused_unions: src_egraph.refused_unions,
+            frozen: src_egraph.frozen,
+            union_log: src_egraph.union_log,
+            meta: src_egraph.meta,
+            colors: src_egraph.colors,
+            epoch: src_egraph.epoch,
+            change_log: src_egraph.change_log,
+            change_history: src_egraph.change_history,
             clean: src_egraph.clean,
         }
     }
@@ -841,13 +1489,137 @@ impl<L: Language, N: Analysis<L>> EGraph<L, N> {
         *new_ids.last().unwrap()
     }
 
+    /// Adds every node of `expr` as a fresh e-class, without
+    /// hashconsing it against the e-nodes already in the e-graph.
+    ///
+    /// This is meant for debugging and teaching: it exposes the two
+    /// phases of an e-graph that [`add`](EGraph::add) hides, since the
+    /// congruent copies this creates are only merged by the next
+    /// [`rebuild`](EGraph::rebuild).
+    /// **Until then the e-graph is inconsistent**: it holds duplicate
+    /// e-nodes, so lookups and searches may miss or double count them.
+    ///
+    /// To watch the rebuild, compare [`find`](EGraph::find) before and
+    /// after it, and look at the number of congruence unions it returns.
+    /// These unions aren't made by a rule, so [`Runner::on_union`] never
+    /// sees them.
+    ///
+    /// This panics if explanations are enabled.
+    ///
+    /// # Example
+    /// ```
+    /// use egg::{*, SymbolLang as S};
+    /// let mut egraph = EGraph::<S, ()>::default();
+    /// let fx = egraph.add_expr(&"(f x)".parse().unwrap());
+    /// assert_eq!(egraph.number_of_classes(), 2);
+    ///
+    /// let fx2 = egraph.add_expr_no_rebuild(&"(f x)".parse().unwrap());
+    /// assert_ne!(egraph.find(fx), egraph.find(fx2));
+    /// assert_eq!(egraph.number_of_classes(), 4);
+    ///
+    /// // both copies of `x`, then both copies of `(f x)`
+    /// assert_eq!(egraph.rebuild(), 2);
+    /// assert_eq!(egraph.find(fx), egraph.find(fx2));
+    /// assert_eq!(egraph.number_of_classes(), 2);
+    /// ```
+    pub fn add_expr_no_rebuild(&mut self, expr: &RecExpr<L>) -> Id {
+        assert!(
+            self.explain.is_none(),
+            "add_expr_no_rebuild doesn't support explanations"
+        );
+        let mut new_ids: Vec<Id> = Vec::with_capacity(expr.len());
+        for node in expr {
+            let enode = node.clone().map_children(|i| new_ids[usize::from(i)]);
+            let id = self.make_eclass(enode.clone(), enode.clone());
+            // keep the old e-node in the memo, rebuilding will find the new
+            // one as pending and merge it by congruence
+            self.memo.entry(enode).or_insert(id);
+            N::modify(self, id);
+            self.clean = false;
+            new_ids.push(id);
+        }
+        *new_ids.last().unwrap()
+    }
+
     /// Adds a [`Pattern`] and a substitution to the [`EGraph`], returning
     /// the eclass of the instantiated pattern.
+    ///
+    /// Each [`ENodeOrVar::Var`] in `pat` is replaced by the [`Id`] bound to it
+    /// in `subst`, and every concrete e-node is added, just like when a
+    /// [`Pattern`] is used as an [`Applier`].
+    /// This panics if `subst` doesn't bind a variable of `pat`.
+    ///
+    /// # Example
+    /// ```
+    /// # use egg::*;
+    /// let mut egraph = EGraph::<SymbolLang, ()>::default();
+    /// let x = egraph.add(SymbolLang::leaf("x"));
+    /// let y = egraph.add(SymbolLang::leaf("y"));
+    ///
+    /// let pat: PatternAst<SymbolLang> = "(+ ?a (* ?a ?b))".parse().unwrap();
+    /// let mut subst = Subst::default();
+    /// subst.insert("?a".parse().unwrap(), x);
+    /// subst.insert("?b".parse().unwrap(), y);
+    ///
+    /// let id = egraph.add_instantiation(&pat, &subst);
+    /// egraph.rebuild();
+    /// assert_eq!(egraph.lookup_expr(&"(+ x (* x y))".parse().unwrap()), Some(id));
+    /// assert_eq!(egraph.number_of_classes(), 4);
+    /// ```
     pub fn add_instantiation(&mut self, pat: &PatternAst<L>, subst: &Subst) -> Id {
         let id = self.add_instantiation_noncanonical(pat, subst);
         self.find(id)
     }
 
+    /// Adds a [`PartialExpr`] to the [`EGraph`], giving each hole a fresh
+    /// e-class.
+    ///
+    /// Since every e-class needs an e-node, the fresh e-class of a hole
+    /// holds the placeholder e-node `hole(var)`, typically a new leaf.
+    /// Returns the e-class of the whole expression, and a [`Subst`] binding
+    /// each hole to its e-class, so the holes can be
+    /// [`fill`](PatternAst::fill)ed or [`union`](EGraph::union)ed later.
+    /// This panics if `expr` has operator variables, as there are no fresh
+    /// operators.
+    ///
+    /// # Example
+    /// ```
+    /// use egg::{*, SymbolLang as S};
+    /// let mut egraph = EGraph::<S, ()>::default();
+    /// let sketch: PartialExpr<S> = "(+ ?a (* ?a ?b))".parse().unwrap();
+    /// let (root, holes) = egraph.add_partial_expr(&sketch, |v| S::leaf(format!("hole{}", v)));
+    ///
+    /// let x = egraph.add(S::leaf("x"));
+    /// egraph.union(holes["?a".parse().unwrap()], x);
+    /// egraph.rebuild();
+    /// let term = "(+ x (* x hole?b))".parse().unwrap();
+    /// assert_eq!(egraph.lookup_expr(&term), Some(egraph.find(root)));
+    /// ```
+    pub fn add_partial_expr(
+        &mut self,
+        expr: &PartialExpr<L>,
+        mut hole: impl FnMut(Var) -> L,
+    ) -> (Id, Subst) {
+        let mut subst = Subst::default();
+        for node in expr {
+            match node {
+                ENodeOrVar::Var(var) => {
+                    if subst.get(*var).is_none() {
+                        let id = self.add(hole(*var));
+                        subst.insert(*var, id);
+                    }
+                }
+                ENodeOrVar::OpVar(var, _) => {
+                    panic!("Can't add operator var '{}' as a hole", var)
+                }
+                ENodeOrVar::Wildcard => panic!("Can't add the wildcard `_` as a hole"),
+                ENodeOrVar::ENode(_) => {}
+            }
+        }
+        let id = self.add_instantiation(expr, &subst);
+        (id, subst)
+    }
+
     /// Similar to [`add_instantiation`](EGraph::add_instantiation) but the `Id` returned may not be
     /// canonical
     ///
@@ -864,8 +1636,22 @@ impl<L: Language, N: Analysis<L>> EGraph<L, N> {
                     new_ids.push(id);
                     new_node_q.push(false);
                 }
-                ENodeOrVar::ENode(node) => {
-                    let new_node = node.clone().map_children(|i| new_ids[usize::from(i)]);
+                ENodeOrVar::Wildcard => panic!(
+                    "Can't instantiate the wildcard `_`, \
+                     are explanations enabled with a wildcard in a searcher?"
+                ),
+                ENodeOrVar::ENode(_) | ENodeOrVar::OpVar(..) => {
+                    let new_node = match node {
+                        ENodeOrVar::OpVar(var, children) => {
+                            let children: Vec<Id> =
+                                children.iter().map(|i| new_ids[usize::from(*i)]).collect();
+                            pattern::op_var_node(self, subst, *var, &children)
+                        }
+                        ENodeOrVar::ENode(node) => {
+                            node.clone().map_children(|i| new_ids[usize::from(i)])
+                        }
+                        ENodeOrVar::Var(_) | ENodeOrVar::Wildcard => unreachable!(),
+                    };
                     let size_before = self.unionfind.size();
                     let next_id = self.add_uncanonical(new_node);
                     if self.unionfind.size() > size_before {
@@ -881,10 +1667,11 @@ impl<L: Language, N: Analysis<L>> EGraph<L, N> {
         *new_ids.last().unwrap()
     }
 
-    /// Lookup the eclass of the given enode.
+    /// Checks whether two e-nodes are congruent under the current
+    /// union-find, i.e. they have the same operator and their children are
+    /// pairwise in the same e-class.
     ///
-    /// You can pass in either an owned enode or a `&mut` enode,
-    /// in which case the enode's children will be canonicalized.
+    /// Neither e-node has to be in the e-graph, and nothing is added.
     ///
     /// # Example
     /// ```
@@ -892,15 +1679,43 @@ impl<L: Language, N: Analysis<L>> EGraph<L, N> {
     /// let mut egraph: EGraph<SymbolLang, ()> = Default::default();
     /// let a = egraph.add(SymbolLang::leaf("a"));
     /// let b = egraph.add(SymbolLang::leaf("b"));
+    /// let f_a = SymbolLang::new("f", vec![a]);
+    /// let f_b = SymbolLang::new("f", vec![b]);
+    /// assert!(!egraph.congruent(&f_a, &f_b));
     ///
-    /// // lookup will find this node if its in the egraph
-    /// let mut node_f_ab = SymbolLang::new("f", vec![a, b]);
-    /// assert_eq!(egraph.lookup(node_f_ab.clone()), None);
-    /// let id = egraph.add(node_f_ab.clone());
-    /// assert_eq!(egraph.lookup(node_f_ab.clone()), Some(id));
-    ///
-    /// // if the query node isn't canonical, and its passed in by &mut instead of owned,
-    /// // its children will be canonicalized
+    /// egraph.union(a, b);
+    /// assert!(egraph.congruent(&f_a, &f_b));
+    /// assert!(!egraph.congruent(&f_a, &SymbolLang::new("g", vec![b])));
+    /// ```
+    pub fn congruent(&self, a: &L, b: &L) -> bool {
+        a.matches(b)
+            && a.len() == b.len()
+            && a.children()
+                .iter()
+                .zip(b.children())
+                .all(|(&x, &y)| self.find(x) == self.find(y))
+    }
+
+    /// Lookup the eclass of the given enode.
+    ///
+    /// You can pass in either an owned enode or a `&mut` enode,
+    /// in which case the enode's children will be canonicalized.
+    ///
+    /// # Example
+    /// ```
+    /// # use egg::*;
+    /// let mut egraph: EGraph<SymbolLang, ()> = Default::default();
+    /// let a = egraph.add(SymbolLang::leaf("a"));
+    /// let b = egraph.add(SymbolLang::leaf("b"));
+    ///
+    /// // lookup will find this node if its in the egraph
+    /// let mut node_f_ab = SymbolLang::new("f", vec![a, b]);
+    /// assert_eq!(egraph.lookup(node_f_ab.clone()), None);
+    /// let id = egraph.add(node_f_ab.clone());
+    /// assert_eq!(egraph.lookup(node_f_ab.clone()), Some(id));
+    ///
+    /// // if the query node isn't canonical, and its passed in by &mut instead of owned,
+    /// // its children will be canonicalized
     /// egraph.union(a, b);
     /// egraph.rebuild();
     /// assert_eq!(egraph.lookup(&mut node_f_ab), Some(id));
@@ -930,6 +1745,39 @@ impl<L: Language, N: Analysis<L>> EGraph<L, N> {
             .and_then(|ids| ids.last().copied())
     }
 
+    /// Returns the e-class that represents the whole of `expr`, if any.
+    ///
+    /// This is the read-only counterpart to [`add_expr`](EGraph::add_expr),
+    /// and the same as [`lookup_expr`](EGraph::lookup_expr).
+    ///
+    /// # Example
+    /// ```
+    /// use egg::{*, SymbolLang as S};
+    /// let mut egraph = EGraph::<S, ()>::default();
+    /// let expr: RecExpr<S> = "(+ x (f y))".parse().unwrap();
+    /// assert_eq!(egraph.find_matches_of_expr(&expr), None);
+    /// let id = egraph.add_expr(&expr);
+    /// assert_eq!(egraph.find_matches_of_expr(&expr), Some(id));
+    ///
+    /// let y = egraph.lookup(S::leaf("y")).unwrap();
+    /// assert!(egraph.contains_node(&S::new("f", vec![y])).is_some());
+    /// assert!(egraph.contains_node(&S::new("g", vec![y])).is_none());
+    /// ```
+    pub fn find_matches_of_expr(&self, expr: &RecExpr<L>) -> Option<Id> {
+        self.lookup_expr(expr)
+    }
+
+    /// Returns the e-class that contains `enode` (after canonicalizing its
+    /// children), if any.
+    ///
+    /// This is the read-only counterpart to [`add`](EGraph::add),
+    /// and the same as [`lookup`](EGraph::lookup) without taking ownership.
+    /// See [`find_matches_of_expr`](EGraph::find_matches_of_expr) for an
+    /// example.
+    pub fn contains_node(&self, enode: &L) -> Option<Id> {
+        self.lookup(enode.clone())
+    }
+
     /// Lookup the eclasses of all the nodes in the given [`RecExpr`].
     pub fn lookup_expr_ids(&self, expr: &RecExpr<L>) -> Option<Vec<Id>> {
         let mut new_ids = Vec::with_capacity(expr.len());
@@ -958,6 +1806,52 @@ impl<L: Language, N: Analysis<L>> EGraph<L, N> {
         self.find(id)
     }
 
+    /// Like [`add`](EGraph::add), but attaches `meta` to every e-node this
+    /// creates, e.g. to record where a term came from.
+    ///
+    /// If `enode` is already in the e-graph, nothing is attached, so the
+    /// metadata of an e-node is always that of whoever added it first.
+    /// Use [`meta`](EGraph::meta) to read it back.
+    ///
+    /// ```
+    /// # use egg::*;
+    /// let mut egraph = EGraph::<SymbolLang, ()>::default();
+    /// let x = egraph.add_with_meta(SymbolLang::leaf("x"), "input.txt:3");
+    /// let y = egraph.add(SymbolLang::leaf("y"));
+    /// assert_eq!(egraph.add_with_meta(SymbolLang::leaf("x"), "elsewhere"), x);
+    ///
+    /// assert_eq!(egraph.meta::<&str>(x), Some(&"input.txt:3"));
+    /// assert_eq!(egraph.meta::<&str>(y), None);
+    /// // asking for the wrong type gives nothing
+    /// assert_eq!(egraph.meta::<String>(x), None);
+    /// ```
+    pub fn add_with_meta<M: Any + Send + Sync>(&mut self, enode: L, meta: M) -> Id {
+        let before = self.nodes.len();
+        let id = self.add_uncanonical(enode);
+        self.tag_nodes_since(before, Arc::new(meta));
+        self.find(id)
+    }
+
+    /// Returns the metadata attached to the e-node created with `id`, if it
+    /// has metadata of type `M`.
+    ///
+    /// The metadata belongs to a single e-node, not to its e-class, so `id`
+    /// must be the `Id` that e-node was created with (the one returned when
+    /// it was first added), not a canonical `Id` found later.
+    /// A [`Runner`] built [`with_provenance`](Runner::with_provenance)
+    /// attaches the [`Symbol`] name of the rule that created each e-node.
+    pub fn meta<M: Any>(&self, id: Id) -> Option<&M> {
+        self.meta.get(&id)?.downcast_ref()
+    }
+
+    /// Attaches `meta` to every e-node created since there were `before`
+    /// of them.
+    pub(crate) fn tag_nodes_since(&mut self, before: usize, meta: Arc<dyn Any + Send + Sync>) {
+        for i in before..self.nodes.len() {
+            self.meta.insert(Id::from(i), meta.clone());
+        }
+    }
+
     /// Similar to [`add`](EGraph::add) but the `Id` returned may not be canonical
     ///
     /// When explanations are enabled calling [`id_to_expr`](EGraph::id_to_expr) on this `Id` will
@@ -1007,6 +1901,7 @@ impl<L: Language, N: Analysis<L>> EGraph<L, N> {
                     *existing_explain
                 } else {
                     let new_id = self.unionfind.make_set();
+                    self.analysis.on_add(new_id, &original);
                     explain.add(original.clone(), new_id);
                     debug_assert_eq!(Id::from(self.nodes.len()), new_id);
                     self.nodes.push(original);
@@ -1030,9 +1925,118 @@ impl<L: Language, N: Analysis<L>> EGraph<L, N> {
         }
     }
 
+    /// Like [`add`](EGraph::add), but also adds the right-leaning regrouping
+    /// of left-nested applications of associative operators, see
+    /// [`Language::is_associative`], and unions it with `enode`.
+    ///
+    /// Concretely, when adding `(op x c)` where the e-class `x` already
+    /// contains some `(op a b)`, this also adds `(op a (op b c))`
+    /// (regrouping its children the same way), unless `(op x c)` is already
+    /// in the e-graph. So the different groupings of a chain end up in the
+    /// same e-class without running an associativity rule, while `enode`
+    /// itself is added as given, so [`lookup`](EGraph::lookup) still finds
+    /// it.
+    /// The unions are justified by `"associativity"` in explanations.
+    /// Like [`union`](EGraph::union), this leaves the e-graph to be
+    /// [`rebuild`](EGraph::rebuild)t.
+    ///
+    /// Regrouping is best-effort: which `(op a b)` of `x` is used depends
+    /// on what `x` contains at that time, and nothing is regrouped on later
+    /// unions.
+    ///
+    /// # Example
+    /// ```
+    /// # use egg::*;
+    /// #[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+    /// struct Assoc(SymbolLang);
+    ///
+    /// impl Language for Assoc {
+    ///     type Discriminant = <SymbolLang as Language>::Discriminant;
+    ///     fn discriminant(&self) -> Self::Discriminant {
+    ///         self.0.discriminant()
+    ///     }
+    ///     fn matches(&self, other: &Self) -> bool {
+    ///         self.0.matches(&other.0)
+    ///     }
+    ///     fn is_associative(&self) -> bool {
+    ///         self.0.op == Symbol::from("+")
+    ///     }
+    ///     fn children(&self) -> &[Id] {
+    ///         self.0.children()
+    ///     }
+    ///     fn children_mut(&mut self) -> &mut [Id] {
+    ///         self.0.children_mut()
+    ///     }
+    /// }
+    ///
+    /// let mut egraph = EGraph::<Assoc, ()>::default();
+    /// let node = |op: &str, children| Assoc(SymbolLang::new(op, children));
+    /// let (a, b, c) = (
+    ///     egraph.add(node("a", vec![])),
+    ///     egraph.add(node("b", vec![])),
+    ///     egraph.add(node("c", vec![])),
+    /// );
+    /// let ab = egraph.add_reassociated(node("+", vec![a, b]));
+    /// let ab_c = egraph.add_reassociated(node("+", vec![ab, c]));
+    /// egraph.rebuild();
+    ///
+    /// let bc = egraph.lookup(node("+", vec![b, c])).unwrap();
+    /// let a_bc = egraph.lookup(node("+", vec![a, bc])).unwrap();
+    /// assert_eq!(egraph.find(ab_c), a_bc);
+    /// assert_eq!(egraph.lookup(node("+", vec![ab, c])), Some(a_bc));
+    /// ```
+    pub fn add_reassociated(&mut self, enode: L) -> Id {
+        self.add_reassociated_in(enode, &mut vec![])
+    }
+
+    /// `spine` holds the e-classes already unfolded on the left,
+    /// so that cyclic e-classes don't send this into a loop.
+    fn add_reassociated_in(&mut self, enode: L, spine: &mut Vec<Id>) -> Id {
+        let regrouped = self.reassociate(&enode, spine);
+        let mut right = enode.clone();
+        let id = self.add(enode);
+        if let Some((a, inner)) = regrouped {
+            let inner = self.add_reassociated_in(inner, spine);
+            right.children_mut().copy_from_slice(&[a, inner]);
+            let right = self.add_reassociated_in(right, spine);
+            self.union_trusted(id, right, "associativity");
+        }
+        self.find(id)
+    }
+
+    /// If `enode` is `(op x c)` with some `(op a b)` in `x`, returns `a`
+    /// and `(op b c)`.
+    fn reassociate(&self, enode: &L, spine: &mut Vec<Id>) -> Option<(Id, L)> {
+        if enode.len() != 2 || !enode.is_associative() {
+            return None;
+        }
+        if self.lookup(enode.clone()).is_some() {
+            return None;
+        }
+        let x = self.find(enode.children()[0]);
+        if spine.contains(&x) {
+            return None;
+        }
+        let nested = self[x].iter().find(|n| n.len() == 2 && n.matches(enode))?;
+        let (a, b) = (nested.children()[0], nested.children()[1]);
+        spine.push(x);
+        let mut inner = enode.clone();
+        inner.children_mut()[0] = b;
+        Some((a, inner))
+    }
+
     /// This function makes a new eclass in the egraph (but doesn't touch explanations)
     fn make_new_eclass(&mut self, enode: L, original: L) -> Id {
+        let id = self.make_eclass(enode.clone(), original);
+        assert!(self.memo.insert(enode, id).is_none());
+        id
+    }
+
+    /// Like [`make_new_eclass`](EGraph::make_new_eclass), but leaves the
+    /// memo alone.
+    fn make_eclass(&mut self, enode: L, original: L) -> Id {
         let id = self.unionfind.make_set();
+        self.analysis.on_add(id, &original);
         log::trace!("  ...adding to {}", id);
         let class = EClass {
             id,
@@ -1051,10 +2055,9 @@ impl<L: Language, N: Analysis<L>> EGraph<L, N> {
 
         // TODO is this needed?
         self.pending.push(id);
+        self.dirty.push(id);
 
         self.classes.insert(id, class);
-        assert!(self.memo.insert(enode, id).is_none());
-
         id
     }
 
@@ -1084,6 +2087,252 @@ impl<L: Language, N: Analysis<L>> EGraph<L, N> {
         equiv_eclasses
     }
 
+    /// Adds both [`RecExpr`]s to the e-graph and checks whether they end up
+    /// in the same e-class.
+    ///
+    /// This grows the e-graph if either term is new; use
+    /// [`equiv_existing`](EGraph::equiv_existing) to query without modifying
+    /// it. As with [`lookup`](EGraph::lookup), the answer is only reliable
+    /// after a [`rebuild`](EGraph::rebuild).
+    ///
+    /// # Example
+    /// ```
+    /// use egg::{*, SymbolLang as S};
+    /// let mut egraph = EGraph::<S, ()>::default();
+    /// let a: RecExpr<S> = "(+ x y)".parse().unwrap();
+    /// let b: RecExpr<S> = "(+ y x)".parse().unwrap();
+    ///
+    /// assert!(!egraph.equiv(&a, &b));
+    /// let (a_id, b_id) = (egraph.add_expr(&a), egraph.add_expr(&b));
+    /// egraph.union(a_id, b_id);
+    /// egraph.rebuild();
+    /// assert!(egraph.equiv(&a, &b));
+    ///
+    /// // the unseen term is added
+    /// let c: RecExpr<S> = "(* x y)".parse().unwrap();
+    /// assert_eq!(egraph.equiv_existing(&a, &c), None);
+    /// assert!(!egraph.equiv(&a, &c));
+    /// assert_eq!(egraph.equiv_existing(&a, &c), Some(false));
+    /// ```
+    pub fn equiv(&mut self, a: &RecExpr<L>, b: &RecExpr<L>) -> bool {
+        let a = self.add_expr(a);
+        let b = self.add_expr(b);
+        self.find(a) == self.find(b)
+    }
+
+    /// Checks whether two [`RecExpr`]s are in the same e-class without
+    /// modifying the e-graph.
+    ///
+    /// Returns `None` if either term is not already represented.
+    /// See [`equiv`](EGraph::equiv) for an example.
+    pub fn equiv_existing(&self, a: &RecExpr<L>, b: &RecExpr<L>) -> Option<bool> {
+        let a = self.lookup_expr(a)?;
+        let b = self.lookup_expr(b)?;
+        Some(self.find(a) == self.find(b))
+    }
+
+    /// Returns the e-classes that are reachable from themselves through the
+    /// children of their e-nodes, sorted by [`Id`].
+    ///
+    /// Cycles are perfectly normal in an e-graph (think `x = x * 1`), but
+    /// unexpected ones can point to a bug in a custom [`Applier`].
+    /// The e-graph should be [`rebuild`](EGraph::rebuild)t before calling this.
+    ///
+    /// # Example
+    /// ```
+    /// # use egg::*;
+    /// let mut egraph = EGraph::<SymbolLang, ()>::default();
+    /// let x = egraph.add_expr(&"x".parse().unwrap());
+    /// let fx = egraph.add_expr(&"(f x)".parse().unwrap());
+    /// egraph.rebuild();
+    /// assert!(egraph.find_cycles().is_empty());
+    ///
+    /// egraph.union(x, fx);
+    /// egraph.rebuild();
+    /// assert_eq!(egraph.find_cycles(), vec![egraph.find(x)]);
+    /// ```
+    pub fn find_cycles(&self) -> Vec<Id> {
+        let children: HashMap<Id, Vec<Id>> = self
+            .classes()
+            .map(|class| {
+                let mut ids: Vec<Id> = class
+                    .iter()
+                    .flat_map(|n| n.children())
+                    .map(|&id| self.find(id))
+                    .collect();
+                ids.sort_unstable();
+                ids.dedup();
+                (class.id, ids)
+            })
+            .collect();
+
+        // an iterative version of Tarjan's strongly connected components
+        let n = self.nodes.len();
+        let mut index = vec![usize::MAX; n];
+        let mut low = vec![usize::MAX; n];
+        let mut on_stack = vec![false; n];
+        let mut stack = vec![];
+        let mut next_index = 0;
+        let mut cycles = vec![];
+
+        for class in self.classes() {
+            if index[usize::from(class.id)] != usize::MAX {
+                continue;
+            }
+
+            let mut work = vec![(class.id, 0)];
+            while let Some(&(v, i)) = work.last() {
+                let vi = usize::from(v);
+                if i == 0 {
+                    index[vi] = next_index;
+                    low[vi] = next_index;
+                    next_index += 1;
+                    stack.push(v);
+                    on_stack[vi] = true;
+                }
+
+                let kids = &children[&v];
+                if i < kids.len() {
+                    work.last_mut().unwrap().1 += 1;
+                    let w = usize::from(kids[i]);
+                    if index[w] == usize::MAX {
+                        work.push((kids[i], 0));
+                    } else if on_stack[w] {
+                        low[vi] = low[vi].min(index[w]);
+                    }
+                    continue;
+                }
+
+                work.pop();
+                if let Some(&(u, _)) = work.last() {
+                    let ui = usize::from(u);
+                    low[ui] = low[ui].min(low[vi]);
+                }
+
+                if low[vi] == index[vi] {
+                    let mut component = vec![];
+                    loop {
+                        let w = stack.pop().unwrap();
+                        on_stack[usize::from(w)] = false;
+                        component.push(w);
+                        if w == v {
+                            break;
+                        }
+                    }
+                    if component.len() > 1 || kids.binary_search(&v).is_ok() {
+                        cycles.extend(component);
+                    }
+                }
+            }
+        }
+
+        cycles.sort_unstable();
+        cycles
+    }
+
+    /// Enumerates every distinct term of at most `max_size` e-nodes that is
+    /// represented by the given e-class, smallest terms first.
+    ///
+    /// Terms are built bottom-up: all terms of size 1 for every e-class
+    /// reachable from `eclass`, then all terms of size 2 from those, and so
+    /// on. Each size is only computed once the smaller terms have been
+    /// consumed, so taking just a few terms is cheap.
+    ///
+    /// The number of terms grows exponentially with the size (and an
+    /// e-class with a cycle represents infinitely many terms), which is why
+    /// `max_size` is required: keep it small, and expect all the terms of
+    /// a given size to be held in memory at once.
+    /// As with searching, the e-graph should be rebuilt first.
+    ///
+    /// # Example
+    /// ```
+    /// use egg::{*, SymbolLang as S};
+    /// let mut egraph = EGraph::<S, ()>::default();
+    /// let x = egraph.add_expr(&"x".parse().unwrap());
+    /// let x1 = egraph.add_expr(&"(* x 1)".parse().unwrap());
+    /// egraph.union(x, x1);
+    /// egraph.rebuild();
+    ///
+    /// // the cycle represents infinitely many terms
+    /// let terms: Vec<String> = egraph.enumerate_terms(x, 5).map(|t| t.to_string()).collect();
+    /// assert_eq!(terms, ["x", "(* x 1)", "(* (* x 1) 1)"]);
+    /// ```
+    pub fn enumerate_terms(
+        &self,
+        eclass: Id,
+        max_size: usize,
+    ) -> impl Iterator<Item = RecExpr<L>> + '_ {
+        let root = self.find(eclass);
+        let mut classes = vec![root];
+        let mut reachable = HashSet::default();
+        reachable.insert(root);
+        let mut i = 0;
+        while i < classes.len() {
+            for node in &self[classes[i]].nodes {
+                for &child in node.children() {
+                    let child = self.find(child);
+                    if reachable.insert(child) {
+                        classes.push(child);
+                    }
+                }
+            }
+            i += 1;
+        }
+
+        // by_size[s] maps each e-class to its terms of size s
+        let mut by_size: Vec<HashMap<Id, Vec<RecExpr<L>>>> = vec![HashMap::default()];
+        (1..=max_size).flat_map(move |size| {
+            let mut level = HashMap::default();
+            for &id in &classes {
+                let mut terms = vec![];
+                let mut seen = HashSet::default();
+                for node in &self[id].nodes {
+                    let children = node.children();
+                    if children.is_empty() {
+                        if size == 1 && seen.insert(RecExpr::from(vec![node.clone()])) {
+                            terms.push(RecExpr::from(vec![node.clone()]));
+                        }
+                        continue;
+                    }
+                    for_each_composition(size - 1, children.len(), &mut |parts| {
+                        let mut options = Vec::with_capacity(parts.len());
+                        for (&child, &part) in children.iter().zip(parts) {
+                            match by_size[part].get(&self.find(child)) {
+                                Some(terms) => options.push(terms),
+                                None => return,
+                            }
+                        }
+                        for_each_product(&options, &mut |picked| {
+                            let mut expr = RecExpr::default();
+                            let mut child_roots = Vec::with_capacity(picked.len());
+                            for term in picked {
+                                let offset = expr.len();
+                                for n in term.iter() {
+                                    let n = n
+                                        .clone()
+                                        .map_children(|c| Id::from(usize::from(c) + offset));
+                                    expr.add(n);
+                                }
+                                child_roots.push(Id::from(expr.len() - 1));
+                            }
+                            let mut child_roots = child_roots.into_iter();
+                            expr.add(node.clone().map_children(|_| child_roots.next().unwrap()));
+                            if seen.insert(expr.clone()) {
+                                terms.push(expr);
+                            }
+                        });
+                    });
+                }
+                if !terms.is_empty() {
+                    level.insert(id, terms);
+                }
+            }
+            let out = level.get(&root).cloned().unwrap_or_default();
+            by_size.push(level);
+            out
+        })
+    }
+
     /// Given two patterns and a substitution, add the patterns
     /// and union them.
     ///
@@ -1139,7 +2388,66 @@ impl<L: Language, N: Analysis<L>> EGraph<L, N> {
         }
     }
 
+    /// Unions every pair of e-classes in `pairs`, then [`rebuild`](EGraph::rebuild)s once.
+    ///
+    /// This is the same as calling [`union`](EGraph::union) on each pair
+    /// followed by a single [`rebuild`](EGraph::rebuild).
+    /// Returns the number of pairs that merged two previously distinct
+    /// e-classes; merges found by congruence during the rebuild are not
+    /// counted.
+    ///
+    /// # Example
+    /// ```
+    /// use egg::{*, SymbolLang as S};
+    /// let mut egraph = EGraph::<S, ()>::default();
+    /// let x = egraph.add(S::leaf("x"));
+    /// let y = egraph.add(S::leaf("y"));
+    /// let z = egraph.add(S::leaf("z"));
+    /// let fx = egraph.add(S::new("f", vec![x]));
+    /// let fy = egraph.add(S::new("f", vec![y]));
+    ///
+    /// // the last pair is already merged by the first two
+    /// let mut manual = egraph.clone();
+    /// assert_eq!(egraph.union_all([(x, y), (y, z), (x, z)]), 2);
+    /// assert_eq!(egraph.find(fx), egraph.find(fy));
+    ///
+    /// manual.union(x, y);
+    /// manual.union(y, z);
+    /// manual.union(x, z);
+    /// manual.rebuild();
+    /// assert_eq!(egraph.number_of_classes(), manual.number_of_classes());
+    /// ```
+    #[track_caller]
+    pub fn union_all(&mut self, pairs: impl IntoIterator<Item = (Id, Id)>) -> usize {
+        let mut merged = 0;
+        for (id1, id2) in pairs {
+            if self.union(id1, id2) {
+                merged += 1;
+            }
+        }
+        self.rebuild();
+        merged
+    }
+
     fn perform_union(&mut self, enode_id1: Id, enode_id2: Id, rule: Option<Justification>) -> bool {
+        if let Some(max) = self.max_nodes_per_class {
+            if !matches!(rule, Some(Justification::Congruence)) {
+                let (id1, id2) = (self.find(enode_id1), self.find(enode_id2));
+                if id1 != id2 && self[id1].len() + self[id2].len() > max {
+                    self.refused_unions += 1;
+                    return false;
+                }
+            }
+        }
+
+        if !self.frozen.is_empty() && !matches!(rule, Some(Justification::Congruence)) {
+            let (id1, id2) = (self.find(enode_id1), self.find(enode_id2));
+            if id1 != id2 && (self.frozen.contains(&id1) || self.frozen.contains(&id2)) {
+                self.refused_unions += 1;
+                return false;
+            }
+        }
+
         N::pre_union(self, enode_id1, enode_id2, &rule);
 
         self.clean = false;
@@ -1153,10 +2461,18 @@ impl<L: Language, N: Analysis<L>> EGraph<L, N> {
             }
             return false;
         }
-        // make sure class2 has fewer parents
-        let class1_parents = self.classes[&id1].parents.len();
-        let class2_parents = self.classes[&id2].parents.len();
-        if class1_parents < class2_parents {
+        // let the analysis pick the root, otherwise make sure class2 has fewer parents
+        // (or the larger id, with canonical_min_id)
+        let swap = match self
+            .analysis
+            .prefer(&self.classes[&id1].data, &self.classes[&id2].data)
+        {
+            Ordering::Less => false,
+            Ordering::Greater => true,
+            Ordering::Equal if self.canonical_min_id => id2 < id1,
+            Ordering::Equal => self.classes[&id1].parents.len() < self.classes[&id2].parents.len(),
+        };
+        if swap {
             std::mem::swap(&mut id1, &mut id2);
         }
 
@@ -1166,6 +2482,12 @@ impl<L: Language, N: Analysis<L>> EGraph<L, N> {
 
         // make id1 the new root
         self.unionfind.union(id1, id2);
+        if self.frozen.swap_remove(&id2) {
+            self.frozen.insert(id1);
+        }
+        if let Some(log) = &mut self.union_log {
+            log.push((id1, id2));
+        }
 
         assert_ne!(id1, id2);
         let class2 = self.classes.remove(&id2).unwrap();
@@ -1173,6 +2495,9 @@ impl<L: Language, N: Analysis<L>> EGraph<L, N> {
         assert_eq!(id1, class1.id);
 
         self.pending.extend(class2.parents.iter().copied());
+        self.dirty.push(id1);
+        self.dirty.push(id2);
+        self.dirty.extend(class2.parents.iter().copied());
         let did_merge = self.analysis.merge(&mut class1.data, class2.data);
         if did_merge.0 {
             self.analysis_pending.extend(class1.parents.iter().copied());
@@ -1212,9 +2537,153 @@ impl<L: Language, N: Analysis<L>> EGraph<L, N> {
     pub fn dump(&self) -> impl Debug + '_ {
         EGraphDump(self)
     }
+
+    /// Returns a hash of the structure of the e-graph, for cheaply checking
+    /// in tests that two e-graphs are the same.
+    ///
+    /// The fingerprint only depends on which terms are in which e-classes,
+    /// not on the [`Id`]s, so e-graphs built by adding the same terms and
+    /// making the same unions in a different order have the same fingerprint.
+    /// It is computed by repeatedly refining a hash of each e-class from the
+    /// operators of its e-nodes and the hashes of their children, and
+    /// combining the sorted hashes of all e-classes.
+    /// The analysis data and explanations are not taken into account.
+    /// E-nodes are hashed through their [`Debug`] output, so the fingerprint
+    /// is stable across runs as long as that is.
+    ///
+    /// Like any hash, different e-graphs may collide, though that is unlikely.
+    ///
+    /// # Example
+    /// ```
+    /// # use egg::*;
+    /// let mut a = EGraph::<SymbolLang, ()>::default();
+    /// let x = a.add_expr(&"(f x)".parse().unwrap());
+    /// let y = a.add_expr(&"(g y)".parse().unwrap());
+    /// a.union(x, y);
+    /// a.rebuild();
+    ///
+    /// let mut b = EGraph::<SymbolLang, ()>::default();
+    /// let y = b.add_expr(&"(g y)".parse().unwrap());
+    /// let x = b.add_expr(&"(f x)".parse().unwrap());
+    /// assert_ne!(a.fingerprint(), b.fingerprint());
+    /// b.union(y, x);
+    /// b.rebuild();
+    /// assert_eq!(a.fingerprint(), b.fingerprint());
+    /// ```
+    pub fn fingerprint(&self) -> u64 {
+        fn hash_of(x: impl std::hash::Hash) -> u64 {
+            use std::hash::Hasher;
+            let mut hasher = rustc_hash::FxHasher::default();
+            x.hash(&mut hasher);
+            hasher.finish()
+        }
+
+        let classes: Vec<&EClass<L, N::Data>> = self.classes().collect();
+        let index: HashMap<Id, usize> = classes
+            .iter()
+            .enumerate()
+            .map(|(i, class)| (self.find(class.id), i))
+            .collect();
+        let ops: Vec<Vec<u64>> = classes
+            .iter()
+            .map(|class| {
+                let ops = class.iter().map(|node| {
+                    let op = node.clone().map_children(|_| Id::from(0));
+                    hash_of(format!("{:?}", op))
+                });
+                ops.collect()
+            })
+            .collect();
+
+        let mut colors = vec![0u64; classes.len()];
+        let mut n_colors = 1;
+        loop {
+            let refined: Vec<u64> = classes
+                .iter()
+                .zip(&ops)
+                .zip(&colors)
+                .map(|((class, ops), &color)| {
+                    let mut nodes: Vec<u64> = class
+                        .iter()
+                        .zip(ops)
+                        .map(|(node, &op)| {
+                            let children: Vec<u64> = node
+                                .children()
+                                .iter()
+                                .map(|&child| colors[index[&self.find(child)]])
+                                .collect();
+                            hash_of((op, children))
+                        })
+                        .collect();
+                    nodes.sort_unstable();
+                    nodes.dedup();
+                    hash_of((color, nodes))
+                })
+                .collect();
+            colors = refined;
+
+            let n_refined = colors.iter().collect::<HashSet<_>>().len();
+            if n_refined <= n_colors {
+                break;
+            }
+            n_colors = n_refined;
+        }
+
+        colors.sort_unstable();
+        hash_of(colors)
+    }
 }
 
 impl<L: Language + Display, N: Analysis<L>> EGraph<L, N> {
+    /// Returns an iterator over the eclasses that contain an enode whose
+    /// operator prints as `op`, like `"+"` for `(+ a b)`.
+    ///
+    /// Like [`classes_for_op`](EGraph::classes_for_op), this goes through
+    /// the operator index, so it only sees e-classes that are there after
+    /// the last [`rebuild`](EGraph::rebuild).
+    /// A discriminant whose e-nodes have an [`op_str`](Language::op_str) is
+    /// kept or skipped as a whole, assuming they all share that operator.
+    /// Otherwise (e.g. for constants, which print differently but share a
+    /// discriminant), its e-nodes are formatted and compared one by one.
+    ///
+    /// ```
+    /// use egg::*;
+    ///
+    /// let mut egraph = EGraph::<SymbolLang, ()>::default();
+    /// let a = egraph.add_expr(&"(+ x (* y 2))".parse().unwrap());
+    /// let b = egraph.add_expr(&"(+ x y)".parse().unwrap());
+    /// egraph.rebuild();
+    ///
+    /// let mut adds: Vec<Id> = egraph.classes_for_op_str("+").collect();
+    /// adds.sort();
+    /// assert_eq!(adds, vec![a, b]);
+    /// assert_eq!(egraph.classes_for_op_str("-").count(), 0);
+    /// ```
+    pub fn classes_for_op_str<'a>(&'a self, op: &'a str) -> impl Iterator<Item = Id> + 'a {
+        let mut seen = HashSet::default();
+        self.classes_by_op
+            .iter()
+            .filter_map(move |(discriminant, ids)| {
+                let first = ids.iter().next()?;
+                let node = self[*first]
+                    .iter()
+                    .find(|node| node.discriminant() == *discriminant)?;
+                match node.op_str() {
+                    Some(s) if s != op => None,
+                    fixed => Some((discriminant, ids, fixed.is_some())),
+                }
+            })
+            .flat_map(move |(discriminant, ids, fixed)| {
+                ids.iter().copied().filter(move |&id| {
+                    fixed
+                        || self[id].iter().any(|node| {
+                            node.discriminant() == *discriminant && node.to_string() == op
+                        })
+                })
+            })
+            .filter(move |&id| seen.insert(id))
+    }
+
     /// Panic if the given eclass doesn't contain the given patterns
     ///
     /// Useful for testing.
@@ -1252,45 +2721,244 @@ impl<L: Language, N: Analysis<L>> EGraph<L, N> {
         let uf = &mut self.unionfind;
 
         for class in self.classes.values_mut() {
-            let old_len = class.len();
-            class
-                .nodes
-                .iter_mut()
-                .for_each(|n| n.update_children(|id| uf.find_mut(id)));
-            class.nodes.sort_unstable();
-            class.nodes.dedup();
-
-            trimmed += old_len - class.nodes.len();
-
-            let mut add = |n: &L| {
-                classes_by_op
-                    .entry(n.discriminant())
-                    .or_default()
-                    .insert(class.id)
-            };
+            trimmed += Self::rebuild_class(class, uf, &mut classes_by_op);
+        }
 
-            // we can go through the ops in order to dedup them, becaue we
-            // just sorted them
-            let mut nodes = class.nodes.iter();
+        #[cfg(debug_assertions)]
+        for ids in classes_by_op.values_mut() {
+            let unique: HashSet<Id> = ids.iter().copied().collect();
+            assert_eq!(ids.len(), unique.len());
+        }
+
+        self.classes_by_op = classes_by_op;
+        self.dirty.clear();
+        trimmed
+    }
+
+    /// Like [`rebuild_classes`](EGraph::rebuild_classes), but only looks
+    /// at the e-classes touched since the last rebuild.
+    fn rebuild_dirty_classes(&mut self) -> usize {
+        let mut trimmed = 0;
+        let mut roots: HashSet<Id> = HashSet::default();
+        let mut stale = vec![];
+        for id in std::mem::take(&mut self.dirty) {
+            let root = self.unionfind.find_mut(id);
+            if root != id {
+                stale.push((id, root));
+            }
+            roots.insert(root);
+        }
+
+        let uf = &mut self.unionfind;
+        for root in roots {
+            let class = self.classes.get_mut(&root).unwrap();
+            trimmed += Self::rebuild_class(class, uf, &mut self.classes_by_op);
+        }
+
+        // classes that were merged away can no longer be found by op,
+        // and their ops are a subset of the ops of their new root
+        for (id, root) in stale {
+            let classes_by_op = &mut self.classes_by_op;
+            let mut nodes = self.classes[&root].nodes.iter();
+            let mut remove = |n: &L| {
+                if let Some(ids) = classes_by_op.get_mut(&n.discriminant()) {
+                    ids.swap_remove(&id);
+                }
+            };
             if let Some(mut prev) = nodes.next() {
-                add(prev);
+                remove(prev);
                 for n in nodes {
                     if !prev.matches(n) {
-                        add(n);
+                        remove(n);
                         prev = n;
                     }
                 }
             }
         }
 
-        #[cfg(debug_assertions)]
-        for ids in classes_by_op.values_mut() {
-            let unique: HashSet<Id> = ids.iter().copied().collect();
-            assert_eq!(ids.len(), unique.len());
+        trimmed
+    }
+
+    /// Canonicalizes, sorts and dedups the nodes of `class`, and records
+    /// `class` in `classes_by_op`. Returns the number of trimmed nodes.
+    fn rebuild_class(
+        class: &mut EClass<L, N::Data>,
+        uf: &mut UnionFind,
+        classes_by_op: &mut HashMap<L::Discriminant, HashSet<Id>>,
+    ) -> usize {
+        let old_len = class.len();
+        class
+            .nodes
+            .iter_mut()
+            .for_each(|n| n.update_children(|id| uf.find_mut(id)));
+        class.nodes.sort_unstable();
+        class.nodes.dedup();
+
+        let mut add = |n: &L| {
+            classes_by_op
+                .entry(n.discriminant())
+                .or_default()
+                .insert(class.id)
+        };
+
+        // we can go through the ops in order to dedup them, becaue we
+        // just sorted them
+        let mut nodes = class.nodes.iter();
+        if let Some(mut prev) = nodes.next() {
+            add(prev);
+            for n in nodes {
+                if !prev.matches(n) {
+                    add(n);
+                    prev = n;
+                }
+            }
         }
 
-        self.classes_by_op = classes_by_op;
-        trimmed
+        old_len - class.nodes.len()
+    }
+
+    /// Checks the invariants of a [rebuilt](EGraph::rebuild) e-graph,
+    /// returning the first violation found.
+    ///
+    /// This checks that every id stored in the e-graph exists, that every
+    /// e-class is stored under its canonical id and every union-find root
+    /// has an e-class (with its analysis data), that the e-nodes of the
+    /// e-classes are canonical and congruence-distinct, and that the
+    /// canonical keys of the hashcons are exactly those e-nodes, mapped to
+    /// their e-classes.
+    /// Non-canonical keys are stale leftovers of merged e-nodes, and are
+    /// allowed since lookups canonicalize e-nodes first.
+    /// It takes time linear in the size of the e-graph, and is meant for
+    /// tests and fuzzing of custom [`Analysis`]es and [`Applier`]s.
+    ///
+    /// ```
+    /// use egg::*;
+    /// let mut egraph = EGraph::<SymbolLang, ()>::default();
+    /// let fx = egraph.add_expr(&"(f x)".parse().unwrap());
+    /// let y = egraph.add(SymbolLang::leaf("y"));
+    /// egraph.rebuild();
+    /// assert_eq!(egraph.check_invariants(), Ok(()));
+    ///
+    /// // an e-node that sneaked in without going through the hashcons
+    /// let fy = SymbolLang::new("f", vec![y]);
+    /// egraph[fx].nodes.push(fy.clone());
+    /// assert_eq!(
+    ///     egraph.check_invariants(),
+    ///     Err(InvariantViolation::HashconsMismatch { node: fy, class: fx, hashcons: None })
+    /// );
+    /// ```
+    pub fn check_invariants(&self) -> Result<(), InvariantViolation<L>> {
+        self.check_ids()?;
+        self.check_congruence()
+    }
+
+    /// Checks the invariants of this e-graph with
+    /// [`check_ids`](EGraph::check_ids) and
+    /// [`check_congruence`](EGraph::check_congruence), rebuilding it in
+    /// between.
+    pub(crate) fn check_and_rebuild(&mut self) -> Result<(), InvariantViolation<L>> {
+        self.check_ids()?;
+        // without pending unions, e.g. after deserializing an e-graph that
+        // was rebuilt, the invariants already hold, so check them before
+        // rebuilding trips over a violation
+        if self.pending.is_empty() {
+            self.check_congruence()?;
+        }
+        self.rebuild();
+        self.check_congruence()
+    }
+
+    /// Checks that every id stored in this e-graph exists, so that
+    /// rebuilding it can't go out of bounds. Unlike
+    /// [`check_congruence`](EGraph::check_congruence), this holds for dirty
+    /// e-graphs too.
+    pub(crate) fn check_ids(&self) -> Result<(), InvariantViolation<L>> {
+        let n = self.unionfind.size();
+        if self.nodes.len() != n || !self.unionfind.is_well_formed() {
+            return Err(InvariantViolation::UnionFind {
+                ids: n,
+                nodes: self.nodes.len(),
+            });
+        }
+        let in_range = |node: &L| node.all(|c| usize::from(c) < n);
+        for (&id, class) in &self.classes {
+            if usize::from(id) >= n {
+                return Err(InvariantViolation::UnknownClass(id));
+            }
+            if let Some(node) = class.nodes.iter().find(|node| !in_range(node)) {
+                return Err(InvariantViolation::UnknownChild {
+                    class: id,
+                    node: node.clone(),
+                });
+            }
+        }
+        for (node, &id) in &self.memo {
+            if usize::from(id) >= n || !in_range(node) {
+                return Err(InvariantViolation::UnknownHashconsId {
+                    node: node.clone(),
+                    class: id,
+                });
+            }
+        }
+        Ok(())
+    }
+
+    /// Checks the congruence invariants of a clean e-graph: e-classes are
+    /// keyed by their canonical id, their e-nodes are canonical and in
+    /// exactly one e-class, and the hashcons agrees with the e-classes.
+    pub(crate) fn check_congruence(&self) -> Result<(), InvariantViolation<L>> {
+        let mut owners: HashMap<&L, Id> = HashMap::default();
+        for (&id, class) in &self.classes {
+            if class.id != id || self.find(id) != id {
+                return Err(InvariantViolation::NonCanonicalClass {
+                    class: class.id,
+                    stored_as: id,
+                });
+            }
+            for node in &class.nodes {
+                if !node.all(|c| self.find(c) == c) {
+                    return Err(InvariantViolation::NonCanonicalNode {
+                        class: id,
+                        node: node.clone(),
+                    });
+                }
+                if let Some(other) = owners.insert(node, id) {
+                    return Err(InvariantViolation::DuplicateNode {
+                        node: node.clone(),
+                        first: other,
+                        second: id,
+                    });
+                }
+                let hashcons = self.memo.get(node).map(|&i| self.find(i));
+                if hashcons != Some(id) {
+                    return Err(InvariantViolation::HashconsMismatch {
+                        node: node.clone(),
+                        class: id,
+                        hashcons,
+                    });
+                }
+            }
+        }
+        for i in 0..self.unionfind.size() {
+            let id = Id::from(i);
+            if self.find(id) == id && !self.classes.contains_key(&id) {
+                return Err(InvariantViolation::MissingClass(id));
+            }
+        }
+        for (node, &id) in &self.memo {
+            // rebuilding leaves the old keys of merged e-nodes behind,
+            // they are never looked up since lookups canonicalize first
+            if !node.all(|c| self.find(c) == c) {
+                continue;
+            }
+            if owners.get(node) != Some(&self.find(id)) {
+                return Err(InvariantViolation::StaleHashcons {
+                    node: node.clone(),
+                    class: id,
+                });
+            }
+        }
+        Ok(())
     }
 
     #[inline(never)]
@@ -1328,10 +2996,12 @@ impl<L: Language, N: Analysis<L>> EGraph<L, N> {
     }
 
     #[inline(never)]
-    fn process_unions(&mut self) -> usize {
+    fn process_unions(&mut self) -> RebuildStats {
         let mut n_unions = 0;
+        let mut n_iters = 0;
 
         while !self.pending.is_empty() || !self.analysis_pending.is_empty() {
+            n_iters += 1;
             while let Some(class) = self.pending.pop() {
                 let mut node = self.nodes[usize::from(class)].clone();
                 node.update_children(|id| self.find_mut(id));
@@ -1359,7 +3029,79 @@ impl<L: Language, N: Analysis<L>> EGraph<L, N> {
         assert!(self.pending.is_empty());
         assert!(self.analysis_pending.is_empty());
 
-        n_unions
+        RebuildStats {
+            congruence_merges: n_unions,
+            worklist_iters: n_iters,
+        }
+    }
+
+    /// Returns the number of rebuilds that changed this e-graph so far.
+    ///
+    /// Together with [`classes_changed_since`](EGraph::classes_changed_since),
+    /// this lets you cache things computed on a clean e-graph and only
+    /// recompute the parts that changed, like [`CachingSearcher`] does.
+    pub fn epoch(&self) -> usize {
+        self.epoch
+    }
+
+    /// Returns the canonical ids of the e-classes whose e-nodes changed
+    /// since the e-graph was at `epoch`, see [`epoch`](EGraph::epoch).
+    ///
+    /// An e-class changed if an e-node was added to it or it was merged
+    /// with another one.
+    /// Its parents are not included, even though their e-nodes may now
+    /// point to a changed e-class.
+    /// Changes made through [`classes_mut`](EGraph::classes_mut) or that
+    /// have not been [`rebuild`](EGraph::rebuild)ed yet are not included.
+    /// Returns `None` if `epoch` is in the future of this e-graph, or too
+    /// far in the past for the history kept by
+    /// [`with_change_tracking`](EGraph::with_change_tracking).
+    ///
+    /// ```
+    /// use egg::{*, SymbolLang as S};
+    /// let mut egraph = EGraph::<S, ()>::default().with_change_tracking(1);
+    /// let x = egraph.add(S::leaf("x"));
+    /// let fy = egraph.add_expr(&"(f y)".parse().unwrap());
+    /// egraph.rebuild();
+    /// let epoch = egraph.epoch();
+    /// assert_eq!(egraph.classes_changed_since(epoch), Some(Default::default()));
+    ///
+    /// egraph.union(x, fy);
+    /// egraph.rebuild();
+    /// let changed = egraph.classes_changed_since(epoch).unwrap();
+    /// assert_eq!(changed.into_iter().collect::<Vec<_>>(), vec![egraph.find(x)]);
+    /// assert_eq!(egraph.classes_changed_since(epoch + 2), None);
+    ///
+    /// // only one epoch is remembered
+    /// egraph.add(S::leaf("z"));
+    /// egraph.rebuild();
+    /// assert_eq!(egraph.classes_changed_since(epoch), None);
+    /// ```
+    pub fn classes_changed_since(&self, epoch: usize) -> Option<HashSet<Id>> {
+        let missed = self.epoch.checked_sub(epoch)?;
+        if missed > self.change_log.len() {
+            return None;
+        }
+        let log = self.change_log.range(self.change_log.len() - missed..);
+        Some(log.flatten().map(|&id| self.find(id)).collect())
+    }
+
+    /// Starts a new epoch if the rebuild about to happen changes something,
+    /// logging the e-classes it touches if changes are tracked.
+    fn record_changes(&mut self) {
+        if self.dirty.is_empty() {
+            return;
+        }
+        self.epoch += 1;
+        if self.change_history > 0 {
+            let mut changed: Vec<Id> = self.dirty.iter().map(|&id| self.find(id)).collect();
+            changed.sort_unstable();
+            changed.dedup();
+            if self.change_log.len() == self.change_history {
+                self.change_log.pop_front();
+            }
+            self.change_log.push_back(changed);
+        }
     }
 
     /// Restores the egraph invariants of congruence and enode uniqueness.
@@ -1400,12 +3142,18 @@ impl<L: Language, N: Analysis<L>> EGraph<L, N> {
     /// assert_eq!(egraph.find(ax), egraph.find(ay));
     /// ```
     pub fn rebuild(&mut self) -> usize {
+        self.rebuild_with_stats().congruence_merges
+    }
+
+    /// Like [`rebuild`](EGraph::rebuild), but returns more statistics.
+    pub(crate) fn rebuild_with_stats(&mut self) -> RebuildStats {
         let old_hc_size = self.memo.len();
         let old_n_eclasses = self.number_of_classes();
 
         let start = Instant::now();
 
-        let n_unions = self.process_unions();
+        let stats = self.process_unions();
+        self.record_changes();
         let trimmed_nodes = self.rebuild_classes();
 
         let elapsed = start.elapsed();
@@ -1422,6 +3170,75 @@ impl<L: Language, N: Analysis<L>> EGraph<L, N> {
             old_n_eclasses,
             self.memo.len(),
             self.number_of_classes(),
+            stats.congruence_merges,
+            trimmed_nodes,
+        );
+
+        debug_assert!(self.check_memo());
+        self.clean = true;
+        stats
+    }
+
+    /// Like [`rebuild`](EGraph::rebuild), but only re-canonicalizes the
+    /// e-classes touched since the last rebuild.
+    ///
+    /// [`rebuild`](EGraph::rebuild) visits every e-class in the e-graph,
+    /// which is wasteful if only a handful of them were
+    /// [`add`](EGraph::add)ed or [`union`](EGraph::union)ed since the last
+    /// rebuild.
+    /// `rebuild_partial` only visits the new e-classes, the merged e-classes,
+    /// and the parents of merged e-classes.
+    /// The resulting e-graph has the same e-classes, e-nodes, and hashcons
+    /// as after a full [`rebuild`](EGraph::rebuild).
+    ///
+    /// This is always safe after [`add`](EGraph::add) and
+    /// [`union`](EGraph::union), but a full [`rebuild`](EGraph::rebuild)
+    /// is required after deserializing an e-graph, or after modifying e-nodes
+    /// directly through [`classes_mut`](EGraph::classes_mut).
+    /// If many e-classes were touched (e.g. after adding a lot of
+    /// expressions), a full rebuild will be about as fast.
+    ///
+    /// This will set [`EGraph::clean`] to `true`.
+    ///
+    /// # Example
+    /// ```
+    /// use egg::{*, SymbolLang as S};
+    /// let mut egraph = EGraph::<S, ()>::default();
+    /// let ax = egraph.add_expr(&"(+ a x)".parse().unwrap());
+    /// let ay = egraph.add_expr(&"(+ a y)".parse().unwrap());
+    /// egraph.rebuild();
+    ///
+    /// let x = egraph.lookup(S::leaf("x")).unwrap();
+    /// let y = egraph.lookup(S::leaf("y")).unwrap();
+    /// egraph.union(x, y);
+    /// egraph.rebuild_partial();
+    /// assert_eq!(egraph.number_of_classes(), 3);
+    /// assert_eq!(egraph.find(ax), egraph.find(ay));
+    /// ```
+    pub fn rebuild_partial(&mut self) -> usize {
+        let old_hc_size = self.memo.len();
+        let old_n_eclasses = self.number_of_classes();
+
+        let start = Instant::now();
+
+        let n_unions = self.process_unions().congruence_merges;
+        self.record_changes();
+        let trimmed_nodes = self.rebuild_dirty_classes();
+
+        let elapsed = start.elapsed();
+        info!(
+            concat!(
+                "PARTIALLY REBUILT! in {}.{:03}s\n",
+                "  Old: hc size {}, eclasses: {}\n",
+                "  New: hc size {}, eclasses: {}\n",
+                "  unions: {}, trimmed nodes: {}"
+            ),
+            elapsed.as_secs(),
+            elapsed.subsec_millis(),
+            old_hc_size,
+            old_n_eclasses,
+            self.memo.len(),
+            self.number_of_classes(),
             n_unions,
             trimmed_nodes,
         );
@@ -1431,6 +3248,65 @@ impl<L: Language, N: Analysis<L>> EGraph<L, N> {
         n_unions
     }
 
+    /// Recomputes the analysis data of every e-class from scratch.
+    ///
+    /// Normally, the data is only ever [`merge`](Analysis::merge)d into,
+    /// so data that was patched or loaded out-of-band (say, after
+    /// deserializing an e-graph) never gets fixed.
+    /// This throws away the data of every e-class and recomputes it
+    /// bottom-up with [`make`](Analysis::make) and
+    /// [`merge`](Analysis::merge): an e-class gets its new data once the
+    /// data of all children of one of its e-nodes is known.
+    /// Then every e-node is [`make`](Analysis::make)d again,
+    /// [`modify`](Analysis::modify) is called on every e-class, and the
+    /// e-graph is [`rebuild`](EGraph::rebuild)t until the data is stable.
+    ///
+    /// The e-graph is rebuilt first, so pending unions don't matter.
+    /// An e-class none of whose e-nodes is grounded in leaves (which
+    /// can't happen in e-graphs built by adding terms) keeps its old data
+    /// as a starting point.
+    pub fn saturate_analysis(&mut self) {
+        self.rebuild();
+
+        let mut ready = HashSet::<Id>::default();
+        let mut todo: UniqueQueue<Id> = self.classes.keys().copied().collect();
+        while let Some(id) = todo.pop() {
+            if ready.contains(&id) {
+                continue;
+            }
+            let nodes: Vec<L> = self[id]
+                .iter()
+                .filter(|node| node.all(|c| ready.contains(&self.find(c))))
+                .cloned()
+                .collect();
+            let mut data: Option<N::Data> = None;
+            for node in &nodes {
+                let node_data = N::make(self, node);
+                match &mut data {
+                    None => data = Some(node_data),
+                    Some(data) => {
+                        self.analysis.merge(data, node_data);
+                    }
+                }
+            }
+            if let Some(data) = data {
+                let id = self.find(id);
+                ready.insert(id);
+                self.classes.get_mut(&id).unwrap().data = data;
+                let parents: Vec<Id> = self[id].parents().map(|p| self.find(p)).collect();
+                todo.extend(parents);
+            }
+        }
+
+        let ids: Vec<Id> = self.classes.keys().copied().collect();
+        for id in ids {
+            N::modify(self, id);
+        }
+        self.analysis_pending
+            .extend((0..self.nodes.len()).map(Id::from));
+        self.rebuild();
+    }
+
     pub(crate) fn check_each_explain(&mut self, rules: &[&Rewrite<L, N>]) -> bool {
         if let Some(explain) = &mut self.explain {
             explain.with_nodes(&self.nodes).check_each_explain(rules)
@@ -1455,11 +3331,190 @@ impl<'a, L: Language, N: Analysis<L>> Debug for EGraphDump<'a, L, N> {
     }
 }
 
+/// Calls `f` with every way of writing `total` as an ordered sum of `parts`
+/// positive numbers.
+fn for_each_composition(total: usize, parts: usize, f: &mut impl FnMut(&[usize])) {
+    fn go(total: usize, parts: usize, acc: &mut Vec<usize>, f: &mut impl FnMut(&[usize])) {
+        if parts == 0 {
+            if total == 0 {
+                f(acc);
+            }
+            return;
+        }
+        for first in 1..=total.saturating_sub(parts - 1) {
+            acc.push(first);
+            go(total - first, parts - 1, acc, f);
+            acc.pop();
+        }
+    }
+    go(total, parts, &mut Vec::with_capacity(parts), f)
+}
+
+/// Calls `f` with every combination that picks one item from each option.
+fn for_each_product<'a, T>(options: &[&'a Vec<T>], f: &mut impl FnMut(&[&'a T])) {
+    if options.iter().any(|o| o.is_empty()) {
+        return;
+    }
+    let mut indices = vec![0; options.len()];
+    let mut picked: Vec<&T> = options.iter().map(|o| &o[0]).collect();
+    loop {
+        f(&picked);
+        // advance the rightmost index that isn't at its end, like an odometer
+        let mut i = options.len();
+        loop {
+            if i == 0 {
+                return;
+            }
+            i -= 1;
+            indices[i] += 1;
+            if indices[i] < options[i].len() {
+                picked[i] = &options[i][indices[i]];
+                break;
+            }
+            indices[i] = 0;
+            picked[i] = &options[i][0];
+        }
+    }
+}
+
 #[cfg(test)]
 mod tests {
 
     use super::*;
 
+    #[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+    struct Assoc(SymbolLang);
+
+    impl Language for Assoc {
+        type Discriminant = <SymbolLang as Language>::Discriminant;
+
+        fn discriminant(&self) -> Self::Discriminant {
+            self.0.discriminant()
+        }
+
+        fn matches(&self, other: &Self) -> bool {
+            self.0.matches(&other.0)
+        }
+
+        fn is_associative(&self) -> bool {
+            self.0.op.as_str() == "+"
+        }
+
+        fn children(&self) -> &[Id] {
+            self.0.children()
+        }
+
+        fn children_mut(&mut self) -> &mut [Id] {
+            self.0.children_mut()
+        }
+    }
+
+    #[test]
+    fn associative_normalization() {
+        fn sum<L: Language>(
+            egraph: &mut EGraph<L, ()>,
+            ops: &[(usize, usize)],
+            node: impl Fn(&str, Vec<Id>) -> L,
+        ) -> Id {
+            let leaves: Vec<Id> = ["a", "b", "c", "d"]
+                .iter()
+                .map(|x| egraph.add(node(x, vec![])))
+                .collect();
+            let mut ids = leaves;
+            for &(l, r) in ops {
+                let id = egraph.add_reassociated(node("+", vec![ids[l], ids[r]]));
+                ids.push(id);
+            }
+            *ids.last().unwrap()
+        }
+
+        // the five groupings of a + b + c + d, as indices into the leaves
+        // followed by the sums added so far
+        let groupings: &[&[(usize, usize)]] = &[
+            &[(0, 1), (4, 2), (5, 3)],
+            &[(1, 2), (0, 4), (5, 3)],
+            &[(0, 1), (2, 3), (4, 5)],
+            &[(1, 2), (4, 3), (0, 5)],
+            &[(2, 3), (1, 4), (0, 5)],
+        ];
+
+        let mut plain = EGraph::<SymbolLang, ()>::default();
+        let mut assoc = EGraph::<Assoc, ()>::default();
+        let mut explained = EGraph::<Assoc, ()>::default().with_explanations_enabled();
+        let mut roots = vec![];
+        for ops in groupings {
+            sum(&mut plain, ops, |op, children| {
+                SymbolLang::new(op, children)
+            });
+            roots.push(sum(&mut assoc, ops, |op, children| {
+                Assoc(SymbolLang::new(op, children))
+            }));
+            sum(&mut explained, ops, |op, children| {
+                Assoc(SymbolLang::new(op, children))
+            });
+        }
+        plain.rebuild();
+        assoc.rebuild();
+        explained.rebuild();
+
+        // the 12 sums are distinct without regrouping, but with it
+        // all groupings of the whole chain share one e-class, as do
+        // (+ (+ a b) c) and (+ a (+ b c)), and (+ (+ b c) d) and (+ b (+ c d))
+        assert_eq!(plain.number_of_classes(), 4 + 12);
+        assert_eq!(assoc.number_of_classes(), 4 + 6);
+        assert!(roots.iter().all(|&r| assoc.find(r) == assoc.find(roots[0])));
+        assert_eq!(explained.number_of_classes(), assoc.number_of_classes());
+
+        // every added term is still there as given
+        let to_assoc = |expr: &str| -> RecExpr<Assoc> {
+            let expr: RecExpr<SymbolLang> = expr.parse().unwrap();
+            expr.as_ref()
+                .iter()
+                .cloned()
+                .map(Assoc)
+                .collect::<Vec<_>>()
+                .into()
+        };
+        for expr in ["(+ (+ (+ a b) c) d)", "(+ (+ a b) (+ c d))"] {
+            let found = assoc.lookup_expr(&to_assoc(expr));
+            assert_eq!(found, Some(assoc.find(roots[0])));
+        }
+
+        // and explanations can go through the regroupings
+        let left = to_assoc("(+ (+ (+ a b) c) d)");
+        let right = to_assoc("(+ a (+ b (+ c d)))");
+        let mut explanation = explained.explain_equivalence(&left, &right);
+        assert!(explanation.make_flat_explanation().len() > 1);
+    }
+
+    #[test]
+    fn classes_for_op_str_formats_data_variants() {
+        define_language! {
+            enum Arith {
+                "+" = Add([Id; 2]),
+                Num(i32),
+            }
+        }
+
+        let mut egraph = EGraph::<Arith, ()>::default();
+        let one = egraph.add(Arith::Num(1));
+        let two = egraph.add(Arith::Num(2));
+        let sum = egraph.add(Arith::Add([one, two]));
+        egraph.rebuild();
+
+        let find =
+            |egraph: &EGraph<Arith, ()>, op| egraph.classes_for_op_str(op).collect::<Vec<_>>();
+        assert_eq!(find(&egraph, "+"), vec![sum]);
+        assert_eq!(find(&egraph, "2"), vec![two]);
+        assert_eq!(find(&egraph, "3"), vec![]);
+
+        egraph.union(one, sum);
+        egraph.rebuild();
+        let root = egraph.find(sum);
+        assert_eq!(find(&egraph, "1"), vec![root]);
+        assert_eq!(find(&egraph, "+"), vec![root]);
+    }
+
     #[test]
     fn simple_add() {
         use SymbolLang as S;
@@ -1480,6 +3535,138 @@ mod tests {
         egraph.rebuild();
     }
 
+    #[test]
+    fn enumerate_terms_with_many_children() {
+        use SymbolLang as S;
+
+        let mut egraph = EGraph::<S, ()>::default();
+        let a = egraph.add_expr(&"a".parse().unwrap());
+        let c = egraph.add_expr(&"c".parse().unwrap());
+        let fa = egraph.add_expr(&"(f a)".parse().unwrap());
+        let root = egraph.add_expr(&"(+ a (g (f a)) b)".parse().unwrap());
+        egraph.union(a, c);
+        egraph.union(fa, c);
+        egraph.rebuild();
+
+        let terms: Vec<RecExpr<S>> = egraph.enumerate_terms(root, 6).collect();
+        let sizes: Vec<usize> = terms.iter().map(|t| t.len()).collect();
+        assert_eq!(sizes, [5, 5, 5, 5, 6, 6, 6, 6, 6, 6, 6, 6]);
+
+        let mut terms: Vec<String> = terms.iter().map(|t| t.to_string()).collect();
+        terms.sort();
+        let mut expected = vec![];
+        for x in ["a", "c"] {
+            for y in ["a", "c"] {
+                expected.push(format!("(+ {} (g {}) b)", x, y));
+                expected.push(format!("(+ (f {}) (g {}) b)", x, y));
+                expected.push(format!("(+ {} (g (f {})) b)", x, y));
+            }
+        }
+        expected.sort();
+        assert_eq!(terms, expected);
+    }
+
+    #[test]
+    fn rebuild_partial_matches_rebuild() {
+        use SymbolLang as S;
+
+        crate::init_logger();
+        let mut full = EGraph::<S, ()>::default();
+        for i in 0..100 {
+            let expr = format!("(f (g x{}) (h x{} y))", i, i + 1).parse().unwrap();
+            full.add_expr(&expr);
+        }
+        full.rebuild();
+        let mut partial = full.clone();
+
+        for i in 0..50 {
+            for egraph in [&mut full, &mut partial] {
+                let a = egraph.lookup(S::leaf(format!("x{}", i))).unwrap();
+                let b = egraph.lookup(S::leaf(format!("x{}", 2 * i))).unwrap();
+                egraph.union(a, b);
+            }
+            full.rebuild();
+            partial.rebuild_partial();
+
+            assert_eq!(full.number_of_classes(), partial.number_of_classes());
+            for class in full.classes() {
+                assert_eq!(class.nodes, partial[class.id].nodes);
+            }
+            for (op, ids) in &full.classes_by_op {
+                let other = &partial.classes_by_op[op];
+                assert_eq!(ids.len(), other.len());
+                assert!(ids.iter().all(|id| other.contains(id)));
+            }
+        }
+    }
+
+    #[test]
+    fn saturate_analysis_from_scratch() {
+        use SymbolLang as S;
+
+        #[derive(Default, Clone)]
+        struct MinSize;
+        impl Analysis<S> for MinSize {
+            type Data = usize;
+            fn make(egraph: &mut EGraph<S, Self>, enode: &S) -> usize {
+                enode.fold(1, |size, c| size + egraph[c].data)
+            }
+            fn merge(&mut self, a: &mut usize, b: usize) -> DidMerge {
+                merge_min(a, b)
+            }
+        }
+
+        crate::init_logger();
+        let rules: &[Rewrite<S, MinSize>] = &[
+            rewrite!("add-0"; "(+ ?a 0)" => "?a"),
+            rewrite!("mul-1"; "(* ?a 1)" => "?a"),
+            rewrite!("commute-add"; "(+ ?a ?b)" => "(+ ?b ?a)"),
+        ];
+        let runner = crate::Runner::default()
+            .with_expr(&"(* (+ (f x) 0) (+ (* y 1) (g z z)))".parse().unwrap())
+            .run(rules);
+        let fresh = runner.egraph;
+
+        let mut egraph = fresh.clone();
+        for class in egraph.classes_mut() {
+            class.data = 0;
+        }
+        egraph.saturate_analysis();
+
+        assert_eq!(egraph.number_of_classes(), fresh.number_of_classes());
+        for class in fresh.classes() {
+            assert_eq!(egraph[class.id].data, class.data);
+        }
+    }
+
+    #[test]
+    fn frozen_classes() {
+        use SymbolLang as S;
+
+        crate::init_logger();
+        let mut egraph = EGraph::<S, ()>::default();
+        let root = egraph.add_expr(&"(+ x 0)".parse().unwrap());
+        let fy = egraph.add_expr(&"(f y)".parse().unwrap());
+        egraph.freeze(root);
+        egraph.freeze(fy);
+
+        let rules: &[Rewrite<S, ()>] = &[rewrite!("add-0"; "(+ ?a 0)" => "?a")];
+        let runner = crate::Runner::default().with_egraph(egraph).run(rules);
+        let mut egraph = runner.egraph;
+        let x = egraph.lookup(S::leaf("x")).unwrap();
+        assert_ne!(egraph.find(root), egraph.find(x));
+        assert!(egraph.refused_unions() > 0);
+
+        // congruence still merges into a frozen class, which stays frozen
+        let fz = egraph.add_expr(&"(f z)".parse().unwrap());
+        let y = egraph.lookup(S::leaf("y")).unwrap();
+        let z = egraph.lookup(S::leaf("z")).unwrap();
+        assert!(egraph.union(y, z));
+        egraph.rebuild();
+        assert_eq!(egraph.find(fy), egraph.find(fz));
+        assert!(egraph.is_frozen(fz));
+    }
+
     #[cfg(all(feature = "serde-1", feature = "serde_json"))]
     #[test]
     fn test_serde() {
@@ -1494,4 +3681,57 @@ mod tests {
         let json_rep = serde_json::to_string_pretty(&egraph).unwrap();
         println!("{}", json_rep);
     }
+
+    #[test]
+    fn checked_egraph_rejects_corruption() {
+        let mut egraph = EGraph::<SymbolLang, ()>::default();
+        let fx = egraph.add_expr(&"(f x)".parse().unwrap());
+        let y = egraph.add(SymbolLang::leaf("y"));
+        egraph.rebuild();
+        assert!(Runner::default()
+            .with_checked_egraph(egraph.clone())
+            .is_ok());
+
+        // the same e-node in two e-classes
+        let mut bad = egraph.clone();
+        let node = bad[fx].nodes[0].clone();
+        bad[y].nodes.push(node);
+        assert!(Runner::default().with_checked_egraph(bad).is_err());
+
+        // a child that doesn't exist
+        let mut bad = egraph.clone();
+        bad[fx].nodes[0] = SymbolLang::new("f", vec![Id::from(100)]);
+        let err = Runner::default().with_checked_egraph(bad).unwrap_err();
+        let err = err.to_string();
+        assert!(err.contains("unknown child"), "{}", err);
+
+        // an e-node missing from the hashcons
+        let mut bad = egraph;
+        let node = bad[fx].nodes[0].clone();
+        bad.memo.swap_remove(&node);
+        let err = Runner::default().with_checked_egraph(bad).unwrap_err();
+        let err = err.to_string();
+        assert!(err.contains("hashcons"), "{}", err);
+    }
+
+    #[test]
+    fn invariants_hold_after_running() {
+        let rules: &[Rewrite<SymbolLang, ()>] = &[
+            rewrite!("comm"; "(+ ?a ?b)" => "(+ ?b ?a)"),
+            rewrite!("assoc"; "(+ ?a (+ ?b ?c))" => "(+ (+ ?a ?b) ?c)"),
+            rewrite!("zero"; "(+ ?a 0)" => "?a"),
+        ];
+        for explain in [false, true] {
+            let runner = Runner::default().with_iter_limit(4);
+            let runner = if explain {
+                runner.with_explanations_enabled()
+            } else {
+                runner
+            };
+            let runner = runner
+                .with_expr(&"(+ x (+ 0 (+ y x)))".parse().unwrap())
+                .run(rules);
+            assert_eq!(runner.egraph.check_invariants(), Ok(()));
+        }
+    }
 }
@@ -1,8 +1,8 @@
 #![allow(clippy::only_used_in_recursion)]
 use crate::Symbol;
 use crate::{
-    util::pretty_print, Analysis, EClass, ENodeOrVar, FromOp, HashMap, HashSet, Id, Language,
-    PatternAst, RecExpr, Rewrite, UnionFind, Var,
+    infix::escape_latex, util::pretty_print, Analysis, EClass, ENodeOrVar, FromOp, HashMap,
+    HashSet, Id, IndexMap, InfixPrinter, Language, PatternAst, RecExpr, Rewrite, UnionFind, Var,
 };
 
 use std::cmp::Ordering;
@@ -235,6 +235,79 @@ impl<L: Language + Display + FromOp> Explanation<L> {
             .collect()
     }
 
+    /// Render the flat explanation as a LaTeX `align*` derivation.
+    ///
+    /// Each line is one term of the [`FlatExplanation`], printed with
+    /// `printer`, with the rewritten subterm in a `\boxed{}` and the rule
+    /// name on the right, marked "reversed" for a
+    /// [`backward_rule`](FlatTerm::backward_rule).
+    /// Operator symbols registered with the printer are emitted verbatim,
+    /// so they can be LaTeX commands like `\cdot`.
+    /// Terms that get too long are wrapped over several lines.
+    /// Like [`summary`](Explanation::summary), this doesn't cache the flat
+    /// explanation.
+    ///
+    /// ```
+    /// use egg::{*, SymbolLang as S};
+    /// let rules: &[Rewrite<S, ()>] = &[rewrite!("mul_one"; "(* ?a 1)" => "?a")];
+    /// let start = "(+ x (* y 1))".parse().unwrap();
+    /// let mut runner = Runner::default()
+    ///     .with_explanations_enabled()
+    ///     .with_expr(&start)
+    ///     .run(rules);
+    ///
+    /// let printer = InfixPrinter::new()
+    ///     .with_op("+", "+", 1, Associativity::Left)
+    ///     .with_op("*", "\\cdot", 2, Associativity::Left);
+    /// let latex = runner
+    ///     .explain_equivalence(&start, &"(+ x y)".parse().unwrap())
+    ///     .to_latex(&printer);
+    /// assert_eq!(
+    ///     latex,
+    ///     "\\begin{align*}\n  \
+    ///        & x + y \\cdot 1 \\\\\n  \
+    ///      = & x + \\boxed{y} && \\text{by mul\\_one}\n\
+    ///      \\end{align*}"
+    /// );
+    /// ```
+    pub fn to_latex(&self, printer: &InfixPrinter) -> String {
+        let flat;
+        let steps = match &self.flat_explanation {
+            Some(steps) => steps,
+            None => {
+                flat = TreeTerm::flatten_proof(&self.explanation_trees);
+                &flat
+            }
+        };
+
+        let mut lines = vec![];
+        for (i, step) in steps.iter().enumerate() {
+            let mut expr = RecExpr::default();
+            let mut rewrite = None;
+            step.add_to_recexpr(&mut expr, &mut rewrite);
+            let highlight = rewrite.as_ref().map(|(id, _)| *id);
+            let term = printer.print_latex(&expr, highlight);
+
+            let mut chunks = wrap_latex(&term, LATEX_LINE_WIDTH).into_iter();
+            let first = chunks.next().unwrap_or_default();
+            let mut line = if i == 0 {
+                format!("  & {}", first)
+            } else {
+                format!("  = & {}", first)
+            };
+            if let Some((_, rule)) = rewrite {
+                line.push_str(&format!(" && \\text{{by {}}}", rule));
+            }
+            lines.push(line);
+            lines.extend(chunks.map(|chunk| format!("  & \\quad {}", chunk)));
+        }
+
+        format!(
+            "\\begin{{align*}}\n{}\n\\end{{align*}}",
+            lines.join(" \\\\\n")
+        )
+    }
+
     fn get_sexp(&self) -> Sexp {
         let mut items = vec![Sexp::String("Explanation".to_string())];
         for e in self.explanation_trees.iter() {
@@ -348,7 +421,65 @@ impl<L: Language + Display + FromOp> Explanation<L> {
     }
 }
 
+/// Aggregate information about an [`Explanation`],
+/// returned by [`Explanation::summary`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ExplanationSummary {
+    /// The number of rewrite steps in the flat explanation.
+    pub num_steps: usize,
+    /// How many steps use each rule (or union reason), in order of first use.
+    pub rules: IndexMap<Symbol, usize>,
+    /// The size (in nodes) of the largest term in the flat explanation.
+    pub max_term_size: usize,
+}
+
 impl<L: Language> Explanation<L> {
+    /// Summarize the flat form of this explanation, without printing it.
+    ///
+    /// This is handy to see which rules dominate a long proof, or whether
+    /// some intermediate term blew up.
+    /// The flat explanation is computed if it hasn't been yet, but not
+    /// cached, see [`make_flat_explanation`](Explanation::make_flat_explanation).
+    ///
+    /// ```
+    /// use egg::{*, SymbolLang as S};
+    /// let rules: &[Rewrite<S, ()>] = &[
+    ///     rewrite!("commute-add"; "(+ ?a ?b)" => "(+ ?b ?a)"),
+    ///     rewrite!("add-0"; "(+ ?a 0)" => "?a"),
+    /// ];
+    /// let mut runner = Runner::default()
+    ///     .with_explanations_enabled()
+    ///     .with_expr(&"(+ 0 (+ x 0))".parse().unwrap())
+    ///     .run(rules);
+    ///
+    /// let summary = runner
+    ///     .explain_equivalence(&"(+ 0 (+ x 0))".parse().unwrap(), &"x".parse().unwrap())
+    ///     .summary();
+    /// assert_eq!(summary.num_steps, summary.rules.values().sum::<usize>());
+    /// assert_eq!(summary.rules[&Symbol::from("add-0")], 2);
+    /// assert_eq!(summary.max_term_size, 5);
+    /// ```
+    pub fn summary(&self) -> ExplanationSummary {
+        let flat;
+        let steps = match &self.flat_explanation {
+            Some(steps) => steps,
+            None => {
+                flat = TreeTerm::flatten_proof(&self.explanation_trees);
+                &flat
+            }
+        };
+
+        let mut rules = IndexMap::default();
+        for step in steps.iter().skip(1) {
+            step.count_rules(&mut rules);
+        }
+        ExplanationSummary {
+            num_steps: steps.len().saturating_sub(1),
+            rules,
+            max_term_size: steps.iter().map(|t| t.size()).max().unwrap_or(0),
+        }
+    }
+
     /// Construct a new explanation given its tree representation.
     pub fn new(explanation_trees: TreeExplanation<L>) -> Explanation<L> {
         Explanation {
@@ -703,6 +834,63 @@ impl<L: Language + Display + FromOp> FlatTerm<L> {
     pub fn get_recexpr(&self) -> RecExpr<L> {
         self.remove_rewrites().to_string().parse().unwrap()
     }
+
+    /// Add this term to `expr` without sharing any subterms, recording
+    /// the id of the rewritten subterm and the escaped rule name.
+    fn add_to_recexpr(&self, expr: &mut RecExpr<L>, rewrite: &mut Option<(Id, String)>) -> Id {
+        let children: Vec<Id> = self
+            .children
+            .iter()
+            .map(|child| child.add_to_recexpr(expr, rewrite))
+            .collect();
+        let mut children = children.into_iter();
+        let id = expr.add(self.node.clone().map_children(|_| children.next().unwrap()));
+        if let Some(rule) = &self.forward_rule {
+            *rewrite = Some((id, rule.with_str(|rule| escape_latex(rule, false))));
+        } else if let Some(rule) = &self.backward_rule {
+            let rule = rule.with_str(|rule| escape_latex(rule, false));
+            *rewrite = Some((id, format!("{}, reversed", rule)));
+        }
+        id
+    }
+}
+
+/// The number of characters after which [`Explanation::to_latex`] tries to
+/// wrap a term.
+const LATEX_LINE_WIDTH: usize = 72;
+
+/// Greedily split `term` at the spaces outside of braces, so that no
+/// `\boxed{}` is cut in half.
+fn wrap_latex(term: &str, width: usize) -> Vec<String> {
+    let mut words = vec![];
+    let mut depth = 0usize;
+    let mut start = 0;
+    for (i, c) in term.char_indices() {
+        match c {
+            '{' => depth += 1,
+            '}' => depth = depth.saturating_sub(1),
+            ' ' if depth == 0 => {
+                words.push(&term[start..i]);
+                start = i + 1;
+            }
+            _ => {}
+        }
+    }
+    words.push(&term[start..]);
+
+    let mut lines = vec![];
+    let mut line = String::new();
+    for word in words {
+        if !line.is_empty() && line.len() + 1 + word.len() > width {
+            lines.push(std::mem::take(&mut line));
+        }
+        if !line.is_empty() {
+            line.push(' ');
+        }
+        line.push_str(word);
+    }
+    lines.push(line);
+    lines
 }
 
 impl<L: Language + Display + FromOp> Display for TreeTerm<L> {
@@ -791,6 +979,19 @@ impl<L: Language> FlatTerm<L> {
         FlatTerm::from_pattern(rhs, rhs.len() - 1, &bindings)
     }
 
+    fn size(&self) -> usize {
+        1 + self.children.iter().map(|c| c.size()).sum::<usize>()
+    }
+
+    fn count_rules(&self, rules: &mut IndexMap<Symbol, usize>) {
+        for rule in self.forward_rule.iter().chain(&self.backward_rule) {
+            *rules.entry(*rule).or_default() += 1;
+        }
+        for child in &self.children {
+            child.count_rules(rules);
+        }
+    }
+
     /// Checks if this term or any child has a [`forward_rule`](FlatTerm::forward_rule).
     pub fn has_rewrite_forward(&self) -> bool {
         self.forward_rule.is_some()
@@ -827,6 +1028,15 @@ impl<L: Language> FlatTerm<L> {
                 });
                 FlatTerm::new(node.clone(), children)
             }
+            ENodeOrVar::OpVar(var, children) => {
+                let node = bindings.get(var).unwrap().node.clone();
+                let children = children
+                    .iter()
+                    .map(|&child| FlatTerm::from_pattern(pattern, usize::from(child), bindings))
+                    .collect();
+                FlatTerm::new(node, children)
+            }
+            ENodeOrVar::Wildcard => panic!("Can't instantiate the wildcard `_`"),
         }
     }
 
@@ -857,6 +1067,19 @@ impl<L: Language> FlatTerm<L> {
                     counter += 1;
                 });
             }
+            ENodeOrVar::OpVar(var, children) => {
+                // operator variables only bind the operator, so only that has to match
+                if let Some(existing) = bindings.get(var) {
+                    assert!(existing.node.matches(&self.node));
+                } else {
+                    bindings.insert(*var, self);
+                }
+                assert_eq!(children.len(), self.children.len());
+                for (term, &child) in self.children.iter().zip(children) {
+                    term.make_bindings(pattern, usize::from(child), bindings);
+                }
+            }
+            ENodeOrVar::Wildcard => {}
         }
     }
 }
@@ -1946,6 +2169,28 @@ mod tests {
 
         egraph.dot().to_dot("target/foo.dot").unwrap();
     }
+
+    #[test]
+    fn wrap_latex_keeps_boxes() {
+        let term = "a + \\boxed{b + c + d} + e";
+        assert_eq!(super::wrap_latex(term, 100), vec![term]);
+        assert_eq!(
+            super::wrap_latex(term, 8),
+            vec!["a +", "\\boxed{b + c + d}", "+ e"]
+        );
+    }
+
+    #[test]
+    fn latex_prefix_commands() {
+        let printer = InfixPrinter::new()
+            .with_op("not", "\\neg", 3, Associativity::None)
+            .with_op("neg", "-", 3, Associativity::None)
+            .with_op("and", "\\land", 1, Associativity::Left);
+        let latex = |s: &str| printer.print_latex(&s.parse::<RecExpr<SymbolLang>>().unwrap(), None);
+        assert_eq!(latex("(not y)"), "{\\neg}y");
+        assert_eq!(latex("(and (not x) (neg y))"), "{\\neg}x \\land -y");
+        assert_eq!(latex("(not (and x y))"), "{\\neg}(x \\land y)");
+    }
 }
 
 #[test]
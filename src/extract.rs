@@ -1,8 +1,13 @@
 use std::cmp::Ordering;
-use std::fmt::Debug;
+use std::fmt::{Debug, Display};
+use std::hash::BuildHasher;
 
-use crate::util::{hashmap_with_capacity, HashMap};
-use crate::{Analysis, EClass, EGraph, Id, Language, RecExpr};
+use num_traits::{Bounded, SaturatingAdd, ToPrimitive, Zero};
+
+#[cfg(not(feature = "deterministic"))]
+use crate::util::SwapRemove;
+use crate::util::{hashmap_with_capacity, HashMap, HashSet};
+use crate::{Analysis, EClass, EGraph, Id, Language, RecExpr, Subst, Var};
 
 /** Extracting a single [`RecExpr`] from an [`EGraph`].
 
@@ -111,12 +116,13 @@ This means that pathological, but nevertheless realistic cases
 might overflow `usize` if you implement a cost function like [`AstSize`],
 even if the actual [`RecExpr`] fits compactly in memory.
 You might want to use [`saturating_add`](u64::saturating_add) to
-ensure your cost function is still monotonic in this situation.
+ensure your cost function is still monotonic in this situation,
+or wrap your cost function in [`Saturating`] which does that for you.
 **/
 pub trait CostFunction<L: Language> {
     /// The `Cost` type. It only requires `PartialOrd` so you can use
-    /// floating point types, but failed comparisons (`NaN`s) will
-    /// result in a panic.
+    /// floating point types. A cost that cannot be compared to itself
+    /// (like a `NaN`) is considered worse than any other cost.
     type Cost: PartialOrd + Debug + Clone;
 
     /// Calculates the cost of an enode whose children are `Cost`s.
@@ -142,6 +148,21 @@ pub trait CostFunction<L: Language> {
         let root = expr.root();
         costs[&root].clone()
     }
+
+    /// Returns `true` if `cost` is infinite, meaning a term with this cost
+    /// must never be extracted.
+    ///
+    /// The [`Extractor`] never selects an e-node with an infinite cost if
+    /// the e-class has a finite alternative, as long as infinite costs
+    /// compare greater than all finite ones (like `usize::MAX` with
+    /// saturating arithmetic, or `f64::INFINITY`).
+    /// [`Extractor::try_find_best`] returns `None` for e-classes whose
+    /// best cost is infinite.
+    ///
+    /// By default, no cost is infinite.
+    fn is_infinite(&self, _cost: &Self::Cost) -> bool {
+        false
+    }
 }
 
 /** A simple [`CostFunction`] that counts total AST size.
@@ -153,7 +174,7 @@ assert_eq!(AstSize.cost_rec(&e), 4);
 ```
 
 **/
-#[derive(Debug)]
+#[derive(Debug, Clone, Copy)]
 pub struct AstSize;
 impl<L: Language> CostFunction<L> for AstSize {
     type Cost = usize;
@@ -174,7 +195,7 @@ assert_eq!(AstDepth.cost_rec(&e), 2);
 ```
 
 **/
-#[derive(Debug)]
+#[derive(Debug, Clone, Copy)]
 pub struct AstDepth;
 impl<L: Language> CostFunction<L> for AstDepth {
     type Cost = usize;
@@ -186,13 +207,316 @@ impl<L: Language> CostFunction<L> for AstDepth {
     }
 }
 
+/** A [`CostFunction`] combinator that adds up costs with saturating
+addition, so deeply shared terms clamp at the maximum cost instead of
+wrapping around.
+
+The `inner` cost function only gives the cost of each e-node on its own:
+it is called with every child costing zero, and [`Saturating`] then adds
+the children's costs with [`saturating_add`](u64::saturating_add).
+So `inner` should be one that sums up its children's costs, like
+[`AstSize`]; wrapping one like [`AstDepth`] would turn it into a sum.
+
+A clamped cost (e.g. `u64::MAX`) is [infinite](CostFunction::is_infinite),
+so the [`Extractor`] treats it as never better than any finite term and
+[`Extractor::try_find_best`] returns `None` for an e-class whose best term
+clamped.
+
+```
+# use egg::*;
+struct OpCost;
+impl CostFunction<SymbolLang> for OpCost {
+    type Cost = u64;
+    fn cost<C>(&mut self, enode: &SymbolLang, mut costs: C) -> u64
+    where
+        C: FnMut(Id) -> u64,
+    {
+        let own = if enode.op == Symbol::from("huge") { u64::MAX / 2 } else { 1 };
+        enode.fold(own, |sum, id| sum + costs(id))
+    }
+}
+
+let mut cf = Saturating::new(OpCost);
+let e: RecExpr<SymbolLang> = "(f huge huge huge)".parse().unwrap();
+assert_eq!(cf.cost_rec(&e), u64::MAX);
+assert!(cf.is_infinite(&u64::MAX));
+
+let mut egraph = EGraph::<SymbolLang, ()>::default();
+let big = egraph.add_expr(&e);
+let small = egraph.add_expr(&"(g x)".parse().unwrap());
+egraph.union(big, small);
+egraph.rebuild();
+let (cost, best) = Extractor::new(&egraph, cf).find_best(big);
+assert_eq!((cost, best.to_string()), (2, "(g x)".to_string()));
+```
+
+**/
+#[derive(Debug, Clone)]
+pub struct Saturating<CF> {
+    /// The cost function giving the cost of each e-node on its own.
+    pub inner: CF,
+}
+
+impl<CF> Saturating<CF> {
+    /// Creates a new [`Saturating`] around the `inner` cost function.
+    pub fn new(inner: CF) -> Self {
+        Saturating { inner }
+    }
+}
+
+impl<L, CF> CostFunction<L> for Saturating<CF>
+where
+    L: Language,
+    CF: CostFunction<L>,
+    CF::Cost: Zero + SaturatingAdd + Bounded,
+{
+    type Cost = CF::Cost;
+
+    fn cost<C>(&mut self, enode: &L, mut costs: C) -> Self::Cost
+    where
+        C: FnMut(Id) -> Self::Cost,
+    {
+        let own = self.inner.cost(enode, |_| CF::Cost::zero());
+        enode.fold(own, |sum, id| sum.saturating_add(&costs(id)))
+    }
+
+    fn is_infinite(&self, cost: &Self::Cost) -> bool {
+        *cost >= CF::Cost::max_value() || self.inner.is_infinite(cost)
+    }
+}
+
+/** A [`CostFunction`] combinator that penalizes nesting of specific operators.
+
+Every e-node matching `op_predicate` that has another matching e-node
+somewhere below it (not necessarily as a direct child) costs an extra
+`penalty_per_level` on top of the `inner` cost.
+So a path of `k` nested matching operators pays the penalty `k - 1` times,
+while matching operators that sit side by side are not penalized.
+
+The cost is a [`NestingCost`], which tracks how deeply matching operators
+nest below each term, since the [`Extractor`] computes costs bottom-up and a
+node cannot see its ancestors. Terms are compared by their cost first, then
+by their nesting.
+
+Because the greedy [`Extractor`] picks the best term for each e-class
+independently, the penalty is only charged where the nesting actually
+happens. A child e-class may still pick a slightly cheaper term with a
+matching operator at its root, even if that makes an ancestor pay the
+penalty; the result is the best term for each class under this accounting,
+not necessarily the one with the fewest nestings.
+
+```
+# use egg::*;
+let mut cf = DepthPenalty::new(AstSize, |n: &SymbolLang| n.op.as_str() == "select", 10);
+
+let nested: RecExpr<SymbolLang> = "(select c (+ 1 (select d x y)) z)".parse().unwrap();
+assert_eq!(cf.cost_rec(&nested).cost, 9 + 10);
+
+let flat: RecExpr<SymbolLang> = "(+ (select c x y) (select d x y))".parse().unwrap();
+assert_eq!(cf.cost_rec(&flat).cost, 9);
+```
+
+**/
+#[derive(Debug)]
+pub struct DepthPenalty<CF, F, P> {
+    /// The cost function used for everything but the penalty.
+    pub inner: CF,
+    /// Picks out the operators whose nesting is penalized.
+    pub op_predicate: F,
+    /// The cost added each time a matching operator nests inside another.
+    pub penalty_per_level: P,
+}
+
+impl<CF, F, P> DepthPenalty<CF, F, P> {
+    /// Creates a new [`DepthPenalty`] around the `inner` cost function.
+    pub fn new(inner: CF, op_predicate: F, penalty_per_level: P) -> Self {
+        DepthPenalty {
+            inner,
+            op_predicate,
+            penalty_per_level,
+        }
+    }
+}
+
+/// The [`Cost`](CostFunction::Cost) of a [`DepthPenalty`].
+#[derive(Debug, Clone, PartialEq, PartialOrd)]
+pub struct NestingCost<C> {
+    /// The inner cost plus all the penalties.
+    pub cost: C,
+    /// The longest chain of matching operators on any path down from here.
+    pub nesting: usize,
+}
+
+impl<L, CF, F, P> CostFunction<L> for DepthPenalty<CF, F, P>
+where
+    L: Language,
+    CF: CostFunction<L>,
+    CF::Cost: std::ops::Add<P, Output = CF::Cost>,
+    F: FnMut(&L) -> bool,
+    P: Clone,
+{
+    type Cost = NestingCost<CF::Cost>;
+
+    fn cost<C>(&mut self, enode: &L, mut costs: C) -> Self::Cost
+    where
+        C: FnMut(Id) -> Self::Cost,
+    {
+        let below = enode.fold(0, |max, id| max.max(costs(id).nesting));
+        let cost = self.inner.cost(enode, |id| costs(id).cost);
+        if (self.op_predicate)(enode) {
+            let cost = if below > 0 {
+                cost + self.penalty_per_level.clone()
+            } else {
+                cost
+            };
+            NestingCost {
+                cost,
+                nesting: below + 1,
+            }
+        } else {
+            NestingCost {
+                cost,
+                nesting: below,
+            }
+        }
+    }
+
+    fn is_infinite(&self, cost: &Self::Cost) -> bool {
+        self.inner.is_infinite(&cost.cost)
+    }
+}
+
+/** A [`CostFunction`] combinator that makes some operators free.
+
+An e-node whose operator (its [`op_str`](Language::op_str), or its
+[`Display`] string if it has none) is in `free` costs
+just the sum of its children's costs, so a leaf in `free` costs zero.
+Every other e-node is costed by `inner`.
+This is handy for things like type coercions that should not make a term
+look more expensive.
+
+Note that a free operator with one child costs exactly as much as its
+child, so the [`Extractor`] is free to pick either when they are in the
+same e-class.
+
+```
+# use egg::*;
+let mut cf = FreeOps::new(AstSize, ["cast"]);
+let e: RecExpr<SymbolLang> = "(+ (cast x) (cast y))".parse().unwrap();
+assert_eq!(cf.cost_rec(&e), 3);
+assert_eq!(AstSize.cost_rec(&e), 5);
+```
+
+**/
+#[derive(Debug, Clone)]
+pub struct FreeOps<CF> {
+    /// The cost function used for all non-free operators.
+    pub inner: CF,
+    /// The operators that cost nothing themselves.
+    pub free: HashSet<String>,
+}
+
+impl<CF> FreeOps<CF> {
+    /// Creates a new [`FreeOps`] around the `inner` cost function.
+    pub fn new<S: Into<String>>(inner: CF, free: impl IntoIterator<Item = S>) -> Self {
+        FreeOps {
+            inner,
+            free: free.into_iter().map(Into::into).collect(),
+        }
+    }
+}
+
+impl<L, CF> CostFunction<L> for FreeOps<CF>
+where
+    L: Language + Display,
+    CF: CostFunction<L>,
+    CF::Cost: Zero + std::ops::Add<Output = CF::Cost>,
+{
+    type Cost = CF::Cost;
+
+    fn cost<C>(&mut self, enode: &L, mut costs: C) -> Self::Cost
+    where
+        C: FnMut(Id) -> Self::Cost,
+    {
+        let free = match enode.op_str() {
+            Some(op) => self.free.contains(op),
+            None => self.free.contains(&enode.to_string()),
+        };
+        if free {
+            enode.fold(CF::Cost::zero(), |sum, id| sum + costs(id))
+        } else {
+            self.inner.cost(enode, costs)
+        }
+    }
+
+    fn is_infinite(&self, cost: &Self::Cost) -> bool {
+        self.inner.is_infinite(cost)
+    }
+}
+
 fn cmp<T: PartialOrd>(a: &Option<T>, b: &Option<T>) -> Ordering {
     // None is high
     match (a, b) {
         (None, None) => Ordering::Equal,
         (None, Some(_)) => Ordering::Greater,
         (Some(_), None) => Ordering::Less,
-        (Some(a), Some(b)) => a.partial_cmp(b).unwrap(),
+        (Some(a), Some(b)) => cmp_cost(a, b),
+    }
+}
+
+pub(crate) fn cmp_cost<T: PartialOrd>(a: &T, b: &T) -> Ordering {
+    // incomparable costs (NaN) are high
+    a.partial_cmp(b).unwrap_or_else(|| {
+        let a_ok = a.partial_cmp(a).is_some();
+        let b_ok = b.partial_cmp(b).is_some();
+        b_ok.cmp(&a_ok)
+    })
+}
+
+/// The cheapest terms of [`Extractor::find_best_bounded`] for one size cap.
+type BoundedTable<C, L> = HashMap<Id, (C, L, Vec<usize>)>;
+
+/// Pushes onto `out` every way to pick a size for each child from its
+/// `options`, such that the sizes add up to exactly `total` when the size of
+/// a child that occurs `mult` times is counted `mult` times.
+fn bounded_allocations(
+    options: &[(&[usize], usize)],
+    total: usize,
+    prefix: &mut Vec<usize>,
+    out: &mut Vec<Vec<usize>>,
+) {
+    match options.split_first() {
+        None => {
+            if total == 0 {
+                out.push(prefix.clone());
+            }
+        }
+        Some(((sizes, mult), rest)) => {
+            for &size in sizes.iter() {
+                if size * mult > total {
+                    break;
+                }
+                prefix.push(size);
+                bounded_allocations(rest, total - size * mult, prefix, out);
+                prefix.pop();
+            }
+        }
+    }
+}
+
+/// A source of randomness for [`Extractor::sample`].
+///
+/// This is implemented for any closure returning numbers uniformly drawn
+/// from `[0, 1)`, so you can wrap the generator of your choice,
+/// e.g. `|| rng.gen::<f64>()` with the `rand` crate.
+pub trait Rng {
+    /// Returns a number uniformly drawn from `[0, 1)`.
+    fn next_f64(&mut self) -> f64;
+}
+
+impl<F: FnMut() -> f64> Rng for F {
+    fn next_f64(&mut self) -> f64 {
+        self()
     }
 }
 
@@ -222,12 +546,388 @@ where
 
     /// Find the cheapest (lowest cost) represented `RecExpr` in the
     /// given eclass.
+    ///
+    /// If the [`CostFunction`] is not monotonic, the cheapest e-nodes may
+    /// form a cycle, from which no term can be built.
+    /// The e-classes that would reach such a cycle instead get the best
+    /// term among those built from the e-classes settled before them, which
+    /// is computed once when the `Extractor` is created.
+    ///
+    /// This panics if the e-class does not represent any finite term; use
+    /// [`try_find_best`](Extractor::try_find_best) to get `None` instead.
     pub fn find_best(&self, eclass: Id) -> (CF::Cost, RecExpr<L>) {
         let (cost, root) = self.costs[&self.egraph.find(eclass)].clone();
         let expr = root.build_recexpr(|id| self.find_best_node(id).clone());
         (cost, expr)
     }
 
+    /// Finds the cheapest term of each of the given e-classes, in the same
+    /// order as `roots`.
+    ///
+    /// The costs of all e-classes are computed once, when the `Extractor`
+    /// is created, so this is as cheap as calling
+    /// [`find_best`](Extractor::find_best) for each root, and panics in
+    /// the same cases.
+    ///
+    /// ```
+    /// use egg::*;
+    ///
+    /// let mut egraph = EGraph::<SymbolLang, ()>::default();
+    /// let a = egraph.add_expr(&"(+ x (* y 1))".parse().unwrap());
+    /// let b = egraph.add_expr(&"(* y 1)".parse().unwrap());
+    /// let y = egraph.add_expr(&"y".parse().unwrap());
+    /// egraph.union(b, y);
+    /// egraph.rebuild();
+    ///
+    /// let extractor = Extractor::new(&egraph, AstSize);
+    /// let best = extractor.find_best_many(&[b, a]);
+    /// assert_eq!((best[0].0, best[0].1.to_string()), (1, "y".to_string()));
+    /// assert_eq!((best[1].0, best[1].1.to_string()), (3, "(+ x y)".to_string()));
+    /// ```
+    pub fn find_best_many(&self, roots: &[Id]) -> Vec<(CF::Cost, RecExpr<L>)> {
+        roots.iter().map(|&root| self.find_best(root)).collect()
+    }
+
+    /// Like [`find_best`](Extractor::find_best), but for the e-class that
+    /// `var` is bound to in `subst`.
+    ///
+    /// Panics if `var` is not bound in `subst`.
+    pub fn find_best_var(&self, subst: &Subst, var: Var) -> (CF::Cost, RecExpr<L>) {
+        self.find_best(subst[var])
+    }
+
+    /// Like [`find_best`](Extractor::find_best), but returns `None` instead
+    /// of panicking if no term could be extracted from the given e-class,
+    /// or if the cheapest term has an infinite cost
+    /// (see [`CostFunction::is_infinite`]).
+    ///
+    /// ```
+    /// use egg::*;
+    /// use ordered_float::OrderedFloat;
+    ///
+    /// // "hole"s are partial terms that must never be extracted
+    /// struct NoHoles;
+    /// impl CostFunction<SymbolLang> for NoHoles {
+    ///     type Cost = OrderedFloat<f64>;
+    ///     fn cost<C>(&mut self, enode: &SymbolLang, mut costs: C) -> Self::Cost
+    ///     where
+    ///         C: FnMut(Id) -> Self::Cost,
+    ///     {
+    ///         let op_cost = match enode.op.as_str() {
+    ///             "hole" => f64::INFINITY,
+    ///             _ => 1.0,
+    ///         };
+    ///         enode.fold(OrderedFloat(op_cost), |sum, id| sum + costs(id))
+    ///     }
+    ///     fn is_infinite(&self, cost: &Self::Cost) -> bool {
+    ///         cost.is_infinite()
+    ///     }
+    /// }
+    ///
+    /// let mut egraph = EGraph::<SymbolLang, ()>::default();
+    /// let hole = egraph.add_expr(&"(f hole)".parse().unwrap());
+    /// let x = egraph.add_expr(&"x".parse().unwrap());
+    /// let lonely = egraph.add_expr(&"(g hole)".parse().unwrap());
+    /// egraph.union(hole, x);
+    /// egraph.rebuild();
+    ///
+    /// let extractor = Extractor::new(&egraph, NoHoles);
+    /// let (cost, best) = extractor.try_find_best(hole).unwrap();
+    /// assert_eq!(cost, OrderedFloat(1.0));
+    /// assert_eq!(best, "x".parse().unwrap());
+    /// assert!(extractor.try_find_best(lonely).is_none());
+    /// ```
+    pub fn try_find_best(&self, eclass: Id) -> Option<(CF::Cost, RecExpr<L>)> {
+        let (cost, root) = self.costs.get(&self.egraph.find(eclass))?;
+        if self.cost_function.is_infinite(cost) {
+            return None;
+        }
+        let expr = root.build_recexpr(|id| self.find_best_node(id).clone());
+        Some((cost.clone(), expr))
+    }
+
+    /// Returns the e-node picked in each e-class reachable from `root`,
+    /// keyed by canonical e-class id, along with the cost of the best term
+    /// of `root`.
+    ///
+    /// This is exactly what [`find_best`](Extractor::find_best) builds its
+    /// [`RecExpr`] from, without any term being reconstructed, so it is
+    /// handy for custom or sharing-aware code generation.
+    /// Like [`find_best`](Extractor::find_best), this never picks e-nodes
+    /// that form a cycle.
+    ///
+    /// # Example
+    /// ```
+    /// # use egg::*;
+    /// let mut egraph = EGraph::<SymbolLang, ()>::default();
+    /// let root = egraph.add_expr(&"(+ (* x 1) (* x 1))".parse().unwrap());
+    /// let x = egraph.add_expr(&"x".parse().unwrap());
+    /// let mul = egraph.add_expr(&"(* x 1)".parse().unwrap());
+    /// egraph.union(x, mul);
+    /// egraph.rebuild();
+    ///
+    /// let extractor = Extractor::new(&egraph, AstSize);
+    /// let (cost, selection) = extractor.find_best_selection(root);
+    /// assert_eq!(cost, 3);
+    /// assert_eq!(selection.len(), 2);
+    /// assert_eq!(selection[&egraph.find(x)], SymbolLang::leaf("x"));
+    /// ```
+    pub fn find_best_selection(&self, root: Id) -> (CF::Cost, HashMap<Id, L>) {
+        let root = self.egraph.find(root);
+        let mut selection: HashMap<Id, L> = HashMap::default();
+        let mut todo = vec![root];
+        while let Some(id) = todo.pop() {
+            if selection.contains_key(&id) {
+                continue;
+            }
+            let node = self.find_best_node(id).clone();
+            todo.extend(node.children().iter().map(|&child| self.egraph.find(child)));
+            selection.insert(id, node);
+        }
+        (self.find_best_cost(root), selection)
+    }
+
+    /// Finds the cheapest term in `root` that uses the e-node `pins` maps
+    /// an e-class to wherever that e-class occurs, optimizing the other
+    /// e-classes freely.
+    ///
+    /// The keys of `pins` are e-class ids, which need not be canonical, and
+    /// the pinned e-nodes must be e-nodes of those e-classes.
+    /// An e-class pinned to an e-node it doesn't have can't be extracted.
+    /// This returns `None` if no finite term of `root` respects the pins,
+    /// e.g. because it can't avoid such an e-class, or because the pins
+    /// force a cycle.
+    /// Unlike [`find_best`](Extractor::find_best), this computes the costs
+    /// again, since a pin changes the cost of every e-class above it.
+    /// That is also why it takes `&mut self`: computing costs calls the
+    /// cost function's [`cost`](CostFunction::cost), which needs it.
+    ///
+    /// # Example
+    /// ```
+    /// use egg::*;
+    /// use std::collections::HashMap;
+    ///
+    /// let mut egraph = EGraph::<SymbolLang, ()>::default();
+    /// let root = egraph.add_expr(&"(+ x (f (g y)))".parse().unwrap());
+    /// let g = egraph.add_expr(&"(g y)".parse().unwrap());
+    /// let y = egraph.add(SymbolLang::leaf("y"));
+    /// let b = egraph.add(SymbolLang::leaf("b"));
+    /// egraph.union(g, b);
+    /// egraph.rebuild();
+    ///
+    /// let mut extractor = Extractor::new(&egraph, AstSize);
+    /// assert_eq!(extractor.find_best(root).1.to_string(), "(+ x (f b))");
+    ///
+    /// let pins = HashMap::from([(g, SymbolLang::new("g", vec![y]))]);
+    /// let (cost, best) = extractor.find_best_with_pins(root, &pins).unwrap();
+    /// assert_eq!((cost, best.to_string()), (5, "(+ x (f (g y)))".to_string()));
+    ///
+    /// // `z` is not in the e-class of `y`, but `b` avoids it
+    /// let pins = HashMap::from([(y, SymbolLang::leaf("z"))]);
+    /// let (_, best) = extractor.find_best_with_pins(root, &pins).unwrap();
+    /// assert_eq!(best.to_string(), "(+ x (f b))");
+    ///
+    /// // nothing avoids `x`
+    /// let x = egraph.lookup(SymbolLang::leaf("x")).unwrap();
+    /// let pins = HashMap::from([(x, SymbolLang::leaf("z"))]);
+    /// assert!(extractor.find_best_with_pins(root, &pins).is_none());
+    /// ```
+    pub fn find_best_with_pins<S: BuildHasher>(
+        &mut self,
+        root: Id,
+        pins: &std::collections::HashMap<Id, L, S>,
+    ) -> Option<(CF::Cost, RecExpr<L>)> {
+        let egraph = self.egraph;
+        let pins: HashMap<Id, L> = pins
+            .iter()
+            .map(|(&id, node)| {
+                let node = node.clone().map_children(|child| egraph.find(child));
+                (egraph.find(id), node)
+            })
+            .collect();
+
+        let mut costs: HashMap<Id, (CF::Cost, L)> = HashMap::default();
+        let mut did_something = true;
+        while did_something {
+            did_something = false;
+            for class in egraph.classes() {
+                let pin = pins.get(&class.id);
+                for node in class.iter() {
+                    if pin.is_some() && pin != Some(node) {
+                        continue;
+                    }
+                    if !node.all(|child| costs.contains_key(&egraph.find(child))) {
+                        continue;
+                    }
+                    let cost = self
+                        .cost_function
+                        .cost(node, |child| costs[&egraph.find(child)].0.clone());
+                    let better = match costs.get(&class.id) {
+                        None => true,
+                        Some((old, _)) => cmp_cost(&cost, old) == Ordering::Less,
+                    };
+                    if better {
+                        costs.insert(class.id, (cost, node.clone()));
+                        did_something = true;
+                    }
+                }
+            }
+        }
+
+        let root = egraph.find(root);
+        let (cost, node) = costs.get(&root)?;
+        if self.cost_function.is_infinite(cost) || self.is_cyclic(&costs, root) {
+            return None;
+        }
+        let expr = node.build_recexpr(|id| costs[&egraph.find(id)].1.clone());
+        Some((cost.clone(), expr))
+    }
+
+    /// Finds the cheapest term in `root` with at most `max_size` nodes, or
+    /// `None` if no term is that small.
+    ///
+    /// Unlike the greedy [`find_best`](Extractor::find_best), this
+    /// searches over sizes too, so it finds the cheapest term within the
+    /// cap even if it is more expensive than the cheapest term overall.
+    /// The size counted is the tree size, where a subterm used twice is
+    /// counted twice. The returned [`RecExpr`] shares such subterms, so it
+    /// never has more than `max_size` nodes, but this is only a heuristic
+    /// for the DAG size: terms that only fit thanks to sharing are missed.
+    ///
+    /// This runs a dynamic program over e-classes and sizes, which takes
+    /// about `max_size` to the power of the largest arity steps for each
+    /// e-node and each size, so it is best suited for small caps.
+    ///
+    /// This takes `&mut self`, unlike [`find_best`](Extractor::find_best),
+    /// because it computes new costs with the cost function, whose
+    /// [`cost`](CostFunction::cost) takes `&mut self`, instead of reading
+    /// the ones computed by [`Extractor::new`].
+    ///
+    /// # Example
+    /// ```
+    /// # use egg::*;
+    /// struct SlowLeaf;
+    /// impl CostFunction<SymbolLang> for SlowLeaf {
+    ///     type Cost = usize;
+    ///     fn cost<C>(&mut self, enode: &SymbolLang, mut costs: C) -> usize
+    ///     where
+    ///         C: FnMut(Id) -> usize,
+    ///     {
+    ///         let op_cost = if enode.op.as_str() == "slow" { 100 } else { 1 };
+    ///         enode.fold(op_cost, |sum, id| sum + costs(id))
+    ///     }
+    /// }
+    ///
+    /// let mut egraph = EGraph::<SymbolLang, ()>::default();
+    /// let fast = egraph.add_expr(&"(f a (g b))".parse().unwrap());
+    /// let slow = egraph.add(SymbolLang::leaf("slow"));
+    /// egraph.union(fast, slow);
+    /// egraph.rebuild();
+    ///
+    /// let mut extractor = Extractor::new(&egraph, SlowLeaf);
+    /// assert_eq!(extractor.find_best(fast).1.to_string(), "(f a (g b))");
+    ///
+    /// let (cost, best) = extractor.find_best_bounded(fast, 3).unwrap();
+    /// assert_eq!((cost, best.to_string()), (100, "slow".into()));
+    /// assert!(extractor.find_best_bounded(fast, 0).is_none());
+    /// ```
+    pub fn find_best_bounded(
+        &mut self,
+        root: Id,
+        max_size: usize,
+    ) -> Option<(CF::Cost, RecExpr<L>)> {
+        // tables[s - 1] maps each e-class to its cheapest term of at most s
+        // nodes, with the e-node and the sizes of its distinct children
+        let mut tables: Vec<BoundedTable<CF::Cost, L>> = vec![];
+        // the sizes at which the cheapest bounded term of an e-class improved
+        let mut breaks: HashMap<Id, Vec<usize>> = HashMap::default();
+        for size in 1..=max_size {
+            let mut table = tables.last().cloned().unwrap_or_default();
+            for class in self.egraph.classes() {
+                let mut improved = false;
+                for node in class.iter() {
+                    let mut children: Vec<(Id, usize)> = vec![];
+                    for &child in node.children() {
+                        let child = self.egraph.find(child);
+                        match children.iter_mut().find(|(id, _)| *id == child) {
+                            Some((_, mult)) => *mult += 1,
+                            None => children.push((child, 1)),
+                        }
+                    }
+                    let options: Option<Vec<(&[usize], usize)>> = children
+                        .iter()
+                        .map(|(id, mult)| breaks.get(id).map(|b| (b.as_slice(), *mult)))
+                        .collect();
+                    let options = match options {
+                        Some(options) => options,
+                        None => continue,
+                    };
+                    let mut allocations = vec![];
+                    bounded_allocations(&options, size - 1, &mut vec![], &mut allocations);
+                    for sizes in allocations {
+                        let (egraph, children) = (self.egraph, &children);
+                        let cost = self.cost_function.cost(node, |id| {
+                            let id = egraph.find(id);
+                            let i = children.iter().position(|(c, _)| *c == id).unwrap();
+                            tables[sizes[i] - 1][&id].0.clone()
+                        });
+                        if self.cost_function.is_infinite(&cost) {
+                            continue;
+                        }
+                        let better = match table.get(&class.id) {
+                            None => true,
+                            Some((old, _, _)) => cmp_cost(&cost, old) == Ordering::Less,
+                        };
+                        if better {
+                            table.insert(class.id, (cost, node.clone(), sizes));
+                            improved = true;
+                        }
+                    }
+                }
+                if improved {
+                    breaks.entry(class.id).or_default().push(size);
+                }
+            }
+            tables.push(table);
+        }
+
+        let root = self.egraph.find(root);
+        let (cost, _, _) = tables.last()?.get(&root)?.clone();
+        let mut expr = RecExpr::default();
+        let mut built = HashMap::default();
+        self.build_bounded(&tables, root, max_size, &mut expr, &mut built);
+        Some((cost, expr))
+    }
+
+    fn build_bounded(
+        &self,
+        tables: &[BoundedTable<CF::Cost, L>],
+        eclass: Id,
+        size: usize,
+        expr: &mut RecExpr<L>,
+        built: &mut HashMap<(Id, usize), Id>,
+    ) -> Id {
+        if let Some(&id) = built.get(&(eclass, size)) {
+            return id;
+        }
+        let (_, node, sizes) = &tables[size - 1][&eclass];
+        let mut distinct: Vec<Id> = vec![];
+        for &child in node.children() {
+            let child = self.egraph.find(child);
+            if !distinct.contains(&child) {
+                distinct.push(child);
+            }
+        }
+        let node = node.clone().map_children(|child| {
+            let child = self.egraph.find(child);
+            let i = distinct.iter().position(|&c| c == child).unwrap();
+            self.build_bounded(tables, child, sizes[i], expr, built)
+        });
+        let id = expr.add(node);
+        built.insert((eclass, size), id);
+        id
+    }
+
     /// Find the cheapest e-node in the given e-class.
     pub fn find_best_node(&self, eclass: Id) -> &L {
         &self.costs[&self.egraph.find(eclass)].1
@@ -239,6 +939,46 @@ where
         cost.clone()
     }
 
+    /// Like [`find_best_cost`](Extractor::find_best_cost), but returns
+    /// `None` for e-classes without a cost instead of panicking.
+    pub(crate) fn get_best_cost(&self, eclass: Id) -> Option<&CF::Cost> {
+        self.costs
+            .get(&self.egraph.find(eclass))
+            .map(|(cost, _)| cost)
+    }
+
+    /// Checks whether the e-nodes picked in `costs` that are reachable from
+    /// `eclass` form a cycle, so that no finite term can be built from them.
+    fn is_cyclic(&self, costs: &HashMap<Id, (CF::Cost, L)>, eclass: Id) -> bool {
+        // Some(false) means in progress, Some(true) means done
+        let mut visited: HashMap<Id, bool> = HashMap::default();
+        let mut todo = vec![(self.egraph.find(eclass), false)];
+        while let Some((id, children_done)) = todo.pop() {
+            if children_done {
+                visited.insert(id, true);
+                continue;
+            }
+            match visited.get(&id) {
+                Some(true) => continue,
+                Some(false) => return true,
+                None => (),
+            }
+            visited.insert(id, false);
+            todo.push((id, true));
+            if let Some((_, node)) = costs.get(&id) {
+                for &child in node.children() {
+                    let child = self.egraph.find(child);
+                    match visited.get(&child) {
+                        Some(true) => (),
+                        Some(false) => return true,
+                        None => todo.push((child, false)),
+                    }
+                }
+            }
+        }
+        false
+    }
+
     fn node_total_cost(&mut self, node: &L) -> Option<CF::Cost> {
         let eg = &self.egraph;
         let has_cost = |id| self.costs.contains_key(&eg.find(id));
@@ -263,7 +1003,7 @@ where
                         self.costs.insert(class.id, new);
                         did_something = true;
                     }
-                    (Some(old), Some(new)) if new.0 < old.0 => {
+                    (Some(old), Some(new)) if cmp_cost(&new.0, &old.0) == Ordering::Less => {
                         self.costs.insert(class.id, new);
                         did_something = true;
                     }
@@ -271,6 +1011,7 @@ where
                 }
             }
         }
+        self.break_cycles();
 
         for class in self.egraph.classes() {
             if !self.costs.contains_key(&class.id) {
@@ -283,6 +1024,79 @@ where
         }
     }
 
+    /// Re-picks the e-nodes of the e-classes whose cheapest e-nodes reach a
+    /// cycle, which a non-monotonic cost function can produce.
+    ///
+    /// The other e-classes are settled first, keeping their e-nodes.
+    /// Then each round settles the remaining e-classes that have an e-node
+    /// whose children are all settled, with the cheapest such e-node, so
+    /// that picks only ever point to e-classes settled in earlier rounds.
+    fn break_cycles(&mut self) {
+        let egraph = self.egraph;
+        // false while in progress or if it reaches a cycle, true once settled
+        let mut settled: HashMap<Id, bool> = HashMap::default();
+        for class in egraph.classes() {
+            let mut todo = vec![(class.id, false)];
+            while let Some((id, children_done)) = todo.pop() {
+                let children = match self.costs.get(&id) {
+                    Some((_, node)) => node.children(),
+                    None => &[],
+                };
+                if children_done {
+                    let ok = self.costs.contains_key(&id)
+                        && children
+                            .iter()
+                            .all(|&c| settled.get(&egraph.find(c)) == Some(&true));
+                    settled.insert(id, ok);
+                } else if !settled.contains_key(&id) {
+                    settled.insert(id, false);
+                    todo.push((id, true));
+                    for &child in children {
+                        let child = egraph.find(child);
+                        if !settled.contains_key(&child) {
+                            todo.push((child, false));
+                        }
+                    }
+                }
+            }
+        }
+
+        let mut unsettled: Vec<Id> = settled
+            .iter()
+            .filter(|(_, &ok)| !ok)
+            .map(|(&id, _)| id)
+            .collect();
+        if unsettled.is_empty() {
+            return;
+        }
+        unsettled.sort_unstable();
+        for id in &unsettled {
+            self.costs.swap_remove(id);
+        }
+        loop {
+            let mut round = vec![];
+            for &id in &unsettled {
+                let (costs, cost_function) = (&self.costs, &mut self.cost_function);
+                let best = egraph[id]
+                    .iter()
+                    .filter(|n| n.all(|c| costs.contains_key(&egraph.find(c))))
+                    .map(|n| {
+                        let cost = cost_function.cost(n, |c| costs[&egraph.find(c)].0.clone());
+                        (cost, n)
+                    })
+                    .min_by(|a, b| cmp_cost(&a.0, &b.0));
+                if let Some((cost, node)) = best {
+                    round.push((id, (cost, node.clone())));
+                }
+            }
+            if round.is_empty() {
+                break;
+            }
+            unsettled.retain(|id| round.iter().all(|(settled, _)| settled != id));
+            self.costs.extend(round);
+        }
+    }
+
     fn make_pass(&mut self, eclass: &EClass<L, N::Data>) -> Option<(CF::Cost, L)> {
         let (cost, node) = eclass
             .iter()
@@ -293,6 +1107,167 @@ where
     }
 }
 
+#[cfg(all(feature = "serde-1", feature = "serde_json"))]
+impl<'a, CF, L, N> Extractor<'a, CF, L, N>
+where
+    CF: CostFunction<L>,
+    CF::Cost: serde::Serialize,
+    L: Language,
+    N: Analysis<L>,
+{
+    /// Returns a JSON object mapping the id (as a string) of every e-class
+    /// with a cost to the cost of its cheapest term, e.g. to annotate an
+    /// exported e-graph with `egg`'s costs for an external extractor.
+    ///
+    /// This is only available with the `serde-1` feature and `serde_json`,
+    /// e.g. through the `reports` feature.
+    ///
+    /// ```
+    /// use egg::*;
+    ///
+    /// let mut egraph = EGraph::<SymbolLang, ()>::default();
+    /// let root = egraph.add_expr(&"(+ x (* y 2))".parse().unwrap());
+    /// egraph.rebuild();
+    ///
+    /// let json = Extractor::new(&egraph, AstSize).to_cost_map_json();
+    /// assert_eq!(json[root.to_string()], 5);
+    /// assert_eq!(json.as_object().unwrap().len(), egraph.number_of_classes());
+    /// ```
+    #[cfg_attr(docsrs, doc(cfg(all(feature = "serde-1", feature = "serde_json"))))]
+    pub fn to_cost_map_json(&self) -> serde_json::Value {
+        let map = self
+            .egraph
+            .classes()
+            .filter_map(|class| {
+                let cost = self.get_best_cost(class.id)?;
+                let cost = serde_json::to_value(cost).expect("failed to serialize a cost");
+                Some((class.id.to_string(), cost))
+            })
+            .collect();
+        serde_json::Value::Object(map)
+    }
+}
+
+impl<'a, CF, L, N> Extractor<'a, CF, L, N>
+where
+    CF: CostFunction<L>,
+    CF::Cost: ToPrimitive,
+    L: Language,
+    N: Analysis<L>,
+{
+    /// Samples a term from `root` instead of always taking the cheapest one.
+    ///
+    /// This walks the e-graph top-down, and in each e-class picks an e-node
+    /// with probability proportional to `exp(-cost / temperature)`,
+    /// where `cost` is the cost of the cheapest term rooted at that e-node.
+    /// A low `temperature` favors cheap terms, a high one approaches a
+    /// uniform choice, and at a `temperature` of `0` this returns the same
+    /// term as [`find_best`](Extractor::find_best).
+    /// E-nodes with an infinite cost are never picked.
+    /// If the walk comes back to an e-class it is already under, it
+    /// finishes that subterm with the cheapest term so that it terminates.
+    ///
+    /// The result only depends on the e-graph and on the numbers drawn from
+    /// `rng`, so a seeded generator gives reproducible samples.
+    ///
+    /// ```
+    /// use egg::*;
+    ///
+    /// let mut egraph = EGraph::<SymbolLang, ()>::default();
+    /// let a = egraph.add_expr(&"(* x 2)".parse().unwrap());
+    /// let b = egraph.add_expr(&"(<< x 1)".parse().unwrap());
+    /// let c = egraph.add_expr(&"(+ x x)".parse().unwrap());
+    /// egraph.union(a, b);
+    /// egraph.union(a, c);
+    /// egraph.rebuild();
+    ///
+    /// // a small seeded generator, any `FnMut() -> f64` works
+    /// let seeded = |mut state: u64| {
+    ///     move || {
+    ///         state = state.wrapping_mul(6364136223846793005).wrapping_add(1442695040888963407);
+    ///         (state >> 11) as f64 / (1u64 << 53) as f64
+    ///     }
+    /// };
+    ///
+    /// let mut extractor = Extractor::new(&egraph, AstSize);
+    /// let best = extractor.find_best(a).1;
+    /// assert_eq!(extractor.sample(a, &mut seeded(7), 0.0), best);
+    ///
+    /// let samples: Vec<_> = (0..10).map(|_| extractor.sample(a, &mut seeded(7), 10.0)).collect();
+    /// let again: Vec<_> = (0..10).map(|_| extractor.sample(a, &mut seeded(7), 10.0)).collect();
+    /// assert_eq!(samples, again);
+    /// for s in &samples {
+    ///     assert_eq!(egraph.lookup_expr(s), Some(egraph.find(a)));
+    /// }
+    /// ```
+    pub fn sample(&mut self, root: Id, rng: &mut impl Rng, temperature: f64) -> RecExpr<L> {
+        if temperature <= 0.0 {
+            return self.find_best(root).1;
+        }
+        let mut expr = RecExpr::default();
+        let mut path = vec![];
+        self.sample_rec(root, rng, temperature, &mut path, &mut expr);
+        expr
+    }
+
+    fn sample_rec(
+        &mut self,
+        eclass: Id,
+        rng: &mut impl Rng,
+        temperature: f64,
+        path: &mut Vec<Id>,
+        expr: &mut RecExpr<L>,
+    ) -> Id {
+        let eclass = self.egraph.find(eclass);
+        if temperature <= 0.0 || path.contains(&eclass) {
+            let node = self.find_best_node(eclass).clone();
+            let node = node.map_children(|child| self.sample_rec(child, rng, 0.0, path, expr));
+            return expr.add(node);
+        }
+
+        let node = self.sample_node(eclass, rng, temperature);
+        path.push(eclass);
+        let node = node.map_children(|child| self.sample_rec(child, rng, temperature, path, expr));
+        path.pop();
+        expr.add(node)
+    }
+
+    fn sample_node(&mut self, eclass: Id, rng: &mut impl Rng, temperature: f64) -> L {
+        let egraph = self.egraph;
+        let mut candidates = vec![];
+        for node in egraph[eclass].iter() {
+            let cost = match self.node_total_cost(node) {
+                Some(cost) if !self.cost_function.is_infinite(&cost) => cost.to_f64(),
+                _ => None,
+            };
+            match cost {
+                Some(cost) if cost.is_finite() => candidates.push((cost, node)),
+                _ => (),
+            }
+        }
+
+        let min = candidates
+            .iter()
+            .map(|&(cost, _)| cost)
+            .fold(f64::INFINITY, f64::min);
+        let weights: Vec<f64> = candidates
+            .iter()
+            .map(|&(cost, _)| (-(cost - min) / temperature).exp())
+            .collect();
+        let mut target = rng.next_f64() * weights.iter().sum::<f64>();
+        for (&weight, &(_, node)) in weights.iter().zip(&candidates) {
+            if target < weight {
+                return node.clone();
+            }
+            target -= weight;
+        }
+        match candidates.last() {
+            Some(&(_, node)) => node.clone(),
+            None => self.find_best_node(eclass).clone(),
+        }
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use crate::*;
@@ -312,4 +1287,87 @@ mod tests {
         let (_, best_expr) = extractor.find_best(runner.roots[0]);
         assert_eq!(best_expr, start);
     }
+
+    #[test]
+    fn self_referential_class() {
+        let mut egraph = EGraph::<SymbolLang, ()>::default();
+        let x = egraph.add_expr(&"x".parse().unwrap());
+        let fx = egraph.add_expr(&"(f x)".parse().unwrap());
+        let gfx = egraph.add_expr(&"(g (f x))".parse().unwrap());
+        egraph.union(x, fx);
+        egraph.rebuild();
+        assert_eq!(egraph.find_cycles(), vec![egraph.find(x)]);
+
+        let extractor = Extractor::new(&egraph, AstSize);
+        assert_eq!(extractor.find_best(fx).1, "x".parse().unwrap());
+
+        // a non-monotonic cost function prefers the cycle
+        struct FreeF;
+        impl CostFunction<SymbolLang> for FreeF {
+            type Cost = usize;
+            fn cost<C>(&mut self, enode: &SymbolLang, _costs: C) -> usize
+            where
+                C: FnMut(Id) -> usize,
+            {
+                (enode.op.as_str() != "f") as usize
+            }
+        }
+        // the e-classes that reach the cycle fall back to acyclic terms
+        let extractor = Extractor::new(&egraph, FreeF);
+        assert_eq!(extractor.try_find_best(x), Some((1, "x".parse().unwrap())));
+        let (_, best) = extractor.find_best(gfx);
+        assert_eq!(best, "(g x)".parse().unwrap());
+        let (_, selection) = extractor.find_best_selection(gfx);
+        assert_eq!(selection[&egraph.find(x)], SymbolLang::leaf("x"));
+    }
+
+    #[test]
+    fn nan_costs_do_not_panic() {
+        struct NanCost;
+        impl CostFunction<SymbolLang> for NanCost {
+            type Cost = f64;
+            fn cost<C>(&mut self, enode: &SymbolLang, mut costs: C) -> f64
+            where
+                C: FnMut(Id) -> f64,
+            {
+                let op_cost = if enode.op.as_str() == "nan" {
+                    f64::NAN
+                } else {
+                    1.0
+                };
+                enode.fold(op_cost, |sum, id| sum + costs(id))
+            }
+        }
+
+        let mut egraph = EGraph::<SymbolLang, ()>::default();
+        let a = egraph.add_expr(&"(f nan)".parse().unwrap());
+        let b = egraph.add_expr(&"(g x)".parse().unwrap());
+        egraph.union(a, b);
+        egraph.rebuild();
+
+        let extractor = Extractor::new(&egraph, NanCost);
+        let (cost, best) = extractor.find_best(a);
+        assert_eq!(cost, 2.0);
+        assert_eq!(best, "(g x)".parse().unwrap());
+    }
+
+    #[test]
+    fn bounded_counts_shared_subterms() {
+        let mut egraph = EGraph::<SymbolLang, ()>::default();
+        let h = egraph.add_expr(&"(h (g x) (g x))".parse().unwrap());
+        let big = egraph.add_expr(&"(k (k (k (k (k (k y))))))".parse().unwrap());
+        // a cycle through the root mustn't send the search in circles
+        let fh = egraph.add(SymbolLang::new("f", vec![h]));
+        egraph.union(h, big);
+        egraph.union(h, fh);
+        egraph.rebuild();
+
+        let mut extractor = Extractor::new(&egraph, AstSize);
+        assert!(extractor.find_best_bounded(h, 4).is_none());
+        let (cost, best) = extractor.find_best_bounded(h, 5).unwrap();
+        assert_eq!(cost, 5);
+        assert_eq!(best.to_string(), "(h (g x) (g x))");
+        assert_eq!(best.len(), 3);
+        assert_eq!(extractor.find_best_bounded(h, 100).unwrap().0, 5);
+    }
 }
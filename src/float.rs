@@ -0,0 +1,169 @@
+/*!
+Float constants that can be e-node leaves.
+!*/
+
+use std::cmp::Ordering;
+use std::fmt::{self, Display};
+use std::hash::{Hash, Hasher};
+use std::num::ParseFloatError;
+use std::str::FromStr;
+
+/**
+An `f64` that can be used as a leaf in [`define_language!`].
+
+Raw `f64`s are neither [`Eq`] nor [`Ord`], which e-nodes need for
+hashconsing and congruence. A [`FloatConst`] canonicalizes its value on
+construction so that equality is well-behaved:
+- `-0.0` becomes `0.0`, so both zeros are the same constant.
+- Every NaN becomes the same quiet NaN, regardless of its sign and
+  payload, and is equal to itself. So all NaN constants are a single
+  constant, instead of no constant being equal to itself.
+
+Comparisons then use [`f64::total_cmp`] on the canonical value, so they
+never panic and are consistent with [`Hash`]. NaN sorts after every
+other value.
+
+This is meant for constants as written in terms: the value is not
+changed in any other way, so constant folding should still be done with
+the regular `f64` arithmetic on [`value`](FloatConst::value()).
+
+# Example
+```
+use egg::*;
+
+define_language! {
+    enum Math {
+        "+" = Add([Id; 2]),
+        Num(FloatConst),
+        Symbol(Symbol),
+    }
+}
+
+let mut egraph = EGraph::<Math, ()>::default();
+let a = egraph.add_expr(&"(+ 0.0 x)".parse().unwrap());
+let b = egraph.add_expr(&"(+ -0.0 x)".parse().unwrap());
+assert_eq!(a, b);
+```
+**/
+#[derive(Debug, Clone, Copy)]
+#[cfg_attr(feature = "serde-1", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde-1", serde(from = "f64", into = "f64"))]
+pub struct FloatConst(f64);
+
+impl FloatConst {
+    /// Creates a new [`FloatConst`], canonicalizing `-0.0` and NaNs.
+    pub fn new(value: f64) -> Self {
+        if value.is_nan() {
+            FloatConst(f64::NAN)
+        } else if value == 0.0 {
+            FloatConst(0.0)
+        } else {
+            FloatConst(value)
+        }
+    }
+
+    /// Returns the canonical value.
+    pub fn value(self) -> f64 {
+        self.0
+    }
+}
+
+impl From<f64> for FloatConst {
+    fn from(value: f64) -> Self {
+        FloatConst::new(value)
+    }
+}
+
+impl From<FloatConst> for f64 {
+    fn from(value: FloatConst) -> Self {
+        value.0
+    }
+}
+
+impl PartialEq for FloatConst {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for FloatConst {}
+
+impl PartialOrd for FloatConst {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for FloatConst {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.0.total_cmp(&other.0)
+    }
+}
+
+impl Hash for FloatConst {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.0.to_bits().hash(state)
+    }
+}
+
+impl Display for FloatConst {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        Display::fmt(&self.0, f)
+    }
+}
+
+impl FromStr for FloatConst {
+    type Err = ParseFloatError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        s.parse().map(FloatConst::new)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::*;
+
+    define_language! {
+        enum Math {
+            "+" = Add([Id; 2]),
+            Num(FloatConst),
+            Symbol(Symbol),
+        }
+    }
+
+    #[test]
+    fn signed_zeros_are_one_class() {
+        let mut egraph = EGraph::<Math, ()>::default();
+        let a = egraph.add_expr(&"(+ 0.0 x)".parse().unwrap());
+        let b = egraph.add_expr(&"(+ -0.0 x)".parse().unwrap());
+        egraph.rebuild();
+        assert_eq!(a, b);
+        assert_eq!(egraph.number_of_classes(), 3);
+    }
+
+    #[test]
+    fn nans_are_one_class() {
+        let nan: FloatConst = "NaN".parse().unwrap();
+        assert_eq!(nan, FloatConst::new(-f64::NAN));
+        assert_eq!(nan, nan);
+        assert!(FloatConst::new(f64::INFINITY) < nan);
+        assert!(nan.value().is_nan());
+
+        let mut egraph = EGraph::<Math, ()>::default();
+        let a = egraph.add(Math::Num(nan));
+        let b = egraph.add(Math::Num(FloatConst::new(f64::from_bits(
+            0x7ff8_0000_0000_0001,
+        ))));
+        let one = egraph.add(Math::Num(FloatConst::new(1.0)));
+        egraph.rebuild();
+        assert_eq!(a, b);
+        assert_ne!(a, one);
+
+        let expr: RecExpr<Math> = "(+ NaN x)".parse().unwrap();
+        let id = egraph.add_expr(&expr);
+        egraph.rebuild();
+        assert_eq!(egraph.lookup_expr(&expr), Some(id));
+        assert_eq!(expr.to_string(), "(+ NaN x)");
+    }
+}
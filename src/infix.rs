@@ -0,0 +1,219 @@
+/*!
+Infix printing of [`RecExpr`]s
+
+Use the [`InfixPrinter`] struct to render terms in conventional notation
+like `a + b * c` instead of s-expressions.
+!*/
+
+use std::fmt::Display;
+
+use crate::{util::HashMap, Id, Language, RecExpr};
+
+/// How an infix operator groups with other operators of the same
+/// precedence, see [`InfixPrinter::with_op`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Associativity {
+    /// `a - b - c` means `(a - b) - c`.
+    Left,
+    /// `a ^ b ^ c` means `a ^ (b ^ c)`.
+    Right,
+    /// Operators of the same precedence are always parenthesized.
+    None,
+}
+
+/**
+Renders [`RecExpr`]s in infix notation, parenthesizing only where
+operator precedence requires it.
+
+Each operator is registered with [`with_op`](InfixPrinter::with_op())
+under its [`Display`] string, i.e. what it prints as in an s-expression.
+A registered operator with two children is printed infix, and one with a
+single child is printed as a prefix operator like `-a`.
+Leaves print as themselves, and every other e-node falls back to function
+call notation like `f(a, b)`.
+
+# Example
+
+```
+use egg::*;
+
+let printer = InfixPrinter::new()
+    .with_op("+", "+", 1, Associativity::Left)
+    .with_op("-", "-", 1, Associativity::Left)
+    .with_op("*", "*", 2, Associativity::Left)
+    .with_op("pow", "^", 3, Associativity::Right)
+    .with_op("neg", "-", 4, Associativity::None);
+
+let show = |s: &str| printer.print(&s.parse::<RecExpr<SymbolLang>>().unwrap());
+assert_eq!(show("(+ a (* b c))"), "a + b * c");
+assert_eq!(show("(* (+ a b) c)"), "(a + b) * c");
+assert_eq!(show("(- a (- b c))"), "a - (b - c)");
+assert_eq!(show("(pow a (pow b c))"), "a ^ b ^ c");
+assert_eq!(show("(neg (+ a b))"), "-(a + b)");
+assert_eq!(show("(+ (f a b) 1)"), "f(a, b) + 1");
+```
+**/
+#[derive(Debug, Clone, Default)]
+pub struct InfixPrinter {
+    ops: HashMap<String, (String, u32, Associativity)>,
+}
+
+impl InfixPrinter {
+    /// Creates an [`InfixPrinter`] with no infix operators, which prints
+    /// everything in function call notation.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Registers `op` to be printed as `symbol`.
+    ///
+    /// Operators with a higher `precedence` bind tighter, so with `*` at a
+    /// higher precedence than `+`, `(+ a (* b c))` prints as `a + b * c`.
+    /// Registering the same `op` again replaces it.
+    pub fn with_op(
+        mut self,
+        op: impl Into<String>,
+        symbol: impl Into<String>,
+        precedence: u32,
+        associativity: Associativity,
+    ) -> Self {
+        self.ops
+            .insert(op.into(), (symbol.into(), precedence, associativity));
+        self
+    }
+
+    /// Renders `expr` in infix notation.
+    pub fn print<L: Language + Display>(&self, expr: &RecExpr<L>) -> String {
+        self.print_styled(expr, Style::Plain)
+    }
+
+    /// Renders `expr` as LaTeX math, boxing the subterm at `highlight`.
+    ///
+    /// Operator symbols are emitted verbatim, so they may be LaTeX
+    /// commands like `\cdot`, but everything else is escaped.
+    /// A prefix command like `\neg` is braced, as in `{\neg}x`.
+    pub(crate) fn print_latex<L: Language + Display>(
+        &self,
+        expr: &RecExpr<L>,
+        highlight: Option<Id>,
+    ) -> String {
+        self.print_styled(expr, Style::Latex(highlight.map(usize::from)))
+    }
+
+    fn print_styled<L: Language + Display>(&self, expr: &RecExpr<L>, style: Style) -> String {
+        if expr.as_ref().is_empty() {
+            return "()".into();
+        }
+        self.print_rec(expr, expr.as_ref().len() - 1, style).0
+    }
+
+    /// Returns the rendered term and its precedence, or `None` if it never
+    /// needs parentheses.
+    fn print_rec<L: Language + Display>(
+        &self,
+        expr: &RecExpr<L>,
+        i: usize,
+        style: Style,
+    ) -> (String, Option<u32>) {
+        let node = &expr.as_ref()[i];
+        let op = node.to_string();
+        let children = node.children();
+        let (s, prec) = match (self.ops.get(&op), children) {
+            (_, []) => (style.atom(&op), None),
+            (Some((symbol, prec, _)), [child]) => {
+                let child = self.operand(expr, *child, style, |p| p < *prec);
+                (format!("{}{}", style.prefix(symbol), child), Some(*prec))
+            }
+            (Some((symbol, prec, assoc)), [left, right]) => {
+                let (prec, assoc) = (*prec, *assoc);
+                let left = self.operand(expr, *left, style, |p| {
+                    p < prec || (p == prec && assoc != Associativity::Left)
+                });
+                let right = self.operand(expr, *right, style, |p| {
+                    p < prec || (p == prec && assoc != Associativity::Right)
+                });
+                (format!("{} {} {}", left, symbol, right), Some(prec))
+            }
+            _ => {
+                let args: Vec<String> = children
+                    .iter()
+                    .map(|&child| self.print_rec(expr, usize::from(child), style).0)
+                    .collect();
+                (format!("{}({})", style.head(&op), args.join(", ")), None)
+            }
+        };
+        match style {
+            Style::Latex(Some(h)) if h == i => (format!("\\boxed{{{}}}", s), prec),
+            _ => (s, prec),
+        }
+    }
+
+    fn operand<L: Language + Display>(
+        &self,
+        expr: &RecExpr<L>,
+        child: Id,
+        style: Style,
+        needs_parens: impl Fn(u32) -> bool,
+    ) -> String {
+        match self.print_rec(expr, usize::from(child), style) {
+            (s, Some(p)) if needs_parens(p) => format!("({})", s),
+            (s, _) => s,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+enum Style {
+    Plain,
+    /// LaTeX math, with the index of the node to box, if any.
+    Latex(Option<usize>),
+}
+
+impl Style {
+    fn atom(self, op: &str) -> String {
+        match self {
+            Style::Plain => op.into(),
+            Style::Latex(_) => escape_latex(op, true),
+        }
+    }
+
+    /// A prefix operator symbol, grouped in LaTeX if it is a command like
+    /// `\neg` so that it doesn't run into its operand.
+    fn prefix(self, symbol: &str) -> String {
+        let is_command =
+            symbol.starts_with('\\') && symbol.ends_with(|c: char| c.is_ascii_alphabetic());
+        match self {
+            Style::Latex(_) if is_command => format!("{{{}}}", symbol),
+            _ => symbol.into(),
+        }
+    }
+
+    fn head(self, op: &str) -> String {
+        match self {
+            Style::Plain => op.into(),
+            Style::Latex(_) => format!("\\operatorname{{{}}}", escape_latex(op, true)),
+        }
+    }
+}
+
+/// Escapes the LaTeX special characters in `s`, for math mode if `math`
+/// and for text mode otherwise.
+pub(crate) fn escape_latex(s: &str, math: bool) -> String {
+    let mut out = String::with_capacity(s.len());
+    for c in s.chars() {
+        match c {
+            '_' | '{' | '}' | '#' | '$' | '%' | '&' => {
+                out.push('\\');
+                out.push(c);
+            }
+            '\\' if math => out.push_str("\\backslash "),
+            '~' if math => out.push_str("\\sim "),
+            '^' if math => out.push_str("\\hat{}"),
+            '\\' => out.push_str("\\textbackslash{}"),
+            '~' => out.push_str("\\textasciitilde{}"),
+            '^' => out.push_str("\\textasciicircum{}"),
+            _ => out.push(c),
+        }
+    }
+    out
+}
@@ -40,11 +40,65 @@ pub trait Language: Debug + Clone + Eq + Ord + Hash {
     #[allow(enum_intrinsics_non_enums)]
     fn discriminant(&self) -> Self::Discriminant;
 
+    /// Returns the operator of this e-node as used by [`Display`] and
+    /// [`FromOp`], if it is a fixed string.
+    ///
+    /// This is cheaper than formatting the e-node, so it can be used to
+    /// bucket e-nodes by operator.
+    /// [`define_language!`] implements this for variants declared with a
+    /// string and returns `None` for data variants like `Num(i32)`.
+    /// [`SymbolLang`] returns `None` for operators interned in a
+    /// [`SymbolTable`], whose strings don't live in the e-node.
+    /// The default implementation returns `None`.
+    fn op_str(&self) -> Option<&str> {
+        None
+    }
+
     /// Returns true if this enode matches another enode.
     /// This should only consider the operator and the arity,
     /// not the children `Id`s.
     fn matches(&self, other: &Self) -> bool;
 
+    /// Returns true if this e-node has the same
+    /// [`discriminant`](Language::discriminant) and arity as `other`.
+    ///
+    /// This never looks at the data of an e-node, so unlike
+    /// [`matches`](Language::matches) it is true for `Num(1)` and `Num(2)`
+    /// in a [`define_language!`] type; `matches` implies `matches_op`, but
+    /// not the other way around.
+    /// That makes it a cheap pre-check, but not a replacement for `matches`
+    /// when e-matching, which is why the e-matching machine keeps using
+    /// `matches`.
+    /// For [`define_language!`] types, `matches` already compares
+    /// discriminants before anything else, and [`Symbol`]s are interned, so
+    /// comparing them never touches the strings.
+    fn matches_op(&self, other: &Self) -> bool {
+        self.discriminant() == other.discriminant() && self.len() == other.len()
+    }
+
+    /// Returns true if this e-node is a binary associative operator,
+    /// for which `(op (op a b) c)` always means the same as
+    /// `(op a (op b c))`.
+    ///
+    /// [`EGraph::add_reassociated`] uses this to also add the right-leaning
+    /// regrouping of left-nested applications of the operator, so the
+    /// different groupings of a chain share e-classes without running an
+    /// associativity rule. Plain [`EGraph::add`] ignores it.
+    ///
+    /// This comes with strong preconditions:
+    /// - The operator must really be associative, or the e-graph will
+    ///   equate terms that are not equal.
+    /// - Only e-nodes with exactly two children are regrouped, so this
+    ///   does not flatten n-ary e-nodes, and an n-ary version of the
+    ///   operator is left untouched.
+    ///
+    /// The default implementation returns `false`, and
+    /// [`define_language!`] does not override it, so implement
+    /// [`Language`] by hand to use this.
+    fn is_associative(&self) -> bool {
+        false
+    }
+
     /// Returns the children of this e-node.
     fn children(&self) -> &[Id];
 
@@ -482,6 +536,89 @@ impl<L: Language> RecExpr<L> {
         self
     }
 
+    /// Reorders the nodes of this expression into a canonical order.
+    ///
+    /// The nodes are laid out in post-order from the root, children before
+    /// parents and left to right, with identical subterms shared and
+    /// unreachable nodes removed.
+    /// This only depends on the term, not on how the [`RecExpr`] was built,
+    /// so two [`RecExpr`]s for the same term are equal (and hash the same)
+    /// after canonicalization, as is anything derived from their nodes.
+    ///
+    /// ```
+    /// # use egg::*;
+    /// let mut a: RecExpr<SymbolLang> = "(+ (f x) (g x))".parse().unwrap();
+    ///
+    /// let mut b = RecExpr::default();
+    /// let x = b.add(SymbolLang::leaf("x"));
+    /// let g = b.add(SymbolLang::new("g", vec![x]));
+    /// let f = b.add(SymbolLang::new("f", vec![x]));
+    /// b.add(SymbolLang::new("+", vec![f, g]));
+    ///
+    /// assert_ne!(a, b);
+    /// a.canonicalize();
+    /// b.canonicalize();
+    /// assert_eq!(a, b);
+    /// ```
+    pub fn canonicalize(&mut self) {
+        if self.nodes.is_empty() {
+            return;
+        }
+        let mut new_ids: Vec<Option<Id>> = vec![None; self.len()];
+        let mut set: IndexSet<L> = IndexSet::default();
+        let mut todo = vec![(self.root(), false)];
+        while let Some((id, children_done)) = todo.pop() {
+            let i = usize::from(id);
+            if new_ids[i].is_some() {
+                continue;
+            }
+            let node = &self.nodes[i];
+            if children_done {
+                let node = node
+                    .clone()
+                    .map_children(|c| new_ids[usize::from(c)].unwrap());
+                new_ids[i] = Some(Id::from(set.insert_full(node).0));
+            } else {
+                todo.push((id, true));
+                // push in reverse so the children are visited left to right
+                for &child in node.children().iter().rev() {
+                    todo.push((child, false));
+                }
+            }
+        }
+        self.nodes = set.into_iter().collect();
+    }
+
+    /// Checks whether `self` and `other` represent the same term, ignoring
+    /// how their nodes are laid out.
+    ///
+    /// The term of a [`RecExpr`] is the one rooted at its last node, so
+    /// nodes that the root does not reach don't matter, and neither does
+    /// whether identical subterms are shared.
+    /// The derived [`PartialEq`] instead compares the nodes one by one.
+    /// This is the same as comparing [`canonicalize`](RecExpr::canonicalize)d
+    /// copies.
+    ///
+    /// ```
+    /// # use egg::*;
+    /// let a: RecExpr<SymbolLang> = "(f x x)".parse().unwrap();
+    ///
+    /// let mut b = RecExpr::default();
+    /// b.add(SymbolLang::leaf("dead"));
+    /// let x = b.add(SymbolLang::leaf("x"));
+    /// b.add(SymbolLang::new("f", vec![x, x]));
+    ///
+    /// assert_ne!(a, b);
+    /// assert!(a.semantically_eq(&b));
+    /// assert!(!a.semantically_eq(&"(f x y)".parse().unwrap()));
+    /// ```
+    pub fn semantically_eq(&self, other: &Self) -> bool {
+        let (mut a, mut b) = (self.clone(), other.clone());
+        a.canonicalize();
+        b.canonicalize();
+        a == b
+    }
+
     pub(crate) fn extract(&self, new_root: Id) -> Self {
         self[new_root].build_recexpr(|id| self[id].clone())
     }
@@ -503,6 +640,118 @@ impl<L: Language> RecExpr<L> {
         self.ids().zip(self)
     }
 
+    /// Returns every node of this expression exactly once, children before
+    /// parents.
+    ///
+    /// Unlike [`items`](RecExpr::items), this does not rely on the nodes
+    /// being stored in a topological order, so it also works after
+    /// transformations that reorder or patch the nodes in place.
+    /// Panics if the expression has a cycle.
+    pub fn topo_iter(&self) -> impl Iterator<Item = (Id, &L)> {
+        self.topo_order().into_iter().map(move |id| (id, &self[id]))
+    }
+
+    /// Returns every node of this expression exactly once, parents before
+    /// children. This is the reverse of [`topo_iter`](RecExpr::topo_iter).
+    /// Panics if the expression has a cycle.
+    pub fn rev_topo_iter(&self) -> impl Iterator<Item = (Id, &L)> {
+        self.topo_order()
+            .into_iter()
+            .rev()
+            .map(move |id| (id, &self[id]))
+    }
+
+    fn topo_order(&self) -> Vec<Id> {
+        #[derive(Clone, Copy, PartialEq)]
+        enum State {
+            Unvisited,
+            Visiting,
+            Done,
+        }
+        let mut state = vec![State::Unvisited; self.len()];
+        let mut order = Vec::with_capacity(self.len());
+        for start in self.ids() {
+            let mut todo = vec![(start, false)];
+            while let Some((id, children_done)) = todo.pop() {
+                let i = usize::from(id);
+                if children_done {
+                    state[i] = State::Done;
+                    order.push(id);
+                    continue;
+                }
+                match state[i] {
+                    State::Done => continue,
+                    State::Visiting => panic!("RecExpr has a cycle through {}", id),
+                    State::Unvisited => {}
+                }
+                state[i] = State::Visiting;
+                todo.push((id, true));
+                for &child in self[id].children().iter().rev() {
+                    if state[usize::from(child)] != State::Done {
+                        todo.push((child, false));
+                    }
+                }
+            }
+        }
+        order
+    }
+
+    /// Returns the number of nodes in the term rooted at the
+    /// [`root`](RecExpr::root), counting a shared subterm once for every
+    /// time it occurs, as if the expression were a tree.
+    ///
+    /// This is the same as the [`AstSize`] of the term, and saturates at
+    /// `usize::MAX` for heavily shared expressions.
+    /// Panics if the expression has a cycle.
+    pub fn tree_size(&self) -> usize {
+        self.fold_from_root(|node, sizes| {
+            node.children()
+                .iter()
+                .fold(1usize, |acc, &c| acc.saturating_add(sizes[usize::from(c)]))
+        })
+    }
+
+    /// Returns the number of distinct subterms of the term rooted at the
+    /// [`root`](RecExpr::root), counting a shared subterm only once, even
+    /// if it is stored more than once.
+    ///
+    /// This is the length of the expression after
+    /// [`canonicalize`](RecExpr::canonicalize).
+    pub fn dag_size(&self) -> usize {
+        let mut canonical = self.clone();
+        canonical.canonicalize();
+        canonical.len()
+    }
+
+    /// Returns the number of nodes on the longest path from the
+    /// [`root`](RecExpr::root) to a leaf, so a single leaf has height 1.
+    ///
+    /// This is the same as the [`AstDepth`] of the term.
+    /// Panics if the expression has a cycle.
+    pub fn height(&self) -> usize {
+        self.fold_from_root(|node, heights| {
+            1 + node
+                .children()
+                .iter()
+                .map(|&c| heights[usize::from(c)])
+                .max()
+                .unwrap_or(0)
+        })
+    }
+
+    /// Computes `f` for every node, children first, and returns the value
+    /// for the root, or 0 for an empty expression.
+    fn fold_from_root(&self, mut f: impl FnMut(&L, &[usize]) -> usize) -> usize {
+        if self.is_empty() {
+            return 0;
+        }
+        let mut values = vec![0; self.len()];
+        for id in self.topo_order() {
+            values[usize::from(id)] = f(&self[id], &values);
+        }
+        values[usize::from(self.root())]
+    }
+
     /// Checks if this expr is a DAG, i.e. doesn't have any back edges
     pub fn is_dag(&self) -> bool {
         self.items().all(|(id, n)| n.all(|child| child < id))
@@ -647,12 +896,84 @@ pub enum RecExprParseError<E> {
     /// because the input had an invalid structure (e.g. unpaired parentheses).
     #[error(transparent)]
     BadSexp(SexpError),
+
+    /// The s-expression was nested deeper than the maximum depth given
+    /// to [`RecExpr::parse_with_max_depth`].
+    #[error("s-expression is nested deeper than the maximum depth of {0}")]
+    TooDeep(usize),
 }
 
-impl<L: FromOp> FromStr for RecExpr<L> {
-    type Err = RecExprParseError<L::Error>;
+/// The maximum nesting depth of s-expressions parsed with
+/// [`RecExpr::from_str`](FromStr::from_str).
+///
+/// See [`RecExpr::parse_with_max_depth`] to use a different limit.
+pub const DEFAULT_MAX_PARSE_DEPTH: usize = 10_000;
 
-    fn from_str(s: &str) -> Result<Self, Self::Err> {
+/// Checks whether any list in `s` is nested deeper than `max_depth`,
+/// without recursing.
+///
+/// This follows the tokenization of [`symbolic_expressions::parser`], so
+/// parentheses inside quoted strings are not counted.
+fn sexp_exceeds_depth(s: &str, max_depth: usize) -> bool {
+    let mut depth = 0usize;
+    let mut chars = s.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '(' => {
+                depth += 1;
+                if depth > max_depth {
+                    return true;
+                }
+            }
+            ')' => depth = depth.saturating_sub(1),
+            '"' => {
+                let mut escape = false;
+                for c in chars.by_ref() {
+                    match c {
+                        '\\' => escape = true,
+                        '"' if !escape => break,
+                        _ => escape = false,
+                    }
+                }
+            }
+            ' ' | '\t' | '\r' | '\n' => (),
+            _ => {
+                // a bare string, which only ends at these characters
+                while let Some(&c) = chars.peek() {
+                    if matches!(c, ' ' | '(' | ')' | '\r' | '\n') {
+                        break;
+                    }
+                    chars.next();
+                }
+            }
+        }
+    }
+    false
+}
+
+impl<L: FromOp> RecExpr<L> {
+    /// Parses a [`RecExpr`] from an s-expression, failing with
+    /// [`RecExprParseError::TooDeep`] if it is nested deeper than
+    /// `max_depth`.
+    ///
+    /// Parsing is recursive, so deeply nested input can overflow the
+    /// stack. [`RecExpr::from_str`](FromStr::from_str) rejects anything
+    /// deeper than [`DEFAULT_MAX_PARSE_DEPTH`], but that can still be too
+    /// deep for threads with small stacks. When parsing untrusted input,
+    /// pick a limit that suits the stack size of the parsing thread.
+    ///
+    /// ```
+    /// # use egg::*;
+    /// let expr = RecExpr::<SymbolLang>::parse_with_max_depth("(f (g x))", 2).unwrap();
+    /// assert_eq!(expr.to_string(), "(f (g x))");
+    ///
+    /// let err = RecExpr::<SymbolLang>::parse_with_max_depth("(f (g (h x)))", 2).unwrap_err();
+    /// assert!(matches!(err, RecExprParseError::TooDeep(2)));
+    /// ```
+    pub fn parse_with_max_depth(
+        s: &str,
+        max_depth: usize,
+    ) -> Result<Self, RecExprParseError<L::Error>> {
         use RecExprParseError::*;
 
         fn parse_sexp_into<L: FromOp>(
@@ -681,13 +1002,216 @@ impl<L: FromOp> FromStr for RecExpr<L> {
             }
         }
 
+        let s = s.trim();
+        if sexp_exceeds_depth(s, max_depth) {
+            return Err(TooDeep(max_depth));
+        }
+
         let mut expr = RecExpr::default();
-        let sexp = symbolic_expressions::parser::parse_str(s.trim()).map_err(BadSexp)?;
+        let sexp = symbolic_expressions::parser::parse_str(s).map_err(BadSexp)?;
         parse_sexp_into(&sexp, &mut expr)?;
         Ok(expr)
     }
 }
 
+impl<L: FromOp> FromStr for RecExpr<L> {
+    type Err = RecExprParseError<L::Error>;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Self::parse_with_max_depth(s, DEFAULT_MAX_PARSE_DEPTH)
+    }
+}
+
+/// An error type for failures when building a [`RecExpr<L>`] from JSON
+/// with [`RecExpr::from_json`].
+#[cfg(all(feature = "serde-1", feature = "serde_json"))]
+#[cfg_attr(docsrs, doc(cfg(all(feature = "serde-1", feature = "serde_json"))))]
+#[derive(Debug, Error)]
+pub enum RecExprJsonError<E> {
+    /// A node was not an object with a string `"op"` field, or its
+    /// `"children"` field was not an array.
+    #[error("malformed JSON node: {0}")]
+    BadNode(serde_json::Value),
+
+    /// A `{"ref": ..}` node referred to an `"id"` that was not defined
+    /// before it.
+    #[error("reference to undefined node id {0}")]
+    UnknownRef(serde_json::Value),
+
+    /// Two nodes were defined with the same `"id"`.
+    #[error("node id {0} is defined twice")]
+    DuplicateId(serde_json::Value),
+
+    /// Attempting to parse an operator into a value of type `L` failed.
+    #[error(transparent)]
+    BadOp(E),
+}
+
+#[cfg(all(feature = "serde-1", feature = "serde_json"))]
+impl<L: FromOp> RecExpr<L> {
+    /// Builds a [`RecExpr`] from a JSON tree of
+    /// `{"op": "f", "children": [..]}` objects, parsing each operator with
+    /// [`FromOp`]. A missing `"children"` field means a leaf.
+    ///
+    /// Shared subtrees can be written once: a node with an `"id"` field
+    /// (of any JSON type) can be referred to by later nodes written as
+    /// `{"ref": id}`, and is then only added once to the [`RecExpr`].
+    /// Later means after it in a depth-first, left-to-right traversal,
+    /// so a subtree cannot refer to itself.
+    ///
+    /// This is only available with the `serde-1` feature and `serde_json`,
+    /// e.g. through the `reports` feature.
+    ///
+    /// ```
+    /// # use egg::*;
+    /// let json = serde_json::json!({
+    ///     "op": "+",
+    ///     "children": [
+    ///         {"op": "*", "id": 0, "children": [{"op": "x"}, {"op": "2"}]},
+    ///         {"ref": 0},
+    ///     ],
+    /// });
+    /// let expr = RecExpr::<SymbolLang>::from_json(&json).unwrap();
+    /// assert_eq!(expr.to_string(), "(+ (* x 2) (* x 2))");
+    /// // the shared product is only there once
+    /// assert_eq!(expr.len(), 4);
+    /// ```
+    pub fn from_json(value: &serde_json::Value) -> Result<Self, RecExprJsonError<L::Error>> {
+        use serde_json::Value;
+        use RecExprJsonError::*;
+
+        fn add<L: FromOp>(
+            value: &Value,
+            expr: &mut RecExpr<L>,
+            ids: &mut HashMap<String, Id>,
+        ) -> Result<Id, RecExprJsonError<L::Error>> {
+            if let Some(r) = value.get("ref") {
+                return match ids.get(&r.to_string()) {
+                    Some(id) => Ok(*id),
+                    None => Err(UnknownRef(r.clone())),
+                };
+            }
+
+            let op = match value.get("op") {
+                Some(Value::String(op)) => op,
+                _ => return Err(BadNode(value.clone())),
+            };
+            let children = match value.get("children") {
+                None => vec![],
+                Some(Value::Array(children)) => children
+                    .iter()
+                    .map(|child| add(child, expr, ids))
+                    .collect::<Result<_, _>>()?,
+                Some(_) => return Err(BadNode(value.clone())),
+            };
+            let node = L::from_op(op, children).map_err(BadOp)?;
+            let id = expr.add(node);
+
+            if let Some(name) = value.get("id") {
+                if ids.insert(name.to_string(), id).is_some() {
+                    return Err(DuplicateId(name.clone()));
+                }
+            }
+            Ok(id)
+        }
+
+        let mut expr = RecExpr::default();
+        add(value, &mut expr, &mut HashMap::default())?;
+        Ok(expr)
+    }
+}
+
+/// The differences between two [`RecExpr`]s, as computed by [`recexpr_diff`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RecExprDiff<L> {
+    /// The differing subterms as `(old, new)` pairs, from left to right.
+    ///
+    /// Each pair is rooted where the two terms diverge, so the parents of
+    /// `old` and `new` are the same operator, and everything outside
+    /// of the pairs is shared by both terms.
+    pub changes: Vec<(RecExpr<L>, RecExpr<L>)>,
+}
+
+impl<L> RecExprDiff<L> {
+    /// Returns `true` if the two terms were the same.
+    pub fn is_empty(&self) -> bool {
+        self.changes.is_empty()
+    }
+}
+
+impl<L: Language + Display> Display for RecExprDiff<L> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (old, new) in &self.changes {
+            writeln!(f, "replaced {} with {}", old, new)?;
+        }
+        Ok(())
+    }
+}
+
+/// Computes the structural differences between two [`RecExpr`]s.
+///
+/// Both terms are walked from their roots together. Identical subterms are
+/// shared and skipped, and e-nodes that [`matches`](Language::matches)
+/// are descended into child by child. Anywhere else, the terms diverge and
+/// the two subterms are reported as a change.
+///
+/// Since [`RecExpr`]s are DAGs, a subterm can be used many times. Both
+/// terms are hashconsed first so comparing subterms is cheap no matter how
+/// much they share, and each pair of diverging subterms is reported only
+/// once, even if it occurs in many places.
+///
+/// ```
+/// # use egg::*;
+/// let before: RecExpr<SymbolLang> = "(+ (* x 2) (f y))".parse().unwrap();
+/// let after: RecExpr<SymbolLang> = "(+ (<< x 1) (f y))".parse().unwrap();
+///
+/// let diff = recexpr_diff(&before, &after);
+/// assert_eq!(diff.changes.len(), 1);
+/// assert_eq!(diff.to_string(), "replaced (* x 2) with (<< x 1)\n");
+/// assert!(recexpr_diff(&before, &before).is_empty());
+/// ```
+pub fn recexpr_diff<L: Language>(a: &RecExpr<L>, b: &RecExpr<L>) -> RecExprDiff<L> {
+    // hashcons both terms into the same id space, so equal ids mean equal terms
+    let mut memo: HashMap<L, usize> = HashMap::default();
+    let mut hashcons = |expr: &RecExpr<L>| -> Vec<usize> {
+        let mut canon = Vec::with_capacity(expr.len());
+        for node in expr {
+            let node = node
+                .clone()
+                .map_children(|id| Id::from(canon[usize::from(id)]));
+            let n = memo.len();
+            canon.push(*memo.entry(node).or_insert(n));
+        }
+        canon
+    };
+    let (canon_a, canon_b) = (hashcons(a), hashcons(b));
+
+    let mut changes = vec![];
+    let mut seen = HashSet::default();
+    if a.is_empty() || b.is_empty() {
+        if a.len() != b.len() {
+            changes.push((a.clone(), b.clone()));
+        }
+        return RecExprDiff { changes };
+    }
+    let mut todo = vec![(a.root(), b.root())];
+    while let Some((ia, ib)) = todo.pop() {
+        let (ca, cb) = (canon_a[usize::from(ia)], canon_b[usize::from(ib)]);
+        if ca == cb || !seen.insert((ca, cb)) {
+            continue;
+        }
+        let (na, nb) = (&a[ia], &b[ib]);
+        if na.matches(nb) {
+            // push in reverse so the changes come out left to right
+            let pairs = na.children().iter().zip(nb.children()).rev();
+            todo.extend(pairs.map(|(&x, &y)| (x, y)));
+        } else {
+            changes.push((a.extract(ia), b.extract(ib)));
+        }
+    }
+    RecExprDiff { changes }
+}
+
 /// Result of [`Analysis::merge`] indicating which of the inputs
 /// are different from the merged result.
 ///
@@ -804,6 +1328,61 @@ pub trait Analysis<L: Language>: Sized {
     /// Note that `enode`'s children may not be canonical
     fn make(egraph: &mut EGraph<L, Self>, enode: &L) -> Self::Data;
 
+    /// An optional hook called whenever an e-node gets a new id, which is
+    /// usually when a genuinely new [`EClass`] is created for it.
+    ///
+    /// This is for lifecycle side effects like allocating an entry in a
+    /// side table keyed by e-class id, as opposed to [`make`](Analysis::make),
+    /// which computes the data.
+    /// It is called right after the id is allocated and before
+    /// [`make`](Analysis::make), so the e-class itself is not in the
+    /// e-graph yet, which is why this only gets the analysis.
+    /// Adding an e-node that is already in the e-graph does not call it,
+    /// except with explanations enabled, where an e-node that is only
+    /// equal to an existing one up to congruence gets an id of its own,
+    /// which is merged into the existing e-class right away.
+    /// Either way, ids are handed to this hook in order, without gaps.
+    ///
+    /// By default it does nothing.
+    ///
+    /// # Example
+    /// ```
+    /// # use egg::*;
+    /// // keeps a name for each e-class, indexed by id
+    /// #[derive(Default)]
+    /// struct Names(Vec<String>);
+    ///
+    /// impl Analysis<SymbolLang> for Names {
+    ///     type Data = ();
+    ///     fn make(_: &mut EGraph<SymbolLang, Self>, _: &SymbolLang) {}
+    ///     fn merge(&mut self, _: &mut (), _: ()) -> DidMerge {
+    ///         DidMerge(false, false)
+    ///     }
+    ///     fn on_add(&mut self, id: Id, enode: &SymbolLang) {
+    ///         assert_eq!(usize::from(id), self.0.len());
+    ///         self.0.push(enode.op.to_string());
+    ///     }
+    /// }
+    ///
+    /// let mut egraph = EGraph::<SymbolLang, Names>::default();
+    /// let f = egraph.add_expr(&"(f x (g x))".parse().unwrap());
+    /// assert_eq!(egraph.analysis.0, vec!["x", "g", "f"]);
+    /// assert_eq!(egraph.analysis.0[usize::from(f)], "f");
+    ///
+    /// // with explanations, `(f b)` gets its own id in the e-class of `(f a)`
+    /// let mut egraph = EGraph::<SymbolLang, Names>::default().with_explanations_enabled();
+    /// let a = egraph.add(SymbolLang::leaf("a"));
+    /// let b = egraph.add(SymbolLang::leaf("b"));
+    /// egraph.union_trusted(a, b, "a = b");
+    /// egraph.rebuild();
+    /// let fa = egraph.add(SymbolLang::new("f", vec![a]));
+    /// let fb = egraph.add(SymbolLang::new("f", vec![b]));
+    /// assert_eq!(egraph.find(fa), egraph.find(fb));
+    /// assert_eq!(egraph.analysis.0, vec!["a", "b", "f", "f"]);
+    /// ```
+    #[allow(unused_variables)]
+    fn on_add(&mut self, id: Id, enode: &L) {}
+
     /// An optional hook that allows inspection before a [`union`] occurs.
     /// When explanations are enabled, it gives two ids that represent the two particular terms being unioned, not the canonical ids for the two eclasses.
     /// It also gives a justification for the union when explanations are enabled.
@@ -865,6 +1444,91 @@ pub trait Analysis<L: Language>: Sized {
     fn allow_ematching_cycles(&self) -> bool {
         true
     }
+
+    /// Whether an e-class with the given data has the given sort.
+    ///
+    /// Sorted pattern variables like `?b:Bool` (see [`Var`]) only match
+    /// e-classes for which this returns `true`, which keeps matches in
+    /// multi-sorted languages well-typed and prunes the search.
+    ///
+    /// By default, this returns `true`, so sorts are ignored.
+    ///
+    /// ```
+    /// # use egg::*;
+    /// #[derive(Default)]
+    /// struct Sorts;
+    /// impl Analysis<SymbolLang> for Sorts {
+    ///     type Data = bool; // is this a boolean?
+    ///     fn make(_egraph: &mut EGraph<SymbolLang, Self>, enode: &SymbolLang) -> bool {
+    ///         matches!(enode.op.as_str(), "true" | "false" | "and" | "<")
+    ///     }
+    ///     fn merge(&mut self, a: &mut bool, b: bool) -> DidMerge {
+    ///         merge_max(a, b)
+    ///     }
+    ///     fn has_sort(&self, is_bool: &bool, sort: &str) -> bool {
+    ///         *is_bool == (sort == "Bool")
+    ///     }
+    /// }
+    ///
+    /// let mut egraph = EGraph::<SymbolLang, Sorts>::default();
+    /// egraph.add_expr(&"(f true (< x y))".parse().unwrap());
+    /// egraph.add_expr(&"(f x y)".parse().unwrap());
+    /// egraph.rebuild();
+    ///
+    /// let pat: Pattern<SymbolLang> = "(f ?a:Bool ?b:Bool)".parse().unwrap();
+    /// assert_eq!(pat.search(&egraph).len(), 1);
+    /// let pat: Pattern<SymbolLang> = "(f ?a ?b)".parse().unwrap();
+    /// assert_eq!(pat.search(&egraph).len(), 2);
+    /// ```
+    #[allow(unused_variables)]
+    fn has_sort(&self, data: &Self::Data, sort: &str) -> bool {
+        true
+    }
+
+    /// Which of two merging e-classes should become the canonical one.
+    ///
+    /// During a union, returning [`Ordering::Less`] makes the e-class with
+    /// data `a` the new root, and [`Ordering::Greater`] the one with data
+    /// `b`. Either way the e-classes are equal, so this doesn't change what
+    /// the e-graph represents, only which [`Id`] [`find`](EGraph::find)
+    /// returns afterwards.
+    ///
+    /// By default, this returns [`Ordering::Equal`], which lets the
+    /// [`EGraph`] pick the root itself (the e-class with more parents,
+    /// which makes rebuilding cheaper).
+    ///
+    /// ```
+    /// # use egg::*;
+    /// use std::cmp::Ordering;
+    ///
+    /// // prefer the root of the e-class containing the smallest leaf
+    /// #[derive(Default)]
+    /// struct MinLeaf;
+    /// impl Analysis<SymbolLang> for MinLeaf {
+    ///     type Data = Option<Symbol>;
+    ///     fn make(_egraph: &mut EGraph<SymbolLang, Self>, enode: &SymbolLang) -> Self::Data {
+    ///         enode.is_leaf().then(|| enode.op)
+    ///     }
+    ///     fn merge(&mut self, a: &mut Self::Data, b: Self::Data) -> DidMerge {
+    ///         merge_option(a, b, |a, b| merge_min(a, b))
+    ///     }
+    ///     fn prefer(&self, a: &Self::Data, b: &Self::Data) -> Ordering {
+    ///         let key = |d: &Self::Data| d.map(|s| s.as_str().to_owned());
+    ///         key(a).cmp(&key(b))
+    ///     }
+    /// }
+    ///
+    /// let mut egraph = EGraph::<SymbolLang, MinLeaf>::default();
+    /// let b = egraph.add(SymbolLang::leaf("b"));
+    /// let a = egraph.add(SymbolLang::leaf("a"));
+    /// egraph.union(b, a);
+    /// egraph.rebuild();
+    /// assert_eq!(egraph.find(b), a);
+    /// ```
+    #[allow(unused_variables)]
+    fn prefer(&self, a: &Self::Data, b: &Self::Data) -> Ordering {
+        Ordering::Equal
+    }
 }
 
 impl<L: Language> Analysis<L> for () {
@@ -926,6 +1590,104 @@ pub fn merge_option<T>(
     }
 }
 
+/** The usual constant folding logic for [`Analysis::modify`].
+
+Many analyses compute a constant for each e-class, and then add that
+constant to the e-class in [`modify`](Analysis::modify).
+Implementing [`ConstantFold`] only requires saying how to get the constant
+out of the analysis data and how to turn it into an e-node;
+[`fold_constant`](ConstantFold::fold_constant) then does the adding and
+the union, and can be called straight from `modify`.
+
+```
+use egg::{*, rewrite as rw};
+
+define_language! {
+    enum SimpleMath {
+        "+" = Add([Id; 2]),
+        "*" = Mul([Id; 2]),
+        Num(i32),
+        Symbol(Symbol),
+    }
+}
+
+#[derive(Default)]
+struct ConstantFolding;
+impl Analysis<SimpleMath> for ConstantFolding {
+    type Data = Option<i32>;
+
+    fn merge(&mut self, to: &mut Self::Data, from: Self::Data) -> DidMerge {
+        egg::merge_max(to, from)
+    }
+
+    fn make(egraph: &mut EGraph<SimpleMath, Self>, enode: &SimpleMath) -> Self::Data {
+        let x = |i: &Id| egraph[*i].data;
+        match enode {
+            SimpleMath::Num(n) => Some(*n),
+            SimpleMath::Add([a, b]) => Some(x(a)? + x(b)?),
+            SimpleMath::Mul([a, b]) => Some(x(a)? * x(b)?),
+            _ => None,
+        }
+    }
+
+    fn modify(egraph: &mut EGraph<SimpleMath, Self>, id: Id) {
+        Self::fold_constant(egraph, id)
+    }
+}
+
+impl ConstantFold<SimpleMath> for ConstantFolding {
+    type Constant = i32;
+
+    fn get_constant(data: &Option<i32>) -> Option<i32> {
+        *data
+    }
+
+    fn make_constant(n: i32) -> SimpleMath {
+        SimpleMath::Num(n)
+    }
+}
+
+let rules = &[rw!("mul-x-0"; "(* ?x 0)" => "0")];
+let expr = "(+ (* 2 3) (* x 0))".parse().unwrap();
+let runner = Runner::<SimpleMath, ConstantFolding>::default()
+    .with_expr(&expr)
+    .run(rules);
+let (egraph, root) = (&runner.egraph, runner.roots[0]);
+assert_eq!(egraph[root].data, Some(6));
+assert_eq!(egraph.lookup(SimpleMath::Num(6)), Some(egraph.find(root)));
+```
+**/
+pub trait ConstantFold<L: Language>: Analysis<L> {
+    /// The type of constants this analysis folds.
+    type Constant;
+
+    /// Returns the constant this data stands for, if any.
+    fn get_constant(data: &Self::Data) -> Option<Self::Constant>;
+
+    /// Builds the e-node representing a constant.
+    fn make_constant(constant: Self::Constant) -> L;
+
+    /// Adds the constant of the given e-class (if it has one) to the e-class.
+    ///
+    /// This does nothing if the constant e-node is already in the e-class.
+    /// When explanations are enabled, the union is justified by
+    /// `"constant_fold"`.
+    fn fold_constant(egraph: &mut EGraph<L, Self>, id: Id) {
+        let constant = match Self::get_constant(&egraph[id].data) {
+            Some(constant) => constant,
+            None => return,
+        };
+        let node = Self::make_constant(constant);
+        if let Some(existing) = egraph.lookup(node.clone()) {
+            if egraph.find(existing) == egraph.find(id) {
+                return;
+            }
+        }
+        let added = egraph.add(node);
+        egraph.union_trusted(id, added, "constant_fold");
+    }
+}
+
 /// A simple language used for testing.
 #[derive(Debug, Hash, PartialEq, Eq, Clone, PartialOrd, Ord)]
 #[cfg_attr(feature = "serde-1", derive(serde::Serialize, serde::Deserialize))]
@@ -956,6 +1718,10 @@ impl Language for SymbolLang {
         self.op
     }
 
+    fn op_str(&self) -> Option<&str> {
+        self.op.as_global().map(|op| op.as_str())
+    }
+
     fn matches(&self, other: &Self) -> bool {
         self.op == other.op && self.len() == other.len()
     }
@@ -985,3 +1751,159 @@ impl FromOp for SymbolLang {
         })
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use crate::*;
+
+    #[test]
+    fn parse_too_deep() {
+        let n = 100_000;
+        let s = format!("{}x{}", "(f ".repeat(n), ")".repeat(n));
+        let err = s.parse::<RecExpr<SymbolLang>>().unwrap_err();
+        assert!(matches!(
+            err,
+            RecExprParseError::TooDeep(DEFAULT_MAX_PARSE_DEPTH)
+        ));
+
+        // parentheses in quoted strings don't count
+        let s = format!("(f \"{}\")", "(".repeat(n));
+        assert!(RecExpr::<SymbolLang>::parse_with_max_depth(&s, 1).is_ok());
+    }
+
+    #[test]
+    fn canonicalize_same_term() {
+        let mut a: RecExpr<SymbolLang> = "(h (f x y) (f x y) (g y))".parse().unwrap();
+        let mut b = RecExpr::default();
+        let y = b.add(SymbolLang::leaf("y"));
+        let g = b.add(SymbolLang::new("g", vec![y]));
+        b.add(SymbolLang::leaf("unused"));
+        let x = b.add(SymbolLang::leaf("x"));
+        let f1 = b.add(SymbolLang::new("f", vec![x, y]));
+        let x2 = b.add(SymbolLang::leaf("x"));
+        let f2 = b.add(SymbolLang::new("f", vec![x2, y]));
+        b.add(SymbolLang::new("h", vec![f1, f2, g]));
+        assert_eq!(a.to_string(), b.to_string());
+        assert_ne!(a, b);
+
+        a.canonicalize();
+        b.canonicalize();
+        assert_eq!(a, b);
+        assert_eq!(a.len(), 5);
+        assert_eq!(a.to_string(), "(h (f x y) (f x y) (g y))");
+
+        // canonicalizing is idempotent
+        let before = a.clone();
+        a.canonicalize();
+        assert_eq!(a, before);
+    }
+
+    #[test]
+    fn matches_op_ignores_data() {
+        define_language! {
+            enum Math {
+                "+" = Add([Id; 2]),
+                "f" = F(Box<[Id]>),
+                Num(i32),
+            }
+        }
+        let (a, b) = (Id::from(0), Id::from(1));
+        assert!(Math::Num(1).matches_op(&Math::Num(2)));
+        assert!(!Math::Num(1).matches(&Math::Num(2)));
+        assert!(Math::Add([a, b]).matches_op(&Math::Add([b, a])));
+        assert!(!Math::Add([a, b]).matches_op(&Math::Num(1)));
+        let f1 = Math::F(vec![a].into());
+        let f2 = Math::F(vec![a, b].into());
+        assert!(!f1.matches_op(&f2));
+        assert!(!f1.matches(&f2));
+    }
+
+    #[test]
+    fn topo_iter_out_of_order() {
+        // (+ (f x) (f x)) with the shared (f x) stored after its parent
+        let mut e: RecExpr<SymbolLang> = RecExpr::default();
+        e.add(SymbolLang::leaf("x"));
+        e.add(SymbolLang::leaf("placeholder"));
+        e.add(SymbolLang::new("f", vec![Id::from(0)]));
+        e[Id::from(1)] = SymbolLang::new("+", vec![Id::from(2), Id::from(2)]);
+        assert!(!e.is_dag());
+
+        let order: Vec<Id> = e.topo_iter().map(|(id, _)| id).collect();
+        assert_eq!(order.len(), 3);
+        let pos = |id: usize| order.iter().position(|&i| i == Id::from(id)).unwrap();
+        assert!(pos(0) < pos(2));
+        assert!(pos(2) < pos(1));
+
+        let rev: Vec<Id> = e.rev_topo_iter().map(|(id, _)| id).collect();
+        assert_eq!(rev, order.iter().rev().copied().collect::<Vec<_>>());
+        for (id, node) in e.topo_iter() {
+            assert!(node
+                .all(|c| order.iter().position(|&i| i == c) < order.iter().position(|&i| i == id)));
+        }
+    }
+
+    #[test]
+    #[should_panic(expected = "cycle")]
+    fn topo_iter_cycle() {
+        let mut e: RecExpr<SymbolLang> = RecExpr::default();
+        e.add(SymbolLang::leaf("x"));
+        e[Id::from(0)] = SymbolLang::new("f", vec![Id::from(0)]);
+        e.topo_iter().count();
+    }
+
+    #[test]
+    fn sizes_of_diamond() {
+        // (f (g x) (g x)) with (g x) shared, plus an unreachable node
+        let mut e: RecExpr<SymbolLang> = RecExpr::default();
+        e.add(SymbolLang::leaf("unused"));
+        let x = e.add(SymbolLang::leaf("x"));
+        let g = e.add(SymbolLang::new("g", vec![x]));
+        e.add(SymbolLang::new("f", vec![g, g]));
+        assert_eq!(e.tree_size(), 5);
+        assert_eq!(e.dag_size(), 3);
+        assert_eq!(e.height(), 3);
+
+        // the same term without sharing has the same sizes
+        let unshared: RecExpr<SymbolLang> = "(f (g x) (g x))".parse().unwrap();
+        assert_eq!(unshared.tree_size(), 5);
+        assert_eq!(unshared.dag_size(), 3);
+        assert_eq!(unshared.height(), 3);
+
+        // a chain of diamonds doubles the tree size at every level
+        let mut chain: RecExpr<SymbolLang> = RecExpr::default();
+        let mut top = chain.add(SymbolLang::leaf("x"));
+        for _ in 0..10 {
+            top = chain.add(SymbolLang::new("f", vec![top, top]));
+        }
+        assert_eq!(chain.tree_size(), 2047);
+        assert_eq!(chain.dag_size(), 11);
+        assert_eq!(chain.height(), 11);
+        assert_eq!(RecExpr::<SymbolLang>::default().tree_size(), 0);
+    }
+
+    #[test]
+    fn diff_shared_subterms() {
+        let a: RecExpr<SymbolLang> = "(f (g x) (g x) (k y))".parse().unwrap();
+        let b: RecExpr<SymbolLang> = "(f (h x) (h x) (k z))".parse().unwrap();
+        let diff = recexpr_diff(&a, &b);
+        let changes: Vec<_> = diff
+            .changes
+            .iter()
+            .map(|(old, new)| (old.to_string(), new.to_string()))
+            .collect();
+        // (g x) => (h x) is reported once even though it occurs twice
+        assert_eq!(
+            changes,
+            [
+                ("(g x)".to_string(), "(h x)".to_string()),
+                ("y".to_string(), "z".to_string()),
+            ]
+        );
+
+        // different arities diverge at the root
+        let c: RecExpr<SymbolLang> = "(f (g x))".parse().unwrap();
+        let diff = recexpr_diff(&a, &c);
+        assert_eq!(diff.changes.len(), 1);
+        assert_eq!(diff.changes[0].0.to_string(), a.to_string());
+    }
+}
@@ -32,16 +32,25 @@ for less or more logging.
 
 mod macros;
 
+#[cfg(feature = "bench-util")]
+pub mod bench;
+
 #[doc(hidden)]
 pub mod test;
 
+#[cfg(feature = "test-util")]
+pub mod testing;
+
 pub mod tutorials;
 
+mod color;
 mod dot;
 mod eclass;
 mod egraph;
 mod explain;
 mod extract;
+mod float;
+mod infix;
 mod language;
 #[cfg(feature = "lp")]
 mod lp_extract;
@@ -91,23 +100,49 @@ impl std::fmt::Display for Id {
     }
 }
 
+/// A long-lived handle to an [`EClass`] that never goes stale.
+///
+/// A raw [`Id`] may stop being canonical once its e-class is unioned
+/// with another. A [`Root`] remembers an [`Id`] of the e-class and is
+/// always turned back into the current canonical [`Id`] with
+/// [`EGraph::resolve`], so callers holding on to e-classes across
+/// iterations never have to call [`EGraph::find`] themselves.
+/// Get one with [`EGraph::root`].
+///
+/// Two [`Root`]s compare equal if they were made from the same canonical
+/// [`Id`], so compare the resolved [`Id`]s to know whether two handles
+/// point to the same e-class now.
+#[derive(Clone, Copy, Debug, Ord, PartialOrd, Eq, PartialEq, Hash)]
+#[cfg_attr(feature = "serde-1", derive(serde::Serialize, serde::Deserialize))]
+pub struct Root(Id);
+
 pub(crate) use {explain::Explain, unionfind::UnionFind};
 
 pub use {
+    color::ColoredEGraph,
     dot::Dot,
     eclass::EClass,
-    egraph::{EGraph, LanguageMapper, SimpleLanguageMapper},
+    egraph::{ColorId, EGraph, InvariantViolation, LanguageMapper, SimpleLanguageMapper},
     explain::{
-        Explanation, FlatExplanation, FlatTerm, Justification, TreeExplanation, TreeTerm,
-        UnionEqualities,
+        Explanation, ExplanationSummary, FlatExplanation, FlatTerm, Justification, TreeExplanation,
+        TreeTerm, UnionEqualities,
     },
     extract::*,
+    float::FloatConst,
+    infix::{Associativity, InfixPrinter},
     language::*,
+    machine::Program,
     multipattern::*,
-    pattern::{ENodeOrVar, Pattern, PatternAst, SearchMatches},
-    rewrite::{Applier, Condition, ConditionEqual, ConditionalApplier, Rewrite, Searcher},
+    pattern::{ENodeOrVar, PartialExpr, Pattern, PatternAst, SearchMatches},
+    rewrite::{
+        parse_rules, rewrite_recexpr, Applier, BudgetedApplier, CachingSearcher, ChildApplier,
+        Condition, ConditionEqual, ConditionalApplier, CostGuardedApplier, Direction,
+        MultiPatternCondition, OpFamilySearcher, PredicateSearcher, ReplaceApplier, Rewrite,
+        RuleParseError, RuleSet, Searcher,
+    },
     run::*,
-    subst::{Subst, Var, VarExposed},
+    subst::{MissingVar, Subst, Var, VarExposed},
+    unionfind::UnionFindStats,
     util::*,
 };
 
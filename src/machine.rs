@@ -1,30 +1,111 @@
 use crate::*;
+use std::fmt;
 use std::result;
+use std::sync::Arc;
 
 type Result = result::Result<(), ()>;
 
 #[derive(Default)]
 struct Machine {
     reg: Vec<Id>,
+    // the e-node ids witnessing the operators bound to operator variables
+    ops: Vec<Id>,
     // a buffer to re-use for lookups
     lookup: Vec<Id>,
+    // only try the first matching e-node of the root e-class
+    canonical_root: bool,
 }
 
 #[derive(Debug, Default, Copy, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
 struct Reg(u32);
 
+/// The compiled e-matching program of a [`Pattern`].
+///
+/// Compiling a [`Pattern`] happens every time one is created, so callers
+/// that build many [`Pattern`]s with the same ast can compile it once,
+/// keep the program from [`Pattern::compiled`], and rebuild the
+/// [`Pattern`] from it with [`Pattern::from_compiled`].
+/// A program is opaque, but it can be cloned, compared and printed.
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub struct Program<L> {
     instructions: Vec<Instruction<L>>,
     subst: Subst,
+    ops: Vec<Var>,
+    // the pattern this was compiled from, empty for multi-patterns
+    pub(crate) ast: PatternAst<L>,
+    pub(crate) leaf_predicates: Vec<(Var, LeafPredicate<L>)>,
+    pub(crate) canonical_root: bool,
 }
 
 #[derive(Debug, Clone, PartialEq, Eq)]
 enum Instruction<L> {
-    Bind { node: L, i: Reg, out: Reg },
-    Compare { i: Reg, j: Reg },
-    Lookup { term: Vec<ENodeOrReg<L>>, i: Reg },
-    Scan { out: Reg },
+    Bind {
+        node: L,
+        i: Reg,
+        out: Reg,
+    },
+    BindOp {
+        arity: usize,
+        op: usize,
+        bound: bool,
+        i: Reg,
+        out: Reg,
+    },
+    Compare {
+        i: Reg,
+        j: Reg,
+    },
+    CheckSort {
+        i: Reg,
+        sort: Box<str>,
+    },
+    CheckLeaf {
+        i: Reg,
+        pred: LeafPredicate<L>,
+    },
+    Lookup {
+        term: Vec<ENodeOrReg<L>>,
+        i: Reg,
+    },
+    Scan {
+        out: Reg,
+    },
+    ScanParents {
+        of: Reg,
+        out: Reg,
+    },
+}
+
+/// A predicate on the leaves of an e-class, compared by identity.
+pub(crate) struct LeafPredicate<L>(pub(crate) Arc<dyn Fn(&L) -> bool + Send + Sync>);
+
+impl<L> LeafPredicate<L> {
+    pub(crate) fn matches<N: Analysis<L>>(&self, eclass: &EClass<L, N::Data>) -> bool
+    where
+        L: Language,
+    {
+        eclass.iter().any(|n| n.is_leaf() && (self.0)(n))
+    }
+}
+
+impl<L> Clone for LeafPredicate<L> {
+    fn clone(&self) -> Self {
+        LeafPredicate(self.0.clone())
+    }
+}
+
+impl<L> PartialEq for LeafPredicate<L> {
+    fn eq(&self, other: &Self) -> bool {
+        Arc::as_ptr(&self.0) as *const u8 == Arc::as_ptr(&other.0) as *const u8
+    }
+}
+
+impl<L> Eq for LeafPredicate<L> {}
+
+impl<L> fmt::Debug for LeafPredicate<L> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("<leaf predicate>")
+    }
 }
 
 #[derive(Debug, Clone, PartialEq, Eq)]
@@ -56,12 +137,63 @@ impl Machine {
                 Instruction::Bind { i, out, node } => {
                     let remaining_instructions = instructions.as_slice();
                     let eclass = &egraph[self.reg(*i)];
+                    if self.canonical_root && i.0 == 0 {
+                        return match eclass.iter().find(|n| node.matches(n)) {
+                            Some(matched) => {
+                                self.reg.truncate(out.0 as usize);
+                                matched.for_each(|id| self.reg.push(id));
+                                self.run(egraph, remaining_instructions, subst, yield_fn)
+                            }
+                            None => Ok(()),
+                        };
+                    }
                     return eclass.for_each_matching_node(node, |matched| {
                         self.reg.truncate(out.0 as usize);
                         matched.for_each(|id| self.reg.push(id));
                         self.run(egraph, remaining_instructions, subst, yield_fn)
                     });
                 }
+                Instruction::BindOp {
+                    arity,
+                    op,
+                    bound,
+                    i,
+                    out,
+                } => {
+                    let remaining_instructions = instructions.as_slice();
+                    let eclass = &egraph[self.reg(*i)];
+                    let take = if self.canonical_root && i.0 == 0 {
+                        1
+                    } else {
+                        usize::MAX
+                    };
+                    for node in eclass.iter().filter(|n| n.len() == *arity).take(take) {
+                        let witness = if *bound {
+                            let witness = self.ops[*op];
+                            if !egraph.id_to_node(witness).matches(node) {
+                                continue;
+                            }
+                            witness
+                        } else {
+                            // any e-node with the same operator will do,
+                            // and one of them is a parent of the first child
+                            let first = egraph[node.children()[0]].parents();
+                            match first
+                                .into_iter()
+                                .find(|&p| egraph.id_to_node(p).matches(node))
+                            {
+                                Some(witness) => witness,
+                                None => continue,
+                            }
+                        };
+                        self.ops.truncate(*op);
+                        self.ops.push(witness);
+                        self.reg.truncate(out.0 as usize);
+                        node.for_each(|id| self.reg.push(id));
+                        self.run(egraph, remaining_instructions, subst, yield_fn)?;
+                    }
+                    return Ok(());
+                }
                 Instruction::Scan { out } => {
                     let remaining_instructions = instructions.as_slice();
                     for class in egraph.classes() {
@@ -71,11 +203,37 @@ impl Machine {
                     }
                     return Ok(());
                 }
+                Instruction::ScanParents { of, out } => {
+                    let remaining_instructions = instructions.as_slice();
+                    let mut parents: Vec<Id> = egraph[self.reg(*of)]
+                        .parents()
+                        .map(|p| egraph.find(p))
+                        .collect();
+                    parents.sort_unstable();
+                    parents.dedup();
+                    for parent in parents {
+                        self.reg.truncate(out.0 as usize);
+                        self.reg.push(parent);
+                        self.run(egraph, remaining_instructions, subst, yield_fn)?
+                    }
+                    return Ok(());
+                }
                 Instruction::Compare { i, j } => {
                     if egraph.find(self.reg(*i)) != egraph.find(self.reg(*j)) {
                         return Ok(());
                     }
                 }
+                Instruction::CheckSort { i, sort } => {
+                    let data = &egraph[self.reg(*i)].data;
+                    if !egraph.analysis.has_sort(data, sort) {
+                        return Ok(());
+                    }
+                }
+                Instruction::CheckLeaf { i, pred } => {
+                    if !pred.matches::<N>(&egraph[self.reg(*i)]) {
+                        return Ok(());
+                    }
+                }
                 Instruction::Lookup { term, i } => {
                     self.lookup.clear();
                     for node in term {
@@ -107,11 +265,16 @@ impl Machine {
 
 struct Compiler<L> {
     v2r: IndexMap<Var, Reg>,
+    ops: IndexSet<Var>,
     free_vars: Vec<HashSet<Var>>,
     subtree_size: Vec<usize>,
-    todo_nodes: HashMap<(Id, Reg), L>,
+    todo_nodes: HashMap<(Id, Reg), ENodeOrVar<L>>,
     instructions: Vec<Instruction<L>>,
     next_reg: Reg,
+    leaf_predicates: Vec<(Var, LeafPredicate<L>)>,
+    // the registers before `next_reg` are bound before running, so even the
+    // first pattern must be scanned for
+    seeded: bool,
 }
 
 impl<L: Language> Compiler<L> {
@@ -120,9 +283,12 @@ impl<L: Language> Compiler<L> {
             free_vars: Default::default(),
             subtree_size: Default::default(),
             v2r: Default::default(),
+            ops: Default::default(),
             todo_nodes: Default::default(),
             instructions: Default::default(),
             next_reg: Reg(0),
+            leaf_predicates: Default::default(),
+            seeded: false,
         }
     }
 
@@ -132,11 +298,33 @@ impl<L: Language> Compiler<L> {
                 if let Some(&j) = self.v2r.get(v) {
                     self.instructions.push(Instruction::Compare { i: reg, j })
                 } else {
-                    self.v2r.insert(*v, reg);
+                    self.bind_var(*v, reg);
                 }
             }
-            ENodeOrVar::ENode(pat) => {
-                self.todo_nodes.insert((id, reg), pat.clone());
+            // matches anything, so there is nothing to check
+            ENodeOrVar::Wildcard => {}
+            node => {
+                self.todo_nodes.insert((id, reg), node.clone());
+            }
+        }
+    }
+
+    fn is_bound(&self, v: &Var) -> bool {
+        self.v2r.contains_key(v) || self.ops.contains(v)
+    }
+
+    fn bind_var(&mut self, v: Var, reg: Reg) {
+        self.v2r.insert(v, reg);
+        if let Some(sort) = v.sort() {
+            let sort = sort.with_str(|s| s.into());
+            self.instructions
+                .push(Instruction::CheckSort { i: reg, sort });
+        }
+        for (var, pred) in &self.leaf_predicates {
+            if *var == v {
+                let pred = pred.clone();
+                self.instructions
+                    .push(Instruction::CheckLeaf { i: reg, pred });
             }
         }
     }
@@ -160,13 +348,27 @@ impl<L: Language> Compiler<L> {
                 ENodeOrVar::Var(v) => {
                     free.insert(*v);
                 }
+                ENodeOrVar::Wildcard => {}
+                ENodeOrVar::OpVar(v, children) => {
+                    assert!(
+                        !children.is_empty(),
+                        "Operator var '{}' needs at least one child",
+                        v
+                    );
+                    free.insert(*v);
+                    size = 1;
+                    for &child in children {
+                        free.extend(&self.free_vars[usize::from(child)]);
+                        size += self.subtree_size[usize::from(child)];
+                    }
+                }
             }
             self.free_vars.push(free);
             self.subtree_size.push(size);
         }
     }
 
-    fn next(&mut self) -> Option<((Id, Reg), L)> {
+    fn next(&mut self) -> Option<((Id, Reg), ENodeOrVar<L>)> {
         // we take the max todo according to this key
         // - prefer grounded
         // - prefer more free variables
@@ -175,7 +377,7 @@ impl<L: Language> Compiler<L> {
             let i = usize::from(*id);
             let n_bound = self.free_vars[i]
                 .iter()
-                .filter(|v| self.v2r.contains_key(*v))
+                .filter(|v| self.is_bound(v))
                 .count();
             let n_free = self.free_vars[i].len() - n_bound;
             let size = self.subtree_size[i] as isize;
@@ -194,7 +396,7 @@ impl<L: Language> Compiler<L> {
     fn is_ground_now(&self, id: Id) -> bool {
         self.free_vars[usize::from(id)]
             .iter()
-            .all(|v| self.v2r.contains_key(v))
+            .all(|v| self.is_bound(v))
     }
 
     fn compile(&mut self, patternbinder: Option<Var>, pattern: &PatternAst<L>) {
@@ -206,7 +408,25 @@ impl<L: Language> Compiler<L> {
         // Check if patternbinder already bound in v2r
         // Behavior common to creating a new pattern
         let add_new_pattern = |comp: &mut Compiler<L>| {
-            if !comp.instructions.is_empty() {
+            if comp.seeded {
+                // the root is a parent of any bound child
+                let bound_child = match &pattern[root] {
+                    ENodeOrVar::ENode(node) => {
+                        node.children().iter().find_map(|&c| match &pattern[c] {
+                            ENodeOrVar::Var(v) => comp.v2r.get(v).copied(),
+                            _ => None,
+                        })
+                    }
+                    _ => None,
+                };
+                comp.instructions.push(match bound_child {
+                    Some(of) => Instruction::ScanParents {
+                        of,
+                        out: comp.next_reg,
+                    },
+                    None => Instruction::Scan { out: comp.next_reg },
+                });
+            } else if !comp.instructions.is_empty() {
                 // After first pattern needs scan
                 comp.instructions
                     .push(Instruction::Scan { out: comp.next_reg });
@@ -222,7 +442,7 @@ impl<L: Language> Compiler<L> {
                 // patternbinder is new variable
                 next_out.0 += 1;
                 add_new_pattern(self);
-                self.v2r.insert(v, self.next_reg); //add to known variables.
+                self.bind_var(v, self.next_reg); //add to known variables.
             }
         } else {
             // No pattern binder
@@ -231,8 +451,38 @@ impl<L: Language> Compiler<L> {
         }
 
         while let Some(((id, reg), node)) = self.next() {
-            if self.is_ground_now(id) && !node.is_leaf() {
-                let extracted = pattern.extract(id);
+            let node = match node {
+                ENodeOrVar::ENode(node) => node,
+                ENodeOrVar::OpVar(v, children) => {
+                    let out = next_out;
+                    next_out.0 += children.len() as u32;
+                    let (op, bound) = match self.ops.get_index_of(&v) {
+                        Some(op) => (op, true),
+                        None => (self.ops.insert_full(v).0, false),
+                    };
+                    self.instructions.push(Instruction::BindOp {
+                        arity: children.len(),
+                        op,
+                        bound,
+                        i: reg,
+                        out,
+                    });
+                    for (i, &child) in children.iter().enumerate() {
+                        self.add_todo(pattern, child, Reg(out.0 + i as u32));
+                    }
+                    continue;
+                }
+                ENodeOrVar::Var(_) | ENodeOrVar::Wildcard => {
+                    unreachable!("vars are never todo")
+                }
+            };
+
+            let extracted = pattern.extract(id);
+            // wildcards can't be looked up, since they aren't bound
+            let has_vars = extracted
+                .iter()
+                .any(|n| matches!(n, ENodeOrVar::OpVar(..) | ENodeOrVar::Wildcard));
+            if self.is_ground_now(id) && !node.is_leaf() && !has_vars {
                 self.instructions.push(Instruction::Lookup {
                     i: reg,
                     term: extracted
@@ -240,6 +490,7 @@ impl<L: Language> Compiler<L> {
                         .map(|n| match n {
                             ENodeOrVar::ENode(n) => ENodeOrReg::ENode(n.clone()),
                             ENodeOrVar::Var(v) => ENodeOrReg::Reg(self.v2r[v]),
+                            ENodeOrVar::OpVar(..) | ENodeOrVar::Wildcard => unreachable!(),
                         })
                         .collect(),
                 });
@@ -271,15 +522,24 @@ impl<L: Language> Compiler<L> {
         Program {
             instructions: self.instructions,
             subst,
+            ops: self.ops.into_iter().collect(),
+            ast: Default::default(),
+            leaf_predicates: self.leaf_predicates,
+            canonical_root: false,
         }
     }
 }
 
 impl<L: Language> Program<L> {
-    pub(crate) fn compile_from_pat(pattern: &PatternAst<L>) -> Self {
+    pub(crate) fn compile_from_pat(
+        pattern: &PatternAst<L>,
+        leaf_predicates: &[(Var, LeafPredicate<L>)],
+    ) -> Self {
         let mut compiler = Compiler::new();
+        compiler.leaf_predicates = leaf_predicates.to_vec();
         compiler.compile(None, pattern);
-        let program = compiler.extract();
+        let mut program = compiler.extract();
+        program.ast = pattern.clone();
         log::debug!("Compiled {:?} to {:?}", pattern.as_ref(), program);
         program
     }
@@ -292,26 +552,173 @@ impl<L: Language> Program<L> {
         compiler.extract()
     }
 
-    pub fn run_with_limit<A>(
+    /// Compiles a multi-pattern to be run by
+    /// [`run_seeded`](Program::run_seeded), with the `seeds` already bound.
+    pub(crate) fn compile_seeded(patterns: &[(Var, PatternAst<L>)], seeds: &[Var]) -> Self {
+        let mut compiler = Compiler::new();
+        compiler.seeded = true;
+        for &v in seeds {
+            compiler.v2r.insert(v, compiler.next_reg);
+            compiler.next_reg.0 += 1;
+        }
+        for (var, pattern) in patterns {
+            compiler.compile(Some(*var), pattern);
+        }
+        compiler.extract()
+    }
+
+    /// Checks whether a program from
+    /// [`compile_seeded`](Program::compile_seeded) matches with its seeds
+    /// bound to `seeds`, stopping at the first match.
+    ///
+    /// Unlike [`run_with_limit`](Program::run_with_limit), this does not
+    /// need a clean e-graph, but may miss matches that only appear once it
+    /// is rebuilt.
+    pub(crate) fn run_seeded<A>(&self, egraph: &EGraph<L, A>, seeds: &[Id]) -> bool
+    where
+        A: Analysis<L>,
+    {
+        let mut machine = Machine::default();
+        machine.reg.extend(seeds.iter().map(|&id| egraph.find(id)));
+        machine
+            .run(egraph, &self.instructions, &self.subst, &mut |_, _| Err(()))
+            .is_err()
+    }
+
+    /// Compiles `pattern` to be run by [`run_grouped`](Program::run_grouped),
+    /// with `var` already bound.
+    ///
+    /// Returns `None` unless `var` is a child of the root e-node, so that
+    /// the root is always among the parents of the e-class bound to `var`.
+    pub(crate) fn compile_grouped(
+        pattern: &PatternAst<L>,
+        leaf_predicates: &[(Var, LeafPredicate<L>)],
+        var: Var,
+    ) -> Option<Self> {
+        match &pattern[pattern.root()] {
+            ENodeOrVar::ENode(node)
+                if node
+                    .children()
+                    .iter()
+                    .any(|&c| pattern[c] == ENodeOrVar::Var(var)) => {}
+            _ => return None,
+        }
+        let mut compiler = Compiler::new();
+        compiler.seeded = true;
+        compiler.leaf_predicates = leaf_predicates.to_vec();
+        compiler.bind_var(var, compiler.next_reg);
+        compiler.next_reg.0 += 1;
+        compiler.compile(None, pattern);
+        Some(compiler.extract())
+    }
+
+    /// Finds the matches of a program from
+    /// [`compile_grouped`](Program::compile_grouped) with its variable bound
+    /// to `eclass`, as the matched e-class and the substitution.
+    pub(crate) fn run_grouped<A>(&self, egraph: &EGraph<L, A>, eclass: Id) -> Vec<(Id, Subst)>
+    where
+        A: Analysis<L>,
+    {
+        assert!(egraph.clean, "Tried to search a dirty e-graph!");
+
+        // the root is right after the bound variable
+        let root = Reg(1);
+        let mut matches = IndexSet::default();
+        let mut machine = Machine::default();
+        machine.reg.push(egraph.find(eclass));
+        machine
+            .run(
+                egraph,
+                &self.instructions,
+                &self.subst,
+                &mut |machine, subst| {
+                    let matched = machine.reg(root);
+                    if !egraph.analysis.allow_ematching_cycles() {
+                        let mut others = machine.reg.iter().enumerate();
+                        if others.any(|(i, &id)| i != root.0 as usize && id == matched) {
+                            return Ok(());
+                        }
+                    }
+                    matches.insert((matched, self.read_subst(machine, subst)));
+                    Ok(())
+                },
+            )
+            .unwrap_or_default();
+        matches.into_iter().collect()
+    }
+
+    /// Reads the substitution of `self.subst`, which maps each variable to
+    /// its register, out of `machine`.
+    fn read_subst(&self, machine: &Machine, subst: &Subst) -> Subst {
+        let vec = subst
+            .vec
+            .iter()
+            // HACK we are reusing Ids here, this is bad
+            .map(|(v, reg_id)| (*v, machine.reg(Reg(usize::from(*reg_id) as u32))))
+            .collect();
+        let ops = if self.ops.is_empty() {
+            None
+        } else {
+            let ops = self.ops.iter().copied().zip(machine.ops.iter().copied());
+            Some(Box::new(ops.collect()))
+        };
+        Subst { vec, ops }
+    }
+
+    pub(crate) fn run_with_limit<A>(
         &self,
         egraph: &EGraph<L, A>,
         eclass: Id,
-        mut limit: usize,
+        limit: usize,
     ) -> Vec<Subst>
     where
         A: Analysis<L>,
+    {
+        let mut matches = Vec::new();
+        self.run_each(egraph, eclass, limit, |subst| {
+            matches.push(subst);
+            true
+        });
+        log::trace!("Ran program, found {:?}", matches);
+        matches
+    }
+
+    /// Like [`run_with_limit`](Program::run_with_limit) without a limit,
+    /// but drops the substitutions already found as soon as they are.
+    pub(crate) fn run_deduped<A>(&self, egraph: &EGraph<L, A>, eclass: Id) -> Vec<Subst>
+    where
+        A: Analysis<L>,
+    {
+        let mut matches = IndexSet::default();
+        self.run_each(egraph, eclass, usize::MAX, |subst| matches.insert(subst));
+        log::trace!("Ran program, found {:?}", matches);
+        matches.into_iter().collect()
+    }
+
+    /// Calls `push` on each match in `eclass`, stopping after `limit` of
+    /// them were kept, i.e. `push` returned `true`.
+    fn run_each<A>(
+        &self,
+        egraph: &EGraph<L, A>,
+        eclass: Id,
+        mut limit: usize,
+        mut push: impl FnMut(Subst) -> bool,
+    ) where
+        A: Analysis<L>,
     {
         assert!(egraph.clean, "Tried to search a dirty e-graph!");
 
         if limit == 0 {
-            return vec![];
+            return;
         }
 
-        let mut machine = Machine::default();
+        let mut machine = Machine {
+            canonical_root: self.canonical_root,
+            ..Machine::default()
+        };
         assert_eq!(machine.reg.len(), 0);
         machine.reg.push(eclass);
 
-        let mut matches = Vec::new();
         machine
             .run(
                 egraph,
@@ -326,14 +733,10 @@ impl<L: Language> Program<L> {
                         }
                     }
 
-                    let subst_vec = subst
-                        .vec
-                        .iter()
-                        // HACK we are reusing Ids here, this is bad
-                        .map(|(v, reg_id)| (*v, machine.reg(Reg(usize::from(*reg_id) as u32))))
-                        .collect();
-                    matches.push(Subst { vec: subst_vec });
-                    limit -= 1;
+                    let kept = push(self.read_subst(machine, subst));
+                    if kept {
+                        limit -= 1;
+                    }
                     if limit != 0 {
                         Ok(())
                     } else {
@@ -342,8 +745,5 @@ impl<L: Language> Program<L> {
                 },
             )
             .unwrap_or_default();
-
-        log::trace!("Ran program, found {:?}", matches);
-        matches
     }
 }
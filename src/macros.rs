@@ -21,6 +21,13 @@ macro.
 Presently, the macro does not support data variant with children, but that may
 be added later.
 
+A string variant with children can be followed by `with ctor, getter`
+to also generate an inherent constructor `ctor(Vec<Id>) -> Self` and an
+accessor `getter(&self) -> Option<&[Id]>` returning the children of
+that variant, which is mostly handy for variadic `Box<[Id]>` variants.
+The constructor panics if the variant can't have that many children,
+see [`LanguageChildren::from_vec`].
+
 # Example
 
 The following macro invocation shows the the accepted forms of variants:
@@ -40,6 +47,8 @@ define_language! {
         // can also do a variable number of children in a boxed slice
         // this will only match if the lengths are the same
         "list" = List(Box<[Id]>),
+        // generates `SimpleLanguage::vec` and `SimpleLanguage::as_vec`
+        "vec" = Vector(Box<[Id]>) with vec, as_vec,
 
         // string variants with a single child `Id`
         // note that this is distinct from `Sub`, even though it has the same
@@ -58,6 +67,17 @@ define_language! {
         Other(Symbol, Vec<Id>),
     }
 }
+
+// build `(vec a b c)` with the generated constructor
+let mut expr = RecExpr::default();
+let children = ["a", "b", "c"]
+    .iter()
+    .map(|s| expr.add(SimpleLanguage::Symbol(s.parse().unwrap())))
+    .collect();
+let vec = expr.add(SimpleLanguage::vec(children));
+assert_eq!(expr.to_string(), "(vec a b c)");
+assert_eq!(expr[vec].as_vec().map(|ids| ids.len()), Some(3));
+assert_eq!(expr[Id::from(0)].as_vec(), None);
 ```
 
 It is also possible to define languages that are generic over some bounded type.
@@ -102,7 +122,7 @@ macro_rules! define_language {
         $crate::__define_language!(
             $(#[$meta])* $vis enum $name [$($($gen),*)?] { $($variants)* }
             [$($($where)*)?]
-            -> {} {} {} {} {} {}
+            -> {} {} {} {} {} {} {} {}
         );
     };
 }
@@ -114,7 +134,7 @@ macro_rules! __define_language {
      [$($where:tt)*]
      ->
      $decl:tt {$($matches:tt)*} $children:tt $children_mut:tt
-     $display:tt {$($from_op:tt)*}
+     $display:tt {$($from_op:tt)*} $op_str:tt {$($helpers:tt)*}
     ) => {
         $(#[$meta])*
         #[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Hash, Clone)]
@@ -136,6 +156,8 @@ macro_rules! __define_language {
 
             fn children(&self) -> &[$crate::Id] { match self $children }
             fn children_mut(&mut self) -> &mut [$crate::Id] { match self $children_mut }
+
+            fn op_str(&self) -> ::std::option::Option<&str> { match self $op_str }
         }
 
         impl<$($gen),*> ::std::fmt::Display for $name <$($gen),*> where $($where)* {
@@ -156,6 +178,10 @@ macro_rules! __define_language {
                 }
             }
         }
+
+        impl<$($gen),*> $name <$($gen),*> where $($where)* {
+            $($helpers)*
+        }
     };
 
     ($(#[$meta:meta])* $vis:vis enum $name:ident [$($gen:ident),*]
@@ -166,7 +192,7 @@ macro_rules! __define_language {
      [$($where:tt)*]
      ->
      { $($decl:tt)* } { $($matches:tt)* } { $($children:tt)* } { $($children_mut:tt)* }
-     { $($display:tt)* } { $($from_op:tt)* }
+     { $($display:tt)* } { $($from_op:tt)* } { $($op_str:tt)* } { $($helpers:tt)* }
     ) => {
         $crate::__define_language!(
             $(#[$meta])* $vis enum $name [$($gen),*]
@@ -179,6 +205,44 @@ macro_rules! __define_language {
             { $($children_mut)*  $name::$variant => &mut [], }
             { $($display)*       ($name::$variant, f) => f.write_str($string), }
             { $($from_op)*       ($string, children) if children.is_empty() => Ok($name::$variant), }
+            { $($op_str)*        $name::$variant => Some($string), }
+            { $($helpers)* }
+        );
+    };
+
+    ($(#[$meta:meta])* $vis:vis enum $name:ident [$($gen:ident),*]
+     {
+         $string:literal = $variant:ident ($ids:ty) with $ctor:ident, $getter:ident,
+         $($variants:tt)*
+     }
+     [$($where:tt)*]
+     ->
+     $decl:tt $matches:tt $children:tt $children_mut:tt
+     $display:tt $from_op:tt $op_str:tt { $($helpers:tt)* }
+    ) => {
+        $crate::__define_language!(
+            $(#[$meta])* $vis enum $name [$($gen),*]
+            { $string = $variant($ids), $($variants)* }
+            [$($where)*]
+            ->
+            $decl $matches $children $children_mut
+            $display $from_op $op_str
+            { $($helpers)*
+              #[doc = concat!("Creates a `", $string, "` node with the given children.")]
+              #[allow(dead_code)]
+              $vis fn $ctor(children: ::std::vec::Vec<$crate::Id>) -> Self {
+                  $name::$variant(<$ids as $crate::LanguageChildren>::from_vec(children))
+              }
+              #[doc = concat!("Returns the children if this is a `", $string, "` node.")]
+              #[allow(dead_code)]
+              $vis fn $getter(&self) -> ::std::option::Option<&[$crate::Id]> {
+                  match self {
+                      $name::$variant(ids) => Some($crate::LanguageChildren::as_slice(ids)),
+                      #[allow(unreachable_patterns)]
+                      _ => None,
+                  }
+              }
+            }
         );
     };
 
@@ -190,7 +254,7 @@ macro_rules! __define_language {
      [$($where:tt)*]
      ->
      { $($decl:tt)* } { $($matches:tt)* } { $($children:tt)* } { $($children_mut:tt)* }
-     { $($display:tt)* } { $($from_op:tt)* }
+     { $($display:tt)* } { $($from_op:tt)* } { $($op_str:tt)* } { $($helpers:tt)* }
     ) => {
         $crate::__define_language!(
             $(#[$meta])* $vis enum $name [$($gen),*]
@@ -207,6 +271,8 @@ macro_rules! __define_language {
                   Ok($name::$variant(children))
               },
             }
+            { $($op_str)*        $name::$variant(..) => Some($string), }
+            { $($helpers)* }
         );
     };
 
@@ -218,7 +284,7 @@ macro_rules! __define_language {
      [$($where:tt)*]
      ->
      { $($decl:tt)* } { $($matches:tt)* } { $($children:tt)* } { $($children_mut:tt)* }
-     { $($display:tt)* } { $($from_op:tt)* }
+     { $($display:tt)* } { $($from_op:tt)* } { $($op_str:tt)* } { $($helpers:tt)* }
     ) => {
         $crate::__define_language!(
             $(#[$meta])* $vis enum $name [$($gen),*]
@@ -231,6 +297,8 @@ macro_rules! __define_language {
             { $($children_mut)*  $name::$variant(_data) => &mut [], }
             { $($display)*       ($name::$variant(data), f) => ::std::fmt::Display::fmt(data, f), }
             { $($from_op)*       (op, children) if op.parse::<$data>().is_ok() && children.is_empty() => Ok($name::$variant(op.parse().unwrap())), }
+            { $($op_str)*        $name::$variant(..) => None, }
+            { $($helpers)* }
         );
     };
 
@@ -242,7 +310,7 @@ macro_rules! __define_language {
      [$($where:tt)*]
      ->
      { $($decl:tt)* } { $($matches:tt)* } { $($children:tt)* } { $($children_mut:tt)* }
-     { $($display:tt)* } { $($from_op:tt)* }
+     { $($display:tt)* } { $($from_op:tt)* } { $($op_str:tt)* } { $($helpers:tt)* }
     ) => {
         $crate::__define_language!(
             $(#[$meta])* $vis enum $name [$($gen)*]
@@ -260,6 +328,8 @@ macro_rules! __define_language {
                   Ok($name::$variant(data, children))
               },
             }
+            { $($op_str)*        $name::$variant(..) => None, }
+            { $($helpers)* }
         );
     };
 }
@@ -287,6 +357,8 @@ where the given expression should implement [`Condition`].
 For each of these, the macro will wrap the given applier in a
 [`ConditionalApplier`] with the given condition, with the first condition being
 the outermost, and the last condition being the innermost.
+So the conditions are ANDed together: the rule only fires if all of them
+hold, and they are checked in order until one fails.
 
 # Example
 ```
@@ -426,6 +498,15 @@ mod tests {
         }
     }
 
+    #[test]
+    fn op_str() {
+        let add = Simple::Add([0.into(), 0.into()]);
+        assert_eq!(add.op_str(), Some("+"));
+        assert_eq!(Simple::Neg(0.into()).op_str(), Some("-"));
+        assert_eq!(Simple::Pi.op_str(), Some("pi"));
+        assert_eq!(Simple::Int(5).op_str(), None);
+    }
+
     #[test]
     fn modify_children() {
         let mut add = Simple::Add([0.into(), 0.into()]);
@@ -450,6 +531,33 @@ mod tests {
         let _: Rewrite<Simple, ()> = rewrite!("bad"; "?a" => "?x");
     }
 
+    #[test]
+    fn rewrite_conditions_and_together() {
+        use std::sync::atomic::{AtomicUsize, Ordering};
+        static CHECKED: AtomicUsize = AtomicUsize::new(0);
+        let is = |op: &'static str| {
+            move |egraph: &mut EGraph<Simple, ()>, _: Id, subst: &Subst| {
+                CHECKED.fetch_add(1, Ordering::Relaxed);
+                let b = subst["?b".parse().unwrap()];
+                egraph[b].iter().any(|n| n.op_str() == Some(op))
+            }
+        };
+        let rules: Vec<Rewrite<Simple, ()>> = vec![
+            rewrite!("both"; "(+ ?a ?b)" => "(* ?a ?b)" if is("pi") if is("pi")),
+            rewrite!("never"; "(+ ?a ?b)" => "(- ?a ?b)" if is("list") if is("pi")),
+        ];
+        let runner = Runner::default()
+            .with_iter_limit(1)
+            .with_expr(&"(+ 1 pi)".parse().unwrap())
+            .run(&rules);
+
+        let egraph = &runner.egraph;
+        assert!(egraph.lookup_expr(&"(* 1 pi)".parse().unwrap()).is_some());
+        assert!(egraph.lookup_expr(&"(- 1 pi)".parse().unwrap()).is_none());
+        // the second condition of "never" is not checked
+        assert_eq!(CHECKED.load(Ordering::Relaxed), 3);
+    }
+
     #[test]
     #[should_panic(expected = "refers to unbound var ?x")]
     fn rewrite_conditional_panic() {
@@ -56,6 +56,12 @@ impl<L: Language> MultiPattern<L> {
         let program = machine::Program::compile_from_multi_pat(&asts);
         Self { asts, program }
     }
+
+    /// Compiles this multipattern to be searched with `seeds` already
+    /// bound, see [`machine::Program::run_seeded`].
+    pub(crate) fn compile_seeded(&self, seeds: &[Var]) -> machine::Program<L> {
+        machine::Program::compile_seeded(&self.asts, seeds)
+    }
 }
 
 #[derive(Debug, Error)]
@@ -111,7 +117,7 @@ impl<L: Language, A: Analysis<L>> Searcher<L, A> for MultiPattern<L> {
         match self.asts.as_slice() {
             [] => panic!("empty multipattern"),
             [(_var, pat), ..] => {
-                if let [ENodeOrVar::Var(_)] = **pat {
+                if let [ENodeOrVar::Var(_) | ENodeOrVar::Wildcard] = **pat {
                     panic!(
                         "Bare cannot be first pattern variable in multipattern: {:?}",
                         self.asts
@@ -136,7 +142,7 @@ impl<L: Language, A: Analysis<L>> Searcher<L, A> for MultiPattern<L> {
         for (v, pat) in &self.asts {
             vars.push(*v);
             for n in pat {
-                if let ENodeOrVar::Var(v) = n {
+                if let ENodeOrVar::Var(v) | ENodeOrVar::OpVar(v, _) = n {
                     vars.push(*v)
                 }
             }
@@ -147,6 +153,22 @@ impl<L: Language, A: Analysis<L>> Searcher<L, A> for MultiPattern<L> {
     }
 }
 
+impl<L: Language> MultiPattern<L> {
+    /// Checks that every pattern can be instantiated, given `subst` and the
+    /// variables bound by the patterns before it, so that applying never
+    /// stops halfway through.
+    fn check_bound(&self, subst: &Subst, placeholder: Id) -> Result<(), MissingVar> {
+        let mut subst = subst.clone();
+        for (v, p) in &self.asts {
+            crate::pattern::check_bound(p, &subst)?;
+            if subst.get(*v).is_none() {
+                subst.insert(*v, placeholder);
+            }
+        }
+        Ok(())
+    }
+}
+
 impl<L: Language, A: Analysis<L>> Applier<L, A> for MultiPattern<L> {
     fn apply_one(
         &self,
@@ -163,16 +185,20 @@ impl<L: Language, A: Analysis<L>> Applier<L, A> for MultiPattern<L> {
         &self,
         egraph: &mut EGraph<L, A>,
         matches: &[SearchMatches<L>],
-        _rule_name: Symbol,
+        rule_name: Symbol,
     ) -> Vec<Id> {
         // TODO explanations?
         // the ids returned are kinda garbage
         let mut added = vec![];
         for mat in matches {
             for subst in &mat.substs {
+                if let Err(e) = self.check_bound(subst, mat.eclass) {
+                    log::warn!("Skipping a match of {}: {}", rule_name, e);
+                    continue;
+                }
                 let mut subst = subst.clone();
                 let mut id_buf = vec![];
-                for (i, (v, p)) in self.asts.iter().enumerate() {
+                for (v, p) in &self.asts {
                     id_buf.resize(p.len(), 0.into());
                     let id1 = crate::pattern::apply_pat(&mut id_buf, p, egraph, &subst);
 
@@ -193,7 +219,7 @@ impl<L: Language, A: Analysis<L>> Applier<L, A> for MultiPattern<L> {
         let mut vars = vec![];
         for (bv, pat) in &self.asts {
             for n in pat {
-                if let ENodeOrVar::Var(v) = n {
+                if let ENodeOrVar::Var(v) | ENodeOrVar::OpVar(v, _) = n {
                     // using vars that are already bound doesn't count
                     if !bound_vars.contains(v) {
                         vars.push(*v)
@@ -316,4 +342,23 @@ mod tests {
         let rw = multi_rewrite!("r"; { p } => "?a = (g ?x ?y)");
         rw.run(&mut g);
     }
+
+    #[test]
+    fn missing_var_applies_nothing() {
+        let mut g = EGraph::default();
+        let a = g.add_string("a");
+
+        // `?b` is unbound, so not even the first pattern may be added
+        let mp: MultiPattern<S> = "?x = (f ?a), ?y = (g ?b)".parse().unwrap();
+        let mut subst = Subst::default();
+        subst.insert("?a".parse().unwrap(), a);
+        let matches = [SearchMatches {
+            eclass: a,
+            substs: vec![subst],
+            ast: None,
+        }];
+        let added = mp.apply_matches(&mut g, &matches, Symbol::from("r"));
+        assert!(added.is_empty());
+        assert_eq!(g.total_size(), 1);
+    }
 }
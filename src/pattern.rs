@@ -73,6 +73,15 @@ pub struct Pattern<L> {
 /// [`Pattern`].
 pub type PatternAst<L> = RecExpr<ENodeOrVar<L>>;
 
+/// A partial program: a [`RecExpr`] that may contain holes.
+///
+/// This is the same type as a [`PatternAst`], so a hole is just a
+/// [`Var`], found holes can be bound by searching it as a [`Pattern`],
+/// and [`fill`](PatternAst::fill) turns it into a complete term.
+/// [`EGraph::add_partial_expr`] adds one to an e-graph with fresh e-classes
+/// for the holes.
+pub type PartialExpr<L> = PatternAst<L>;
+
 impl<L: Language> PatternAst<L> {
     /// Returns a new `PatternAst` with the variables renames canonically
     pub fn alpha_rename(&self) -> Self {
@@ -89,11 +98,16 @@ impl<L: Language> PatternAst<L> {
 
         for n in self {
             new.add(match n {
-                ENodeOrVar::ENode(_) => n.clone(),
+                ENodeOrVar::ENode(_) | ENodeOrVar::Wildcard => n.clone(),
                 ENodeOrVar::Var(v) => {
                     let i = vars.len();
                     ENodeOrVar::Var(*vars.entry(*v).or_insert_with(|| mkvar(i)))
                 }
+                ENodeOrVar::OpVar(v, children) => {
+                    let i = vars.len();
+                    let v = *vars.entry(*v).or_insert_with(|| mkvar(i));
+                    ENodeOrVar::OpVar(v, children.clone())
+                }
             });
         }
 
@@ -106,6 +120,58 @@ impl<L: Language> PatternAst<L> {
         apply_pat(&mut id_buf, self, egraph, subst)
     }
 
+    /// Fills the holes of this [`PartialExpr`] with terms from `egraph`,
+    /// returning `None` if `subst` doesn't bind all of them.
+    ///
+    /// Each hole is replaced by the smallest term (by [`AstSize`]) of the
+    /// e-class `subst` binds it to, and operator variables by the operator
+    /// they are bound to.
+    /// This runs an [`Extractor`] over the whole e-graph, so fill many
+    /// holes at once rather than one at a time.
+    ///
+    /// # Example
+    /// ```
+    /// # use egg::*;
+    /// let mut egraph = EGraph::<SymbolLang, ()>::default();
+    /// egraph.add_expr(&"(* (+ x 1) 2)".parse().unwrap());
+    /// egraph.rebuild();
+    ///
+    /// let sketch: PartialExpr<SymbolLang> = "(* ?a 2)".parse().unwrap();
+    /// let pattern = Pattern::from(sketch.clone());
+    /// let matches = pattern.search(&egraph);
+    /// let filled = sketch.fill(&egraph, &matches[0].substs[0]).unwrap();
+    /// assert_eq!(filled.to_string(), "(* (+ x 1) 2)");
+    /// assert!(sketch.fill(&egraph, &Subst::default()).is_none());
+    /// ```
+    pub fn fill<A: Analysis<L>>(&self, egraph: &EGraph<L, A>, subst: &Subst) -> Option<RecExpr<L>> {
+        let extractor = Extractor::new(egraph, AstSize);
+        let mut expr = RecExpr::default();
+        let mut ids: Vec<Id> = Vec::with_capacity(self.len());
+        for node in self {
+            let id = match node {
+                ENodeOrVar::Var(v) => {
+                    let (_, term) = extractor.find_best(*subst.get(*v)?);
+                    let mut term_ids: Vec<Id> = Vec::with_capacity(term.len());
+                    for n in term {
+                        let n = n.map_children(|c| term_ids[usize::from(c)]);
+                        term_ids.push(expr.add(n));
+                    }
+                    *term_ids.last().unwrap()
+                }
+                ENodeOrVar::ENode(n) => expr.add(n.clone().map_children(|c| ids[usize::from(c)])),
+                ENodeOrVar::OpVar(v, children) => {
+                    let node = egraph.id_to_node(subst.get_op(*v)?).clone();
+                    let mut children = children.iter();
+                    let n = node.map_children(|_| ids[usize::from(*children.next().unwrap())]);
+                    expr.add(n)
+                }
+                ENodeOrVar::Wildcard => return None,
+            };
+            ids.push(id);
+        }
+        Some(expr)
+    }
+
     /// apply a substition (i.e., a map from [Var] back to [PatternAst]) to `self`
     pub fn apply_pattern_subst(self, subst: Vec<(Var, Self)>) -> Self {
         // insert the "new" terms at the begining, keep track of their index in `ret`
@@ -124,9 +190,7 @@ impl<L: Language> PatternAst<L> {
 
             // update the offset
             for e in &mut ret[n..] {
-                if let ENodeOrVar::ENode(fun) = e {
-                    fun.update_children(|id| (usize::from(id) + n).into())
-                }
+                e.update_children(|id| (usize::from(id) + n).into())
             }
             // the head is actually used in place of the variables
             let head = ret.pop().unwrap();
@@ -141,14 +205,15 @@ impl<L: Language> PatternAst<L> {
 
             for e in &mut ret[n..] {
                 match e {
-                    ENodeOrVar::ENode(fun) => {
-                        fun.update_children(|id| (usize::from(id) + n).into())
+                    ENodeOrVar::ENode(_) | ENodeOrVar::OpVar(..) => {
+                        e.update_children(|id| (usize::from(id) + n).into())
                     }
                     ENodeOrVar::Var(v) => {
                         if let Some(head) = idx_map.get(v) {
                             *e = head.clone()
                         }
                     }
+                    ENodeOrVar::Wildcard => {}
                 }
             }
         }
@@ -160,15 +225,141 @@ impl<L: Language> Pattern<L> {
     /// Creates a new pattern from the given pattern ast.
     pub fn new(ast: PatternAst<L>) -> Self {
         let ast = ast.compact();
-        let program = machine::Program::compile_from_pat(&ast);
+        let program = machine::Program::compile_from_pat(&ast, &[]);
         Pattern { ast, program }
     }
 
-    /// Returns a list of the [`Var`]s in this pattern.
+    /// Returns the compiled e-matching program of this pattern, which can
+    /// be cached and turned back into a [`Pattern`] with
+    /// [`from_compiled`](Pattern::from_compiled()) to skip compiling it
+    /// again.
+    ///
+    /// # Example
+    /// ```
+    /// # use egg::*;
+    /// let pattern: Pattern<SymbolLang> = "(+ ?a (* ?b ?a))".parse().unwrap();
+    /// let program = pattern.compiled().clone();
+    ///
+    /// let again = Pattern::from_compiled(program);
+    /// assert_eq!(again, pattern);
+    /// assert_eq!(again.to_string(), "(+ ?a (* ?b ?a))");
+    /// ```
+    pub fn compiled(&self) -> &machine::Program<L> {
+        &self.program
+    }
+
+    /// Creates a pattern from a program returned by
+    /// [`compiled`](Pattern::compiled()), without compiling anything.
+    ///
+    /// The program remembers the ast and the
+    /// [leaf predicates](Pattern::with_leaf_predicate()) of the pattern
+    /// it comes from, so the result is equal to that pattern.
+    pub fn from_compiled(program: machine::Program<L>) -> Self {
+        Pattern {
+            ast: program.ast.clone(),
+            program,
+        }
+    }
+
+    /// Only match when the e-class bound to `var` contains a leaf e-node
+    /// satisfying `predicate`, e.g. a positive integer constant.
+    ///
+    /// The check runs inside the e-matching machine as soon as `var` is
+    /// bound, so failing matches are pruned early instead of being
+    /// filtered by a [`Condition`] afterwards.
+    /// Several predicates on the same variable must all hold, but each
+    /// may be satisfied by a different leaf.
+    /// Predicates only affect searching, not applying, and are not part
+    /// of the [`Display`] output of the pattern.
+    ///
+    /// # Panics
+    /// Panics if `var` is not a variable of this pattern.
+    ///
+    /// # Example
+    /// ```
+    /// use egg::*;
+    ///
+    /// define_language! {
+    ///     enum Math {
+    ///         Num(i32),
+    ///         "f" = F(Id),
+    ///         Symbol(Symbol),
+    ///     }
+    /// }
+    ///
+    /// let mut egraph = EGraph::<Math, ()>::default();
+    /// let pos = egraph.add_expr(&"(f 3)".parse().unwrap());
+    /// egraph.add_expr(&"(f -3)".parse().unwrap());
+    /// egraph.add_expr(&"(f x)".parse().unwrap());
+    /// egraph.rebuild();
+    ///
+    /// let c: Var = "?c".parse().unwrap();
+    /// let positive = "(f ?c)"
+    ///     .parse::<Pattern<Math>>()
+    ///     .unwrap()
+    ///     .with_leaf_predicate(c, |n| matches!(n, Math::Num(i) if *i > 0));
+    /// let matched: Vec<Id> = positive.search(&egraph).iter().map(|m| m.eclass).collect();
+    /// assert_eq!(matched, vec![pos]);
+    /// ```
+    pub fn with_leaf_predicate(
+        mut self,
+        var: Var,
+        predicate: impl Fn(&L) -> bool + Send + Sync + 'static,
+    ) -> Self {
+        assert!(
+            self.vars().contains(&var),
+            "Leaf predicate on {}, which is not a variable of the pattern",
+            var
+        );
+        let predicate = machine::LeafPredicate(std::sync::Arc::new(predicate));
+        let mut leaf_predicates = std::mem::take(&mut self.program.leaf_predicates);
+        leaf_predicates.push((var, predicate));
+        let canonical_root = self.program.canonical_root;
+        self.program = machine::Program::compile_from_pat(&self.ast, &leaf_predicates);
+        self.program.canonical_root = canonical_root;
+        self
+    }
+
+    /// Only match the root of this pattern against one e-node per e-class:
+    /// the first e-node, in the e-class's sorted order, with the root's
+    /// operator.
+    ///
+    /// **This makes searching incomplete.**
+    /// Matches rooted at the other e-nodes with that operator are missed,
+    /// even if they are the only ones whose children match the rest of the
+    /// pattern, so this is only sound for rules where any representative
+    /// will do, e.g. normalization rules whose results all end up in the
+    /// same e-class anyway.
+    /// In exchange, an e-class with many e-nodes of the same operator is
+    /// matched at most once per binding of the representative's children,
+    /// which can drastically cut the number of matches.
+    /// Only the root is affected; patterns rooted at a variable or made
+    /// only of ground terms are searched as usual.
+    ///
+    /// # Example
+    /// ```
+    /// use egg::*;
+    /// let mut egraph = EGraph::<SymbolLang, ()>::default();
+    /// let a = egraph.add_expr(&"(f a)".parse().unwrap());
+    /// let b = egraph.add_expr(&"(f b)".parse().unwrap());
+    /// egraph.union(a, b);
+    /// egraph.rebuild();
+    ///
+    /// let pat: Pattern<SymbolLang> = "(f ?x)".parse().unwrap();
+    /// assert_eq!(pat.n_matches(&egraph), 2);
+    /// assert_eq!(pat.with_canonical_root().n_matches(&egraph), 1);
+    /// ```
+    pub fn with_canonical_root(mut self) -> Self {
+        self.program.canonical_root = true;
+        self
+    }
+
+    /// Returns a list of the [`Var`]s in this pattern, including operator
+    /// variables.
     pub fn vars(&self) -> Vec<Var> {
         let mut vars = vec![];
         for n in &self.ast {
-            if let ENodeOrVar::Var(v) = n {
+            if let ENodeOrVar::Var(v) | ENodeOrVar::OpVar(v, _) = n {
                 if !vars.contains(v) {
                     vars.push(*v)
                 }
@@ -176,6 +367,100 @@ impl<L: Language> Pattern<L> {
         }
         vars
     }
+
+    /// Returns a copy of this pattern with its variables, including
+    /// operator variables, renamed according to `map`.
+    ///
+    /// Variables that are not keys of `map` are left as they are.
+    /// Leaf predicates and [`with_canonical_root`](Pattern::with_canonical_root)
+    /// carry over to the renamed pattern.
+    /// Mapping two variables to the same one makes them non-linear, so the
+    /// renamed pattern only matches where both were bound to the same
+    /// e-class.
+    ///
+    /// ```
+    /// use egg::*;
+    /// use std::collections::HashMap;
+    ///
+    /// let pattern: Pattern<SymbolLang> = "(+ ?x (?f ?y ?x))".parse().unwrap();
+    /// let map: HashMap<Var, Var> = [("?x", "?x1"), ("?f", "?g")]
+    ///     .iter()
+    ///     .map(|(a, b)| (a.parse().unwrap(), b.parse().unwrap()))
+    ///     .collect();
+    /// assert_eq!(pattern.rename_vars(&map).to_string(), "(+ ?x1 (?g ?y ?x1))");
+    /// ```
+    pub fn rename_vars<S: std::hash::BuildHasher>(
+        &self,
+        map: &std::collections::HashMap<Var, Var, S>,
+    ) -> Pattern<L> {
+        let rename = |v: &Var| *map.get(v).unwrap_or(v);
+        let ast: PatternAst<L> = self
+            .ast
+            .iter()
+            .map(|n| match n {
+                ENodeOrVar::ENode(_) | ENodeOrVar::Wildcard => n.clone(),
+                ENodeOrVar::Var(v) => ENodeOrVar::Var(rename(v)),
+                ENodeOrVar::OpVar(v, children) => ENodeOrVar::OpVar(rename(v), children.clone()),
+            })
+            .collect();
+        let leaf_predicates: Vec<_> = self
+            .program
+            .leaf_predicates
+            .iter()
+            .map(|(v, predicate)| (rename(v), predicate.clone()))
+            .collect();
+        let mut program = machine::Program::compile_from_pat(&ast, &leaf_predicates);
+        program.canonical_root = self.program.canonical_root;
+        Pattern { ast, program }
+    }
+
+    /// Returns how many times each [`Var`] occurs in this pattern.
+    ///
+    /// Occurrences are counted in the pattern as written, so
+    /// `(+ ?a (* ?a ?b))` gives `?a` a count of 2 and `?b` a count of 1.
+    pub fn var_occurrences(&self) -> HashMap<Var, usize> {
+        // the ast is compacted, so shared subterms are visited once per parent
+        let mut occurrences = vec![0usize; self.ast.len()];
+        if let Some(last) = occurrences.last_mut() {
+            *last = 1;
+        }
+        for (i, n) in self.ast.items().rev() {
+            let count = occurrences[usize::from(i)];
+            n.for_each(|child| {
+                let child = &mut occurrences[usize::from(child)];
+                *child = child.saturating_add(count);
+            });
+        }
+
+        let mut vars = HashMap::default();
+        for (i, n) in self.ast.items() {
+            if let ENodeOrVar::Var(v) | ENodeOrVar::OpVar(v, _) = n {
+                *vars.entry(*v).or_default() += occurrences[usize::from(i)];
+            }
+        }
+        vars
+    }
+
+    /// Returns `true` if every [`Var`] occurs at most once in this pattern.
+    ///
+    /// Non-linear patterns like `(- ?a ?a)` have to check that the
+    /// occurrences match the same e-class, which makes them more expensive
+    /// to search.
+    ///
+    /// # Example
+    /// ```
+    /// # use egg::*;
+    /// let linear: Pattern<SymbolLang> = "(+ ?a (* ?b ?c))".parse().unwrap();
+    /// assert!(linear.is_linear());
+    ///
+    /// let non_linear: Pattern<SymbolLang> = "(+ (* ?a ?b) (* ?a ?b))".parse().unwrap();
+    /// assert!(!non_linear.is_linear());
+    /// let a: Var = "?a".parse().unwrap();
+    /// assert_eq!(non_linear.var_occurrences()[&a], 2);
+    /// ```
+    pub fn is_linear(&self) -> bool {
+        self.var_occurrences().values().all(|&n| n <= 1)
+    }
 }
 
 impl<L: Language + Display> Pattern<L> {
@@ -194,6 +479,27 @@ pub enum ENodeOrVar<L> {
     ENode(L),
     /// A pattern variable
     Var(Var),
+    /// An operator variable applied to some children, like `?f` in
+    /// `(?f ?x ?y)`.
+    ///
+    /// In a searcher, this matches any e-node with exactly that many
+    /// children, binding the variable to its operator (see
+    /// [`Subst::get_op`]). In an applier, it builds an e-node with the
+    /// bound operator and the given children, so the arity has to be the
+    /// same as where the variable was bound.
+    /// Operator variables need at least one child.
+    OpVar(Var, Vec<Id>),
+    /// A wildcard, written `_`, that matches any e-class without binding
+    /// it to a variable.
+    ///
+    /// Unlike a variable used only once, like `?unused`, every wildcard
+    /// can match a different e-class, and none of them shows up in the
+    /// [`Subst`] or in [`Pattern::vars`].
+    /// Since there is no binding to instantiate it with, a wildcard can't
+    /// be used in an applier, and a pattern with wildcards can't be the
+    /// searcher of a rewrite while explanations are enabled: the [`Runner`]
+    /// panics on such a rule before running anything.
+    Wildcard,
 }
 
 /// The discriminant for the language of [`Pattern`]s.
@@ -201,6 +507,8 @@ pub enum ENodeOrVar<L> {
 pub enum ENodeOrVarDiscriminant<L: Language> {
     ENode(L::Discriminant),
     Var(Var),
+    OpVar(Var),
+    Wildcard,
 }
 
 impl<L: Language> Language for ENodeOrVar<L> {
@@ -211,6 +519,15 @@ impl<L: Language> Language for ENodeOrVar<L> {
         match self {
             ENodeOrVar::ENode(n) => ENodeOrVarDiscriminant::ENode(n.discriminant()),
             ENodeOrVar::Var(v) => ENodeOrVarDiscriminant::Var(*v),
+            ENodeOrVar::OpVar(v, _) => ENodeOrVarDiscriminant::OpVar(*v),
+            ENodeOrVar::Wildcard => ENodeOrVarDiscriminant::Wildcard,
+        }
+    }
+
+    fn op_str(&self) -> Option<&str> {
+        match self {
+            ENodeOrVar::ENode(n) => n.op_str(),
+            ENodeOrVar::Var(_) | ENodeOrVar::OpVar(..) | ENodeOrVar::Wildcard => None,
         }
     }
 
@@ -221,14 +538,16 @@ impl<L: Language> Language for ENodeOrVar<L> {
     fn children(&self) -> &[Id] {
         match self {
             ENodeOrVar::ENode(n) => n.children(),
-            ENodeOrVar::Var(_) => &[],
+            ENodeOrVar::Var(_) | ENodeOrVar::Wildcard => &[],
+            ENodeOrVar::OpVar(_, children) => children,
         }
     }
 
     fn children_mut(&mut self) -> &mut [Id] {
         match self {
             ENodeOrVar::ENode(n) => n.children_mut(),
-            ENodeOrVar::Var(_) => &mut [],
+            ENodeOrVar::Var(_) | ENodeOrVar::Wildcard => &mut [],
+            ENodeOrVar::OpVar(_, children) => children,
         }
     }
 }
@@ -237,7 +556,8 @@ impl<L: Language + Display> Display for ENodeOrVar<L> {
     fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
         match self {
             Self::ENode(node) => Display::fmt(node, f),
-            Self::Var(var) => Display::fmt(var, f),
+            Self::Var(var) | Self::OpVar(var, _) => Display::fmt(var, f),
+            Self::Wildcard => write!(f, "_"),
         }
     }
 }
@@ -247,7 +567,7 @@ pub enum ENodeOrVarParseError<E> {
     #[error(transparent)]
     BadVar(<Var as FromStr>::Err),
 
-    #[error("tried to parse pattern variable {0:?} as an operator")]
+    #[error("tried to parse sorted pattern variable {0:?} as an operator")]
     UnexpectedVar(String),
 
     #[error(transparent)]
@@ -260,11 +580,16 @@ impl<L: FromOp> FromOp for ENodeOrVar<L> {
     fn from_op(op: &str, children: Vec<Id>) -> Result<Self, Self::Error> {
         use ENodeOrVarParseError::*;
 
-        if op.starts_with('?') && op.len() > 1 {
+        if op == "_" && children.is_empty() {
+            Ok(Self::Wildcard)
+        } else if op.starts_with('?') && op.len() > 1 {
+            let var: Var = op.parse().map_err(BadVar)?;
             if children.is_empty() {
-                op.parse().map(Self::Var).map_err(BadVar)
-            } else {
+                Ok(Self::Var(var))
+            } else if var.sort().is_some() {
                 Err(UnexpectedVar(op.to_owned()))
+            } else {
+                Ok(Self::OpVar(var, children))
             }
         } else {
             L::from_op(op, children).map(Self::ENode).map_err(BadOp)
@@ -312,7 +637,9 @@ impl<L: Language> TryFrom<PatternAst<L>> for RecExpr<L> {
         ast.into_iter()
             .map(|n| match n {
                 ENodeOrVar::ENode(n) => Ok(n),
-                ENodeOrVar::Var(v) => Err(v),
+                ENodeOrVar::Var(v) | ENodeOrVar::OpVar(v, _) => Err(v),
+                // wildcards have no variable, so report one named after them
+                ENodeOrVar::Wildcard => Err("?_".parse().unwrap()),
             })
             .collect()
     }
@@ -362,15 +689,50 @@ impl<L: Language, A: Analysis<L>> Searcher<L, A> for Pattern<L> {
                     Some(ids) => rewrite::search_eclasses_with_limit(self, egraph, ids, limit),
                 }
             }
-            ENodeOrVar::Var(_) => rewrite::search_eclasses_with_limit(
-                self,
-                egraph,
-                egraph.classes().map(|e| e.id),
-                limit,
-            ),
+            ENodeOrVar::Var(_) | ENodeOrVar::OpVar(..) | ENodeOrVar::Wildcard => {
+                rewrite::search_eclasses_with_limit(
+                    self,
+                    egraph,
+                    egraph.classes().map(|e| e.id),
+                    limit,
+                )
+            }
         }
     }
 
+    fn search_iter<'a>(
+        &'a self,
+        egraph: &'a EGraph<L, A>,
+    ) -> Box<dyn Iterator<Item = (Id, Subst)> + 'a> {
+        match self.ast.last().unwrap() {
+            ENodeOrVar::ENode(e) => match egraph.classes_for_op(&e.discriminant()) {
+                None => Box::new(std::iter::empty()),
+                Some(ids) => rewrite::search_eclasses_batched(self, egraph, ids),
+            },
+            ENodeOrVar::Var(_) | ENodeOrVar::OpVar(..) | ENodeOrVar::Wildcard => {
+                rewrite::search_eclasses_batched(self, egraph, egraph.classes().map(|e| e.id))
+            }
+        }
+    }
+
+    fn search_grouped_by(&self, egraph: &EGraph<L, A>, var: Var) -> HashMap<Id, Vec<Subst>> {
+        rewrite::assert_bound::<L, A, _>(self, var);
+        let program = match Program::compile_grouped(&self.ast, &self.program.leaf_predicates, var)
+        {
+            Some(program) if !self.program.canonical_root => program,
+            _ => return rewrite::search_grouped_by(self, egraph, var),
+        };
+        let mut groups: HashMap<Id, Vec<Subst>> = HashMap::default();
+        for class in egraph.classes() {
+            let matches = program.run_grouped(egraph, class.id);
+            if !matches.is_empty() {
+                let substs = matches.into_iter().map(|(_, subst)| subst).collect();
+                groups.insert(class.id, substs);
+            }
+        }
+        groups
+    }
+
     fn search_eclass_with_limit(
         &self,
         egraph: &EGraph<L, A>,
@@ -415,6 +777,10 @@ where
         for mat in matches {
             let sast = mat.ast.as_ref().map(|cow| cow.as_ref());
             for subst in &mat.substs {
+                if let Err(e) = check_bound(&self.ast, subst) {
+                    warn!("Skipping a match of {}: {}", rule_name, e);
+                    continue;
+                }
                 let did_something;
                 let id;
                 if egraph.are_explanations_enabled() {
@@ -443,6 +809,10 @@ where
         searcher_ast: Option<&PatternAst<L>>,
         rule_name: Symbol,
     ) -> Vec<Id> {
+        if let Err(e) = check_bound(&self.ast, subst) {
+            warn!("Skipping a match of {}: {}", rule_name, e);
+            return vec![];
+        }
         let mut id_buf = vec![0.into(); self.ast.len()];
         let id = apply_pat(&mut id_buf, &self.ast, egraph, subst);
 
@@ -461,11 +831,61 @@ where
         }
     }
 
+    fn is_noop(&self, egraph: &EGraph<L, A>, eclass: Id, subst: &Subst) -> bool {
+        check_bound(&self.ast, subst).is_ok()
+            && lookup_pat(&self.ast, egraph, subst) == Some(egraph.find(eclass))
+    }
+
     fn vars(&self) -> Vec<Var> {
         Pattern::vars(self)
     }
 }
 
+/// Looks up the instantiation of `pat` under `subst` without adding
+/// anything, like [`EGraph::lookup_expr`].
+pub(crate) fn lookup_pat<L: Language, A: Analysis<L>>(
+    pat: &[ENodeOrVar<L>],
+    egraph: &EGraph<L, A>,
+    subst: &Subst,
+) -> Option<Id> {
+    let mut ids: Vec<Id> = Vec::with_capacity(pat.len());
+    for pat_node in pat {
+        let id = match pat_node {
+            ENodeOrVar::Var(w) => egraph.find(subst[*w]),
+            ENodeOrVar::ENode(e) => {
+                egraph.lookup(e.clone().map_children(|child| ids[usize::from(child)]))?
+            }
+            ENodeOrVar::OpVar(v, children) => {
+                let children: Vec<Id> = children.iter().map(|c| ids[usize::from(*c)]).collect();
+                egraph.lookup(op_var_node(egraph, subst, *v, &children))?
+            }
+            ENodeOrVar::Wildcard => return None,
+        };
+        ids.push(id);
+    }
+    ids.last().copied()
+}
+
+/// Checks that `subst` binds every variable of `pat`, so that instantiating
+/// it with [`apply_pat`] cannot panic halfway through.
+pub(crate) fn check_bound<L: Language>(
+    pat: &[ENodeOrVar<L>],
+    subst: &Subst,
+) -> Result<(), MissingVar> {
+    for node in pat {
+        match node {
+            ENodeOrVar::Var(v) => {
+                subst.try_index(*v)?;
+            }
+            ENodeOrVar::OpVar(v, _) => {
+                subst.get_op(*v).ok_or(MissingVar(*v))?;
+            }
+            ENodeOrVar::ENode(_) | ENodeOrVar::Wildcard => {}
+        }
+    }
+    Ok(())
+}
+
 pub(crate) fn apply_pat<L: Language, A: Analysis<L>>(
     ids: &mut [Id],
     pat: &[ENodeOrVar<L>],
@@ -483,6 +903,13 @@ pub(crate) fn apply_pat<L: Language, A: Analysis<L>>(
                 trace!("adding: {:?}", n);
                 egraph.add(n)
             }
+            ENodeOrVar::OpVar(v, children) => {
+                let children: Vec<Id> = children.iter().map(|c| ids[usize::from(*c)]).collect();
+                let n = op_var_node(egraph, subst, *v, &children);
+                trace!("adding: {:?}", n);
+                egraph.add(n)
+            }
+            ENodeOrVar::Wildcard => panic!("Can't instantiate the wildcard `_`"),
         };
         ids[i] = id;
     }
@@ -490,11 +917,68 @@ pub(crate) fn apply_pat<L: Language, A: Analysis<L>>(
     *ids.last().unwrap()
 }
 
+/// Builds an e-node with the operator that `subst` binds the operator
+/// variable `var` to, and the given children.
+pub(crate) fn op_var_node<L: Language, A: Analysis<L>>(
+    egraph: &EGraph<L, A>,
+    subst: &Subst,
+    var: Var,
+    children: &[Id],
+) -> L {
+    let node = match subst.get_op(var) {
+        Some(node) => egraph.id_to_node(node),
+        None => panic!("Operator var '{}' not found in {:?}", var, subst),
+    };
+    assert_eq!(
+        node.len(),
+        children.len(),
+        "Operator var '{}' is bound to an operator with a different arity",
+        var
+    );
+    let mut children = children.iter();
+    node.clone().map_children(|_| *children.next().unwrap())
+}
+
 impl<L: Language> Pattern<L> {
     /// apply a [Subst] in a [Pattern]. It returns the corresponding (possibly new) [Id]
     pub fn apply_susbt<A: Analysis<L>>(&self, egraph: &mut EGraph<L, A>, subst: &Subst) -> Id {
         self.ast.apply_susbt(egraph, subst)
     }
+
+    /// Like [`search`](Searcher::search), but each returned
+    /// [`SearchMatches`] only contains unique [`Subst`]s, in the order they
+    /// were first found.
+    ///
+    /// Duplicates are dropped as soon as the e-matching finds them, so this
+    /// saves memory when a search finds the same bindings many times, e.g.
+    /// because of [wildcards](ENodeOrVar::Wildcard).
+    /// Note that this changes the number of matches, which schedulers like
+    /// [`BackoffScheduler`] use to decide when to ban a rule, so [`Rewrite`]s
+    /// never dedup their matches on their own.
+    pub fn search_deduped<A: Analysis<L>>(
+        &self,
+        egraph: &EGraph<L, A>,
+    ) -> Vec<SearchMatches<'_, L>> {
+        let search = |eclass: Id| {
+            let substs = self.program.run_deduped(egraph, eclass);
+            let ast = Some(Cow::Borrowed(&self.ast));
+            Some(SearchMatches {
+                eclass,
+                substs,
+                ast,
+            })
+            .filter(|m| !m.substs.is_empty())
+        };
+        match self.ast.last().unwrap() {
+            ENodeOrVar::ENode(e) => match egraph.classes_for_op(&e.discriminant()) {
+                None => vec![],
+                Some(ids) => ids.filter_map(search).collect(),
+            },
+            ENodeOrVar::Var(_) | ENodeOrVar::OpVar(..) | ENodeOrVar::Wildcard => {
+                egraph.classes().filter_map(|c| search(c.id)).collect()
+            }
+        }
+    }
 }
 
 #[cfg(test)]
@@ -567,6 +1051,44 @@ mod tests {
         assert_eq!(n_matches("(h ?x 0 0)"), 1);
     }
 
+    #[test]
+    fn operator_variables() {
+        crate::init_logger();
+        let mut egraph = EGraph::default().with_explanations_enabled();
+        egraph.add_expr(&"(f a b)".parse().unwrap());
+        egraph.add_expr(&"(+ a b)".parse().unwrap());
+        egraph.add_expr(&"(g a)".parse().unwrap());
+        egraph.add_expr(&"(h a b c)".parse().unwrap());
+        egraph.add_expr(&"(g (g a))".parse().unwrap());
+        egraph.add_expr(&"(g (k a))".parse().unwrap());
+        egraph.rebuild();
+
+        let n_matches = |s: &str| s.parse::<Pattern<S>>().unwrap().n_matches(&egraph);
+        assert_eq!(n_matches("(?f ?x ?y)"), 2);
+        assert_eq!(n_matches("(?f a ?y)"), 2);
+        assert_eq!(n_matches("(?f ?x)"), 4);
+        assert_eq!(n_matches("(?f (?f ?x))"), 1);
+        assert_eq!(n_matches("(?f (?g ?x))"), 2);
+
+        let pat: Pattern<S> = "(?f ?x ?y)".parse().unwrap();
+        assert_eq!(pat.vars().len(), 3);
+        assert_eq!(pat.to_string(), "(?f ?x ?y)");
+
+        let rules = &[rewrite!("swap"; "(?f ?x ?y)" => "(?f ?y ?x)")];
+        let mut runner = Runner::default().with_egraph(egraph).run(rules);
+        for (before, after) in [("(f a b)", "(f b a)"), ("(+ a b)", "(+ b a)")] {
+            let (before, after) = (before.parse().unwrap(), after.parse().unwrap());
+            let id = runner.egraph.lookup_expr(&before).unwrap();
+            assert_eq!(runner.egraph.lookup_expr(&after), Some(id));
+            let expl = runner.explain_equivalence(&before, &after);
+            assert_eq!(expl.summary().rules[&Symbol::from("swap")], 1);
+        }
+        assert!(runner
+            .egraph
+            .lookup_expr(&"(h c b a)".parse().unwrap())
+            .is_none());
+    }
+
     #[test]
     fn search_with_limit() {
         crate::init_logger();
@@ -600,4 +1122,200 @@ mod tests {
             assert_eq!(m1.substs.len(), usize::min(limit, match_size));
         }
     }
+
+    #[test]
+    fn search_deduped_drops_duplicates() {
+        let mut egraph = EGraph::default();
+        let fab = egraph.add_expr(&"(f a b)".parse().unwrap());
+        let fac = egraph.add_expr(&"(f a c)".parse().unwrap());
+        let fbc = egraph.add_expr(&"(f b c)".parse().unwrap());
+        egraph.union(fab, fac);
+        egraph.union(fab, fbc);
+        egraph.rebuild();
+
+        // both (f a b) and (f a c) bind ?x to a
+        let pat: Pattern<S> = "(f ?x _)".parse().unwrap();
+        let all = pat.search(&egraph);
+        assert_eq!(all[0].substs.len(), 3);
+        let deduped = pat.search_deduped(&egraph);
+        assert_eq!(deduped.len(), 1);
+        let bound: Vec<Id> = deduped[0]
+            .substs
+            .iter()
+            .map(|s| s["?x".parse().unwrap()])
+            .collect();
+        let (a, b) = (
+            egraph.lookup(S::leaf("a")).unwrap(),
+            egraph.lookup(S::leaf("b")).unwrap(),
+        );
+        assert_eq!(bound, vec![a, b]);
+    }
+
+    #[test]
+    fn search_deduped() {
+        let init_expr = &"(+ 1 (+ 2 (+ 3 (+ 4 5))))".parse().unwrap();
+        let rules: Vec<Rewrite<_, ()>> = vec![
+            rewrite!("comm"; "(+ ?x ?y)" => "(+ ?y ?x)"),
+            rewrite!("assoc"; "(+ ?x (+ ?y ?z))" => "(+ (+ ?x ?y) ?z)"),
+        ];
+        let runner = Runner::default().with_expr(init_expr).run(&rules);
+        let egraph = &runner.egraph;
+
+        let pat = &"(+ ?x (+ ?y ?z))".parse::<Pattern<S>>().unwrap();
+        let all = pat.search(egraph);
+        let deduped = pat.search_deduped(egraph);
+        assert_eq!(all.len(), deduped.len());
+        for (m, d) in all.iter().zip(&deduped) {
+            assert_eq!(m.eclass, d.eclass);
+            for (i, s) in d.substs.iter().enumerate() {
+                assert!(!d.substs[i + 1..].contains(s));
+            }
+            assert!(m.substs.iter().all(|s| d.substs.contains(s)));
+        }
+    }
+
+    #[test]
+    fn search_iter() {
+        let init_expr = &"(+ 1 (+ 2 (+ 3 (+ 4 5))))".parse().unwrap();
+        let rules: Vec<Rewrite<_, ()>> = vec![
+            rewrite!("comm"; "(+ ?x ?y)" => "(+ ?y ?x)"),
+            rewrite!("assoc"; "(+ ?x (+ ?y ?z))" => "(+ (+ ?x ?y) ?z)"),
+        ];
+        let runner = Runner::default().with_expr(init_expr).run(&rules);
+        let egraph = &runner.egraph;
+
+        let pat = &"(+ ?x (+ ?y ?z))".parse::<Pattern<S>>().unwrap();
+        let all: Vec<(Id, Subst)> = pat.search_iter(egraph).collect();
+        assert_eq!(all.len(), pat.n_matches(egraph));
+
+        let expected: Vec<(Id, Subst)> = pat
+            .search(egraph)
+            .into_iter()
+            .flat_map(|m| {
+                let eclass = m.eclass;
+                m.substs.into_iter().map(move |s| (eclass, s))
+            })
+            .collect();
+        assert_eq!(all, expected);
+
+        assert_eq!(pat.search_iter(egraph).take(3).count(), 3);
+    }
+
+    #[test]
+    fn search_grouped_by_matches_bucketing() {
+        let init_expr = &"(+ 1 (+ 2 (+ 1 (+ 2 1))))".parse().unwrap();
+        let rules: Vec<Rewrite<_, ()>> = vec![
+            rewrite!("comm"; "(+ ?x ?y)" => "(+ ?y ?x)"),
+            rewrite!("assoc"; "(+ ?x (+ ?y ?z))" => "(+ (+ ?x ?y) ?z)"),
+        ];
+        let runner = Runner::default().with_expr(init_expr).run(&rules);
+        let egraph = &runner.egraph;
+
+        let sorted = |groups: HashMap<Id, Vec<Subst>>, vars: &[Var]| {
+            let mut groups: Vec<(Id, Vec<Vec<Id>>)> = groups
+                .into_iter()
+                .map(|(key, substs)| {
+                    let mut substs: Vec<Vec<Id>> = substs
+                        .iter()
+                        .map(|subst| vars.iter().map(|&v| subst[v]).collect())
+                        .collect();
+                    substs.sort();
+                    (key, substs)
+                })
+                .collect();
+            groups.sort();
+            groups
+        };
+        for (pat, var) in [
+            ("(+ ?x (+ ?y ?z))", "?x"),
+            ("(+ ?x (+ ?y ?z))", "?y"),
+            ("(+ ?x ?x)", "?x"),
+            ("(+ ?x (+ ?x ?y))", "?x"),
+        ] {
+            let pat: Pattern<S> = pat.parse().unwrap();
+            let var: Var = var.parse().unwrap();
+            let vars = pat.vars();
+            let grouped = sorted(pat.search_grouped_by(egraph, var), &vars);
+            let bucketed = sorted(rewrite::search_grouped_by(&pat, egraph, var), &vars);
+            assert!(!grouped.is_empty());
+            assert_eq!(grouped, bucketed);
+        }
+    }
+
+    #[test]
+    fn apply_unbound_var() {
+        let mut egraph = EGraph::default();
+        let x = egraph.add(S::leaf("x"));
+        egraph.rebuild();
+        let size = egraph.total_size();
+
+        let searcher: Pattern<S> = "?x".parse().unwrap();
+        let applier: Pattern<S> = "(f ?x ?y)".parse().unwrap();
+        let mut subst = Subst::default();
+        subst.insert("?x".parse().unwrap(), x);
+
+        let matches = vec![SearchMatches {
+            eclass: x,
+            substs: vec![subst.clone()],
+            ast: Some(std::borrow::Cow::Owned(searcher.ast.clone())),
+        }];
+        let rule = Symbol::from("unbound");
+        assert!(applier
+            .apply_matches(&mut egraph, &matches, rule)
+            .is_empty());
+        assert!(applier
+            .apply_one(&mut egraph, x, &subst, None, rule)
+            .is_empty());
+        // nothing was added before the missing var was noticed
+        assert_eq!(egraph.total_size(), size);
+    }
+
+    #[test]
+    fn wildcards() {
+        let mut egraph = EGraph::default();
+        let fxy = egraph.add_expr(&"(f x y)".parse().unwrap());
+        let fxx = egraph.add_expr(&"(f x x)".parse().unwrap());
+        let gx = egraph.add_expr(&"(g x)".parse().unwrap());
+        egraph.rebuild();
+
+        // unlike a variable, each wildcard can match a different e-class
+        let pat: Pattern<S> = "(f _ _)".parse().unwrap();
+        assert_eq!(pat.to_string(), "(f _ _)");
+        assert!(pat.vars().is_empty());
+        let mut found: Vec<Id> = pat.search(&egraph).iter().map(|m| m.eclass).collect();
+        found.sort();
+        assert_eq!(found, vec![fxy, fxx]);
+        let nonlinear: Pattern<S> = "(f ?a ?a)".parse().unwrap();
+        assert_eq!(nonlinear.search(&egraph).len(), 1);
+
+        // wildcards don't bind anything
+        let pat: Pattern<S> = "(f ?a _)".parse().unwrap();
+        assert_eq!(pat.vars(), vec!["?a".parse().unwrap()]);
+        let m = pat.search_eclass(&egraph, fxy).unwrap();
+        assert_eq!(m.substs[0].len(), 1);
+
+        // a wildcard root matches every e-class
+        let pat: Pattern<S> = "_".parse().unwrap();
+        assert_eq!(pat.search(&egraph).len(), egraph.number_of_classes());
+        assert!(pat.search_eclass(&egraph, gx).is_some());
+
+        assert!(Rewrite::<S, ()>::new(
+            "bad",
+            "(g ?a)".parse::<Pattern<S>>().unwrap(),
+            "(h ?a _)".parse::<Pattern<S>>().unwrap(),
+        )
+        .is_err());
+    }
+
+    #[test]
+    #[should_panic(expected = "Rewrite w has a wildcard `_` in its searcher")]
+    fn wildcards_rejected_with_explanations() {
+        let rules: Vec<Rewrite<S, ()>> = vec![rewrite!("w"; "(f _ ?x)" => "?x")];
+        let expr = "(f a b)".parse().unwrap();
+        Runner::default()
+            .with_explanations_enabled()
+            .with_expr(&expr)
+            .with_hook(|_| panic!("the rule should be rejected before the first iteration"))
+            .run(&rules);
+    }
 }
@@ -1,6 +1,11 @@
 use pattern::apply_pat;
+use std::borrow::Cow;
 use std::fmt::{self, Debug, Display};
-use std::sync::Arc;
+use std::iter::FromIterator;
+use std::str::FromStr;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use thiserror::Error;
 
 use crate::*;
 
@@ -22,6 +27,9 @@ pub struct Rewrite<L, N> {
     pub searcher: Arc<dyn Searcher<L, N> + Sync + Send>,
     /// The applier (right-hand side) of the rewrite.
     pub applier: Arc<dyn Applier<L, N> + Sync + Send>,
+    /// The most substitutions the [`Runner`] applies per iteration,
+    /// see [`with_match_limit`](Rewrite::with_match_limit()).
+    pub match_limit: Option<usize>,
 }
 
 impl<L, N> Debug for Rewrite<L, N>
@@ -46,6 +54,10 @@ where
             d.field("applier", &"<< applier >>");
         }
 
+        if let Some(limit) = self.match_limit {
+            d.field("match_limit", &limit);
+        }
+
         d.finish()
     }
 }
@@ -63,6 +75,12 @@ impl<L: Language, N: Analysis<L>> Rewrite<L, N> {
         let searcher = Arc::new(searcher);
         let applier = Arc::new(applier);
 
+        if let Some(ast) = applier.get_pattern_ast() {
+            if ast.iter().any(|n| matches!(n, ENodeOrVar::Wildcard)) {
+                return Err(format!("Rewrite {} has a wildcard in its applier", name));
+            }
+        }
+
         let bound_vars = searcher.vars();
         for v in applier.vars() {
             if !bound_vars.contains(&v) {
@@ -74,9 +92,46 @@ impl<L: Language, N: Analysis<L>> Rewrite<L, N> {
             name,
             searcher,
             applier,
+            match_limit: None,
         })
     }
 
+    /// Caps how many substitutions of this rule the [`Runner`] applies in
+    /// each iteration.
+    ///
+    /// The [`Runner`] truncates the matches of this rule to `limit`
+    /// substitutions before applying them, whatever the
+    /// [`RewriteScheduler`] does.
+    /// The substitutions kept move along with each iteration, so that all
+    /// matches are applied eventually.
+    /// When some are dropped, the [`Runner`] only reports
+    /// [`StopReason::Saturated`] once enough iterations in a row to go
+    /// through all of them changed nothing.
+    /// So together with a scheduler that limits matches too, like the
+    /// [`BackoffScheduler`], the smaller of the two limits wins (although
+    /// the [`BackoffScheduler`] bans the rule instead of truncating its
+    /// matches).
+    /// This only affects the [`Runner`]; [`search`](Rewrite::search()) and
+    /// [`apply`](Rewrite::apply()) ignore it.
+    ///
+    /// # Example
+    /// ```
+    /// # use egg::*;
+    /// let rules: &[Rewrite<SymbolLang, ()>] = &[
+    ///     rewrite!("commute-add"; "(+ ?a ?b)" => "(+ ?b ?a)").with_match_limit(1),
+    /// ];
+    /// let runner = Runner::default()
+    ///     .with_expr(&"(+ a b)".parse().unwrap())
+    ///     .with_expr(&"(+ c d)".parse().unwrap())
+    ///     .with_iter_limit(1)
+    ///     .run(rules);
+    /// assert_eq!(runner.iterations[0].applied[&Symbol::from("commute-add")], 1);
+    /// ```
+    pub fn with_match_limit(mut self, limit: usize) -> Self {
+        self.match_limit = Some(limit);
+        self
+    }
+
     /// Call [`search`] on the [`Searcher`].
     ///
     /// [`search`]: Searcher::search()
@@ -95,9 +150,199 @@ impl<L: Language, N: Analysis<L>> Rewrite<L, N> {
     ///
     /// [`apply_matches`]: Applier::apply_matches()
     pub fn apply(&self, egraph: &mut EGraph<L, N>, matches: &[SearchMatches<L>]) -> Vec<Id> {
+        if egraph.are_explanations_enabled() {
+            self.check_explainable();
+        }
         self.applier.apply_matches(egraph, matches, self.name)
     }
 
+    /// Panics if explanations can't record why this rewrite applies, i.e.
+    /// if its searcher has a [wildcard](ENodeOrVar::Wildcard), before any
+    /// match is applied.
+    pub(crate) fn check_explainable(&self) {
+        if let Some(ast) = self.searcher.get_pattern_ast() {
+            assert!(
+                !ast.iter().any(|n| matches!(n, ENodeOrVar::Wildcard)),
+                "Rewrite {} has a wildcard `_` in its searcher, which explanations can't \
+                 instantiate; use a variable like `?unused` instead",
+                self.name
+            );
+        }
+    }
+
+    /// Apply this rewrite to a single match, as if it were the only one
+    /// found by [`search`](Rewrite::search()).
+    ///
+    /// This goes through [`apply_matches`](Applier::apply_matches()) with
+    /// the searcher's pattern ast, so explanations are recorded just like
+    /// in a normal [`Runner`] iteration. Like [`apply`](Rewrite::apply()),
+    /// this does not [`rebuild`](EGraph::rebuild()) the e-graph.
+    ///
+    /// # Example
+    /// ```
+    /// # use egg::*;
+    /// let rule: Rewrite<SymbolLang, ()> = rewrite!("commute-add"; "(+ ?a ?b)" => "(+ ?b ?a)");
+    /// let mut egraph = EGraph::default().with_explanations_enabled();
+    /// let ab = egraph.add_expr(&"(+ a b)".parse().unwrap());
+    /// egraph.rebuild();
+    ///
+    /// let m = rule.searcher.search_eclass(&egraph, ab).unwrap();
+    /// rule.apply_once(&mut egraph, m.eclass, &m.substs[0]);
+    /// egraph.rebuild();
+    ///
+    /// let ba = egraph.add_expr(&"(+ b a)".parse().unwrap());
+    /// assert_eq!(egraph.find(ab), egraph.find(ba));
+    /// let expl = egraph.explain_equivalence(&"(+ a b)".parse().unwrap(), &"(+ b a)".parse().unwrap());
+    /// assert_eq!(expl.summary().rules[&Symbol::from("commute-add")], 1);
+    /// ```
+    pub fn apply_once(&self, egraph: &mut EGraph<L, N>, eclass: Id, subst: &Subst) -> Vec<Id> {
+        let matches = SearchMatches {
+            eclass,
+            substs: vec![subst.clone()],
+            ast: self
+                .searcher
+                .get_pattern_ast()
+                .map(std::borrow::Cow::Borrowed),
+        };
+        self.apply(egraph, &[matches])
+    }
+
+    /// Lists every match of the lefthand side as an `(eclass, subst)`
+    /// pair, ready to be picked from and passed to
+    /// [`apply_once`](Rewrite::apply_once()) or
+    /// [`apply_in_direction`](Rewrite::apply_in_direction()).
+    ///
+    /// This is the same as
+    /// [`search_and_list_in_direction`](Rewrite::search_and_list_in_direction())
+    /// with [`Direction::Forward`].
+    pub fn search_and_list(&self, egraph: &EGraph<L, N>) -> Vec<(Id, Subst)> {
+        self.search_and_list_in_direction(egraph, Direction::Forward)
+    }
+
+    /// Lists every match of the side of this rule that `dir` rewrites
+    /// from as an `(eclass, subst)` pair.
+    ///
+    /// # Panics
+    /// With [`Direction::Backward`], panics if the righthand side is not
+    /// a pattern (see [`Applier::get_pattern_ast`]).
+    pub fn search_and_list_in_direction(
+        &self,
+        egraph: &EGraph<L, N>,
+        dir: Direction,
+    ) -> Vec<(Id, Subst)> {
+        let list = |matches: Vec<SearchMatches<L>>| -> Vec<(Id, Subst)> {
+            matches
+                .into_iter()
+                .flat_map(|m| {
+                    let eclass = m.eclass;
+                    m.substs.into_iter().map(move |subst| (eclass, subst))
+                })
+                .collect()
+        };
+        match dir {
+            Direction::Forward => list(self.search(egraph)),
+            Direction::Backward => list(self.backward_searcher().search(egraph)),
+        }
+    }
+
+    /// Applies this rule to a single match in the given direction.
+    ///
+    /// [`Direction::Forward`] is the same as
+    /// [`apply_once`](Rewrite::apply_once()).
+    /// [`Direction::Backward`] treats `eclass` and `subst` as a match of
+    /// the righthand side, as listed by
+    /// [`search_and_list_in_direction`](Rewrite::search_and_list_in_direction()),
+    /// and unions `eclass` with the instantiated lefthand side.
+    /// Backward applications are justified in explanations by the rule
+    /// name suffixed with `-rev`, like the reverse rule of a bidirectional
+    /// [`rewrite!`], and they ignore any conditions of the applier.
+    ///
+    /// Like [`apply`](Rewrite::apply()), this does not
+    /// [`rebuild`](EGraph::rebuild()) the e-graph.
+    /// Returns the ids of the e-classes that changed.
+    ///
+    /// # Panics
+    /// With [`Direction::Backward`], panics if either side is not a
+    /// pattern, if the lefthand side has a wildcard `_`, if `subst` does
+    /// not bind every variable of both sides, or if the righthand side instantiated with `subst` is not already in
+    /// `eclass`.
+    /// These are all checked before the e-graph is changed.
+    ///
+    /// # Example
+    /// ```
+    /// # use egg::*;
+    /// let rule: Rewrite<SymbolLang, ()> = rewrite!("mul-1"; "(* ?a 1)" => "?a");
+    /// let mut egraph = EGraph::default();
+    /// let x = egraph.add_expr(&"x".parse().unwrap());
+    /// egraph.rebuild();
+    ///
+    /// assert!(rule.search_and_list(&egraph).is_empty());
+    /// let backward = rule.search_and_list_in_direction(&egraph, Direction::Backward);
+    /// let (eclass, subst) = &backward[0];
+    /// rule.apply_in_direction(&mut egraph, *eclass, subst, Direction::Backward);
+    /// egraph.rebuild();
+    ///
+    /// assert_eq!(egraph.lookup_expr(&"(* x 1)".parse().unwrap()), Some(x));
+    /// ```
+    pub fn apply_in_direction(
+        &self,
+        egraph: &mut EGraph<L, N>,
+        eclass: Id,
+        subst: &Subst,
+        dir: Direction,
+    ) -> Vec<Id> {
+        match dir {
+            Direction::Forward => self.apply_once(egraph, eclass, subst),
+            Direction::Backward => {
+                let (lhs, rhs) = match (
+                    self.searcher.get_pattern_ast(),
+                    self.applier.get_pattern_ast(),
+                ) {
+                    (Some(lhs), Some(rhs)) => (lhs, rhs),
+                    _ => panic!(
+                        "Rewrite {} cannot be applied backward, both sides must be patterns",
+                        self.name
+                    ),
+                };
+                assert!(
+                    !lhs.iter().any(|n| matches!(n, ENodeOrVar::Wildcard)),
+                    "Rewrite {} cannot be applied backward, its lefthand side has a wildcard",
+                    self.name
+                );
+                for side in [lhs, rhs] {
+                    if let Err(e) = pattern::check_bound(side, subst) {
+                        panic!("Rewrite {} cannot be applied backward: {}", self.name, e);
+                    }
+                }
+                let eclass = egraph.find(eclass);
+                assert_eq!(
+                    pattern::lookup_pat(rhs, egraph, subst),
+                    Some(eclass),
+                    "The righthand side of {} does not match in e-class {}",
+                    self.name,
+                    eclass
+                );
+                let (id, did_union) =
+                    egraph.union_instantiations(rhs, lhs, subst, format!("{}-rev", self.name));
+                if did_union {
+                    vec![egraph.find(id)]
+                } else {
+                    vec![]
+                }
+            }
+        }
+    }
+
+    fn backward_searcher(&self) -> Pattern<L> {
+        match self.applier.get_pattern_ast() {
+            Some(ast) => Pattern::new(ast.clone()),
+            None => panic!(
+                "Rewrite {} cannot be applied backward, its righthand side is not a pattern",
+                self.name
+            ),
+        }
+    }
+
     /// This `run` is for testing use only. You should use things
     /// from the `egg::run` module
     #[cfg(test)]
@@ -122,6 +367,391 @@ impl<L: Language, N: Analysis<L>> Rewrite<L, N> {
     }
 }
 
+/// Which way to apply a [`Rewrite`], see
+/// [`apply_in_direction`](Rewrite::apply_in_direction()).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Direction {
+    /// From the lefthand side to the righthand side, as the [`Runner`] does.
+    Forward,
+    /// From the righthand side to the lefthand side.
+    Backward,
+}
+
+/// A collection of [`Rewrite`]s with unique names.
+///
+/// Inserting a [`Rewrite`] with the same name as one already in the set
+/// replaces the old one, keeping its position.
+/// Otherwise, rules are kept in insertion order.
+///
+/// A `&RuleSet` can be passed directly to [`Runner::run`](Runner::run()).
+///
+/// # Example
+/// ```
+/// # use egg::*;
+/// let mut rules: RuleSet<SymbolLang, ()> = vec![
+///     rewrite!("arith/commute-add"; "(+ ?a ?b)" => "(+ ?b ?a)"),
+///     rewrite!("arith/add-0"; "(+ ?a 0)" => "?a"),
+///     rewrite!("bool/and-true"; "(and ?a true)" => "?a"),
+/// ]
+/// .into_iter()
+/// .collect();
+///
+/// assert!(rules.get("arith/add-0").is_some());
+/// assert_eq!(rules.with_prefix("arith/").count(), 2);
+///
+/// // same name, so this replaces the old rule
+/// rules.insert(rewrite!("arith/add-0"; "(+ 0 ?a)" => "?a"));
+/// assert_eq!(rules.len(), 3);
+///
+/// let runner = Runner::default()
+///     .with_expr(&"(+ 0 x)".parse().unwrap())
+///     .run(&rules);
+/// ```
+#[derive(Clone)]
+pub struct RuleSet<L, N> {
+    rules: IndexMap<Symbol, Rewrite<L, N>>,
+}
+
+impl<L, N> Default for RuleSet<L, N> {
+    fn default() -> Self {
+        Self {
+            rules: Default::default(),
+        }
+    }
+}
+
+impl<L, N> Debug for RuleSet<L, N>
+where
+    L: Language + Display + 'static,
+    N: Analysis<L> + 'static,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_list().entries(self.rules.values()).finish()
+    }
+}
+
+impl<L, N> RuleSet<L, N> {
+    /// Creates an empty [`RuleSet`].
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds a [`Rewrite`] to the set, returning the rewrite it replaced
+    /// if there already was one with the same name.
+    pub fn insert(&mut self, rewrite: Rewrite<L, N>) -> Option<Rewrite<L, N>> {
+        self.rules.insert(rewrite.name, rewrite)
+    }
+
+    /// Returns the [`Rewrite`] with the given name, if any.
+    pub fn get(&self, name: impl Into<Symbol>) -> Option<&Rewrite<L, N>> {
+        self.rules.get(&name.into())
+    }
+
+    /// Iterates over the [`Rewrite`]s whose names start with `prefix`.
+    pub fn with_prefix<'a>(&'a self, prefix: &'a str) -> impl Iterator<Item = &'a Rewrite<L, N>> {
+        self.iter()
+            .filter(move |rw| rw.name.with_str(|name| name.starts_with(prefix)))
+    }
+
+    /// Iterates over the [`Rewrite`]s in this set.
+    pub fn iter(&self) -> impl ExactSizeIterator<Item = &Rewrite<L, N>> {
+        self.rules.values()
+    }
+
+    /// Returns the number of [`Rewrite`]s in this set.
+    pub fn len(&self) -> usize {
+        self.rules.len()
+    }
+
+    /// Returns `true` if there are no [`Rewrite`]s in this set.
+    pub fn is_empty(&self) -> bool {
+        self.rules.is_empty()
+    }
+}
+
+impl<L, N> Extend<Rewrite<L, N>> for RuleSet<L, N> {
+    fn extend<I: IntoIterator<Item = Rewrite<L, N>>>(&mut self, iter: I) {
+        for rw in iter {
+            self.insert(rw);
+        }
+    }
+}
+
+impl<L, N> FromIterator<Rewrite<L, N>> for RuleSet<L, N> {
+    fn from_iter<I: IntoIterator<Item = Rewrite<L, N>>>(iter: I) -> Self {
+        let mut set = Self::new();
+        set.extend(iter);
+        set
+    }
+}
+
+impl<'a, L, N> IntoIterator for &'a RuleSet<L, N> {
+    type Item = &'a Rewrite<L, N>;
+    type IntoIter = indexmap::map::Values<'a, Symbol, Rewrite<L, N>>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.rules.values()
+    }
+}
+
+/// An error raised by [`parse_rules`].
+#[derive(Debug, Error)]
+pub enum RuleParseError<E> {
+    /// A line was not of the form `name: lhs => rhs` or `name: lhs <=> rhs`.
+    #[error("line {0}: expected `name: lhs => rhs` or `name: lhs <=> rhs`")]
+    BadLine(usize),
+    /// One side of a rule failed to parse as a [`Pattern`].
+    #[error("line {line}: {error}")]
+    BadPattern {
+        /// The (1-based) line the rule is on.
+        line: usize,
+        /// The error from parsing the pattern.
+        error: E,
+    },
+    /// The rule was rejected by [`Rewrite::new`], e.g. because of an unbound
+    /// variable.
+    #[error("line {line}: {message}")]
+    BadRule {
+        /// The (1-based) line the rule is on.
+        line: usize,
+        /// The error from [`Rewrite::new`].
+        message: String,
+    },
+}
+
+/// Parses a list of [`Rewrite`]s from text, one per line.
+///
+/// Each line has the form `name: lhs => rhs`, where both sides are parsed
+/// as [`Pattern`]s.
+/// A line of the form `name: lhs <=> rhs` makes two rules, `name` and
+/// `name-rev`, just like the bidirectional form of [`rewrite!`].
+/// Blank lines and lines starting with `#` are ignored.
+/// Conditional rules are not supported.
+///
+/// # Example
+/// ```
+/// # use egg::*;
+/// let rules: Vec<Rewrite<SymbolLang, ()>> = parse_rules(
+///     "
+///     ## arithmetic
+///     commute-add: (+ ?a ?b) => (+ ?b ?a)
+///     assoc-add: (+ ?a (+ ?b ?c)) <=> (+ (+ ?a ?b) ?c)
+///     ",
+/// )
+/// .unwrap();
+///
+/// let names: Vec<_> = rules.iter().map(|rw| rw.name.as_str()).collect();
+/// assert_eq!(names, ["commute-add", "assoc-add", "assoc-add-rev"]);
+///
+/// let err = parse_rules::<SymbolLang, ()>("oops: (+ ?a ?b) => ?c").unwrap_err();
+/// assert!(matches!(err, RuleParseError::BadRule { line: 1, .. }));
+/// ```
+#[allow(clippy::type_complexity)]
+pub fn parse_rules<L, N>(
+    text: &str,
+) -> Result<Vec<Rewrite<L, N>>, RuleParseError<<Pattern<L> as FromStr>::Err>>
+where
+    L: FromOp + Send + Sync + 'static,
+    N: Analysis<L>,
+{
+    let mut rules = vec![];
+    for (i, line) in text.lines().enumerate() {
+        let line_no = i + 1;
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+
+        let (name, rule) = line
+            .split_once(':')
+            .ok_or(RuleParseError::BadLine(line_no))?;
+        let name = name.trim();
+        let (bidirectional, (lhs, rhs)) = if let Some(sides) = rule.split_once("<=>") {
+            (true, sides)
+        } else if let Some(sides) = rule.split_once("=>") {
+            (false, sides)
+        } else {
+            return Err(RuleParseError::BadLine(line_no));
+        };
+        if name.is_empty() {
+            return Err(RuleParseError::BadLine(line_no));
+        }
+
+        let parse = |pat: &str| {
+            pat.trim()
+                .parse::<Pattern<L>>()
+                .map_err(|error| RuleParseError::BadPattern {
+                    line: line_no,
+                    error,
+                })
+        };
+        let (lhs, rhs) = (parse(lhs)?, parse(rhs)?);
+        let make = |name: String, lhs: Pattern<L>, rhs: Pattern<L>| {
+            Rewrite::new(name, lhs, rhs).map_err(|message| RuleParseError::BadRule {
+                line: line_no,
+                message,
+            })
+        };
+        if bidirectional {
+            rules.push(make(name.to_string(), lhs.clone(), rhs.clone())?);
+            rules.push(make(format!("{}-rev", name), rhs, lhs)?);
+        } else {
+            rules.push(make(name.to_string(), lhs, rhs)?);
+        }
+    }
+    Ok(rules)
+}
+
+/// Rewrites `expr` with `rules` as a classic term rewriting system,
+/// replacing terms instead of adding them to an [`EGraph`].
+///
+/// This is meant for a final normalization pass, e.g. on a term extracted
+/// after equality saturation, where the union semantics of the e-graph are
+/// not wanted.
+/// Terms are rewritten innermost first, and every term built by a
+/// righthand side is normalized again, until no rule matches anywhere.
+/// When several rules match the same term, the first one in `rules` wins.
+///
+/// Only rules whose searcher and applier are both patterns (see
+/// [`Searcher::get_pattern_ast`]) are used, the rest are skipped.
+/// There is no e-graph to check conditions or run analyses on, so a rule
+/// is applied wherever its lefthand side matches. In particular, the
+/// conditions of a [`ConditionalApplier`] are ignored.
+/// Rules whose applier is a [`ReplaceApplier`] only do something here.
+/// The rules must be terminating, or this will not return: a rule like
+/// `(+ ?a ?b) => (+ ?b ?a)` rewrites forever.
+///
+/// # Example
+/// ```
+/// # use egg::*;
+/// let rules: &[Rewrite<SymbolLang, ()>] = &[
+///     rewrite!("add-0"; "(+ ?a 0)" => "?a"),
+///     rewrite!("mul-1"; "(* ?a 1)" => "?a"),
+///     rewrite!("sub-self"; "(- ?a ?a)" => "0"),
+///     rewrite!("double"; "(+ ?a ?a)" => "(* 2 ?a)"),
+/// ];
+/// let expr = "(+ (- (* x 1) (+ x 0)) (+ (* y 1) y))".parse().unwrap();
+/// let normal = rewrite_recexpr(&expr, rules);
+/// assert_eq!(normal.to_string(), "(+ 0 (* 2 y))");
+/// ```
+pub fn rewrite_recexpr<L: Language, N: Analysis<L>>(
+    expr: &RecExpr<L>,
+    rules: &[Rewrite<L, N>],
+) -> RecExpr<L> {
+    if expr.is_empty() {
+        return RecExpr::default();
+    }
+    let mut rewriter = TermRewriter {
+        rules: rules
+            .iter()
+            .filter_map(|rw| {
+                Some((
+                    rw.searcher.get_pattern_ast()?,
+                    rw.applier.get_pattern_ast()?,
+                ))
+            })
+            .collect(),
+        out: RecExpr::default(),
+        memo: HashMap::default(),
+    };
+    let mut new_ids = vec![Id::from(0); expr.len()];
+    for (id, node) in expr.topo_iter() {
+        let node = node.clone().map_children(|c| new_ids[usize::from(c)]);
+        new_ids[usize::from(id)] = rewriter.reduce(node);
+    }
+    let out = &rewriter.out;
+    out[new_ids[usize::from(expr.root())]].build_recexpr(|id| out[id].clone())
+}
+
+/// The state of [`rewrite_recexpr`]. `out` only grows, and `memo` maps
+/// every node added to it to the id of its normal form.
+struct TermRewriter<'a, L> {
+    rules: Vec<(&'a PatternAst<L>, &'a PatternAst<L>)>,
+    out: RecExpr<L>,
+    memo: HashMap<L, Id>,
+}
+
+impl<'a, L: Language> TermRewriter<'a, L> {
+    /// Normalizes `node`, whose children must already be normal.
+    fn reduce(&mut self, node: L) -> Id {
+        if let Some(&id) = self.memo.get(&node) {
+            return id;
+        }
+        let id = self.out.add(node.clone());
+        let mut result = id;
+        for i in 0..self.rules.len() {
+            let (lhs, rhs) = self.rules[i];
+            let mut subst = Subst::default();
+            if Self::matches(lhs, lhs.root(), &self.out, id, &mut subst) {
+                result = self.instantiate(rhs, &subst);
+                break;
+            }
+        }
+        self.memo.insert(node, result);
+        result
+    }
+
+    fn matches(
+        pat: &PatternAst<L>,
+        pat_id: Id,
+        expr: &RecExpr<L>,
+        id: Id,
+        subst: &mut Subst,
+    ) -> bool {
+        let node = &expr[id];
+        let children = match &pat[pat_id] {
+            // the rewritten terms are hash-consed, so equal terms have equal ids
+            ENodeOrVar::Var(v) => {
+                return match subst.get(*v) {
+                    Some(&bound) => bound == id,
+                    None => {
+                        subst.insert(*v, id);
+                        true
+                    }
+                }
+            }
+            ENodeOrVar::ENode(e) if e.matches(node) => e.children(),
+            ENodeOrVar::OpVar(v, children) if children.len() == node.len() => {
+                match subst.get_op(*v) {
+                    Some(bound) if !expr[bound].matches(node) => return false,
+                    Some(_) => {}
+                    None => {
+                        subst.insert_op(*v, id);
+                    }
+                }
+                children
+            }
+            ENodeOrVar::Wildcard => return true,
+            _ => return false,
+        };
+        children
+            .iter()
+            .zip(node.children())
+            .all(|(&p, &c)| Self::matches(pat, p, expr, c, subst))
+    }
+
+    fn instantiate(&mut self, pat: &PatternAst<L>, subst: &Subst) -> Id {
+        let mut ids = vec![Id::from(0); pat.len()];
+        for (i, pat_node) in pat.items() {
+            let node = match pat_node {
+                ENodeOrVar::Var(v) => {
+                    ids[usize::from(i)] = subst[*v];
+                    continue;
+                }
+                ENodeOrVar::ENode(e) => e.clone().map_children(|c| ids[usize::from(c)]),
+                ENodeOrVar::OpVar(v, children) => {
+                    let mut children = children.iter().map(|c| ids[usize::from(*c)]);
+                    let op = &self.out[subst.get_op(*v).unwrap()];
+                    op.clone().map_children(|_| children.next().unwrap())
+                }
+                ENodeOrVar::Wildcard => unreachable!("appliers have no wildcards"),
+            };
+            ids[usize::from(i)] = self.reduce(node);
+        }
+        *ids.last().unwrap()
+    }
+}
+
 /// Searches the given list of e-classes with a limit.
 pub(crate) fn search_eclasses_with_limit<'a, I, S, L, N>(
     searcher: &'a S,
@@ -153,6 +783,66 @@ where
     ms
 }
 
+/// Buckets the matches of [`Searcher::search_iter`] by the binding of `var`.
+pub(crate) fn search_grouped_by<L, N, S>(
+    searcher: &S,
+    egraph: &EGraph<L, N>,
+    var: Var,
+) -> HashMap<Id, Vec<Subst>>
+where
+    L: Language,
+    N: Analysis<L>,
+    S: Searcher<L, N> + ?Sized,
+{
+    assert_bound(searcher, var);
+    let mut groups: HashMap<Id, Vec<Subst>> = HashMap::default();
+    for (_eclass, subst) in searcher.search_iter(egraph) {
+        let key = egraph.find(subst[var]);
+        groups.entry(key).or_default().push(subst);
+    }
+    groups
+}
+
+/// Panics if `var` is not bound by `searcher`.
+pub(crate) fn assert_bound<L, N, S>(searcher: &S, var: Var)
+where
+    L: Language,
+    N: Analysis<L>,
+    S: Searcher<L, N> + ?Sized,
+{
+    assert!(
+        searcher.vars().contains(&var),
+        "{} is not bound by this searcher",
+        var
+    );
+}
+
+/// Searches the given e-classes one at a time, as the iterator reaches them.
+///
+/// Each e-class is searched in full with [`Searcher::search_eclass`], and its
+/// matches are then yielded one by one, so the laziness is per e-class,
+/// not per match.
+pub(crate) fn search_eclasses_batched<'a, I, S, L, N>(
+    searcher: &'a S,
+    egraph: &'a EGraph<L, N>,
+    eclasses: I,
+) -> Box<dyn Iterator<Item = (Id, Subst)> + 'a>
+where
+    L: Language,
+    N: Analysis<L>,
+    S: Searcher<L, N> + ?Sized,
+    I: IntoIterator<Item = Id>,
+    I::IntoIter: 'a,
+{
+    Box::new(eclasses.into_iter().flat_map(move |eclass| {
+        let substs = match searcher.search_eclass(egraph, eclass) {
+            None => vec![],
+            Some(m) => m.substs,
+        };
+        substs.into_iter().map(move |subst| (eclass, subst))
+    }))
+}
+
 /// The lefthand side of a [`Rewrite`].
 ///
 /// A [`Searcher`] is something that can search the egraph and find
@@ -198,6 +888,92 @@ where
         search_eclasses_with_limit(self, egraph, egraph.classes().map(|e| e.id), limit)
     }
 
+    /// Search the whole [`EGraph`] one e-class at a time, yielding
+    /// `(eclass, subst)` pairs.
+    ///
+    /// Unlike [`search`], this does not collect every match up front:
+    /// an e-class is only searched once the iterator reaches it, so a
+    /// caller that stops early (e.g. with [`Iterator::take`]) only pays
+    /// for the e-classes it has visited.
+    /// Each of those is searched in full, though, so stopping in the
+    /// middle of an e-class's matches saves nothing.
+    ///
+    /// [`search`]: Searcher::search
+    fn search_iter<'a>(
+        &'a self,
+        egraph: &'a EGraph<L, N>,
+    ) -> Box<dyn Iterator<Item = (Id, Subst)> + 'a> {
+        search_eclasses_batched(self, egraph, egraph.classes().map(|e| e.id))
+    }
+
+    /// Lazily yield every binding of every match as a flat
+    /// `(eclass, var, bound)` triple.
+    ///
+    /// This is [`search_iter`](Searcher::search_iter) with each [`Subst`]
+    /// flattened, which is handy for loading matches into a relational view.
+    ///
+    /// ```
+    /// use egg::*;
+    /// let mut egraph = EGraph::<SymbolLang, ()>::default();
+    /// let a = egraph.add_expr(&"(+ x y)".parse().unwrap());
+    /// egraph.rebuild();
+    ///
+    /// let pat: Pattern<SymbolLang> = "(+ ?a ?b)".parse().unwrap();
+    /// let mut bindings: Vec<_> = pat.all_bindings(&egraph).collect();
+    /// bindings.sort();
+    ///
+    /// let x = egraph.lookup(SymbolLang::leaf("x")).unwrap();
+    /// let y = egraph.lookup(SymbolLang::leaf("y")).unwrap();
+    /// let (va, vb): (Var, Var) = ("?a".parse().unwrap(), "?b".parse().unwrap());
+    /// let mut expected = vec![(a, va, x), (a, vb, y)];
+    /// expected.sort();
+    /// assert_eq!(bindings, expected);
+    /// ```
+    fn all_bindings<'a>(
+        &'a self,
+        egraph: &'a EGraph<L, N>,
+    ) -> Box<dyn Iterator<Item = (Id, Var, Id)> + 'a> {
+        Box::new(self.search_iter(egraph).flat_map(|(eclass, subst)| {
+            subst
+                .iter()
+                .map(|(v, id)| (eclass, *v, id))
+                .collect::<Vec<_>>()
+        }))
+    }
+
+    /// Search the whole [`EGraph`] and bucket the substitutions by the
+    /// e-class that `var` is bound to.
+    ///
+    /// The keys are canonical, so two substitutions binding `var` to
+    /// equivalent e-classes end up in the same bucket.
+    /// A [`Pattern`] whose root e-node has `var` as a child, like `?a` in
+    /// `(edge ?a ?b)`, is searched from each binding of `var` up through its
+    /// parents, so each bucket is built by the e-matching machine directly.
+    /// Otherwise, and by default, this buckets the matches of
+    /// [`search_iter`](Searcher::search_iter()), which costs as much as
+    /// collecting every match.
+    /// Panics if `var` is not bound by this searcher.
+    ///
+    /// ```
+    /// use egg::*;
+    /// let mut egraph = EGraph::<SymbolLang, ()>::default();
+    /// egraph.add_expr(&"(edge a b)".parse().unwrap());
+    /// egraph.add_expr(&"(edge a c)".parse().unwrap());
+    /// egraph.add_expr(&"(edge b c)".parse().unwrap());
+    /// egraph.rebuild();
+    ///
+    /// let pat: Pattern<SymbolLang> = "(edge ?a ?b)".parse().unwrap();
+    /// let groups = pat.search_grouped_by(&egraph, "?a".parse().unwrap());
+    /// let a = egraph.lookup(SymbolLang::leaf("a")).unwrap();
+    /// let b = egraph.lookup(SymbolLang::leaf("b")).unwrap();
+    /// assert_eq!(groups.len(), 2);
+    /// assert_eq!(groups[&a].len(), 2);
+    /// assert_eq!(groups[&b].len(), 1);
+    /// ```
+    fn search_grouped_by(&self, egraph: &EGraph<L, N>, var: Var) -> HashMap<Id, Vec<Subst>> {
+        search_grouped_by(self, egraph, var)
+    }
+
     /// Returns the number of matches in the e-graph
     fn n_matches(&self, egraph: &EGraph<L, N>) -> usize {
         self.search(egraph).iter().map(|m| m.substs.len()).sum()
@@ -212,6 +988,361 @@ where
     fn vars(&self) -> Vec<Var>;
 }
 
+/// A [`Searcher`] that matches every e-class containing an e-node that
+/// satisfies `predicate`, binding `var` to that e-class.
+///
+/// This is an escape hatch for searches that are awkward to write as a
+/// [`Pattern`], such as "any e-node whose operator is in this set",
+/// without having to implement the whole [`Searcher`] trait.
+/// It finds at most one match per e-class, and combines with
+/// [`Condition`]s through a [`ConditionalApplier`] like any other searcher.
+///
+/// # Example
+/// ```
+/// # use egg::*;
+/// let x: Var = "?x".parse().unwrap();
+/// let arith = PredicateSearcher::new(x, |n: &SymbolLang| ["+", "*"].contains(&n.op.as_str()));
+/// let rules: &[Rewrite<SymbolLang, ()>] = &[
+///     Rewrite::new("tag", arith, "(arith ?x)".parse::<Pattern<_>>().unwrap()).unwrap(),
+/// ];
+/// let runner = Runner::default()
+///     .with_iter_limit(1)
+///     .with_expr(&"(- (+ a b) (* a (f b)))".parse().unwrap())
+///     .run(rules);
+///
+/// let egraph = &runner.egraph;
+/// assert!(egraph.lookup_expr(&"(arith (+ a b))".parse().unwrap()).is_some());
+/// assert!(egraph.lookup_expr(&"(arith (* a (f b)))".parse().unwrap()).is_some());
+/// assert!(egraph.lookup_expr(&"(arith (f b))".parse().unwrap()).is_none());
+/// ```
+#[derive(Clone, Debug)]
+pub struct PredicateSearcher<F> {
+    /// The variable bound to each matching e-class.
+    pub var: Var,
+    /// The predicate one of the e-nodes of a matching e-class satisfies.
+    pub predicate: F,
+}
+
+impl<F> PredicateSearcher<F> {
+    /// Creates a new [`PredicateSearcher`].
+    pub fn new(var: Var, predicate: F) -> Self {
+        PredicateSearcher { var, predicate }
+    }
+}
+
+impl<L, N, F> Searcher<L, N> for PredicateSearcher<F>
+where
+    L: Language,
+    N: Analysis<L>,
+    F: Fn(&L) -> bool,
+{
+    fn search_eclass_with_limit(
+        &self,
+        egraph: &EGraph<L, N>,
+        eclass: Id,
+        limit: usize,
+    ) -> Option<SearchMatches<'_, L>> {
+        if limit == 0 || !egraph[eclass].iter().any(&self.predicate) {
+            return None;
+        }
+        let mut subst = Subst::with_capacity(1);
+        subst.insert(self.var, eclass);
+        Some(SearchMatches {
+            eclass,
+            substs: vec![subst],
+            ast: None,
+        })
+    }
+
+    fn vars(&self) -> Vec<Var> {
+        vec![self.var]
+    }
+}
+
+/// A [`Searcher`] for a family of rules that only differ by their
+/// operators, like distributivity of `*` over `+` and of `and` over `or`.
+///
+/// The `pattern` uses operator variables (like `?f` in `(?f ?x ?y)`) where
+/// the operators differ, and each family lists the operator
+/// [`discriminant`](Language::discriminant) each of these variables must
+/// be bound to. A match is kept if it agrees with at least one family.
+/// The righthand side can be a [`Pattern`] using the same operator
+/// variables, which rebuilds e-nodes with the operators that were matched.
+///
+/// # Example
+/// ```
+/// use egg::*;
+/// let (mul, add): (Var, Var) = ("?mul".parse().unwrap(), "?add".parse().unwrap());
+/// let distribute = OpFamilySearcher::new(
+///     "(?mul ?a (?add ?b ?c))".parse().unwrap(),
+///     vec![
+///         vec![(mul, Symbol::from("*")), (add, Symbol::from("+"))],
+///         vec![(mul, Symbol::from("and")), (add, Symbol::from("or"))],
+///     ],
+/// );
+/// let applier: Pattern<SymbolLang> = "(?add (?mul ?a ?b) (?mul ?a ?c))".parse().unwrap();
+/// let rules = vec![Rewrite::new("distribute", distribute, applier).unwrap()];
+///
+/// let runner = Runner::<SymbolLang, ()>::default()
+///     .with_expr(&"(* x (+ y z))".parse().unwrap())
+///     .with_expr(&"(and p (or q r))".parse().unwrap())
+///     .with_expr(&"(+ x (* y z))".parse().unwrap())
+///     .with_iter_limit(1)
+///     .run(&rules);
+///
+/// let found = |s: &str| runner.egraph.lookup_expr(&s.parse().unwrap()).is_some();
+/// assert!(found("(+ (* x y) (* x z))"));
+/// assert!(found("(or (and p q) (and p r))"));
+/// assert!(!found("(* (+ x y) (+ x z))"));
+/// ```
+#[derive(Debug, Clone)]
+pub struct OpFamilySearcher<L: Language> {
+    pattern: Pattern<L>,
+    families: Vec<Vec<(Var, L::Discriminant)>>,
+}
+
+impl<L: Language> OpFamilySearcher<L> {
+    /// Creates a new [`OpFamilySearcher`] matching `pattern` with the
+    /// operators of one of the `families`.
+    ///
+    /// Panics if a family refers to a variable that is not an operator
+    /// variable of `pattern`.
+    pub fn new(pattern: Pattern<L>, families: Vec<Vec<(Var, L::Discriminant)>>) -> Self {
+        for (v, _) in families.iter().flatten() {
+            assert!(
+                pattern
+                    .ast
+                    .iter()
+                    .any(|n| matches!(n, ENodeOrVar::OpVar(w, _) if w == v)),
+                "{} is not an operator variable of the pattern",
+                v
+            );
+        }
+        OpFamilySearcher { pattern, families }
+    }
+
+    /// The pattern, with operator variables, shared by the family.
+    pub fn pattern(&self) -> &Pattern<L> {
+        &self.pattern
+    }
+
+    /// The allowed operators for the operator variables of the pattern.
+    pub fn families(&self) -> &[Vec<(Var, L::Discriminant)>] {
+        &self.families
+    }
+}
+
+impl<L, N> Searcher<L, N> for OpFamilySearcher<L>
+where
+    L: Language,
+    N: Analysis<L>,
+{
+    fn search_eclass_with_limit(
+        &self,
+        egraph: &EGraph<L, N>,
+        eclass: Id,
+        limit: usize,
+    ) -> Option<SearchMatches<'_, L>> {
+        let mut matches = self
+            .pattern
+            .search_eclass_with_limit(egraph, eclass, usize::MAX)?;
+        matches.substs.retain(|subst| {
+            self.families.iter().any(|family| {
+                family.iter().all(|(v, discriminant)| {
+                    matches!(subst.get_op(*v), Some(op)
+                        if egraph.id_to_node(op).discriminant() == *discriminant)
+                })
+            })
+        });
+        matches.substs.truncate(limit);
+        if matches.substs.is_empty() {
+            None
+        } else {
+            Some(matches)
+        }
+    }
+
+    fn get_pattern_ast(&self) -> Option<&PatternAst<L>> {
+        Some(&self.pattern.ast)
+    }
+
+    fn vars(&self) -> Vec<Var> {
+        self.pattern.vars()
+    }
+}
+
+/// A [`Searcher`] that remembers the matches of another one, and only
+/// searches again the e-classes that may have changed.
+///
+/// Each [`search`](Searcher::search) records the e-graph's
+/// [`epoch`](EGraph::epoch).
+/// The next search asks the e-graph which e-classes
+/// [changed](EGraph::classes_changed_since) since, searches again those
+/// and all their ancestors, and reuses the other matches with their ids
+/// canonicalized.
+/// This pays off for rules that only match in a part of the e-graph that
+/// rarely changes.
+///
+/// This assumes that the matches of the wrapped searcher in an e-class
+/// only depend on the e-nodes reachable from that e-class, as is the case
+/// for [`Pattern`]s, but not e.g. for searchers that look at analysis data.
+/// A [`CachingSearcher`] should only ever search a single e-graph.
+///
+/// The e-graph must [track its changes](EGraph::with_change_tracking),
+/// otherwise every search starts from scratch.
+/// Searches with a limit, like those of the [`BackoffScheduler`], are also
+/// served from the cache: the e-classes to search again are always searched
+/// in full so that the cache stays complete, and only the returned matches
+/// are cut down to `limit`.
+///
+/// # Example
+/// ```
+/// use egg::*;
+/// let pat: Pattern<SymbolLang> = "(+ ?a ?b)".parse().unwrap();
+/// let searcher = CachingSearcher::new(pat.clone());
+///
+/// let mut egraph = EGraph::<SymbolLang, ()>::default().with_change_tracking(1);
+/// egraph.add_expr(&"(* (+ a b) c)".parse().unwrap());
+/// egraph.rebuild();
+/// assert_eq!(searcher.search(&egraph).len(), 1);
+///
+/// // only the new e-classes and their ancestors are searched again
+/// egraph.add_expr(&"(+ c d)".parse().unwrap());
+/// egraph.rebuild();
+/// assert_eq!(searcher.search(&egraph).len(), 2);
+/// assert_eq!(pat.search(&egraph).len(), 2);
+/// ```
+#[derive(Debug)]
+pub struct CachingSearcher<L: Language, S> {
+    searcher: S,
+    cache: Mutex<Option<SearchCache<L>>>,
+}
+
+/// The matches found by a [`CachingSearcher`] at some epoch.
+#[derive(Debug)]
+struct SearchCache<L: Language> {
+    epoch: usize,
+    matches: HashMap<Id, (Vec<Subst>, Option<PatternAst<L>>)>,
+}
+
+impl<L: Language, S> CachingSearcher<L, S> {
+    /// Creates a new [`CachingSearcher`] with an empty cache.
+    pub fn new(searcher: S) -> Self {
+        CachingSearcher {
+            searcher,
+            cache: Mutex::new(None),
+        }
+    }
+}
+
+impl<L: Language, S: Clone> Clone for CachingSearcher<L, S> {
+    fn clone(&self) -> Self {
+        CachingSearcher::new(self.searcher.clone())
+    }
+}
+
+impl<L, N, S> Searcher<L, N> for CachingSearcher<L, S>
+where
+    L: Language,
+    N: Analysis<L>,
+    S: Searcher<L, N>,
+{
+    fn search_eclass_with_limit(
+        &self,
+        egraph: &EGraph<L, N>,
+        eclass: Id,
+        limit: usize,
+    ) -> Option<SearchMatches<'_, L>> {
+        self.searcher
+            .search_eclass_with_limit(egraph, eclass, limit)
+    }
+
+    fn search_with_limit(&self, egraph: &EGraph<L, N>, limit: usize) -> Vec<SearchMatches<'_, L>> {
+        let own = |m: SearchMatches<L>| (m.eclass, (m.substs, m.ast.map(Cow::into_owned)));
+        let mut cache = self.cache.lock().unwrap();
+        let changed = cache
+            .as_ref()
+            .and_then(|c| egraph.classes_changed_since(c.epoch));
+        let matches = match (cache.take(), changed) {
+            (Some(old), Some(changed)) => {
+                let stale = ancestors(egraph, changed);
+                let mut matches: HashMap<_, _> = old
+                    .matches
+                    .into_iter()
+                    .filter_map(|(eclass, (mut substs, ast))| {
+                        let eclass = egraph.find(eclass);
+                        if stale.contains(&eclass) {
+                            return None;
+                        }
+                        for subst in &mut substs {
+                            for (_, id) in subst.vec.iter_mut() {
+                                *id = egraph.find(*id);
+                            }
+                        }
+                        Some((eclass, (substs, ast)))
+                    })
+                    .collect();
+                for eclass in stale {
+                    if let Some(m) = self.searcher.search_eclass(egraph, eclass) {
+                        let (eclass, m) = own(m);
+                        matches.insert(eclass, m);
+                    }
+                }
+                matches
+            }
+            _ => self.searcher.search(egraph).into_iter().map(own).collect(),
+        };
+
+        let mut budget = limit;
+        let mut result = vec![];
+        for class in egraph.classes() {
+            if budget == 0 {
+                break;
+            }
+            if let Some((substs, ast)) = matches.get(&class.id) {
+                let substs: Vec<Subst> = substs.iter().take(budget).cloned().collect();
+                budget -= substs.len();
+                result.push(SearchMatches {
+                    eclass: class.id,
+                    substs,
+                    ast: ast.clone().map(Cow::Owned),
+                });
+            }
+        }
+        *cache = Some(SearchCache {
+            epoch: egraph.epoch(),
+            matches,
+        });
+        result
+    }
+
+    fn get_pattern_ast(&self) -> Option<&PatternAst<L>> {
+        self.searcher.get_pattern_ast()
+    }
+
+    fn vars(&self) -> Vec<Var> {
+        self.searcher.vars()
+    }
+}
+
+/// Returns `classes` and all the e-classes above them.
+fn ancestors<L: Language, N: Analysis<L>>(
+    egraph: &EGraph<L, N>,
+    classes: HashSet<Id>,
+) -> HashSet<Id> {
+    let mut todo: Vec<Id> = classes.iter().copied().collect();
+    let mut seen = classes;
+    while let Some(id) = todo.pop() {
+        for parent in egraph[id].parents() {
+            let parent = egraph.find(parent);
+            if seen.insert(parent) {
+                todo.push(parent);
+            }
+        }
+    }
+    seen
+}
+
 /// The righthand side of a [`Rewrite`].
 ///
 /// An [`Applier`] is anything that can do something with a
@@ -313,29 +1444,252 @@ where
 ///     }
 /// }
 ///
-/// let start = "(+ x (* y z))".parse().unwrap();
-/// Runner::default().with_expr(&start).run(rules);
+/// let start = "(+ x (* y z))".parse().unwrap();
+/// Runner::default().with_expr(&start).run(rules);
+/// ```
+pub trait Applier<L, N>
+where
+    L: Language,
+    N: Analysis<L>,
+{
+    /// Apply many substitutions.
+    ///
+    /// This method should call [`apply_one`] for each match.
+    ///
+    /// It returns the ids resulting from the calls to [`apply_one`].
+    /// The default implementation does this and should suffice for
+    /// most use cases.
+    ///
+    /// [`apply_one`]: Applier::apply_one()
+    fn apply_matches(
+        &self,
+        egraph: &mut EGraph<L, N>,
+        matches: &[SearchMatches<L>],
+        rule_name: Symbol,
+    ) -> Vec<Id> {
+        let mut added = vec![];
+        for mat in matches {
+            let ast = if egraph.are_explanations_enabled() {
+                mat.ast.as_ref().map(|cow| cow.as_ref())
+            } else {
+                None
+            };
+            for subst in &mat.substs {
+                let ids = self.apply_one(egraph, mat.eclass, subst, ast, rule_name);
+                added.extend(ids)
+            }
+        }
+        added
+    }
+
+    /// For patterns, get the ast directly as a reference.
+    fn get_pattern_ast(&self) -> Option<&PatternAst<L>> {
+        None
+    }
+
+    /// Apply a single substitution.
+    ///
+    /// An [`Applier`] should add things and union them with `eclass`.
+    /// Appliers can also inspect the eclass if necessary using the
+    /// `eclass` parameter.
+    ///
+    /// This should return a list of [`Id`]s of eclasses that
+    /// were changed. There can be zero, one, or many.
+    /// When explanations mode is enabled, a [`PatternAst`] for
+    /// the searcher is provided.
+    ///
+    /// [`apply_matches`]: Applier::apply_matches()
+    fn apply_one(
+        &self,
+        egraph: &mut EGraph<L, N>,
+        eclass: Id,
+        subst: &Subst,
+        searcher_ast: Option<&PatternAst<L>>,
+        rule_name: Symbol,
+    ) -> Vec<Id>;
+
+    /// Returns `true` if applying `subst` at `eclass` is known to leave the
+    /// e-graph unchanged.
+    ///
+    /// The [`Runner`] uses this to stop early in
+    /// [aggressive saturation](Runner::with_aggressive_saturation()) mode.
+    /// The default implementation returns `false`, which is always safe.
+    #[allow(unused_variables)]
+    fn is_noop(&self, egraph: &EGraph<L, N>, eclass: Id, subst: &Subst) -> bool {
+        false
+    }
+
+    /// Returns a list of variables that this Applier assumes are bound.
+    ///
+    /// `egg` will check that the corresponding `Searcher` binds those
+    /// variables.
+    /// By default this return an empty `Vec`, which basically turns off the
+    /// checking.
+    fn vars(&self) -> Vec<Var> {
+        vec![]
+    }
+}
+
+/// An [`Applier`] that checks a [`Condition`] before applying.
+///
+/// A [`ConditionalApplier`] simply calls [`check`] on the
+/// [`Condition`] before calling [`apply_one`] on the inner
+/// [`Applier`].
+///
+/// See the [`rewrite!`] macro documentation for an example.
+///
+/// [`apply_one`]: Applier::apply_one()
+/// [`check`]: Condition::check()
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ConditionalApplier<C, A> {
+    /// The [`Condition`] to [`check`] before calling [`apply_one`] on
+    /// `applier`.
+    ///
+    /// [`apply_one`]: Applier::apply_one()
+    /// [`check`]: Condition::check()
+    pub condition: C,
+    /// The inner [`Applier`] to call once `condition` passes.
+    ///
+    pub applier: A,
+}
+
+impl<C, A, N, L> Applier<L, N> for ConditionalApplier<C, A>
+where
+    L: Language,
+    C: Condition<L, N>,
+    A: Applier<L, N>,
+    N: Analysis<L>,
+{
+    fn get_pattern_ast(&self) -> Option<&PatternAst<L>> {
+        self.applier.get_pattern_ast()
+    }
+
+    fn apply_one(
+        &self,
+        egraph: &mut EGraph<L, N>,
+        eclass: Id,
+        subst: &Subst,
+        searcher_ast: Option<&PatternAst<L>>,
+        rule_name: Symbol,
+    ) -> Vec<Id> {
+        if self.condition.check(egraph, eclass, subst) {
+            self.applier
+                .apply_one(egraph, eclass, subst, searcher_ast, rule_name)
+        } else {
+            vec![]
+        }
+    }
+
+    fn vars(&self) -> Vec<Var> {
+        let mut vars = self.applier.vars();
+        vars.extend(self.condition.vars());
+        vars
+    }
+}
+
+/// An [`Applier`] that only fires when it would make a cheaper term.
+///
+/// A [`CostGuardedApplier`] wraps an `inner` [`Applier`] whose right-hand
+/// side is a [`Pattern`], and skips every match where the instantiated
+/// pattern would not be strictly cheaper under `cost_fn` than what the
+/// matched e-class already has.
+/// This keeps optimization-only rule sets from filling the e-graph with
+/// equal or worse terms.
+///
+/// The costs are approximated so that the right-hand side never has to be
+/// added first:
+/// - The cost of the left-hand side is taken to be the cost of the best
+///   term currently in the matched e-class, which is at most the cost of
+///   the matched term itself. So in effect the rule fires only if it would
+///   improve the e-class.
+/// - The cost of the right-hand side is computed from the pattern, using the
+///   best cost of the e-class bound to each variable.
+/// - Both are computed by an [`Extractor`] built once per call to
+///   [`apply_matches`](Applier::apply_matches()), before any match is
+///   applied, so matches applied in the same batch do not see each other's
+///   effects.
+///
+/// Building the [`Extractor`] visits the whole e-graph, so this is
+/// better suited to a few selected rules than to a large rule set.
+/// If `inner` has no pattern, every match is applied unguarded.
+///
+/// # Example
 /// ```
-pub trait Applier<L, N>
+/// # use egg::*;
+/// let rules: &[Rewrite<SymbolLang, ()>] = &[
+///     rewrite!("expand"; "(* ?a 2)" => {
+///         CostGuardedApplier::new("(+ ?a ?a)".parse::<Pattern<_>>().unwrap(), AstSize)
+///     }),
+///     rewrite!("shrink"; "(* ?a 1)" => {
+///         CostGuardedApplier::new("?a".parse::<Pattern<_>>().unwrap(), AstSize)
+///     }),
+/// ];
+/// let runner = Runner::default()
+///     .with_expr(&"(* (* (f x) 1) 2)".parse().unwrap())
+///     .run(rules);
+///
+/// let egraph = &runner.egraph;
+/// assert!(egraph.lookup_expr(&"(* (f x) 2)".parse().unwrap()).is_some());
+/// // (+ (f x) (f x)) has size 5, so it is never added
+/// assert!(egraph.lookup_expr(&"(+ (f x) (f x))".parse().unwrap()).is_none());
+/// ```
+#[derive(Clone, Debug)]
+pub struct CostGuardedApplier<A, CF> {
+    /// The inner [`Applier`] to call for matches that make a cheaper term.
+    pub inner: A,
+    /// The [`CostFunction`] used to compare terms.
+    pub cost_fn: CF,
+}
+
+impl<A, CF> CostGuardedApplier<A, CF> {
+    /// Creates a new [`CostGuardedApplier`].
+    pub fn new(inner: A, cost_fn: CF) -> Self {
+        CostGuardedApplier { inner, cost_fn }
+    }
+}
+
+impl<A, CF, L, N> Applier<L, N> for CostGuardedApplier<A, CF>
 where
     L: Language,
     N: Analysis<L>,
+    A: Applier<L, N>,
+    CF: CostFunction<L> + Clone,
 {
-    /// Apply many substitutions.
-    ///
-    /// This method should call [`apply_one`] for each match.
-    ///
-    /// It returns the ids resulting from the calls to [`apply_one`].
-    /// The default implementation does this and should suffice for
-    /// most use cases.
-    ///
-    /// [`apply_one`]: Applier::apply_one()
     fn apply_matches(
         &self,
         egraph: &mut EGraph<L, N>,
         matches: &[SearchMatches<L>],
         rule_name: Symbol,
     ) -> Vec<Id> {
+        let rhs = match self.inner.get_pattern_ast() {
+            Some(rhs) => rhs,
+            None => return self.inner.apply_matches(egraph, matches, rule_name),
+        };
+
+        let mut keep = vec![];
+        {
+            let extractor = Extractor::new(egraph, self.cost_fn.clone());
+            let mut cost_fn = self.cost_fn.clone();
+            for mat in matches {
+                let lhs_cost = extractor.get_best_cost(mat.eclass);
+                for subst in &mat.substs {
+                    let rhs_cost = pattern_cost(
+                        rhs,
+                        &mut cost_fn,
+                        |v| extractor.get_best_cost(subst[v]).cloned(),
+                        |v, children| pattern::op_var_node(egraph, subst, v, children),
+                    );
+                    let cheaper = match (rhs_cost, lhs_cost) {
+                        (Some(rhs), Some(lhs)) => rhs < *lhs,
+                        (Some(_), None) => true,
+                        (None, _) => false,
+                    };
+                    keep.push(cheaper);
+                }
+            }
+        }
+
+        let mut keep = keep.into_iter();
         let mut added = vec![];
         for mat in matches {
             let ast = if egraph.are_explanations_enabled() {
@@ -344,30 +1698,19 @@ where
                 None
             };
             for subst in &mat.substs {
-                let ids = self.apply_one(egraph, mat.eclass, subst, ast, rule_name);
-                added.extend(ids)
+                if keep.next().unwrap() {
+                    let ids = self.apply_one(egraph, mat.eclass, subst, ast, rule_name);
+                    added.extend(ids)
+                }
             }
         }
         added
     }
 
-    /// For patterns, get the ast directly as a reference.
     fn get_pattern_ast(&self) -> Option<&PatternAst<L>> {
-        None
+        self.inner.get_pattern_ast()
     }
 
-    /// Apply a single substitution.
-    ///
-    /// An [`Applier`] should add things and union them with `eclass`.
-    /// Appliers can also inspect the eclass if necessary using the
-    /// `eclass` parameter.
-    ///
-    /// This should return a list of [`Id`]s of eclasses that
-    /// were changed. There can be zero, one, or many.
-    /// When explanations mode is enabled, a [`PatternAst`] for
-    /// the searcher is provided.
-    ///
-    /// [`apply_matches`]: Applier::apply_matches()
     fn apply_one(
         &self,
         egraph: &mut EGraph<L, N>,
@@ -375,51 +1718,125 @@ where
         subst: &Subst,
         searcher_ast: Option<&PatternAst<L>>,
         rule_name: Symbol,
-    ) -> Vec<Id>;
+    ) -> Vec<Id> {
+        self.inner
+            .apply_one(egraph, eclass, subst, searcher_ast, rule_name)
+    }
 
-    /// Returns a list of variables that this Applier assumes are bound.
-    ///
-    /// `egg` will check that the corresponding `Searcher` binds those
-    /// variables.
-    /// By default this return an empty `Vec`, which basically turns off the
-    /// checking.
     fn vars(&self) -> Vec<Var> {
-        vec![]
+        self.inner.vars()
     }
 }
 
-/// An [`Applier`] that checks a [`Condition`] before applying.
+/// An [`Applier`] that rewrites a single child of the matched e-node.
 ///
-/// A [`ConditionalApplier`] simply calls [`check`] on the
-/// [`Condition`] before calling [`apply_one`] on the inner
-/// [`Applier`].
+/// A [`ChildApplier`] is built from the `parent` pattern, which should be
+/// the searcher of the rule, and adds the `parent` term with its child at
+/// `position` replaced by `inner`, then unions it with the matched e-class.
+/// The other children are instantiated from `parent` like in a plain
+/// pattern rewrite, so they are the very e-classes that were matched.
 ///
-/// See the [`rewrite!`] macro documentation for an example.
+/// So the expected shape is a searcher whose root is an e-node, often
+/// with a variable for each child, such as `(if ?c ?then ?else)`,
+/// and an `inner` pattern over the variables of the searcher.
+/// With a `position` of 1 and an `inner` of `(simplify ?then)`, this is
+/// the same as the right-hand side `(if ?c (simplify ?then) ?else)`,
+/// without having to repeat the siblings.
 ///
-/// [`apply_one`]: Applier::apply_one()
-/// [`check`]: Condition::check()
+/// # Example
+/// ```
+/// # use egg::*;
+/// let parent: Pattern<SymbolLang> = "(if ?c ?then ?else)".parse().unwrap();
+/// let inner: Pattern<SymbolLang> = "(simplify ?then)".parse().unwrap();
+/// let applier = ChildApplier::new(parent.clone(), 1, inner);
+/// let rules: &[Rewrite<SymbolLang, ()>] = &[Rewrite::new("then", parent, applier).unwrap()];
+/// let runner = Runner::default()
+///     .with_iter_limit(1)
+///     .with_expr(&"(if c t e)".parse().unwrap())
+///     .run(rules);
+///
+/// let expected = "(if c (simplify t) e)".parse().unwrap();
+/// let root = runner.egraph.find(runner.roots[0]);
+/// assert_eq!(runner.egraph.lookup_expr(&expected), Some(root));
+/// ```
 #[derive(Clone, Debug, PartialEq, Eq)]
-pub struct ConditionalApplier<C, A> {
-    /// The [`Condition`] to [`check`] before calling [`apply_one`] on
-    /// `applier`.
-    ///
-    /// [`apply_one`]: Applier::apply_one()
-    /// [`check`]: Condition::check()
-    pub condition: C,
-    /// The inner [`Applier`] to call once `condition` passes.
+pub struct ChildApplier<L> {
+    position: usize,
+    inner: Pattern<L>,
+    applier: Pattern<L>,
+}
+
+impl<L: Language> ChildApplier<L> {
+    /// Creates a new [`ChildApplier`] that replaces the child at `position`
+    /// of `parent` with `inner`.
     ///
-    pub applier: A,
+    /// # Panics
+    /// Panics if the root of `parent` is a variable, or if it has no child
+    /// at `position`.
+    pub fn new(parent: Pattern<L>, position: usize, inner: Pattern<L>) -> Self {
+        fn copy<L: Language>(to: &mut PatternAst<L>, from: &PatternAst<L>, id: Id) -> Id {
+            let node = from[id].clone().map_children(|child| copy(to, from, child));
+            to.add(node)
+        }
+
+        let root = match &parent.ast[parent.ast.root()] {
+            ENodeOrVar::ENode(node) => node.clone(),
+            _ => panic!("ChildApplier needs a parent pattern rooted at an e-node"),
+        };
+        assert!(
+            position < root.len(),
+            "ChildApplier position {} is out of bounds for {}",
+            position,
+            root.len()
+        );
+
+        let mut ast = PatternAst::default();
+        let mut i = 0;
+        let root = root.map_children(|child| {
+            let id = if i == position {
+                copy(&mut ast, &inner.ast, inner.ast.root())
+            } else {
+                copy(&mut ast, &parent.ast, child)
+            };
+            i += 1;
+            id
+        });
+        ast.add(ENodeOrVar::ENode(root));
+
+        ChildApplier {
+            position,
+            inner,
+            applier: Pattern::new(ast),
+        }
+    }
+
+    /// The position of the child that is rewritten.
+    pub fn position(&self) -> usize {
+        self.position
+    }
+
+    /// The pattern the child is rewritten to.
+    pub fn inner(&self) -> &Pattern<L> {
+        &self.inner
+    }
 }
 
-impl<C, A, N, L> Applier<L, N> for ConditionalApplier<C, A>
+impl<L, N> Applier<L, N> for ChildApplier<L>
 where
     L: Language,
-    C: Condition<L, N>,
-    A: Applier<L, N>,
     N: Analysis<L>,
 {
+    fn apply_matches(
+        &self,
+        egraph: &mut EGraph<L, N>,
+        matches: &[SearchMatches<L>],
+        rule_name: Symbol,
+    ) -> Vec<Id> {
+        self.applier.apply_matches(egraph, matches, rule_name)
+    }
+
     fn get_pattern_ast(&self) -> Option<&PatternAst<L>> {
-        self.applier.get_pattern_ast()
+        Some(&self.applier.ast)
     }
 
     fn apply_one(
@@ -430,19 +1847,209 @@ where
         searcher_ast: Option<&PatternAst<L>>,
         rule_name: Symbol,
     ) -> Vec<Id> {
-        if self.condition.check(egraph, eclass, subst) {
-            self.applier
-                .apply_one(egraph, eclass, subst, searcher_ast, rule_name)
-        } else {
-            vec![]
+        self.applier
+            .apply_one(egraph, eclass, subst, searcher_ast, rule_name)
+    }
+
+    fn vars(&self) -> Vec<Var> {
+        self.applier.vars()
+    }
+}
+
+/// An [`Applier`] that stops adding to the e-graph once a budget runs out.
+///
+/// Every call to [`apply_one`](Applier::apply_one()) subtracts the number
+/// of e-nodes the `inner` applier added from `budget`, and once `budget`
+/// reaches zero this applier does nothing.
+/// The budget is checked before each call, so the last call may go over it.
+/// Since the budget is an [`Arc`], several rules can share it to cap the
+/// total structure they synthesize together.
+/// It is never refilled, so reset it yourself between runs.
+///
+/// Which matches get applied before the budget is exhausted depends on the
+/// order in which rules and matches are applied, which makes the result of
+/// saturation order-dependent.
+/// Either choose a budget large enough that it is never hit in normal runs,
+/// or only use it for exploratory runs.
+///
+/// # Example
+/// ```
+/// # use egg::*;
+/// use std::sync::{atomic::{AtomicUsize, Ordering}, Arc};
+///
+/// let budget = Arc::new(AtomicUsize::new(10));
+/// let grow = |name: &str, lhs: &str, rhs: &str| {
+///     let rhs: Pattern<SymbolLang> = rhs.parse().unwrap();
+///     Rewrite::new(name, lhs.parse::<Pattern<_>>().unwrap(), BudgetedApplier::new(rhs, budget.clone()))
+///         .unwrap()
+/// };
+/// let rules: Vec<Rewrite<SymbolLang, ()>> = vec![
+///     grow("grow-f", "(f ?a)", "(f (s ?a))"),
+///     grow("grow-g", "(g ?a)", "(g (s ?a))"),
+/// ];
+///
+/// let runner = Runner::default()
+///     .with_iter_limit(100)
+///     .with_scheduler(SimpleScheduler)
+///     .with_expr(&"(f x)".parse().unwrap())
+///     .with_expr(&"(g x)".parse().unwrap())
+///     .run(&rules);
+/// assert_eq!(budget.load(Ordering::Relaxed), 0);
+/// // the 3 initial e-nodes, the budget, and an overshoot of at most one
+/// // since an application adds at most two e-nodes
+/// assert!(runner.egraph.total_size() <= 3 + 10 + 1);
+/// ```
+#[derive(Clone, Debug)]
+pub struct BudgetedApplier<A> {
+    /// The inner [`Applier`] to call while there is budget left.
+    pub inner: A,
+    /// How many more e-nodes the appliers sharing this budget may add.
+    pub budget: Arc<AtomicUsize>,
+}
+
+impl<A> BudgetedApplier<A> {
+    /// Creates a new [`BudgetedApplier`] drawing from `budget`.
+    pub fn new(inner: A, budget: Arc<AtomicUsize>) -> Self {
+        BudgetedApplier { inner, budget }
+    }
+}
+
+impl<A, L, N> Applier<L, N> for BudgetedApplier<A>
+where
+    L: Language,
+    N: Analysis<L>,
+    A: Applier<L, N>,
+{
+    fn get_pattern_ast(&self) -> Option<&PatternAst<L>> {
+        self.inner.get_pattern_ast()
+    }
+
+    fn apply_one(
+        &self,
+        egraph: &mut EGraph<L, N>,
+        eclass: Id,
+        subst: &Subst,
+        searcher_ast: Option<&PatternAst<L>>,
+        rule_name: Symbol,
+    ) -> Vec<Id> {
+        if self.budget.load(Ordering::Relaxed) == 0 {
+            return vec![];
         }
+        let before = egraph.total_size();
+        let ids = self
+            .inner
+            .apply_one(egraph, eclass, subst, searcher_ast, rule_name);
+        let added = egraph.total_size().saturating_sub(before);
+        let _ = self
+            .budget
+            .fetch_update(Ordering::Relaxed, Ordering::Relaxed, |left| {
+                Some(left.saturating_sub(added))
+            });
+        ids
     }
 
     fn vars(&self) -> Vec<Var> {
-        let mut vars = self.applier.vars();
-        vars.extend(self.condition.vars());
-        vars
+        self.inner.vars()
+    }
+}
+
+/// An [`Applier`] for rules that should only replace terms, never union
+/// them.
+///
+/// In an [`EGraph`], a [`ReplaceApplier`] never does anything, so
+/// [`Runner`](crate::Runner)s leave its rule alone.
+/// But it still exposes the `inner` pattern through
+/// [`get_pattern_ast`](Applier::get_pattern_ast), so [`rewrite_recexpr`]
+/// applies the rule as a classic term rewrite. This lets a cleanup rule
+/// that discards the matched structure sit in the same rule set as the
+/// equalities, for a final normalization pass after saturation.
+///
+/// # Example
+/// ```
+/// # use egg::*;
+/// let lhs: Pattern<SymbolLang> = "(debug ?a)".parse().unwrap();
+/// let rhs: Pattern<SymbolLang> = "?a".parse().unwrap();
+/// let rules: Vec<Rewrite<SymbolLang, ()>> = vec![
+///     rewrite!("mul-1"; "(* ?a 1)" => "?a"),
+///     Rewrite::new("strip-debug", lhs, ReplaceApplier::new(rhs)).unwrap(),
+/// ];
+///
+/// let expr: RecExpr<SymbolLang> = "(debug (* x 1))".parse().unwrap();
+/// let runner = Runner::default().with_expr(&expr).run(&rules);
+/// let (_, best) = Extractor::new(&runner.egraph, AstSize).find_best(runner.roots[0]);
+/// assert_eq!(best.to_string(), "(debug x)");
+/// assert_eq!(rewrite_recexpr(&best, &rules).to_string(), "x");
+/// ```
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ReplaceApplier<A> {
+    /// The righthand side used by [`rewrite_recexpr`].
+    pub inner: A,
+}
+
+impl<A> ReplaceApplier<A> {
+    /// Creates a new [`ReplaceApplier`] that replaces with `inner`.
+    pub fn new(inner: A) -> Self {
+        ReplaceApplier { inner }
+    }
+}
+
+impl<A, L, N> Applier<L, N> for ReplaceApplier<A>
+where
+    L: Language,
+    N: Analysis<L>,
+    A: Applier<L, N>,
+{
+    fn get_pattern_ast(&self) -> Option<&PatternAst<L>> {
+        self.inner.get_pattern_ast()
+    }
+
+    fn apply_one(
+        &self,
+        _egraph: &mut EGraph<L, N>,
+        _eclass: Id,
+        _subst: &Subst,
+        _searcher_ast: Option<&PatternAst<L>>,
+        _rule_name: Symbol,
+    ) -> Vec<Id> {
+        vec![]
+    }
+
+    fn is_noop(&self, _egraph: &EGraph<L, N>, _eclass: Id, _subst: &Subst) -> bool {
+        true
+    }
+
+    fn vars(&self) -> Vec<Var> {
+        self.inner.vars()
+    }
+}
+
+/// Computes the cost of a [`PatternAst`], given the cost of each variable
+/// and the e-node each operator variable stands for.
+/// Returns `None` if some variable has no cost.
+fn pattern_cost<L, CF>(
+    pat: &PatternAst<L>,
+    cost_fn: &mut CF,
+    mut var_cost: impl FnMut(Var) -> Option<CF::Cost>,
+    mut op_node: impl FnMut(Var, &[Id]) -> L,
+) -> Option<CF::Cost>
+where
+    L: Language,
+    CF: CostFunction<L>,
+{
+    let mut costs: Vec<CF::Cost> = Vec::with_capacity(pat.len());
+    for node in pat {
+        let cost = match node {
+            ENodeOrVar::Var(v) => var_cost(*v)?,
+            ENodeOrVar::Wildcard => return None,
+            ENodeOrVar::ENode(n) => cost_fn.cost(n, |id| costs[usize::from(id)].clone()),
+            ENodeOrVar::OpVar(v, children) => {
+                let n = op_node(*v, children);
+                cost_fn.cost(&n, |id| costs[usize::from(id)].clone())
+            }
+        };
+        costs.push(cost);
     }
+    costs.pop()
 }
 
 /// A condition to check in a [`ConditionalApplier`].
@@ -538,6 +2145,84 @@ where
     }
 }
 
+/// A [`Condition`] that passes if a [`MultiPattern`] matches in a way
+/// that agrees with the current substitution.
+///
+/// The variables in `bound` must be bound by the searcher of the rule;
+/// a match of the multipattern only counts if it binds each of them to the
+/// same e-class as the substitution does. All its other variables are
+/// existential, so this can express preconditions like "`?a`, `?b` and
+/// some `?c` form a triangle".
+///
+/// The search starts from the e-classes of the `bound` variables: a
+/// pattern whose root has a bound child is only looked for among the
+/// parents of that child, and the search stops at the first match.
+/// The e-graph is never rebuilt, so when other matches were applied
+/// earlier in the same iteration, matches that need those unions'
+/// congruences are missed until the next iteration.
+///
+/// # Example
+/// ```
+/// use egg::*;
+/// let mut egraph = EGraph::<SymbolLang, ()>::default();
+/// for edge in ["(edge a b)", "(edge b c)", "(edge c a)", "(edge c d)"] {
+///     egraph.add_expr(&edge.parse().unwrap());
+/// }
+/// egraph.rebuild();
+///
+/// let triangle = MultiPatternCondition::new(
+///     "?e1 = (edge ?a ?b), ?e2 = (edge ?b ?c), ?e3 = (edge ?c ?a)".parse().unwrap(),
+///     vec!["?a".parse().unwrap(), "?b".parse().unwrap()],
+/// );
+/// let rules: Vec<Rewrite<SymbolLang, ()>> = vec![rewrite!("tri";
+///     "(edge ?a ?b)" => "(in-triangle ?a ?b)" if triangle)];
+/// let runner = Runner::default().with_egraph(egraph).with_iter_limit(1).run(&rules);
+///
+/// let found = |s: &str| runner.egraph.lookup_expr(&s.parse().unwrap()).is_some();
+/// assert!(found("(in-triangle a b)"));
+/// assert!(found("(in-triangle c a)"));
+/// assert!(!found("(in-triangle c d)"));
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MultiPatternCondition<L> {
+    program: machine::Program<L>,
+    bound: Vec<Var>,
+}
+
+impl<L: Language> MultiPatternCondition<L> {
+    /// Creates a new [`MultiPatternCondition`] that checks `pattern`
+    /// against the bindings of the `bound` variables.
+    ///
+    /// Panics if a variable in `bound` is not a variable of `pattern`.
+    pub fn new(pattern: MultiPattern<L>, bound: Vec<Var>) -> Self {
+        let vars = Searcher::<L, ()>::vars(&pattern);
+        for v in &bound {
+            assert!(
+                vars.contains(v),
+                "{} is not a variable of the multipattern",
+                v
+            );
+        }
+        let program = pattern.compile_seeded(&bound);
+        MultiPatternCondition { program, bound }
+    }
+}
+
+impl<L, N> Condition<L, N> for MultiPatternCondition<L>
+where
+    L: Language,
+    N: Analysis<L>,
+{
+    fn check(&self, egraph: &mut EGraph<L, N>, _eclass: Id, subst: &Subst) -> bool {
+        let seeds: Vec<Id> = self.bound.iter().map(|&v| subst[v]).collect();
+        self.program.run_seeded(egraph, &seeds)
+    }
+
+    fn vars(&self) -> Vec<Var> {
+        self.bound.clone()
+    }
+}
+
 #[cfg(test)]
 mod tests {
 
@@ -546,6 +2231,25 @@ mod tests {
 
     type EGraph = crate::EGraph<S, ()>;
 
+    #[test]
+    fn backward_mismatch_changes_nothing() {
+        let rule: Rewrite<S, ()> = rewrite!("mul-1"; "(* ?a 1)" => "?a");
+        let mut egraph = EGraph::default();
+        let x = egraph.add(S::leaf("x"));
+        let y = egraph.add(S::leaf("y"));
+        egraph.rebuild();
+
+        // `?a` is bound to `x`, which is not in the e-class of `y`
+        let mut subst = Subst::default();
+        subst.insert("?a".parse().unwrap(), x);
+        let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+            rule.apply_in_direction(&mut egraph, y, &subst, Direction::Backward)
+        }));
+        assert!(result.is_err());
+        assert_eq!(egraph.total_size(), 2);
+        assert_eq!(egraph.number_of_classes(), 2);
+    }
+
     #[test]
     fn conditional_rewrite() {
         crate::init_logger();
@@ -648,4 +2352,133 @@ mod tests {
         fold_add.run(&mut egraph);
         assert_eq!(egraph.equivs(&start, &goal), vec![egraph.find(root)]);
     }
+
+    #[test]
+    fn rewrite_recexpr_op_vars() {
+        struct Nothing;
+        impl Applier<S, ()> for Nothing {
+            fn apply_one(
+                &self,
+                _egraph: &mut EGraph,
+                _eclass: Id,
+                _subst: &Subst,
+                _searcher_ast: Option<&PatternAst<S>>,
+                _rule_name: Symbol,
+            ) -> Vec<Id> {
+                vec![]
+            }
+        }
+
+        let rules: Vec<Rewrite<S, ()>> = vec![
+            // distributes any binary op over `if`, which exposes more folds
+            rewrite!("lift-if"; "(?f (if ?c ?a ?b) ?d)" => "(if ?c (?f ?a ?d) (?f ?b ?d))"),
+            rewrite!("add-0"; "(+ ?a 0)" => "?a"),
+            rewrite!("same-if"; "(if ?c ?a ?a)" => "?a"),
+            // not a pattern, so skipped
+            rewrite!("nothing"; "(+ ?a ?b)" => { Nothing }),
+        ];
+        let expr = "(* (+ (if c x x) 0) (+ y y))".parse().unwrap();
+        assert_eq!(rewrite_recexpr(&expr, &rules).to_string(), "(* x (+ y y))");
+
+        let expr = "(+ (if c x y) 0)".parse().unwrap();
+        assert_eq!(rewrite_recexpr(&expr, &rules).to_string(), "(if c x y)");
+    }
+
+    #[test]
+    fn multipattern_condition_is_seeded() {
+        let mut egraph = EGraph::default();
+        let ab = egraph.add_expr(&"(edge a b)".parse().unwrap());
+        egraph.add_expr(&"(edge b (g c))".parse().unwrap());
+        egraph.add_expr(&"(h (g c))".parse().unwrap());
+        egraph.rebuild();
+        let var = |s: &str| Var::from_str(s).unwrap();
+        let mut subst = Subst::default();
+        subst.insert(var("?a"), egraph.lookup(S::leaf("a")).unwrap());
+        subst.insert(var("?b"), egraph.lookup(S::leaf("b")).unwrap());
+        subst.insert(var("?e"), ab);
+
+        let check = |egraph: &mut EGraph, pat: &str, bound: &[&str]| {
+            let bound = bound.iter().map(|v| var(v)).collect();
+            MultiPatternCondition::new(pat.parse().unwrap(), bound).check(egraph, ab, &subst)
+        };
+        // a bound pattern variable, a bound child and no bound child at all
+        assert!(check(&mut egraph, "?e = (edge ?a ?b)", &["?e", "?a"]));
+        assert!(check(
+            &mut egraph,
+            "?f = (edge ?b (g ?c)), ?k = (h (g ?c))",
+            &["?b"]
+        ));
+        assert!(!check(&mut egraph, "?f = (edge ?a (g ?c))", &["?a"]));
+        assert!(check(
+            &mut egraph,
+            "?f = (h (g ?c)), ?e = (edge ?a ?b)",
+            &["?e"]
+        ));
+        assert!(!check(
+            &mut egraph,
+            "?f = (h (g ?c)), ?e = (edge ?b ?a)",
+            &["?e", "?a"]
+        ));
+
+        // the e-graph is never rebuilt by a condition
+        let d = egraph.add(S::leaf("d"));
+        let b = egraph.lookup(S::leaf("b")).unwrap();
+        egraph.union(d, b);
+        assert!(!egraph.clean);
+        assert!(check(&mut egraph, "?e = (edge ?a ?b)", &["?a", "?b"]));
+        assert!(!egraph.clean);
+    }
+
+    #[test]
+    fn caching_searcher_agrees() {
+        let pat: Pattern<S> = "(f (g ?x) ?y)".parse().unwrap();
+        let cached = CachingSearcher::new(pat.clone());
+        let matches = |egraph: &EGraph, searcher: &dyn Searcher<S, ()>| {
+            let mut found: Vec<(Id, Vec<Subst>)> = searcher
+                .search(egraph)
+                .into_iter()
+                .map(|m| (m.eclass, m.substs))
+                .collect();
+            found.sort();
+            found
+        };
+
+        let mut egraph = EGraph::default().with_change_tracking(4);
+        let fa = egraph.add_expr(&"(f a b)".parse().unwrap());
+        egraph.add_expr(&"(h (f (g c) d))".parse().unwrap());
+        egraph.rebuild();
+        assert_eq!(matches(&egraph, &cached).len(), 1);
+
+        // a match appears below an untouched e-class
+        let a = egraph.lookup(S::leaf("a")).unwrap();
+        let gc = egraph.add_expr(&"(g c)".parse().unwrap());
+        egraph.union(a, gc);
+        egraph.rebuild();
+        assert_eq!(matches(&egraph, &cached), matches(&egraph, &pat));
+        assert_eq!(matches(&egraph, &cached).len(), 2);
+
+        // merging bound e-classes canonicalizes the cached matches
+        let b = egraph.lookup(S::leaf("b")).unwrap();
+        let d = egraph.lookup(S::leaf("d")).unwrap();
+        egraph.union(b, d);
+        egraph.rebuild();
+        assert_eq!(matches(&egraph, &cached), matches(&egraph, &pat));
+
+        // nothing changed
+        assert_eq!(
+            egraph.classes_changed_since(egraph.epoch()).unwrap().len(),
+            0
+        );
+        assert_eq!(matches(&egraph, &cached), matches(&egraph, &pat));
+        assert!(matches(&egraph, &cached)
+            .iter()
+            .any(|(id, _)| *id == egraph.find(fa)));
+
+        // limited searches are cut down from the cache
+        let limited = cached.search_with_limit(&egraph, 1);
+        assert_eq!(limited.iter().map(|m| m.substs.len()).sum::<usize>(), 1);
+        assert_eq!(cached.search_with_limit(&egraph, 0).len(), 0);
+        let epoch = cached.cache.lock().unwrap().as_ref().unwrap().epoch;
+        assert_eq!(epoch, egraph.epoch());
+    }
 }
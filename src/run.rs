@@ -1,4 +1,7 @@
 use std::fmt::{self, Debug, Formatter};
+use std::ops::Range;
+use std::sync::atomic::{AtomicBool, Ordering as AtomicOrdering};
+use std::sync::Arc;
 
 use log::*;
 
@@ -55,6 +58,12 @@ Here are some of the things [`Runner`] does for you:
   If this limit is hit, it stops with
   [`StopReason::TimeLimit`].
 
+- Interruption
+
+  You can give the runner a flag that another thread may set to stop it.
+  If the flag is set, it stops with
+  [`StopReason::Interrupted`].
+
 - Rule scheduling
 
   Some rules enable themselves, blowing up the [`EGraph`] and
@@ -145,12 +154,31 @@ pub struct Runner<L: Language, N: Analysis<L>, IterData = ()> {
     /// Why the `Runner` stopped. This will be `None` if it hasn't
     /// stopped yet.
     pub stop_reason: Option<StopReason>,
+    /// The ranges of [`iterations`](Runner::iterations) run by each phase
+    /// of [`run_phases`](Runner::run_phases()), in order.
+    /// This is empty if the `Runner` was run with [`run`](Runner::run()).
+    pub phases: Vec<Range<usize>>,
+
+    /// The rules added by [`add_rules`](Runner::add_rules()), which
+    /// [`run_one_iteration`](Runner::run_one_iteration()) runs.
+    pub rules: Vec<Rewrite<L, N>>,
 
     /// The hooks added by the
     /// [`with_hook`](Runner::with_hook()) method, in insertion order.
     #[allow(clippy::type_complexity)]
     pub hooks: Vec<Box<dyn FnMut(&mut Self) -> Result<(), String>>>,
 
+    #[allow(clippy::type_complexity)]
+    union_callbacks: Vec<Box<dyn FnMut(Id, Id, Symbol)>>,
+
+    #[allow(clippy::type_complexity)]
+    class_filter: Option<Box<dyn Fn(&N::Data) -> bool>>,
+    known_matches: Option<HashSet<(Symbol, Id, Subst)>>,
+    // how many iterations in a row changed nothing, see `truncate_matches`
+    idle_iterations: usize,
+    trace: Option<Vec<TraceEntry>>,
+    provenance: bool,
+    deterministic_apply: bool,
     limits: RunnerLimits,
     scheduler: Box<dyn RewriteScheduler<L, N>>,
 }
@@ -162,6 +190,7 @@ pub struct RunnerLimits {
     node_limit: usize,
     time_limit: Duration,
     start_time: Option<Instant>,
+    stop_flag: Option<Arc<AtomicBool>>,
 }
 
 impl RunnerLimits {
@@ -171,6 +200,12 @@ impl RunnerLimits {
         L: Language,
         N: Analysis<L>,
     {
+        if let Some(flag) = &self.stop_flag {
+            if flag.load(AtomicOrdering::Relaxed) {
+                return Err(StopReason::Interrupted);
+            }
+        }
+
         let elapsed = self.start_time.unwrap().elapsed();
         if elapsed > self.time_limit {
             return Err(StopReason::TimeLimit(elapsed.as_secs_f64()));
@@ -212,7 +247,16 @@ where
             iterations,
             roots,
             stop_reason,
+            phases,
+            rules,
             hooks,
+            union_callbacks,
+            class_filter,
+            known_matches,
+            idle_iterations,
+            trace,
+            provenance,
+            deterministic_apply,
             limits,
             scheduler: _,
         } = self;
@@ -222,7 +266,22 @@ where
             .field("iterations", iterations)
             .field("roots", roots)
             .field("stop_reason", stop_reason)
+            .field("phases", phases)
+            .field("rules", &rules.iter().map(|rw| rw.name).collect::<Vec<_>>())
             .field("hooks", &vec![format_args!("<dyn FnMut ..>"); hooks.len()])
+            .field(
+                "union_callbacks",
+                &vec![format_args!("<dyn FnMut ..>"); union_callbacks.len()],
+            )
+            .field(
+                "class_filter",
+                &class_filter.as_ref().map(|_| format_args!("<dyn Fn ..>")),
+            )
+            .field("known_matches", &known_matches.as_ref().map(|m| m.len()))
+            .field("idle_iterations", idle_iterations)
+            .field("trace", &trace.as_ref().map(|t| t.len()))
+            .field("provenance", provenance)
+            .field("deterministic_apply", deterministic_apply)
             .field("limits", limits)
             .field("scheduler", &format_args!("<dyn RewriteScheduler ..>"))
             .finish()
@@ -243,6 +302,8 @@ pub enum StopReason {
     NodeLimit(usize),
     /// The time limit was hit. The data is the time limit in seconds.
     TimeLimit(f64),
+    /// The flag given to [`Runner::with_stop_flag`] was set.
+    Interrupted,
     /// Some other reason to stop.
     Other(String),
 }
@@ -264,6 +325,9 @@ pub struct Report {
     pub egraph_classes: usize,
     pub memo_size: usize,
     pub rebuilds: usize,
+    pub rebuild_unions: usize,
+    pub rebuild_iters: usize,
+    pub congruence_merges: usize,
     pub total_time: f64,
     pub search_time: f64,
     pub apply_time: f64,
@@ -279,6 +343,7 @@ impl std::fmt::Display for Report {
         writeln!(f, "  Iterations: {}", self.iterations)?;
         writeln!(f, "  Egraph size: {} nodes, {} classes, {} memo", self.egraph_nodes, self.egraph_classes, self.memo_size)?;
         writeln!(f, "  Rebuilds: {}", self.rebuilds)?;
+        writeln!(f, "  Unions: {} ({} by congruence, {} worklist iterations)", self.rebuild_unions, self.congruence_merges, self.rebuild_iters)?;
         writeln!(f, "  Total time: {}", self.total_time)?;
         writeln!(f, "    Search:  ({:.2}) {}", self.search_time / self.total_time, self.search_time)?;
         writeln!(f, "    Apply:   ({:.2}) {}", self.apply_time / self.total_time, self.apply_time)?;
@@ -287,6 +352,49 @@ impl std::fmt::Display for Report {
     }
 }
 
+/// Explains how far a [`Runner`] got towards proving an equality,
+/// see [`goal_report`](Runner::goal_report()).
+///
+/// Its [`Display`](std::fmt::Display) output is a readable summary.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct GoalReport<L> {
+    /// Whether both sides are in the e-graph, in the same e-class.
+    pub proven: bool,
+    /// The maximal subterms of the left-hand side that are in the
+    /// e-graph, with their canonical e-class, in the order they appear.
+    /// This is just the whole side if it is in the e-graph.
+    pub lhs_present: Vec<(RecExpr<L>, Id)>,
+    /// Like [`lhs_present`](GoalReport::lhs_present), for the right-hand
+    /// side.
+    pub rhs_present: Vec<(RecExpr<L>, Id)>,
+    /// The e-classes of [`lhs_present`](GoalReport::lhs_present) and
+    /// [`rhs_present`](GoalReport::rhs_present) that also contain a subterm
+    /// of the other side, i.e. where the two sides have been shown to
+    /// coincide, sorted.
+    pub shared: Vec<Id>,
+}
+
+impl<L: Language + std::fmt::Display> std::fmt::Display for GoalReport<L> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        writeln!(f, "Goal report")?;
+        writeln!(f, "===========")?;
+        writeln!(f, "  Proven: {}", self.proven)?;
+        for (side, present) in [("Left", &self.lhs_present), ("Right", &self.rhs_present)] {
+            writeln!(f, "  {} side, present subterms:", side)?;
+            for (term, id) in present {
+                let shared = if self.shared.contains(id) {
+                    ", shared"
+                } else {
+                    ""
+                };
+                writeln!(f, "    {} (e-class {}{})", term, id, shared)?;
+            }
+        }
+        Ok(())
+    }
+}
+
 /// Data generated by running a [`Runner`] one iteration.
 ///
 /// If the `serde-1` feature is enabled, this implements
@@ -307,6 +415,9 @@ pub struct Iteration<IterData> {
     /// A map from rule name to number of times it was _newly_ applied
     /// in this iteration.
     pub applied: IndexMap<Symbol, usize>,
+    /// The total number of _new_ applications in this iteration, i.e. the
+    /// sum of [`applied`](Iteration::applied).
+    pub applied_total: usize,
     /// Seconds spent running hooks.
     pub hook_time: f64,
     /// Seconds spent searching in this iteration.
@@ -322,10 +433,33 @@ pub struct Iteration<IterData> {
     pub data: IterData,
     /// The number of rebuild iterations done after this iteration completed.
     pub n_rebuilds: usize,
+    /// The number of unions done in this iteration after running the hooks,
+    /// both by applying rules and by congruence while rebuilding.
+    pub rebuild_unions: usize,
+    /// The number of times [`rebuild`](EGraph::rebuild()) processed its
+    /// worklist in this iteration.
+    pub rebuild_iters: usize,
+    /// The number of unions found by congruence while rebuilding in this
+    /// iteration.
+    pub congruence_merges: usize,
     /// If the runner stopped on this iterations, this is the reason
     pub stop_reason: Option<StopReason>,
 }
 
+/// One substitution handed to an [`Applier`] by a [`Runner`], as recorded
+/// by [`with_trace`](Runner::with_trace()).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TraceEntry {
+    /// The iteration in which the substitution was applied.
+    pub iteration: usize,
+    /// The name of the applied [`Rewrite`].
+    pub rule_name: Symbol,
+    /// The matched e-class, as found by the search.
+    pub eclass: Id,
+    /// The substitution, as found by the search.
+    pub subst: Subst,
+}
+
 /// Type alias for the result of a [`Runner`].
 pub type RunnerResult<T> = std::result::Result<T, StopReason>;
 
@@ -343,12 +477,22 @@ where
                 node_limit: 10_000,
                 time_limit: Duration::from_secs(5),
                 start_time: None,
+                stop_flag: None,
             },
             egraph: EGraph::new(analysis),
             roots: vec![],
             iterations: vec![],
             stop_reason: None,
+            phases: vec![],
+            rules: vec![],
             hooks: vec![],
+            union_callbacks: vec![],
+            class_filter: None,
+            known_matches: None,
+            idle_iterations: 0,
+            trace: None,
+            provenance: false,
+            deterministic_apply: false,
             scheduler: Box::new(BackoffScheduler::default()),
         }
     }
@@ -361,12 +505,22 @@ where
                 node_limit: 10_000,
                 time_limit: Duration::from_secs(5),
                 start_time: None,
+                stop_flag: None,
             },
             egraph,
             roots: vec![],
             iterations: vec![],
             stop_reason: None,
+            phases: vec![],
+            rules: vec![],
             hooks: vec![],
+            union_callbacks: vec![],
+            class_filter: None,
+            known_matches: None,
+            idle_iterations: 0,
+            trace: None,
+            provenance: false,
+            deterministic_apply: false,
             scheduler: Box::new(BackoffScheduler::default()),
         }
     }
@@ -389,6 +543,30 @@ where
         self
     }
 
+    /// Sets a flag that stops the runner with [`StopReason::Interrupted`]
+    /// once it is set, e.g. from another thread.
+    ///
+    /// The flag is checked as often as the other limits, so the runner
+    /// stops after the rule it is currently applying, and the e-graph is
+    /// rebuilt before it returns.
+    ///
+    /// ```
+    /// use egg::*;
+    /// use std::sync::{atomic::AtomicBool, Arc};
+    ///
+    /// let stop = Arc::new(AtomicBool::new(true));
+    /// let runner = Runner::<SymbolLang, ()>::default()
+    ///     .with_stop_flag(stop.clone())
+    ///     .with_expr(&"(+ a b)".parse().unwrap())
+    ///     .run(&[rewrite!("comm"; "(+ ?a ?b)" => "(+ ?b ?a)")]);
+    /// assert!(matches!(runner.stop_reason, Some(StopReason::Interrupted)));
+    /// assert!(runner.egraph.clean);
+    /// ```
+    pub fn with_stop_flag(mut self, flag: Arc<AtomicBool>) -> Self {
+        self.limits.stop_flag = Some(flag);
+        self
+    }
+
     /// Add a hook to instrument or modify the behavior of a [`Runner`].
     /// Each hook will run at the beginning of each iteration, i.e. before
     /// all the rewrites.
@@ -420,6 +598,246 @@ where
         self
     }
 
+    /// Calls `f` on each union made by a rewrite while the [`Runner`]
+    /// applies rules, with the two merged e-classes and the name of the
+    /// rule.
+    ///
+    /// The unions of each rule are reported as soon as that rule has been
+    /// applied, before the e-graph is rebuilt, so the ids are those of the
+    /// two e-classes just before they were merged, the one that stays the
+    /// root first.
+    /// Unions found by congruence during [`rebuild`](EGraph::rebuild())
+    /// are not reported, and neither are those made by the hooks.
+    ///
+    /// # Example
+    /// ```
+    /// # use egg::*;
+    /// use std::{cell::RefCell, rc::Rc};
+    ///
+    /// let rules: &[Rewrite<SymbolLang, ()>] = &[
+    ///     rewrite!("mul-one"; "(* ?a 1)" => "?a"),
+    /// ];
+    /// let unions = Rc::new(RefCell::new(vec![]));
+    /// let log = unions.clone();
+    /// let runner = Runner::<SymbolLang, ()>::default()
+    ///     .with_expr(&"(f (* x 1))".parse().unwrap())
+    ///     .on_union(move |a, b, rule| log.borrow_mut().push((a, b, rule)))
+    ///     .run(rules);
+    ///
+    /// let unions = unions.borrow();
+    /// assert_eq!(unions.len(), 1);
+    /// let (a, b, rule) = unions[0];
+    /// assert_eq!(rule, Symbol::from("mul-one"));
+    /// assert_eq!(runner.egraph.find(a), runner.egraph.find(b));
+    /// ```
+    pub fn on_union<F>(mut self, f: F) -> Self
+    where
+        F: FnMut(Id, Id, Symbol) + 'static,
+    {
+        self.union_callbacks.push(Box::new(f));
+        self
+    }
+
+    /// Record which rule created each new e-node.
+    ///
+    /// Every e-node added while applying a rule gets the rule's name as
+    /// its [`meta`](EGraph::meta)data, so `runner.egraph.meta::<Symbol>(id)`
+    /// tells which rule introduced the e-node created with `id`.
+    /// E-nodes added by hooks or before running are not tagged.
+    ///
+    /// # Example
+    /// ```
+    /// # use egg::*;
+    /// let rules: &[Rewrite<SymbolLang, ()>] = &[rewrite!("comm"; "(+ ?a ?b)" => "(+ ?b ?a)")];
+    /// let runner = Runner::default()
+    ///     .with_expr(&"(+ x y)".parse().unwrap())
+    ///     .with_provenance()
+    ///     .run(rules);
+    ///
+    /// let egraph = &runner.egraph;
+    /// let x = egraph.lookup(SymbolLang::leaf("x")).unwrap();
+    /// assert_eq!(egraph.meta::<Symbol>(x), None);
+    /// // only `(+ y x)` was created by a rule
+    /// let tagged: Vec<Symbol> = (0..egraph.nodes().len())
+    ///     .filter_map(|i| egraph.meta::<Symbol>(Id::from(i)).copied())
+    ///     .collect();
+    /// assert_eq!(tagged, vec![Symbol::from("comm")]);
+    /// ```
+    pub fn with_provenance(mut self) -> Self {
+        self.provenance = true;
+        self
+    }
+
+    /// Apply the matches of each iteration in a canonical order.
+    ///
+    /// When enabled, the rules are applied in order of their names, the
+    /// matches of each rule in order of their (canonical) e-class ids, and
+    /// the substitutions of each match in order of their variable names and
+    /// canonical ids, instead of the order in which they were found.
+    /// So the e-graph no longer depends on the order of the rules, as the
+    /// example below checks.
+    /// Together with the `deterministic` feature, which replaces the hash
+    /// maps inside the [`EGraph`] with ordered ones, this makes the final
+    /// e-graph reproducible across runs and platforms.
+    /// Default: `false`
+    ///
+    /// # Example
+    /// ```
+    /// # use egg::*;
+    /// let comm: Rewrite<SymbolLang, ()> = rewrite!("comm"; "(+ ?a ?b)" => "(+ ?b ?a)");
+    /// let assoc = rewrite!("assoc"; "(+ ?a (+ ?b ?c))" => "(+ (+ ?a ?b) ?c)");
+    /// // the e-nodes in the order they were added, i.e. by id
+    /// let run = |rules: &[&Rewrite<_, _>], deterministic_apply| {
+    ///     let runner = Runner::default()
+    ///         .with_expr(&"(+ a (+ b (+ c d)))".parse().unwrap())
+    ///         .with_iter_limit(3)
+    ///         .with_deterministic_apply(deterministic_apply)
+    ///         .run(rules.iter().copied());
+    ///     runner.egraph.nodes().to_vec()
+    /// };
+    /// // by default, the order of the rules decides which e-nodes come first
+    /// assert_ne!(run(&[&comm, &assoc], false), run(&[&assoc, &comm], false));
+    /// assert_eq!(run(&[&comm, &assoc], true), run(&[&assoc, &comm], true));
+    /// ```
+    pub fn with_deterministic_apply(mut self, deterministic_apply: bool) -> Self {
+        self.deterministic_apply = deterministic_apply;
+        self
+    }
+
+    /// Stop rewriting e-classes whose analysis data satisfies `filter`.
+    ///
+    /// Before applying each rule, the [`Runner`] drops the matches rooted in
+    /// e-classes for which `filter` returns `true`, so no rule will rewrite
+    /// them (although they can still be merged into by matches elsewhere).
+    /// Unlike a [`Condition`], this applies uniformly to every rule, which
+    /// lets an [`Analysis`] prune the search space as it learns more, e.g.
+    /// by marking classes that are known to be errors.
+    ///
+    /// Calling this again replaces the previous filter.
+    ///
+    /// # Example
+    /// ```
+    /// # use egg::*;
+    /// #[derive(Default)]
+    /// struct IsError;
+    /// impl Analysis<SymbolLang> for IsError {
+    ///     type Data = bool;
+    ///     fn make(egraph: &mut EGraph<SymbolLang, Self>, enode: &SymbolLang) -> bool {
+    ///         enode.op.as_str() == "error"
+    ///             || enode.children.iter().any(|&c| egraph[c].data)
+    ///     }
+    ///     fn merge(&mut self, a: &mut bool, b: bool) -> DidMerge {
+    ///         merge_max(a, b)
+    ///     }
+    /// }
+    ///
+    /// let rules: &[Rewrite<SymbolLang, IsError>] = &[
+    ///     rewrite!("commute-add"; "(+ ?a ?b)" => "(+ ?b ?a)"),
+    /// ];
+    /// let runner = Runner::default()
+    ///     .with_expr(&"(+ a b)".parse().unwrap())
+    ///     .with_expr(&"(+ error b)".parse().unwrap())
+    ///     .with_class_filter(|is_error| *is_error)
+    ///     .run(rules);
+    ///
+    /// let egraph = &runner.egraph;
+    /// assert!(egraph.lookup_expr(&"(+ b a)".parse().unwrap()).is_some());
+    /// assert!(egraph.lookup_expr(&"(+ b error)".parse().unwrap()).is_none());
+    /// ```
+    pub fn with_class_filter<F>(self, filter: F) -> Self
+    where
+        F: Fn(&N::Data) -> bool + 'static,
+    {
+        Self {
+            class_filter: Some(Box::new(filter)),
+            ..self
+        }
+    }
+
+    /// Never apply the same match twice, so the final iteration does not
+    /// have to re-apply everything just to find that nothing changed.
+    ///
+    /// Normally, the [`Runner`] applies every match it finds in every
+    /// iteration, and it stops with [`StopReason::Saturated`] after an
+    /// iteration where none of them changed the e-graph.
+    /// In this mode, the [`Runner`] remembers each match (the rule, the
+    /// matched e-class and the substitution, all canonicalized) that it has
+    /// applied, and drops these from later searches.
+    /// Without [hooks](Runner::with_hook) or explanations, this also
+    /// searches again right after each iteration that changed the e-graph:
+    /// if every match found is either old or a [no-op](Applier::is_noop),
+    /// the next iteration could not change anything, so the [`Runner`]
+    /// stops with [`StopReason::Saturated`] right away, one iteration
+    /// earlier than it normally would.
+    /// Otherwise, an iteration whose searches produce no new matches
+    /// applies nothing and stops as saturated, skipping all of the
+    /// redundant application work.
+    ///
+    /// For rules whose appliers always add the same thing for the same
+    /// match, the final e-graph is the same as without this mode.
+    /// But a match is also never retried if its applier didn't do anything
+    /// the first time, so rules with [`Condition`]s or custom [`Applier`]s
+    /// that depend on changing analysis data may be skipped when they
+    /// would have fired later. Remembering the matches also takes memory
+    /// proportional to the total number of matches.
+    pub fn with_aggressive_saturation(self) -> Self {
+        Self {
+            known_matches: Some(Default::default()),
+            ..self
+        }
+    }
+
+    /// Record every substitution that is handed to an [`Applier`] into
+    /// [`trace`](Runner::trace()), so the run can be reproduced with
+    /// [`replay`](Runner::replay()).
+    ///
+    /// Substitutions are recorded whether or not applying them changed the
+    /// e-graph, in the order in which they were applied, after the
+    /// [`RewriteScheduler`] and any match limits have had their say.
+    ///
+    /// Tracing keeps one [`TraceEntry`] (including a copy of its [`Subst`])
+    /// for every application over the whole run, so memory grows with the
+    /// total number of matches applied, which can easily be much larger
+    /// than the final e-graph. Default: `false`
+    pub fn with_trace(self, enabled: bool) -> Self {
+        Self {
+            trace: if enabled { Some(vec![]) } else { None },
+            ..self
+        }
+    }
+
+    /// Returns the applications recorded so far if tracing was turned on
+    /// with [`with_trace`](Runner::with_trace()), or an empty slice.
+    pub fn trace(&self) -> &[TraceEntry] {
+        self.trace.as_deref().unwrap_or(&[])
+    }
+
+    /// Returns the rules banned by the [`RewriteScheduler`] so far, as
+    /// `(rule, iteration_banned, iterations_banned_for)`.
+    /// See [`BackoffScheduler::ban_history`].
+    ///
+    /// # Example
+    /// ```
+    /// # use egg::*;
+    /// let rules: &[Rewrite<SymbolLang, ()>] = &[
+    ///     rewrite!("commute-add"; "(+ ?a ?b)" => "(+ ?b ?a)"),
+    ///     rewrite!("assoc-add"; "(+ ?a (+ ?b ?c))" => "(+ (+ ?a ?b) ?c)"),
+    /// ];
+    /// let runner = Runner::default()
+    ///     .with_scheduler(BackoffScheduler::default().with_initial_match_limit(4))
+    ///     .with_expr(&"(+ a (+ b (+ c d)))".parse().unwrap())
+    ///     .run(rules);
+    ///
+    /// let history = runner.ban_history();
+    /// assert!(!history.is_empty());
+    /// for (rule, iteration, length) in history {
+    ///     println!("{} banned at iteration {} for {}", rule, iteration, length);
+    /// }
+    /// ```
+    pub fn ban_history(&self) -> Vec<(Symbol, usize, usize)> {
+        self.scheduler.ban_history()
+    }
+
     /// Change out the [`RewriteScheduler`] used by this [`Runner`].
     /// The default one is [`BackoffScheduler`].
     ///
@@ -444,6 +862,32 @@ where
         Self { egraph, ..self }
     }
 
+    /// Like [`with_egraph`](Runner::with_egraph()), but first checks that
+    /// `egraph` is well formed, e.g. after deserializing it from an
+    /// untrusted source.
+    ///
+    /// This checks that every id in the e-graph exists, then
+    /// [`rebuild`](EGraph::rebuild)s it and checks the rest of
+    /// [`check_invariants`](EGraph::check_invariants).
+    /// Returns the first violation found, if any.
+    /// This takes time linear in the size of the e-graph, so trusted
+    /// e-graphs should use [`with_egraph`](Runner::with_egraph()).
+    ///
+    /// ```
+    /// use egg::*;
+    /// let mut egraph = EGraph::<SymbolLang, ()>::default();
+    /// egraph.add_expr(&"(+ x (* y 2))".parse().unwrap());
+    /// let runner = Runner::default().with_checked_egraph(egraph).unwrap();
+    /// assert!(runner.egraph.clean);
+    /// ```
+    pub fn with_checked_egraph(
+        self,
+        mut egraph: EGraph<L, N>,
+    ) -> Result<Self, InvariantViolation<L>> {
+        egraph.check_and_rebuild()?;
+        Ok(self.with_egraph(egraph))
+    }
+
     /// Run this `Runner` until it stops.
     /// After this, the field
     /// [`stop_reason`](Runner::stop_reason) is guaranteed to be
@@ -455,10 +899,142 @@ where
         N: 'a,
     {
         let rules: Vec<&Rewrite<L, N>> = rules.into_iter().collect();
-        check_rules(&rules);
+        check_rules(&rules, &self.egraph);
+        self.run_rules(&rules);
+        self
+    }
+
+    /// Run this `Runner` over several sets of rules in strict phases.
+    ///
+    /// Each phase runs its rules until the [`EGraph`] saturates, and then
+    /// the next phase starts with a [`reset`](RewriteScheduler::reset())
+    /// scheduler.
+    /// This is useful for compiler-pass-like structures, e.g.
+    /// canonicalize, then optimize, then lower.
+    ///
+    /// The iteration, node and time limits are shared by all the phases.
+    /// If a phase stops for any reason other than
+    /// [`StopReason::Saturated`], the remaining phases are skipped.
+    /// Either way, [`stop_reason`](Runner::stop_reason) is the reason
+    /// the last phase that ran stopped, and the iterations of each phase
+    /// are recorded in [`phases`](Runner::phases).
+    ///
+    /// # Example
+    /// ```
+    /// # use egg::*;
+    /// let expand: &[Rewrite<SymbolLang, ()>] = &[rewrite!("double"; "(* 2 ?a)" => "(+ ?a ?a)")];
+    /// let contract: &[Rewrite<SymbolLang, ()>] = &[rewrite!("shift"; "(+ ?a ?a)" => "(<< ?a 1)")];
+    ///
+    /// let runner = Runner::default()
+    ///     .with_expr(&"(* 2 x)".parse().unwrap())
+    ///     .run_phases(&[expand, contract]);
+    ///
+    /// assert_eq!(runner.phases.len(), 2);
+    /// assert!(matches!(runner.stop_reason, Some(StopReason::Saturated)));
+    /// let first_phase = &runner.iterations[runner.phases[0].clone()];
+    /// assert!(first_phase.iter().all(|i| !i.applied.contains_key(&Symbol::from("shift"))));
+    /// assert_eq!(
+    ///     runner.egraph.lookup_expr(&"(<< x 1)".parse().unwrap()),
+    ///     Some(runner.egraph.find(runner.roots[0])),
+    /// );
+    /// ```
+    pub fn run_phases(mut self, phases: &[&[Rewrite<L, N>]]) -> Self {
+        assert!(!phases.is_empty(), "run_phases needs at least one phase");
+        for phase in phases {
+            let rules: Vec<&Rewrite<L, N>> = phase.iter().collect();
+            check_rules(&rules, &self.egraph);
+            self.scheduler.reset();
+            self.idle_iterations = 0;
+            self.stop_reason = None;
+
+            let start = self.iterations.len();
+            self.run_rules(&rules);
+            self.phases.push(start..self.iterations.len());
+
+            if !matches!(self.stop_reason, Some(StopReason::Saturated)) {
+                break;
+            }
+        }
+        self
+    }
+
+    /// Adds `rules` to the ones run by
+    /// [`run_one_iteration`](Runner::run_one_iteration()), for instance to
+    /// add lemmas in the middle of an interactive session without losing
+    /// the e-graph.
+    ///
+    /// The scheduler forgets what it knew about rules with the same names
+    /// through [`reset_rules`](RewriteScheduler::reset_rules()), so the new
+    /// rules are not banned right away, while the other rules keep their
+    /// state. The [`stop_reason`](Runner::stop_reason) is cleared, since a
+    /// saturated e-graph may not be saturated under the new rules.
+    ///
+    /// The [`iterations`](Runner::iterations) are kept, so the history
+    /// shows iterations run under different rule sets one after the other.
+    ///
+    /// # Example
+    /// ```
+    /// # use egg::*;
+    /// let mut runner = Runner::<SymbolLang, ()>::default()
+    ///     .with_expr(&"(* (+ a 0) 1)".parse().unwrap());
+    /// runner.add_rules(vec![rewrite!("add-0"; "(+ ?a 0)" => "?a")]);
+    /// while runner.stop_reason.is_none() {
+    ///     runner.run_one_iteration();
+    /// }
+    /// assert!(matches!(runner.stop_reason, Some(StopReason::Saturated)));
+    ///
+    /// runner.add_rules(vec![rewrite!("mul-1"; "(* ?a 1)" => "?a")]);
+    /// while runner.stop_reason.is_none() {
+    ///     runner.run_one_iteration();
+    /// }
+    /// let a = runner.egraph.lookup_expr(&"a".parse().unwrap());
+    /// assert_eq!(a, Some(runner.egraph.find(runner.roots[0])));
+    /// ```
+    pub fn add_rules(&mut self, rules: Vec<Rewrite<L, N>>) {
+        let names: Vec<Symbol> = rules.iter().map(|rw| rw.name).collect();
+        self.rules.extend(rules);
+        check_rules(&self.rules.iter().collect::<Vec<_>>(), &self.egraph);
+        self.scheduler.reset_rules(&names);
+        self.idle_iterations = 0;
+        self.stop_reason = None;
+    }
+
+    /// Runs one iteration of the rules added with
+    /// [`add_rules`](Runner::add_rules()) and returns it.
+    ///
+    /// If the [`Runner`] should stop after this iteration, because it
+    /// saturated or hit a limit, [`stop_reason`](Runner::stop_reason) is
+    /// set. Running more iterations after that is allowed, e.g. after
+    /// adding more rules, and clears it first.
+    ///
+    /// The limits are checked as for [`run`](Runner::run()), except that
+    /// the clock of the [time limit](Runner::with_time_limit()) restarts
+    /// with each call, so time spent between calls doesn't count.
+    /// The [iteration limit](Runner::with_iter_limit()) still counts all
+    /// [`iterations`](Runner::iterations), including those of earlier
+    /// calls, and the [node limit](Runner::with_node_limit()) the size of
+    /// the e-graph.
+    pub fn run_one_iteration(&mut self) -> &Iteration<IterData> {
+        self.limits.start_time = Some(Instant::now());
+        self.stop_reason = None;
+        self.egraph.rebuild();
+        let rules = std::mem::take(&mut self.rules);
+        let iter = self.run_one(&rules.iter().collect::<Vec<_>>());
+        self.rules = rules;
+        self.iterations.push(iter);
+
+        let stop_reason = self.iterations.last().unwrap().stop_reason.clone();
+        if let Some(stop_reason) = stop_reason.or_else(|| self.check_limits().err()) {
+            info!("Stopping: {:?}", stop_reason);
+            self.stop_reason = Some(stop_reason);
+        }
+        self.iterations.last().unwrap()
+    }
+
+    fn run_rules(&mut self, rules: &[&Rewrite<L, N>]) {
         self.egraph.rebuild();
         loop {
-            let iter = self.run_one(&rules);
+            let iter = self.run_one(rules);
             self.iterations.push(iter);
             let stop_reason = self.iterations.last().unwrap().stop_reason.clone();
             // we need to check_limits after the iteration is complete to check for iter_limit
@@ -471,7 +1047,6 @@ where
 
         assert!(!self.iterations.is_empty());
         assert!(self.stop_reason.is_some());
-        self
     }
 
     /// Enable explanations for this runner's egraph.
@@ -522,6 +1097,65 @@ where
         println!("{}", self.report())
     }
 
+    /// Returns the `(classes, nodes)` size of the egraph at the start of
+    /// each iteration, e.g. for plotting how fast a run grows.
+    ///
+    /// The sizes are measured by the `Runner` itself, so they are filled in
+    /// no matter which [`RewriteScheduler`] is used.
+    ///
+    /// ```
+    /// use egg::*;
+    /// let rules: &[Rewrite<SymbolLang, ()>] = &[rewrite!("grow"; "(f ?a)" => "(f (s ?a))")];
+    /// let runner = Runner::default()
+    ///     .with_expr(&"(f x)".parse().unwrap())
+    ///     .with_iter_limit(3)
+    ///     .run(rules);
+    /// let curve = runner.size_curve();
+    /// assert_eq!(curve.len(), runner.iterations.len());
+    /// assert_eq!(curve[0], (2, 2));
+    /// assert!(curve.windows(2).all(|w| w[0].0 <= w[1].0 && w[0].1 <= w[1].1));
+    /// ```
+    pub fn size_curve(&self) -> Vec<(usize, usize)> {
+        self.iterations
+            .iter()
+            .map(|i| (i.egraph_classes, i.egraph_nodes))
+            .collect()
+    }
+
+    /// Returns a rough estimate between 0 and 1 of how close this run is
+    /// to saturation, e.g. for a progress bar.
+    ///
+    /// This is a heuristic based on how many rule applications changed the
+    /// egraph in the last [`Iteration`], relative to the most in any
+    /// iteration so far: the estimate grows as fewer applications find
+    /// anything new. It is 0 before the first iteration and 1 once the
+    /// runner has [`Saturated`](StopReason::Saturated), but it need not be
+    /// monotonic in between, and it says nothing about how close the
+    /// other limits are.
+    ///
+    /// ```
+    /// use egg::*;
+    /// let rules: &[Rewrite<SymbolLang, ()>] = &[rewrite!("comm"; "(+ ?a ?b)" => "(+ ?b ?a)")];
+    /// let runner = Runner::default().with_expr(&"(+ a (+ b c))".parse().unwrap());
+    /// assert_eq!(runner.progress_estimate(), 0.0);
+    /// let runner = runner.run(rules);
+    /// assert_eq!(runner.progress_estimate(), 1.0);
+    /// ```
+    pub fn progress_estimate(&self) -> f64 {
+        if let Some(StopReason::Saturated) = self.stop_reason {
+            return 1.0;
+        }
+        let last = match self.iterations.last() {
+            Some(last) => last.applied_total,
+            None => return 0.0,
+        };
+        let peak = self.iterations.iter().map(|i| i.applied_total).max();
+        match peak {
+            Some(peak) if peak > 0 => 1.0 - last as f64 / peak as f64,
+            _ => 1.0,
+        }
+    }
+
     /// Creates a [`Report`] summarizing this `Runner`s run.
     pub fn report(&self) -> Report {
         Report {
@@ -531,6 +1165,9 @@ where
             egraph_classes: self.egraph.number_of_classes(),
             memo_size: self.egraph.total_size(),
             rebuilds: self.iterations.iter().map(|i| i.n_rebuilds).sum(),
+            rebuild_unions: self.iterations.iter().map(|i| i.rebuild_unions).sum(),
+            rebuild_iters: self.iterations.iter().map(|i| i.rebuild_iters).sum(),
+            congruence_merges: self.iterations.iter().map(|i| i.congruence_merges).sum(),
             search_time: self.iterations.iter().map(|i| i.search_time).sum(),
             apply_time: self.iterations.iter().map(|i| i.apply_time).sum(),
             rebuild_time: self.iterations.iter().map(|i| i.rebuild_time).sum(),
@@ -561,6 +1198,7 @@ where
 
         let egraph_nodes_after_hooks = self.egraph.total_size();
         let egraph_classes_after_hooks = self.egraph.number_of_classes();
+        let egraph_enodes_after_hooks = self.egraph.nodes().len();
 
         let i = self.iterations.len();
         trace!("EGraph {:?}", self.egraph.dump());
@@ -581,17 +1219,94 @@ where
             // })
         });
 
+        if let Some(known) = &self.known_matches {
+            let egraph = &self.egraph;
+            for (rw, ms) in rules.iter().zip(&mut matches) {
+                for m in ms.iter_mut() {
+                    let eclass = m.eclass;
+                    m.substs.retain(|subst| {
+                        !known.contains(&known_match(egraph, rw.name, eclass, subst))
+                    });
+                }
+                ms.retain(|m| !m.substs.is_empty());
+            }
+        }
+
+        let mut rules_and_matches: Vec<_> = rules.iter().zip(matches).collect();
+        if self.deterministic_apply {
+            let egraph = &self.egraph;
+            rules_and_matches
+                .sort_by(|(a, _), (b, _)| a.name.with_str(|a| b.name.with_str(|b| a.cmp(b))));
+            for (_, ms) in &mut rules_and_matches {
+                for m in ms.iter_mut() {
+                    m.substs.sort_by_cached_key(|subst| {
+                        let mut key: Vec<(String, Id)> = subst
+                            .iter()
+                            .map(|(v, id)| (v.to_string(), egraph.find(id)))
+                            .collect();
+                        key.sort();
+                        key
+                    });
+                }
+                ms.sort_by_key(|m| egraph.find(m.eclass));
+            }
+        }
+
         let search_time = start_time.elapsed().as_secs_f64();
         info!("Search time: {}", search_time);
 
         let apply_time = Instant::now();
+        // how many iterations it takes the truncated matches to come around
+        let mut rotation = 1;
+        if !self.union_callbacks.is_empty() {
+            self.egraph.union_log = Some(vec![]);
+        }
 
         result = result.and_then(|_| {
-            rules.iter().zip(matches).try_for_each(|(rw, ms)| {
+            rules_and_matches.into_iter().try_for_each(|(rw, mut ms)| {
+                if let Some(filter) = &self.class_filter {
+                    ms.retain(|m| !filter(&self.egraph[m.eclass].data));
+                }
+                if let Some(limit) = rw.match_limit {
+                    rotation =
+                        rotation.max(truncate_matches(&mut ms, limit, i.wrapping_mul(limit)));
+                }
                 let total_matches: usize = ms.iter().map(|m| m.substs.len()).sum();
                 debug!("Applying {} {} times", rw.name, total_matches);
 
+                if let Some(trace) = &mut self.trace {
+                    for m in &ms {
+                        trace.extend(m.substs.iter().map(|subst| TraceEntry {
+                            iteration: i,
+                            rule_name: rw.name,
+                            eclass: m.eclass,
+                            subst: subst.clone(),
+                        }));
+                    }
+                }
+
+                // only the matches that are actually applied become known, not
+                // the ones filtered, truncated or cut off by a limit
+                if let Some(known) = &mut self.known_matches {
+                    for m in &ms {
+                        for subst in &m.substs {
+                            known.insert(known_match(&self.egraph, rw.name, m.eclass, subst));
+                        }
+                    }
+                }
+
+                let nodes_before = self.egraph.nodes().len();
                 let actually_matched = self.scheduler.apply_rewrite(i, &mut self.egraph, rw, ms);
+                if self.provenance {
+                    self.egraph.tag_nodes_since(nodes_before, Arc::new(rw.name));
+                }
+                if let Some(unions) = &mut self.egraph.union_log {
+                    for (a, b) in unions.drain(..) {
+                        for f in &mut self.union_callbacks {
+                            f(a, b, rw.name);
+                        }
+                    }
+                }
                 if actually_matched > 0 {
                     if let Some(count) = applied.get_mut(&rw.name) {
                         *count += actually_matched;
@@ -604,11 +1319,13 @@ where
             })
         });
 
+        self.egraph.union_log = None;
         let apply_time = apply_time.elapsed().as_secs_f64();
         info!("Apply time: {}", apply_time);
 
         let rebuild_time = Instant::now();
-        let n_rebuilds = self.egraph.rebuild();
+        let rebuild_stats = self.egraph.rebuild_with_stats();
+        let n_rebuilds = rebuild_stats.congruence_merges;
         if self.egraph.are_explanations_enabled() {
             debug_assert!(self.egraph.check_each_explain(rules));
         }
@@ -621,8 +1338,7 @@ where
             self.egraph.number_of_classes()
         );
 
-        let can_be_saturated = applied.is_empty()
-            && self.scheduler.can_stop(i)
+        let unchanged = applied.is_empty()
             // now make sure the hooks didn't do anything
             && (egraph_nodes == egraph_nodes_after_hooks)
             && (egraph_classes == egraph_classes_after_hooks)
@@ -630,12 +1346,44 @@ where
             // nodes without applying) didn't do anything
             && (egraph_nodes == self.egraph.total_size())
             && (egraph_classes == self.egraph.number_of_classes());
+        let can_stop = unchanged && self.scheduler.can_stop(i);
+        if can_stop {
+            self.idle_iterations += 1;
+        } else {
+            self.idle_iterations = 0;
+        }
 
-        if can_be_saturated {
+        // truncated matches were all tried once a whole rotation of
+        // iterations has changed nothing, unless aggressive saturation
+        // filtered some of them out along the way
+        let truncated = rotation > 1;
+        let can_be_saturated = can_stop
+            && (!truncated || (self.known_matches.is_none() && self.idle_iterations >= rotation));
+
+        // without hooks, nothing can change the e-graph before the next
+        // search, so it can be done now to see whether it finds anything new
+        let saturated_early = !unchanged
+            && !truncated
+            && result.is_ok()
+            && self.hooks.is_empty()
+            && !self.egraph.are_explanations_enabled()
+            && self.known_matches.as_ref().is_some_and(|known| {
+                let egraph = &self.egraph;
+                rules.iter().all(|rw| {
+                    rw.searcher.search_iter(egraph).all(|(eclass, subst)| {
+                        known.contains(&known_match(egraph, rw.name, eclass, &subst))
+                            || rw.applier.is_noop(egraph, eclass, &subst)
+                    })
+                })
+            })
+            && self.scheduler.can_stop(i);
+
+        if can_be_saturated || saturated_early {
             result = result.and(Err(StopReason::Saturated))
         }
 
         Iteration {
+            applied_total: applied.values().sum(),
             applied,
             egraph_nodes,
             egraph_classes,
@@ -644,12 +1392,121 @@ where
             apply_time,
             rebuild_time,
             n_rebuilds,
+            // every new e-class adds an e-node, and every union removes an e-class
+            rebuild_unions: (egraph_classes_after_hooks + self.egraph.nodes().len())
+                - (egraph_enodes_after_hooks + self.egraph.number_of_classes()),
+            rebuild_iters: rebuild_stats.worklist_iters,
+            congruence_merges: rebuild_stats.congruence_merges,
             data: IterData::make(self),
             total_time: start_time.elapsed().as_secs_f64(),
             stop_reason: result.err(),
         }
     }
 
+    /// Reports how close the e-graph is to proving `lhs = rhs`, for instance
+    /// to find out why a [`Runner`] saturated without proving a goal.
+    ///
+    /// It lists the largest subterms of each side that are represented in
+    /// the e-graph, and the e-classes where subterms of both sides meet.
+    /// Subterms that are not listed and are not part of a listed one were
+    /// never built, which is usually where a missing rewrite is.
+    ///
+    /// # Example
+    /// ```
+    /// # use egg::*;
+    /// let rules: &[Rewrite<SymbolLang, ()>] = &[rewrite!("mul-one"; "(* ?a 1)" => "?a")];
+    /// let runner = Runner::default()
+    ///     .with_expr(&"(+ (* x 1) (* y 2))".parse().unwrap())
+    ///     .run(rules);
+    ///
+    /// let report = runner.goal_report(
+    ///     &"(+ (* x 1) (* y 2))".parse().unwrap(),
+    ///     &"(+ x (+ y y))".parse().unwrap(),
+    /// );
+    /// assert!(!report.proven);
+    /// // the whole left-hand side is there, but on the right only x and y are
+    /// assert_eq!(report.lhs_present.len(), 1);
+    /// let right: Vec<String> = report.rhs_present.iter().map(|(t, _)| t.to_string()).collect();
+    /// assert_eq!(right, vec!["x", "y"]);
+    /// // x has been shown equal to (* x 1), and y is a subterm of both sides
+    /// let class = |s: &str| runner.egraph.lookup_expr(&s.parse().unwrap()).unwrap();
+    /// assert_eq!(report.shared, vec![class("x"), class("y")]);
+    /// println!("{}", report);
+    /// ```
+    pub fn goal_report(&self, lhs: &RecExpr<L>, rhs: &RecExpr<L>) -> GoalReport<L> {
+        let lhs_classes = self.present_classes(lhs);
+        let rhs_classes = self.present_classes(rhs);
+        let lhs_present = Self::maximal_present(lhs, &lhs_classes);
+        let rhs_present = Self::maximal_present(rhs, &rhs_classes);
+
+        let mut shared: Vec<Id> = lhs_classes
+            .iter()
+            .flatten()
+            .filter(|id| rhs_classes.contains(&Some(**id)))
+            .copied()
+            .collect();
+        shared.sort_unstable();
+        shared.dedup();
+        // only keep the largest shared e-classes, not those of their subterms
+        shared.retain(|id| {
+            lhs_present.iter().any(|(_, p)| p == id) || rhs_present.iter().any(|(_, p)| p == id)
+        });
+
+        let proven = match (lhs_classes.last(), rhs_classes.last()) {
+            (Some(Some(l)), Some(Some(r))) => l == r,
+            _ => false,
+        };
+        GoalReport {
+            proven,
+            lhs_present,
+            rhs_present,
+            shared,
+        }
+    }
+
+    /// The canonical e-class of each subterm of `expr`, if it is in the
+    /// e-graph.
+    fn present_classes(&self, expr: &RecExpr<L>) -> Vec<Option<Id>> {
+        let mut classes: Vec<Option<Id>> = Vec::with_capacity(expr.len());
+        for node in expr {
+            let class = if node.all(|child| classes[usize::from(child)].is_some()) {
+                let node = node
+                    .clone()
+                    .map_children(|child| classes[usize::from(child)].unwrap());
+                self.egraph.lookup(node)
+            } else {
+                None
+            };
+            classes.push(class);
+        }
+        classes
+    }
+
+    /// The present subterms of `expr` that are the root or have a parent
+    /// that is not present.
+    fn maximal_present(expr: &RecExpr<L>, classes: &[Option<Id>]) -> Vec<(RecExpr<L>, Id)> {
+        let mut maximal = vec![false; expr.len()];
+        if let Some(root) = maximal.last_mut() {
+            *root = true;
+        }
+        for (node, class) in expr.as_ref().iter().zip(classes) {
+            if class.is_none() {
+                node.for_each(|child| maximal[usize::from(child)] = true);
+            }
+        }
+
+        let mut present: Vec<(RecExpr<L>, Id)> = vec![];
+        for (i, class) in classes.iter().enumerate() {
+            if let (true, Some(id)) = (maximal[i], class) {
+                let term = expr[i.into()].build_recexpr(|child| expr[child].clone());
+                if !present.contains(&(term.clone(), *id)) {
+                    present.push((term, *id));
+                }
+            }
+        }
+        present
+    }
+
     fn try_start(&mut self) {
         self.limits.start_time.get_or_insert_with(Instant::now);
     }
@@ -660,7 +1517,97 @@ where
     }
 }
 
-fn check_rules<L, N>(rules: &[&Rewrite<L, N>]) {
+impl<L, N> Runner<L, N, ()>
+where
+    L: Language,
+    N: Analysis<L>,
+{
+    /// Re-apply the applications recorded by
+    /// [`with_trace`](Runner::with_trace()) to `egraph`, in order.
+    ///
+    /// Each entry is applied with [`Rewrite::apply_once`] using the rule
+    /// of the same name from `rules`, and the e-graph is rebuilt between
+    /// iterations and at the end, just like the [`Runner`] does.
+    /// For the [`Id`]s in the trace to mean the same thing, `egraph` must
+    /// start out as the [`Runner`]'s did (e.g. with the same expressions
+    /// added in the same order), and any hooks that changed the e-graph
+    /// are not replayed. Entries may be dropped from the trace (say, by a
+    /// minimizer), as long as the remaining ones still refer to
+    /// e-classes that exist.
+    ///
+    /// Returns the number of entries that changed the e-graph.
+    ///
+    /// # Panics
+    /// Panics if an entry names a rule that is not in `rules`.
+    ///
+    /// # Example
+    /// ```
+    /// # use egg::*;
+    /// let rules: &[Rewrite<SymbolLang, ()>] = &[
+    ///     rewrite!("commute-add"; "(+ ?a ?b)" => "(+ ?b ?a)"),
+    ///     rewrite!("add-0"; "(+ ?a 0)" => "?a"),
+    /// ];
+    /// let expr = "(+ 0 (+ x 0))".parse().unwrap();
+    /// let runner = Runner::default().with_trace(true).with_expr(&expr).run(rules);
+    /// assert!(!runner.trace().is_empty());
+    ///
+    /// let mut egraph = EGraph::default();
+    /// let root = egraph.add_expr(&expr);
+    /// Runner::replay(&mut egraph, rules, runner.trace());
+    /// assert_eq!(egraph.number_of_classes(), runner.egraph.number_of_classes());
+    /// assert_eq!(egraph.lookup_expr(&"x".parse().unwrap()), Some(egraph.find(root)));
+    /// ```
+    pub fn replay<'a, R>(egraph: &mut EGraph<L, N>, rules: R, trace: &[TraceEntry]) -> usize
+    where
+        R: IntoIterator<Item = &'a Rewrite<L, N>>,
+        L: 'a,
+        N: 'a,
+    {
+        let rules: HashMap<Symbol, &Rewrite<L, N>> =
+            rules.into_iter().map(|rw| (rw.name, rw)).collect();
+        egraph.rebuild();
+
+        let mut changed = 0;
+        let mut iteration = trace.first().map(|entry| entry.iteration);
+        for entry in trace {
+            if Some(entry.iteration) != iteration {
+                egraph.rebuild();
+                iteration = Some(entry.iteration);
+            }
+            let rw = rules
+                .get(&entry.rule_name)
+                .unwrap_or_else(|| panic!("No rule named {} to replay", entry.rule_name));
+            if !rw.apply_once(egraph, entry.eclass, &entry.subst).is_empty() {
+                changed += 1;
+            }
+        }
+        egraph.rebuild();
+        changed
+    }
+}
+
+/// The canonical form of a match remembered by
+/// [`with_aggressive_saturation`](Runner::with_aggressive_saturation).
+fn known_match<L: Language, N: Analysis<L>>(
+    egraph: &EGraph<L, N>,
+    rule: Symbol,
+    eclass: Id,
+    subst: &Subst,
+) -> (Symbol, Id, Subst) {
+    let mut canonical = Subst::with_capacity(subst.len());
+    for (v, id) in subst.iter() {
+        canonical.insert(*v, egraph.find(id));
+    }
+    (rule, egraph.find(eclass), canonical)
+}
+
+fn check_rules<L: Language, N: Analysis<L>>(rules: &[&Rewrite<L, N>], egraph: &EGraph<L, N>) {
+    if egraph.are_explanations_enabled() {
+        for rw in rules {
+            rw.check_explainable();
+        }
+    }
+
     let mut name_counts = IndexMap::default();
     for rw in rules {
         *name_counts.entry(rw.name).or_default() += 1
@@ -700,6 +1647,30 @@ where
         true
     }
 
+    /// Returns the rules banned so far, as
+    /// `(rule, iteration_banned, iterations_banned_for)`.
+    ///
+    /// This is what [`Runner::ban_history`] reports.
+    /// Default implementation returns an empty `Vec`, since it bans nothing.
+    fn ban_history(&self) -> Vec<(Symbol, usize, usize)> {
+        vec![]
+    }
+
+    /// Forget any state accumulated while running rules, e.g. bans.
+    ///
+    /// This is called by [`Runner::run_phases`](Runner::run_phases())
+    /// before each phase.
+    /// Default implementation does nothing.
+    fn reset(&mut self) {}
+
+    /// Forget the state accumulated for the given rules only, so that they
+    /// start afresh, e.g. are not banned.
+    ///
+    /// This is called by [`Runner::add_rules`](Runner::add_rules()) with
+    /// the names of the added rules.
+    /// Default implementation does nothing.
+    fn reset_rules(&mut self, rules: &[Symbol]) {}
+
     /// A hook allowing you to customize rewrite searching behavior.
     /// Useful to implement rule management.
     ///
@@ -820,6 +1791,7 @@ pub struct BackoffScheduler {
     default_match_limit: usize,
     default_ban_length: usize,
     stats: IndexMap<Symbol, RuleStats>,
+    ban_history: Vec<(Symbol, usize, usize)>,
 }
 
 #[derive(Debug)]
@@ -877,6 +1849,21 @@ impl BackoffScheduler {
         self.rule_stats(name.into()).ban_length = length;
         self
     }
+
+    /// Every ban so far, as `(rule, iteration_banned, iterations_banned_for)`,
+    /// in the order they happened.
+    ///
+    /// The ban length is the one chosen when banning; the ban can end
+    /// earlier if the [`Runner`] fast-forwards because every rule is banned.
+    /// A rule that shows up here over and over again is a good candidate
+    /// for removing or reformulating.
+    /// The history is kept across [`reset`](RewriteScheduler::reset)s.
+    ///
+    /// After a run, the same history is available from
+    /// [`Runner::ban_history`].
+    pub fn ban_history(&self) -> Vec<(Symbol, usize, usize)> {
+        self.ban_history.clone()
+    }
 }
 
 impl Default for BackoffScheduler {
@@ -885,6 +1872,7 @@ impl Default for BackoffScheduler {
             stats: Default::default(),
             default_match_limit: 1_000,
             default_ban_length: 5,
+            ban_history: vec![],
         }
     }
 }
@@ -894,6 +1882,28 @@ where
     L: Language,
     N: Analysis<L>,
 {
+    fn ban_history(&self) -> Vec<(Symbol, usize, usize)> {
+        BackoffScheduler::ban_history(self)
+    }
+
+    fn reset(&mut self) {
+        for stats in self.stats.values_mut() {
+            stats.times_applied = 0;
+            stats.banned_until = 0;
+            stats.times_banned = 0;
+        }
+    }
+
+    fn reset_rules(&mut self, rules: &[Symbol]) {
+        for name in rules {
+            if let Some(stats) = self.stats.get_mut(name) {
+                stats.times_applied = 0;
+                stats.banned_until = 0;
+                stats.times_banned = 0;
+            }
+        }
+    }
+
     fn can_stop(&mut self, iteration: usize) -> bool {
         let n_stats = self.stats.len();
 
@@ -919,7 +1929,7 @@ where
             for (name, s) in &mut banned {
                 s.banned_until -= delta;
                 if s.banned_until == iteration {
-                    unbanned.push(name.as_str());
+                    unbanned.push(name.to_string());
                 }
             }
 
@@ -971,6 +1981,7 @@ where
                 threshold,
                 total_len,
             );
+            self.ban_history.push((rewrite.name, iteration, ban_length));
             vec![]
         } else {
             stats.times_applied += 1;
@@ -979,6 +1990,36 @@ where
     }
 }
 
+/// Keeps only `limit` substitutions in `matches`, starting at the
+/// `offset`-th one and wrapping around, so that moving the offset in each
+/// iteration eventually applies every match.
+/// Returns how many iterations that takes, i.e. 1 if nothing was dropped.
+fn truncate_matches<L: Language>(
+    matches: &mut Vec<SearchMatches<'_, L>>,
+    limit: usize,
+    offset: usize,
+) -> usize {
+    let total: usize = matches.iter().map(|m| m.substs.len()).sum();
+    if total <= limit {
+        return 1;
+    }
+    let start = offset % total;
+    let mut k = 0;
+    matches.retain_mut(|m| {
+        m.substs.retain(|_| {
+            let keep = (k + total - start) % total < limit;
+            k += 1;
+            keep
+        });
+        !m.substs.is_empty()
+    });
+    if limit == 0 {
+        usize::MAX
+    } else {
+        total.div_ceil(limit)
+    }
+}
+
 /// Custom data to inject into the [`Iteration`]s recorded by a [`Runner`]
 ///
 /// This trait allows you to add custom data to the [`Iteration`]s
@@ -1006,3 +2047,120 @@ where
 {
     fn make(_: &Runner<L, N, Self>) -> Self {}
 }
+
+#[cfg(test)]
+mod tests {
+    use crate::*;
+
+    #[test]
+    fn aggressive_saturation() {
+        let rules: &[Rewrite<SymbolLang, ()>] = &[
+            rewrite!("commute-add"; "(+ ?a ?b)" => "(+ ?b ?a)"),
+            rewrite!("assoc-add"; "(+ ?a (+ ?b ?c))" => "(+ (+ ?a ?b) ?c)"),
+            rewrite!("add-0"; "(+ ?a 0)" => "?a"),
+        ];
+        let expr = "(+ a (+ b (+ 0 c)))".parse().unwrap();
+        let normal = Runner::default().with_expr(&expr).run(rules);
+        let aggressive = Runner::default()
+            .with_expr(&expr)
+            .with_aggressive_saturation()
+            .run(rules);
+
+        assert!(matches!(normal.stop_reason, Some(StopReason::Saturated)));
+        assert!(matches!(
+            aggressive.stop_reason,
+            Some(StopReason::Saturated)
+        ));
+        // the final iteration that only re-applies old matches is skipped
+        assert_eq!(normal.iterations.len(), aggressive.iterations.len() + 1);
+        assert!(aggressive.iterations.last().unwrap().applied_total > 0);
+        assert_eq!(
+            normal.egraph.number_of_classes(),
+            aggressive.egraph.number_of_classes()
+        );
+        assert_eq!(normal.egraph.total_size(), aggressive.egraph.total_size());
+        assert_eq!(
+            normal.egraph.class_size_histogram(),
+            aggressive.egraph.class_size_histogram()
+        );
+        let root = aggressive.egraph.find(aggressive.roots[0]);
+        for expr in ["(+ (+ a b) c)", "(+ c (+ b a))"] {
+            let id = aggressive.egraph.lookup_expr(&expr.parse().unwrap());
+            assert_eq!(id, Some(root));
+        }
+    }
+
+    #[test]
+    fn idle_time_between_iterations_is_free() {
+        let mut runner = Runner::<SymbolLang, ()>::default()
+            .with_expr(&"(* (+ a 0) 1)".parse().unwrap())
+            .with_time_limit(Duration::from_millis(100));
+        runner.add_rules(vec![rewrite!("add-0"; "(+ ?a 0)" => "?a")]);
+        runner.run_one_iteration();
+
+        std::thread::sleep(Duration::from_millis(300));
+        runner.add_rules(vec![rewrite!("mul-1"; "(* ?a 1)" => "?a")]);
+        let iteration = runner.run_one_iteration();
+        assert_eq!(iteration.applied.get(&Symbol::from("mul-1")), Some(&1));
+        assert!(!matches!(
+            runner.stop_reason,
+            Some(StopReason::TimeLimit(_))
+        ));
+    }
+
+    #[test]
+    fn aggressive_saturation_with_match_limit() {
+        let rules: &[Rewrite<SymbolLang, ()>] =
+            &[rewrite!("commute-add"; "(+ ?a ?b)" => "(+ ?b ?a)").with_match_limit(1)];
+        let runner = Runner::default()
+            .with_expr(&"(+ a b)".parse().unwrap())
+            .with_expr(&"(+ c d)".parse().unwrap())
+            .with_scheduler(SimpleScheduler)
+            .with_aggressive_saturation()
+            .run(rules);
+
+        // the match dropped by the limit is applied in a later iteration
+        let egraph = &runner.egraph;
+        assert!(egraph.lookup_expr(&"(+ b a)".parse().unwrap()).is_some());
+        assert!(egraph.lookup_expr(&"(+ d c)".parse().unwrap()).is_some());
+        assert!(matches!(runner.stop_reason, Some(StopReason::Saturated)));
+    }
+
+    #[test]
+    fn match_limit_applies_every_match() {
+        let rules: &[Rewrite<SymbolLang, ()>] =
+            &[rewrite!("commute-add"; "(+ ?a ?b)" => "(+ ?b ?a)").with_match_limit(1)];
+        let runner = Runner::default()
+            .with_expr(&"(+ a b)".parse().unwrap())
+            .with_expr(&"(+ c d)".parse().unwrap())
+            .with_scheduler(SimpleScheduler)
+            .with_iter_limit(10)
+            .run(rules);
+
+        let egraph = &runner.egraph;
+        assert!(egraph.lookup_expr(&"(+ b a)".parse().unwrap()).is_some());
+        assert!(egraph.lookup_expr(&"(+ d c)".parse().unwrap()).is_some());
+        // it saturates once both matches were applied again without change
+        assert!(matches!(runner.stop_reason, Some(StopReason::Saturated)));
+        assert!(runner.iterations.len() < 10);
+    }
+
+    #[test]
+    fn rebuild_stats() {
+        let rules: &[Rewrite<SymbolLang, ()>] = &[rewrite!("a-b"; "a" => "b")];
+        let runner = Runner::default()
+            .with_expr(&"(+ (f a) (f b))".parse().unwrap())
+            .run(rules);
+
+        let first = &runner.iterations[0];
+        assert_eq!(first.applied[&Symbol::from("a-b")], 1);
+        // a = b, and then (f a) = (f b) by congruence
+        assert_eq!(first.rebuild_unions, 2);
+        assert_eq!(first.congruence_merges, 1);
+        assert!(first.rebuild_iters >= 1);
+
+        let report = runner.report();
+        assert_eq!(report.rebuild_unions, 2);
+        assert_eq!(report.congruence_merges, 1);
+    }
+}
@@ -11,6 +11,13 @@ use thiserror::Error;
 /// This implements [`FromStr`], and will only parse if it has a
 /// leading `?`.
 ///
+/// A variable can also have a _sort_, written as a suffix like `?e:Expr`.
+/// A sorted variable only matches e-classes that the [`Analysis`] says
+/// have that sort, see [`Analysis::has_sort`].
+/// The sort is part of the variable's name, so `?e:Expr` and `?e` are
+/// different variables; refer to it as `?e:Expr` on both sides of a rule.
+/// Variables without a sort match any e-class.
+///
 /// [`FromStr`]: std::str::FromStr
 #[derive(Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
 #[cfg_attr(feature = "serde-1", derive(serde::Serialize, serde::Deserialize))]
@@ -45,6 +52,23 @@ impl Var {
         }
     }
 
+    /// Returns the sort of this variable, if it has one.
+    ///
+    /// ```rust
+    /// # use egg::*;
+    /// let e: Var = "?e:Expr".parse().unwrap();
+    /// assert_eq!(e.sort(), Some(Symbol::from("Expr")));
+    /// assert_eq!("?e".parse::<Var>().unwrap().sort(), None);
+    /// ```
+    pub fn sort(&self) -> Option<Symbol> {
+        match self.0 {
+            VarInner::Sym(sym) => {
+                sym.with_str(|s| s.split_once(':').map(|(_, sort)| Symbol::from(sort)))
+            }
+            VarInner::Num(_) => None,
+        }
+    }
+
     /// Exposes the content of a variable
     pub fn expose(&self) -> VarExposed {
         match self.0 {
@@ -94,6 +118,8 @@ pub enum VarParseError {
     MissingQuestionMark(String),
     #[error("number pattern variable {0:?} was malformed")]
     BadNumber(String),
+    #[error("sorted pattern variable {0:?} should look like ?name:Sort")]
+    BadSort(String),
 }
 
 impl FromStr for Var {
@@ -107,7 +133,12 @@ impl FromStr for Var {
                 .parse()
                 .map(|num| Var(VarInner::Num(num)))
                 .map_err(|_| BadNumber(s.to_owned())),
-            [b'?', ..] if s.len() > 1 => Ok(Var(VarInner::Sym(Symbol::from(s)))),
+            [b'?', ..] if s.len() > 1 => match s.split_once(':') {
+                Some((name, sort)) if name.len() < 2 || sort.is_empty() || sort.contains(':') => {
+                    Err(BadSort(s.to_owned()))
+                }
+                _ => Ok(Var(VarInner::Sym(Symbol::from(s)))),
+            },
             _ => Err(MissingQuestionMark(s.to_owned())),
         }
     }
@@ -139,9 +170,17 @@ impl From<u32> for Var {
 
 /// A substitution mapping [`Var`]s to eclass [`Id`]s.
 ///
+/// A `Subst` found by a [`Pattern`] with operator variables, like `?f` in
+/// `(?f ?x ?y)`, also binds those to operators, see
+/// [`get_op`](Subst::get_op()).
+///
 #[derive(Default, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
 pub struct Subst {
     pub(crate) vec: smallvec::SmallVec<[(Var, Id); 3]>,
+    // behind a thin pointer, since most substitutions bind no operator
+    // variables
+    #[allow(clippy::box_collection)]
+    pub(crate) ops: Option<Box<Vec<(Var, Id)>>>,
 }
 
 impl Subst {
@@ -149,9 +188,37 @@ impl Subst {
     pub fn with_capacity(capacity: usize) -> Self {
         Self {
             vec: smallvec::SmallVec::with_capacity(capacity),
+            ops: None,
         }
     }
 
+    /// Bind the operator variable `var` to the operator of the e-node
+    /// `node`, returning the old e-node if present.
+    ///
+    /// `node` is an e-node id as used by [`EGraph::id_to_node`], not an
+    /// e-class; only its operator matters, not its children.
+    pub fn insert_op(&mut self, var: Var, node: Id) -> Option<Id> {
+        let ops = self.ops.get_or_insert_with(Default::default);
+        for pair in ops.iter_mut() {
+            if pair.0 == var {
+                return Some(std::mem::replace(&mut pair.1, node));
+            }
+        }
+        ops.push((var, node));
+        None
+    }
+
+    /// Retrieve what the operator variable `var` is bound to.
+    ///
+    /// This is an e-node id (see [`EGraph::id_to_node`]) for an e-node
+    /// with the bound operator, see [`insert_op`](Subst::insert_op()).
+    pub fn get_op(&self, var: Var) -> Option<Id> {
+        self.ops
+            .iter()
+            .flat_map(|ops| ops.iter())
+            .find_map(|(v, id)| if *v == var { Some(*id) } else { None })
+    }
+
     /// Insert something, returning the old `Id` if present.
     pub fn insert(&mut self, var: Var, id: Id) -> Option<Id> {
         for pair in &mut self.vec {
@@ -171,6 +238,21 @@ impl Subst {
             .find_map(|(v, id)| if *v == var { Some(id) } else { None })
     }
 
+    /// Like indexing with a [`Var`], but returns a [`MissingVar`] error
+    /// instead of panicking if `var` is not bound.
+    ///
+    /// ```
+    /// # use egg::*;
+    /// let (x, y): (Var, Var) = ("?x".parse().unwrap(), "?y".parse().unwrap());
+    /// let mut subst = Subst::default();
+    /// subst.insert(x, Id::from(3));
+    /// assert_eq!(subst.try_index(x), Ok(Id::from(3)));
+    /// assert_eq!(subst.try_index(y), Err(MissingVar(y)));
+    /// ```
+    pub fn try_index(&self, var: Var) -> Result<Id, MissingVar> {
+        self.get(var).copied().ok_or(MissingVar(var))
+    }
+
     /// Get the length of the substitution
     pub fn len(&self) -> usize {
         self.vec.len()
@@ -188,6 +270,12 @@ impl Subst {
     }
 }
 
+/// The error returned by [`Subst::try_index`] for a [`Var`] the
+/// substitution does not bind.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Error)]
+#[error("variable {0} is not bound by the substitution")]
+pub struct MissingVar(pub Var);
+
 impl std::ops::Index<Var> for Subst {
     type Output = Id;
 
@@ -210,6 +298,12 @@ impl Debug for Subst {
                 write!(f, ", ")?;
             }
         }
+        for (i, (var, node)) in self.ops.iter().flat_map(|ops| ops.iter()).enumerate() {
+            if len > 0 || i > 0 {
+                write!(f, ", ")?;
+            }
+            write!(f, "{}: op of node {}", var, node)?;
+        }
         write!(f, "}}")
     }
 }
@@ -218,6 +312,7 @@ impl FromIterator<(Var, Id)> for Subst {
     fn from_iter<T: IntoIterator<Item = (Var, Id)>>(iter: T) -> Self {
         Self {
             vec: iter.into_iter().unique_by(|(x, _)| *x).collect(),
+            ops: None,
         }
     }
 }
@@ -226,6 +321,15 @@ impl FromIterator<(Var, Id)> for Subst {
 mod tests {
     use super::*;
 
+    #[test]
+    fn ops_cost_one_pointer() {
+        let vec_size = std::mem::size_of::<smallvec::SmallVec<[(Var, Id); 3]>>();
+        assert_eq!(
+            std::mem::size_of::<Subst>(),
+            vec_size + std::mem::size_of::<usize>()
+        );
+    }
+
     #[test]
     fn var_parse() {
         assert_eq!(Var::from_str("?a").unwrap().to_string(), "?a");
@@ -0,0 +1,173 @@
+/*!
+Helpers for testing your own [`Language`](crate::Language)s.
+
+This module is only available with the `test-util` feature.
+!*/
+
+use std::fmt::Display;
+
+use crate::{util::SplitMix64, FromOp, Id, RecExpr};
+
+/**
+Checks that random terms of `L` survive printing and parsing.
+
+This builds random [`RecExpr`]s from `seed`, with shared subterms, out of
+the operators in `ops`, given as `(op, arity)` pairs like in
+[`random_egraph`](crate::bench::random_egraph), and panics with the
+offending term unless:
+
+- every e-node prints to an operator that [`FromOp`] parses back to the
+  same e-node,
+- every term prints to a string that parses back to the same term, and
+- with the `reports` feature, every term also survives a JSON round-trip
+  through [`serde_json`](https://docs.rs/serde_json), both as the string
+  that its [`Serialize`](https://docs.rs/serde/latest/serde/trait.Serialize.html)
+  implementation writes and as the tree of e-nodes that
+  [`RecExpr::from_json`] reads, with shared subterms written once.
+
+Terms are compared after [`canonicalize`](RecExpr::canonicalize), since
+parsing does not share subterms.
+There is no dependency on a fuzzing crate, so the operators to try are
+listed explicitly; include a few of each kind of leaf your
+[`define_language!`](crate::define_language) type accepts, like numbers,
+symbols and negative numbers.
+The same `seed` and `ops` always check the same terms.
+
+# Panics
+
+Panics if any check fails, if `ops` has no leaf (arity 0) operator, or if
+an operator in `ops` fails to parse with [`FromOp`].
+
+# Example
+
+```
+use egg::{*, testing::assert_language_roundtrip};
+
+define_language! {
+    enum Math {
+        "+" = Add([Id; 2]),
+        "neg" = Neg(Id),
+        Num(i32),
+        Symbol(Symbol),
+    }
+}
+
+let ops = [("+", 2), ("neg", 1), ("0", 0), ("-7", 0), ("x", 0)];
+assert_language_roundtrip::<Math>(42, &ops);
+```
+**/
+#[cfg_attr(docsrs, doc(cfg(feature = "test-util")))]
+pub fn assert_language_roundtrip<L: FromOp + Display>(seed: u64, ops: &[(&str, usize)]) {
+    assert!(
+        ops.iter().any(|&(_, arity)| arity == 0),
+        "assert_language_roundtrip needs at least one leaf operator"
+    );
+
+    let mut rng = SplitMix64(seed);
+    for _ in 0..100 {
+        let mut expr = random_expr::<L>(&mut rng, ops);
+        for node in expr.iter() {
+            let op = node.to_string();
+            let parsed = L::from_op(&op, node.children().to_vec()).unwrap_or_else(|e| {
+                panic!(
+                    "e-node {:?} printed as {:?}, which fails to parse: {:?}",
+                    node, op, e
+                )
+            });
+            assert_eq!(
+                &parsed, node,
+                "e-node printed as {:?}, which parses to a different e-node",
+                op
+            );
+        }
+
+        expr.canonicalize();
+        let s = expr.to_string();
+        let mut parsed: RecExpr<L> = s
+            .parse()
+            .unwrap_or_else(|e| panic!("{:?} fails to parse back: {:?}", s, e));
+        parsed.canonicalize();
+        assert_eq!(parsed, expr, "{:?} parses back to a different term", s);
+
+        #[cfg(all(feature = "serde-1", feature = "serde_json"))]
+        {
+            // the serde form of a `RecExpr` is its string
+            let json = serde_json::to_string(&expr).unwrap();
+            let back: String = serde_json::from_str(&json).unwrap();
+            let mut parsed: RecExpr<L> = back
+                .parse()
+                .unwrap_or_else(|e| panic!("JSON {} fails to parse back: {:?}", json, e));
+            parsed.canonicalize();
+            assert_eq!(
+                parsed, expr,
+                "JSON {} parses back to a different term",
+                json
+            );
+
+            let tree = json_tree(&expr);
+            let mut parsed = RecExpr::<L>::from_json(&tree)
+                .unwrap_or_else(|e| panic!("JSON tree {} fails to parse back: {:?}", tree, e));
+            parsed.canonicalize();
+            assert_eq!(
+                parsed, expr,
+                "JSON tree {} parses back to a different term",
+                tree
+            );
+        }
+    }
+}
+
+/// The tree of e-nodes that [`RecExpr::from_json`] reads, where each e-node
+/// is written the first time and referred to by its index after that.
+#[cfg(all(feature = "serde-1", feature = "serde_json"))]
+fn json_tree<L: FromOp + Display>(expr: &RecExpr<L>) -> serde_json::Value {
+    fn node<L: FromOp + Display>(
+        expr: &RecExpr<L>,
+        id: Id,
+        written: &mut Vec<bool>,
+    ) -> serde_json::Value {
+        let i = usize::from(id);
+        if written[i] {
+            return serde_json::json!({ "ref": i });
+        }
+        written[i] = true;
+        let e = &expr[id];
+        let mut value = serde_json::json!({ "op": e.to_string(), "id": i });
+        if !e.is_leaf() {
+            let children: Vec<_> = e
+                .children()
+                .iter()
+                .map(|&c| node(expr, c, written))
+                .collect();
+            value["children"] = children.into();
+        }
+        value
+    }
+
+    let root = Id::from(expr.len() - 1);
+    node(expr, root, &mut vec![false; expr.len()])
+}
+
+/// A random term of up to 20 e-nodes whose children are earlier ones, so
+/// subterms get shared.
+fn random_expr<L: FromOp>(rng: &mut SplitMix64, ops: &[(&str, usize)]) -> RecExpr<L> {
+    let len = 1 + rng.below(20);
+    let mut expr = RecExpr::default();
+    while expr.len() < len {
+        let (op, arity) = ops[rng.below(ops.len())];
+        if arity > 0 && expr.is_empty() {
+            continue;
+        }
+        let children: Vec<Id> = (0..arity)
+            .map(|_| Id::from(rng.below(expr.len())))
+            .collect();
+        let node = L::from_op(op, children).unwrap_or_else(|e| {
+            panic!(
+                "assert_language_roundtrip failed to parse operator {}: {:?}",
+                op, e
+            )
+        });
+        expr.add(node);
+    }
+    expr
+}
@@ -1,10 +1,74 @@
 use crate::Id;
 use std::fmt::Debug;
+#[cfg(feature = "union-find-stats")]
+use std::sync::atomic::{AtomicUsize, Ordering};
 
 #[derive(Debug, Clone, Default)]
 #[cfg_attr(feature = "serde-1", derive(serde::Serialize, serde::Deserialize))]
 pub struct UnionFind {
     parents: Vec<Id>,
+    #[cfg(feature = "union-find-stats")]
+    #[cfg_attr(feature = "serde-1", serde(skip))]
+    counters: FindCounters,
+}
+
+/// Statistics about the union-find of an [`EGraph`](crate::EGraph), see
+/// [`EGraph::union_find_stats`](crate::EGraph::union_find_stats).
+///
+/// The counters of finds are only kept with the `union-find-stats`
+/// feature, which adds a little overhead to every find. Without it, they
+/// are always 0.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde-1", derive(serde::Serialize))]
+#[non_exhaustive]
+pub struct UnionFindStats {
+    /// The number of ids ever created.
+    pub ids: usize,
+    /// The number of disjoint sets, i.e. ids that are their own leader.
+    pub sets: usize,
+    /// The longest path from an id to its leader, where a leader has
+    /// height 0. This is the longest path as of now, so it is shorter
+    /// after path compression.
+    pub max_height: usize,
+    /// The number of finds since the counters were last reset.
+    pub finds: usize,
+    /// The number of parent links followed by those finds.
+    pub find_steps: usize,
+    /// The number of parent links shortened by path compression.
+    pub compressions: usize,
+}
+
+impl UnionFindStats {
+    /// The average number of parent links followed per find, or 0 if
+    /// there were no finds. Values close to 1 mean path compression keeps
+    /// the trees flat.
+    pub fn average_find_steps(&self) -> f64 {
+        if self.finds == 0 {
+            0.0
+        } else {
+            self.find_steps as f64 / self.finds as f64
+        }
+    }
+}
+
+#[cfg(feature = "union-find-stats")]
+#[derive(Debug, Default)]
+struct FindCounters {
+    finds: AtomicUsize,
+    find_steps: AtomicUsize,
+    compressions: AtomicUsize,
+}
+
+#[cfg(feature = "union-find-stats")]
+impl Clone for FindCounters {
+    fn clone(&self) -> Self {
+        let copy = |c: &AtomicUsize| AtomicUsize::new(c.load(Ordering::Relaxed));
+        FindCounters {
+            finds: copy(&self.finds),
+            find_steps: copy(&self.find_steps),
+            compressions: copy(&self.compressions),
+        }
+    }
 }
 
 impl UnionFind {
@@ -18,6 +82,18 @@ impl UnionFind {
         self.parents.len()
     }
 
+    /// Whether every parent is an id of this union-find, so that finds
+    /// can't go out of bounds.
+    pub fn is_well_formed(&self) -> bool {
+        self.parents
+            .iter()
+            .all(|&p| usize::from(p) < self.parents.len())
+    }
+
+    pub fn shrink_to_fit(&mut self) {
+        self.parents.shrink_to_fit();
+    }
+
     fn parent(&self, query: Id) -> Id {
         self.parents[usize::from(query)]
     }
@@ -27,21 +103,90 @@ impl UnionFind {
     }
 
     pub fn find(&self, mut current: Id) -> Id {
+        let mut steps = 0;
         while current != self.parent(current) {
-            current = self.parent(current)
+            current = self.parent(current);
+            steps += 1;
         }
+        self.record_find(steps, 0);
         current
     }
 
     pub fn find_mut(&mut self, mut current: Id) -> Id {
+        let (mut steps, mut compressions) = (0, 0);
         while current != self.parent(current) {
             let grandparent = self.parent(self.parent(current));
+            if grandparent != self.parent(current) {
+                compressions += 1;
+            }
             *self.parent_mut(current) = grandparent;
             current = grandparent;
+            steps += 1;
         }
+        self.record_find(steps, compressions);
         current
     }
 
+    #[inline(always)]
+    #[allow(unused_variables)]
+    fn record_find(&self, steps: usize, compressions: usize) {
+        #[cfg(feature = "union-find-stats")]
+        {
+            let counters = &self.counters;
+            counters.finds.fetch_add(1, Ordering::Relaxed);
+            counters.find_steps.fetch_add(steps, Ordering::Relaxed);
+            counters
+                .compressions
+                .fetch_add(compressions, Ordering::Relaxed);
+        }
+    }
+
+    pub fn stats(&self) -> UnionFindStats {
+        // heights[i] is the distance from i to its leader, once computed
+        let mut heights: Vec<Option<usize>> = vec![None; self.parents.len()];
+        let mut path = vec![];
+        for start in 0..self.parents.len() {
+            let mut current = start;
+            while heights[current].is_none() {
+                let parent = usize::from(self.parents[current]);
+                if parent == current {
+                    heights[current] = Some(0);
+                    break;
+                }
+                path.push(current);
+                current = parent;
+            }
+            let mut height = heights[current].unwrap();
+            for i in path.drain(..).rev() {
+                height += 1;
+                heights[i] = Some(height);
+            }
+        }
+
+        #[allow(unused_mut)]
+        let mut stats = UnionFindStats {
+            ids: self.parents.len(),
+            sets: heights.iter().filter(|h| **h == Some(0)).count(),
+            max_height: heights.iter().flatten().copied().max().unwrap_or(0),
+            ..Default::default()
+        };
+        #[cfg(feature = "union-find-stats")]
+        {
+            let counters = &self.counters;
+            stats.finds = counters.finds.load(Ordering::Relaxed);
+            stats.find_steps = counters.find_steps.load(Ordering::Relaxed);
+            stats.compressions = counters.compressions.load(Ordering::Relaxed);
+        }
+        stats
+    }
+
+    pub fn reset_stats(&mut self) {
+        #[cfg(feature = "union-find-stats")]
+        {
+            self.counters = FindCounters::default();
+        }
+    }
+
     /// Given two leader ids, unions the two eclasses making root1 the leader.
     pub fn union(&mut self, root1: Id, root2: Id) -> Id {
         *self.parent_mut(root2) = root1;
@@ -89,4 +234,38 @@ mod tests {
         let expected = vec![0, 0, 0, 0, 4, 5, 6, 6, 6, 6];
         assert_eq!(uf.parents, ids(expected));
     }
+
+    #[test]
+    fn stats() {
+        let id = Id::from;
+        let mut uf = UnionFind::default();
+        for _ in 0..5 {
+            uf.make_set();
+        }
+        // a chain 3 -> 2 -> 1 -> 0, and 4 on its own
+        uf.union(id(2), id(3));
+        uf.union(id(1), id(2));
+        uf.union(id(0), id(1));
+        uf.reset_stats();
+
+        let stats = uf.stats();
+        assert_eq!((stats.ids, stats.sets, stats.max_height), (5, 2, 3));
+
+        assert_eq!(uf.find(id(3)), id(0));
+        assert_eq!(uf.find_mut(id(3)), id(0));
+        assert_eq!(uf.stats().max_height, 2);
+
+        let stats = uf.stats();
+        if cfg!(feature = "union-find-stats") {
+            assert_eq!(
+                (stats.finds, stats.find_steps, stats.compressions),
+                (2, 5, 1)
+            );
+        } else {
+            assert_eq!(
+                (stats.finds, stats.find_steps, stats.compressions),
+                (0, 0, 0)
+            );
+        }
+    }
 }
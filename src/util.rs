@@ -1,4 +1,15 @@
-use std::{fmt, iter::FromIterator};
+use std::{
+    cell::RefCell,
+    fmt,
+    iter::FromIterator,
+    marker::PhantomData,
+    num::NonZeroU32,
+    str::FromStr,
+    sync::{
+        atomic::{AtomicU32, AtomicUsize, Ordering},
+        Arc, Mutex, OnceLock,
+    },
+};
 use symbolic_expressions::Sexp;
 
 use fmt::{Debug, Display, Formatter};
@@ -9,9 +20,13 @@ use serde::{Deserialize, Serialize};
 #[allow(unused_imports)]
 use crate::*;
 
-/// An interned string.
+/// An interned string in the global table of the
+/// [`symbol_table`](https://crates.io/crates/symbol_table) crate.
 ///
-/// This is provided by the [`symbol_table`](https://crates.io/crates/symbol_table) crate.
+/// Every global [`Symbol`] is one of these, see [`Symbol::as_global`].
+pub use symbol_table::GlobalSymbol;
+
+/// An interned string.
 ///
 /// Internally, `egg` frequently compares [`Var`]s and elements of
 /// [`Language`]s. To keep comparisons fast, `egg` provides [`Symbol`] a simple
@@ -26,9 +41,12 @@ use crate::*;
 /// table, returning the index (inserting it if not found).
 /// That integer is used to cheaply implement
 /// `Copy`, `Clone`, `PartialEq`, `Eq`, `PartialOrd`, `Ord`, and `Hash`.
+/// The global table is the one of [`GlobalSymbol`], which is provided by the
+/// [`symbol_table`](https://crates.io/crates/symbol_table) crate.
 ///
-/// The internal symbol cache leaks the strings, which should be
+/// The global table leaks the strings, which should be
 /// fine if you only put in things like variable names and identifiers.
+/// Otherwise, see [`SymbolTable`] to intern strings for a limited time.
 ///
 /// # Example
 /// ```rust
@@ -40,7 +58,479 @@ use crate::*;
 /// assert_ne!(Symbol::from("foo"), Symbol::from("bar"));
 /// ```
 ///
-pub use symbol_table::GlobalSymbol as Symbol;
+#[derive(Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Symbol(NonZeroU32);
+
+// A global symbol has the bits of its `GlobalSymbol`: the shard of the global
+// table in the top `SHARD_BITS`, and the index in that shard below. The top
+// bit of that index is never reached in practice, so it marks the symbols of
+// a `SymbolTable` instead, whose other 31 bits hold the id of the table and
+// the index of the string in it.
+const SHARD_BITS: u32 = 4;
+const _: () = assert!(symbol_table::DEFAULT_N_SHARDS == 1 << SHARD_BITS);
+const SCOPED: u32 = 1 << (31 - SHARD_BITS);
+const BELOW_SCOPED: u32 = SCOPED - 1;
+const TABLE_BITS: u32 = 11;
+const INDEX_BITS: u32 = 31 - TABLE_BITS;
+
+/// The highest index in each shard of the global table that `egg` has
+/// handed out a [`Symbol`] for, so [`lookup_global`] can find them.
+static GLOBAL_SEEN: [AtomicU32; 1 << SHARD_BITS] = [const { AtomicU32::new(0) }; 1 << SHARD_BITS];
+
+/// How many [`SymbolTable`]s are entered on any thread, so that
+/// [`Symbol::new`] can skip looking for the active table when there is none.
+static ENTERED: AtomicUsize = AtomicUsize::new(0);
+
+thread_local! {
+    /// The table entered on this thread, see [`SymbolTable::enter`].
+    static ACTIVE: RefCell<Option<Arc<ScopedTable>>> = const { RefCell::new(None) };
+}
+
+/// The global strings seen so far, by string.
+///
+/// The global table of [`GlobalSymbol`] cannot be searched without
+/// inserting, so this is only filled in from [`GLOBAL_SEEN`] when a
+/// [`SymbolTable`] needs it.
+#[derive(Default)]
+struct GlobalIndex {
+    ids: HashMap<&'static str, Symbol>,
+    synced: [u32; 1 << SHARD_BITS],
+}
+
+/// Returns the global symbol of `s`, if `egg` interned it globally before.
+fn lookup_global(s: &str) -> Option<Symbol> {
+    static INDEX: OnceLock<Mutex<GlobalIndex>> = OnceLock::new();
+    let mut index = INDEX.get_or_init(Default::default).lock().unwrap();
+    let GlobalIndex { ids, synced } = &mut *index;
+    for (shard, seen) in GLOBAL_SEEN.iter().enumerate() {
+        let seen = seen.load(Ordering::Acquire);
+        for i in synced[shard] + 1..=seen {
+            let bits = NonZeroU32::new((shard as u32) << (32 - SHARD_BITS) | i).unwrap();
+            ids.insert(GlobalSymbol::from(bits).as_str(), Symbol(bits));
+        }
+        synced[shard] = synced[shard].max(seen);
+    }
+    ids.get(s).copied()
+}
+
+impl Symbol {
+    /// Interns `s`, in the [active](SymbolTable::enter) [`SymbolTable`] if
+    /// there is one, see [`intern_in`](Symbol::intern_in), and in the
+    /// global table otherwise.
+    pub fn new(s: impl AsRef<str>) -> Self {
+        let s = s.as_ref();
+        if ENTERED.load(Ordering::Relaxed) > 0 {
+            let scoped = ACTIVE.with(|active| {
+                let active = active.borrow();
+                active.as_ref().map(|table| table.intern(s))
+            });
+            if let Some(sym) = scoped {
+                return sym;
+            }
+        }
+        GlobalSymbol::new(s).into()
+    }
+
+    /// Interns `s` in `table`, unless it is not there yet but already in
+    /// the global table.
+    ///
+    /// This does not need `table` to be [entered](SymbolTable::enter), but
+    /// the resulting symbol can only be displayed while it is.
+    ///
+    /// ```rust
+    /// use egg::{Symbol, SymbolTable};
+    ///
+    /// let table = SymbolTable::new();
+    /// let local = Symbol::intern_in(&table, "only in the table");
+    /// assert_eq!(table.resolve(local), "only in the table");
+    /// // strings that are already global stay global
+    /// let foo = Symbol::from("foo");
+    /// assert_eq!(Symbol::intern_in(&table, "foo"), foo);
+    /// ```
+    pub fn intern_in(table: &SymbolTable, s: &str) -> Self {
+        table.table.intern(s)
+    }
+
+    /// Returns the [`GlobalSymbol`] of this symbol, or `None` if it was
+    /// interned in a [`SymbolTable`].
+    pub fn as_global(self) -> Option<GlobalSymbol> {
+        if self.0.get() & SCOPED == 0 {
+            Some(GlobalSymbol::from(self.0))
+        } else {
+            None
+        }
+    }
+
+    /// The id of the table of a scoped symbol and its index in there.
+    fn scoped_parts(self) -> (u32, u32) {
+        let bits = self.0.get();
+        let payload = (bits & BELOW_SCOPED) | (bits >> (32 - SHARD_BITS)) << (31 - SHARD_BITS);
+        (payload >> INDEX_BITS, payload & ((1 << INDEX_BITS) - 1))
+    }
+
+    fn from_scoped_parts(table: u32, index: u32) -> Self {
+        let payload = table << INDEX_BITS | index;
+        let bits =
+            (payload & BELOW_SCOPED) | SCOPED | (payload >> (31 - SHARD_BITS)) << (32 - SHARD_BITS);
+        Symbol(NonZeroU32::new(bits).unwrap())
+    }
+
+    /// Calls `f` with the string behind this symbol, without copying it.
+    ///
+    /// # Panics
+    /// Panics if this symbol was interned in a [`SymbolTable`] that is not
+    /// [entered](SymbolTable::enter) on this thread.
+    pub(crate) fn with_str<R>(self, f: impl FnOnce(&str) -> R) -> R {
+        if let Some(global) = self.as_global() {
+            return f(global.as_str());
+        }
+        ACTIVE.with(|active| {
+            let active = active.borrow();
+            let s = active.as_ref().and_then(|table| table.resolve(self));
+            f(s.expect("resolved a scoped symbol outside of its symbol table"))
+        })
+    }
+
+    /// Returns the string behind this symbol.
+    ///
+    /// Since the returned string lives forever, calling this on a symbol
+    /// interned in a [`SymbolTable`] copies its string for good, even after
+    /// the table is dropped (only once per distinct string).
+    /// `egg` itself never does that; compare symbols or use their
+    /// [`Display`] implementation when that matters.
+    ///
+    /// # Panics
+    /// Panics if this symbol was interned in a [`SymbolTable`] that is not
+    /// [entered](SymbolTable::enter) on this thread.
+    pub fn as_str(&self) -> &'static str {
+        static COPIES: OnceLock<Mutex<HashSet<&'static str>>> = OnceLock::new();
+        if let Some(global) = self.as_global() {
+            return global.as_str();
+        }
+        self.with_str(|s| {
+            let mut copies = COPIES.get_or_init(Default::default).lock().unwrap();
+            match copies.get(s) {
+                Some(&copy) => copy,
+                None => {
+                    let copy: &'static str = Box::leak(s.into());
+                    copies.insert(copy);
+                    copy
+                }
+            }
+        })
+    }
+}
+
+impl From<GlobalSymbol> for Symbol {
+    fn from(global: GlobalSymbol) -> Self {
+        let bits = NonZeroU32::from(global).get();
+        assert!(bits & SCOPED == 0, "too many global symbols");
+        let shard = (bits >> (32 - SHARD_BITS)) as usize;
+        let index = bits & BELOW_SCOPED;
+        let seen = &GLOBAL_SEEN[shard];
+        if seen.load(Ordering::Relaxed) < index {
+            seen.fetch_max(index, Ordering::Release);
+        }
+        Symbol(NonZeroU32::new(bits).unwrap())
+    }
+}
+
+impl From<&str> for Symbol {
+    fn from(s: &str) -> Self {
+        Symbol::new(s)
+    }
+}
+
+impl From<String> for Symbol {
+    fn from(s: String) -> Self {
+        Symbol::new(s)
+    }
+}
+
+impl From<&String> for Symbol {
+    fn from(s: &String) -> Self {
+        Symbol::new(s)
+    }
+}
+
+impl FromStr for Symbol {
+    type Err = std::convert::Infallible;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Ok(s.into())
+    }
+}
+
+impl From<Symbol> for &'static str {
+    fn from(sym: Symbol) -> Self {
+        sym.as_str()
+    }
+}
+
+/// Unlike [`Display`], this does not panic for a symbol whose
+/// [`SymbolTable`] is not entered, and shows where it comes from instead.
+impl Debug for Symbol {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        if let Some(global) = self.as_global() {
+            return Debug::fmt(global.as_str(), f);
+        }
+        ACTIVE.with(|active| {
+            let active = active.borrow();
+            match active.as_ref().and_then(|table| table.resolve(*self)) {
+                Some(s) => Debug::fmt(s, f),
+                None => {
+                    let (table, index) = self.scoped_parts();
+                    write!(f, "<symbol {} of symbol table {}>", index, table)
+                }
+            }
+        })
+    }
+}
+
+/// # Panics
+/// Panics for a symbol interned in a [`SymbolTable`] that is not
+/// [entered](SymbolTable::enter) on this thread.
+impl Display for Symbol {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        self.with_str(|s| Display::fmt(s, f))
+    }
+}
+
+#[cfg(feature = "serde-1")]
+impl Serialize for Symbol {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        self.with_str(|s| serializer.serialize_str(s))
+    }
+}
+
+#[cfg(feature = "serde-1")]
+impl<'de> Deserialize<'de> for Symbol {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        String::deserialize(deserializer).map(Symbol::from)
+    }
+}
+
+/// A scoped string interner whose strings are freed when it is dropped.
+///
+/// The global table behind [`Symbol`] never shrinks, so a long-running
+/// process that interns many unrelated strings will keep all of them alive.
+/// A [`SymbolTable`] can instead be created for a single piece of work (say a
+/// request in a server), and everything interned in it is reclaimed when the
+/// table goes out of scope.
+///
+/// While a table is [entered](SymbolTable::enter), every [`Symbol`] created
+/// on this thread is interned in it, including the [`Var`]s and operators
+/// parsed by [`Pattern`]s and [`SymbolLang`].
+/// Strings that are already in the global table when they are first
+/// interned in the table keep their global symbol, so rules built
+/// beforehand still match the e-graphs built in the scope.
+/// [`Symbol::intern_in`] interns in a table without entering it.
+/// Creating symbols while no table is
+/// entered on any thread costs the same as with [`GlobalSymbol`].
+///
+/// The lifetime rules are simple:
+/// - Strings resolved by [`SymbolTable::resolve`] borrow from the table, so
+///   they cannot outlive it.
+/// - A scoped [`Symbol`] is just an integer. It may outlive its table, but
+///   it must only ever be resolved by the table that created it, either
+///   explicitly or by displaying it while that table is entered.
+///   Resolving it anywhere else panics, and its [`Debug`] implementation
+///   shows the table id instead of the string.
+/// - While its table is alive, a scoped [`Symbol`] is only equal to the
+///   symbols of the same table. The ids of dropped tables are reused, so
+///   symbols that outlive their table may equal those of a later one.
+/// - [`Symbol::as_str`] copies the string for good.
+///
+/// At most 2048 tables can be alive at once, each with up to about a
+/// million strings; going over either limit panics.
+///
+/// # Example
+/// ```rust
+/// use egg::*;
+///
+/// let rules: Vec<Rewrite<SymbolLang, ()>> = vec![rewrite!("comm"; "(+ ?a ?b)" => "(+ ?b ?a)")];
+///
+/// let table = SymbolTable::new();
+/// {
+///     let _scope = table.enter();
+///     let expr: RecExpr<SymbolLang> = "(+ request_42 1)".parse().unwrap();
+///     let runner = Runner::default().with_expr(&expr).run(&rules);
+///     assert_eq!(runner.egraph.number_of_classes(), 3);
+///     assert_eq!(expr.to_string(), "(+ request_42 1)");
+/// }
+/// let foo = table.intern("zzz");
+/// assert_eq!(foo, table.intern("zzz"));
+/// assert_ne!(foo, table.intern("yyy"));
+/// assert_eq!(table.resolve(foo), "zzz");
+/// // another table has other symbols
+/// let other = SymbolTable::new();
+/// assert_ne!(other.intern("yyy"), table.intern("zzz"));
+/// assert_ne!(other.intern("zzz"), table.intern("zzz"));
+/// // displaying needs the table, but debugging does not
+/// assert!(format!("{:?}", foo).starts_with("<symbol"));
+/// drop(table); // "request_42", "zzz" and "yyy" are freed here
+/// ```
+#[derive(Default)]
+pub struct SymbolTable {
+    table: Arc<ScopedTable>,
+}
+
+/// The strings of a [`SymbolTable`], shared with the thread-local
+/// [`ACTIVE`] table when it is entered.
+struct ScopedTable {
+    id: u32,
+    strs: symbol_table::SymbolTable,
+    ids: Mutex<ScopedIds>,
+}
+
+#[derive(Default)]
+struct ScopedIds {
+    by_inner: HashMap<symbol_table::Symbol, Symbol>,
+    inner: Vec<symbol_table::Symbol>,
+}
+
+/// The ids of the live [`SymbolTable`]s.
+#[derive(Default)]
+struct TableIds {
+    free: Vec<u32>,
+    next: u32,
+}
+
+fn table_ids() -> &'static Mutex<TableIds> {
+    static IDS: OnceLock<Mutex<TableIds>> = OnceLock::new();
+    IDS.get_or_init(Default::default)
+}
+
+impl Default for ScopedTable {
+    fn default() -> Self {
+        let mut ids = table_ids().lock().unwrap();
+        let id = ids.free.pop().unwrap_or_else(|| {
+            assert!(ids.next < 1 << TABLE_BITS, "too many symbol tables");
+            ids.next += 1;
+            ids.next - 1
+        });
+        Self {
+            id,
+            strs: Default::default(),
+            ids: Default::default(),
+        }
+    }
+}
+
+impl Drop for ScopedTable {
+    fn drop(&mut self) {
+        table_ids().lock().unwrap().free.push(self.id);
+    }
+}
+
+impl ScopedTable {
+    fn intern(&self, s: &str) -> Symbol {
+        let inner = self.strs.intern(s);
+        let mut ids = self.ids.lock().unwrap();
+        let ScopedIds {
+            by_inner,
+            inner: all,
+        } = &mut *ids;
+        if let Some(&sym) = by_inner.get(&inner) {
+            return sym;
+        }
+        let sym = match lookup_global(s) {
+            Some(global) => global,
+            None => {
+                let index = all.len() as u32;
+                assert!(
+                    index < 1 << INDEX_BITS,
+                    "too many symbols in a symbol table"
+                );
+                Symbol::from_scoped_parts(self.id, index)
+            }
+        };
+        all.push(inner);
+        by_inner.insert(inner, sym);
+        sym
+    }
+
+    /// Returns the string of `sym`, or `None` if it does not come from this
+    /// table.
+    fn resolve(&self, sym: Symbol) -> Option<&str> {
+        let (table, index) = sym.scoped_parts();
+        if sym.as_global().is_some() || table != self.id {
+            return None;
+        }
+        let inner = self.ids.lock().unwrap().inner.get(index as usize).copied();
+        inner.map(|inner| self.strs.resolve(inner))
+    }
+}
+
+impl SymbolTable {
+    /// Creates a new, empty [`SymbolTable`].
+    ///
+    /// # Panics
+    /// Panics if 2048 tables are already alive.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Interns `s` in this table, see [`Symbol::intern_in`].
+    pub fn intern(&self, s: &str) -> Symbol {
+        Symbol::intern_in(self, s)
+    }
+
+    /// Returns the string that `sym` was interned from.
+    ///
+    /// # Panics
+    /// Panics unless `sym` is global or was created by this table.
+    pub fn resolve(&self, sym: Symbol) -> &str {
+        match sym.as_global() {
+            Some(global) => global.as_str(),
+            None => self
+                .table
+                .resolve(sym)
+                .expect("resolved a symbol in the wrong symbol table"),
+        }
+    }
+
+    /// Makes this the active table of the current thread until the returned
+    /// guard is dropped, see the [type-level docs](SymbolTable).
+    ///
+    /// # Panics
+    /// Panics if a table is already entered on this thread.
+    pub fn enter(&self) -> SymbolScope<'_> {
+        ACTIVE.with(|active| {
+            let mut active = active.borrow_mut();
+            assert!(active.is_none(), "a symbol table is already entered");
+            *active = Some(self.table.clone());
+        });
+        ENTERED.fetch_add(1, Ordering::Relaxed);
+        SymbolScope {
+            _table: PhantomData,
+        }
+    }
+}
+
+impl Debug for SymbolTable {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.debug_struct("SymbolTable")
+            .field("id", &self.table.id)
+            .finish_non_exhaustive()
+    }
+}
+
+/// The guard returned by [`SymbolTable::enter`], which leaves the table
+/// when dropped.
+#[derive(Debug)]
+pub struct SymbolScope<'a> {
+    // borrows the table so it outlives the scope, and is not `Send` since
+    // the active table is per thread
+    _table: PhantomData<(&'a SymbolTable, *const ())>,
+}
+
+impl Drop for SymbolScope<'_> {
+    fn drop(&mut self) {
+        ACTIVE.with(|active| active.borrow_mut().take());
+        ENTERED.fetch_sub(1, Ordering::Relaxed);
+    }
+}
 
 pub(crate) type BuildHasher = rustc_hash::FxBuildHasher;
 
@@ -59,6 +549,28 @@ mod hashmap {
     use super::BuildHasher;
     pub(crate) type HashMap<K, V> = hashbrown::HashMap<K, V, BuildHasher>;
     pub(crate) type HashSet<K> = hashbrown::HashSet<K, BuildHasher>;
+
+    /// Lets code remove from a [`HashMap`] or [`HashSet`] with the
+    /// `swap_remove` of the `deterministic` feature's index maps, where the
+    /// plain `remove` is deprecated.
+    pub(crate) trait SwapRemove<Q: ?Sized> {
+        type Removed;
+        fn swap_remove(&mut self, key: &Q) -> Self::Removed;
+    }
+
+    impl<K: std::hash::Hash + Eq, V> SwapRemove<K> for HashMap<K, V> {
+        type Removed = Option<V>;
+        fn swap_remove(&mut self, key: &K) -> Option<V> {
+            self.remove(key)
+        }
+    }
+
+    impl<K: std::hash::Hash + Eq> SwapRemove<K> for HashSet<K> {
+        type Removed = bool;
+        fn swap_remove(&mut self, key: &K) -> bool {
+            self.remove(key)
+        }
+    }
 }
 
 pub(crate) fn hashmap_with_capacity<K, V>(cap: usize) -> hashmap::HashMap<K, V> {
@@ -71,6 +583,26 @@ pub(crate) type IndexSet<K> = indexmap::IndexSet<K, BuildHasher>;
 pub(crate) type Instant = quanta::Instant;
 pub(crate) type Duration = std::time::Duration;
 
+/// A tiny pseudo-random generator, so the output of the random helpers
+/// doesn't depend on the platform or on an external crate.
+#[cfg(any(feature = "bench-util", feature = "test-util"))]
+pub(crate) struct SplitMix64(pub(crate) u64);
+
+#[cfg(any(feature = "bench-util", feature = "test-util"))]
+impl SplitMix64 {
+    pub(crate) fn next(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^ (z >> 31)
+    }
+
+    pub(crate) fn below(&mut self, n: usize) -> usize {
+        (self.next() % n as u64) as usize
+    }
+}
+
 pub(crate) fn concat_vecs<T>(to: &mut Vec<T>, mut from: Vec<T>) {
     if to.len() < from.len() {
         std::mem::swap(to, &mut from)
@@ -164,6 +696,11 @@ where
         }
     }
 
+    pub fn shrink_to_fit(&mut self) {
+        self.set.shrink_to_fit();
+        self.queue.shrink_to_fit();
+    }
+
     pub fn pop(&mut self) -> Option<T> {
         let res = self.queue.pop_front();
         res.as_ref().map(|t| self.set.remove(t));